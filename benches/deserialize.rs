@@ -1,7 +1,9 @@
 use clvmr::allocator::Allocator;
 use clvmr::serde::{
-    node_from_bytes, node_from_bytes_backrefs, node_to_bytes_backrefs,
+    backref_stats_from_bytes, node_from_bytes, node_from_bytes_backrefs,
+    node_from_bytes_backrefs_with_limits, node_from_stream, node_to_bytes_backrefs,
     serialized_length_from_bytes, serialized_length_from_bytes_trusted, tree_hash_from_stream,
+    DeserializationLimits,
 };
 use criterion::{criterion_group, criterion_main, Criterion};
 use std::include_bytes;
@@ -41,7 +43,8 @@ fn deserialize_benchmark(c: &mut Criterion) {
             },
         );
 
-        // we don't support compressed CLVM in tree_hash_from_stream yet
+        // tree_hash_from_stream doesn't support compressed CLVM, but
+        // backref_stats_from_bytes() computes the tree hash of it
         if name_suffix.is_empty() {
             group.bench_function(format!("tree_hash_from_stream{name_suffix}"), |b| {
                 b.iter(|| {
@@ -53,6 +56,14 @@ fn deserialize_benchmark(c: &mut Criterion) {
             });
         }
 
+        group.bench_function(format!("backref_stats_from_bytes{name_suffix}"), |b| {
+            b.iter(|| {
+                let start = Instant::now();
+                backref_stats_from_bytes(bl).expect("backref_stats_from_bytes");
+                start.elapsed()
+            })
+        });
+
         let mut a = Allocator::new();
         let iter_checkpoint = a.checkpoint();
 
@@ -64,6 +75,25 @@ fn deserialize_benchmark(c: &mut Criterion) {
                 start.elapsed()
             })
         });
+
+        // with a depth limit, the depth of every back referenced sub tree has
+        // to be computed. Without one (above), that work is skipped
+        let limits = DeserializationLimits {
+            max_depth: 10000,
+            ..Default::default()
+        };
+        group.bench_function(
+            format!("node_from_bytes_backrefs_with_limits{name_suffix}"),
+            |b| {
+                b.iter(|| {
+                    a.restore_checkpoint(&iter_checkpoint);
+                    let start = Instant::now();
+                    node_from_bytes_backrefs_with_limits(&mut a, bl, limits)
+                        .expect("node_from_bytes_backrefs_with_limits");
+                    start.elapsed()
+                })
+            },
+        );
     }
 
     let mut a = Allocator::new();
@@ -77,6 +107,17 @@ fn deserialize_benchmark(c: &mut Criterion) {
         })
     });
 
+    // the Cursor based API wraps the same parser, it should be just as fast
+    group.bench_function("node_from_stream", |b| {
+        b.iter(|| {
+            a.restore_checkpoint(&iter_checkpoint);
+            let mut cur = std::io::Cursor::new(&block[..]);
+            let start = Instant::now();
+            node_from_stream(&mut a, &mut cur).expect("node_from_stream");
+            start.elapsed()
+        })
+    });
+
     group.finish();
 }
 
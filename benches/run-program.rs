@@ -205,6 +205,7 @@ fn run_program_benchmark(c: &mut Criterion) {
         ("compressed-2000", prev_generator),
         ("concat", tuple3::<16, 397>),
         ("count-even", generate_list::<15000>),
+        ("deep-path", generate_list::<1000>),
         ("factorial", single_value::<10000>),
         ("hash-string", long_strings),
         ("hash-tree", large_tree::<16>),
@@ -217,6 +218,7 @@ fn run_program_benchmark(c: &mut Criterion) {
         ("matrix-multiply", matrix::<50, 50>),
         ("point-pow", point_pow),
         ("pubkey-tree", large_tree::<10>),
+        ("sha256-chain", none),
         ("shift-left", pair::<410>),
         ("substr", long_string),
         ("substr-tree", long_string),
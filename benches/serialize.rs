@@ -1,6 +1,7 @@
-use clvmr::allocator::Allocator;
+use clvmr::allocator::{Allocator, NodePtr};
 use clvmr::serde::{
-    node_from_bytes, node_from_bytes_backrefs, node_to_bytes, node_to_bytes_backrefs, Serializer,
+    node_from_bytes, node_from_bytes_backrefs, node_to_bytes, node_to_bytes_backrefs, BackrefCache,
+    CompressionLevel, Serializer,
 };
 use criterion::black_box;
 use criterion::{criterion_group, criterion_main, Criterion};
@@ -32,6 +33,17 @@ fn serialize_benchmark(c: &mut Criterion) {
             })
         });
 
+        for level in [CompressionLevel::Fast, CompressionLevel::Max] {
+            group.bench_function(format!("node_to_bytes_backrefs {level:?} {name}"), |b| {
+                b.iter(|| {
+                    let start = Instant::now();
+                    let mut cache = BackrefCache::new().with_compression_level(level);
+                    black_box(cache.node_to_bytes(&a, node).expect("node_to_bytes"));
+                    start.elapsed()
+                })
+            });
+        }
+
         group.bench_function(format!("Serializer {name}"), |b| {
             b.iter(|| {
                 let start = Instant::now();
@@ -43,6 +55,46 @@ fn serialize_benchmark(c: &mut Criterion) {
         });
     }
 
+    // a tree like the result of an arithmetic heavy program, where almost all
+    // atoms are small numbers. Pairs of (i i*i), in a list
+    let mut a = Allocator::new();
+    let mut node = NodePtr::NIL;
+    for i in 0..50000_u32 {
+        let square = a
+            .new_small_number((i * i) & 0x3ff_ffff)
+            .expect("new_small_number");
+        let square = a.new_pair(square, NodePtr::NIL).expect("new_pair");
+        let value = a.new_small_number(i).expect("new_small_number");
+        let item = a.new_pair(value, square).expect("new_pair");
+        node = a.new_pair(item, node).expect("new_pair");
+    }
+
+    group.bench_function("node_to_bytes small-atoms", |b| {
+        b.iter(|| {
+            let start = Instant::now();
+            black_box(node_to_bytes(&a, node).expect("node_to_bytes"));
+            start.elapsed()
+        })
+    });
+
+    group.bench_function("node_to_bytes_backrefs small-atoms", |b| {
+        b.iter(|| {
+            let start = Instant::now();
+            black_box(node_to_bytes_backrefs(&a, node).expect("node_to_bytes_backrefs"));
+            start.elapsed()
+        })
+    });
+
+    group.bench_function("Serializer small-atoms", |b| {
+        b.iter(|| {
+            let start = Instant::now();
+            let mut ser = Serializer::new(None);
+            let _ = ser.add(&a, node);
+            black_box(ser.into_inner());
+            start.elapsed()
+        })
+    });
+
     group.finish();
 }
 
@@ -1,6 +1,8 @@
+use clvmr::allocator::{Allocator, NodePtr};
+use clvmr::serde::tree_hash;
+use clvmr::sha256::{sha256_backend, Sha256};
 use criterion::{criterion_group, criterion_main, Criterion};
-
-use chia_sha2::Sha256;
+use std::hint::black_box;
 
 const BYTE_LENGTHS: [u8; 6] = [8, 16, 32, 64, 96, 128];
 const MAX_VAL: u8 = 250;
@@ -19,7 +21,21 @@ fn hash_bytes(bytes: &[u8]) -> [u8; 32] {
     sha256.finalize()
 }
 
+fn large_tree(a: &mut Allocator, depth: u32) -> NodePtr {
+    if depth == 0 {
+        a.new_atom(b"foobar").expect("new_atom")
+    } else {
+        let left = large_tree(a, depth - 1);
+        let right = large_tree(a, depth - 1);
+        a.new_pair(left, right).expect("new_pair")
+    }
+}
+
 fn sha256_hash_benchmark(c: &mut Criterion) {
+    // compare the results of running with and without the "sha2-asm" and
+    // "openssl" features to measure the difference between backends
+    println!("sha256 backend: {:?}", sha256_backend());
+
     // setup benchmark
     let mut group = c.benchmark_group("sha256_hash");
 
@@ -34,7 +50,23 @@ fn sha256_hash_benchmark(c: &mut Criterion) {
             }
         })
     });
-    // create
+
+    for len in [32, 1024, 65536] {
+        let buf = vec![0xcc_u8; len];
+        group.bench_function(format!("hash-{len}"), |b| {
+            b.iter(|| hash_bytes(black_box(&buf)))
+        });
+    }
+
+    let mut a = Allocator::new();
+    let tree = large_tree(&mut a, 16);
+    group.bench_function("tree_hash", |b| b.iter(|| tree_hash(&a, black_box(tree))));
+
+    #[cfg(feature = "rayon")]
+    group.bench_function("tree_hash_parallel", |b| {
+        b.iter(|| clvmr::serde::tree_hash_parallel(&a, black_box(tree)))
+    });
+
     group.finish();
 }
 
@@ -0,0 +1,52 @@
+use clvmr::allocator::{Allocator, NodePtr};
+use clvmr::traverse_path::{traverse_path, traverse_path_fast};
+use criterion::{criterion_group, criterion_main, Criterion};
+use std::hint::black_box;
+
+// a list of 1000 items, to look up deep paths in
+fn make_list(a: &mut Allocator) -> NodePtr {
+    let mut list = NodePtr::NIL;
+    for i in 0..1000_u32 {
+        let item = a.new_number(i.into()).unwrap();
+        list = a.new_pair(item, list).unwrap();
+    }
+    list
+}
+
+// the path to the first item after skipping `depth` items (i.e. "rest"
+// `depth` times)
+fn list_path(depth: u32) -> Vec<u8> {
+    let bits = depth + 2;
+    let mut path = vec![0_u8; bits.div_ceil(8) as usize];
+    // the sentinel bit
+    let last = path.len() - 1;
+    path[last - (bits as usize - 1) / 8] |= 1 << ((bits - 1) % 8);
+    for i in 0..depth {
+        path[last - i as usize / 8] |= 1 << (i % 8);
+    }
+    path
+}
+
+fn traverse_path_benchmark(c: &mut Criterion) {
+    let mut a = Allocator::new();
+    let list = make_list(&mut a);
+
+    let mut group = c.benchmark_group("traverse_path");
+    for depth in [5, 20, 60, 200, 900] {
+        let path = list_path(depth);
+        group.bench_function(format!("buffer-{depth}"), |b| {
+            b.iter(|| traverse_path(&a, black_box(&path), list).unwrap())
+        });
+    }
+    for depth in [5, 20] {
+        let path = list_path(depth);
+        let path = path.iter().fold(0_u32, |acc, b| (acc << 8) | *b as u32);
+        group.bench_function(format!("u32-{depth}"), |b| {
+            b.iter(|| traverse_path_fast(&a, black_box(path), list).unwrap())
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(traverse_path_bench, traverse_path_benchmark);
+criterion_main!(traverse_path_bench);
@@ -0,0 +1,15 @@
+fn main() {
+    #[cfg(feature = "cbindgen")]
+    {
+        let crate_dir = std::env::var("CARGO_MANIFEST_DIR").unwrap();
+        let config = cbindgen::Config::from_file(format!("{crate_dir}/cbindgen.toml"))
+            .expect("failed to read cbindgen.toml");
+        cbindgen::Builder::new()
+            .with_crate(&crate_dir)
+            .with_config(config)
+            .generate()
+            .expect("failed to generate C header")
+            .write_to_file(format!("{crate_dir}/include/clvm.h"));
+    }
+    println!("cargo:rerun-if-changed=src");
+}
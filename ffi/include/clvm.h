@@ -0,0 +1,185 @@
+#ifndef CLVM_H
+#define CLVM_H
+
+/* This file is generated by cbindgen. Do not edit it by hand, build the clvm_ffi crate with the "cbindgen" feature instead. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+typedef enum ClvmError {
+  CLVM_ERROR_OK = 0,
+  /**
+   * a required pointer argument was null
+   */
+  CLVM_ERROR_NULL_POINTER = 1,
+  /**
+   * a `ClvmNode` handle that wasn't returned by this allocator
+   */
+  CLVM_ERROR_INVALID_NODE = 2,
+  /**
+   * the buffer is not a valid CLVM serialization
+   */
+  CLVM_ERROR_DESERIALIZE = 3,
+  /**
+   * the program failed, for any other reason than the ones below
+   */
+  CLVM_ERROR_EVAL = 4,
+  /**
+   * the program exceeded the maximum cost
+   */
+  CLVM_ERROR_COST_EXCEEDED = 5,
+  /**
+   * the allocator ran out of heap, atoms or pairs
+   */
+  CLVM_ERROR_OUT_OF_MEMORY = 6,
+  /**
+   * the node could not be serialized
+   */
+  CLVM_ERROR_SERIALIZE = 7,
+} ClvmError;
+
+typedef struct ClvmAllocator ClvmAllocator;
+
+/**
+ * A handle to a node in a `ClvmAllocator`
+ */
+typedef uint32_t ClvmNode;
+
+/**
+ * Create a new allocator. It must be freed with `clvm_allocator_free()`.
+ * `LIMIT_HEAP` and `LIMIT_ATOM_SIZE` in `flags` limit the allocator, the
+ * other flags are ignored. The heap limit is a property of the allocator, so
+ * `LIMIT_HEAP` only takes effect when passed here. `LIMIT_ATOM_SIZE` passed
+ * here applies to every atom created with this allocator (including by
+ * `clvm_deserialize()`), while passing it to `clvm_run_program()` only limits
+ * the atoms created by that program.
+ */
+struct ClvmAllocator *clvm_allocator_new(uint32_t flags);
+
+/**
+ * Free an allocator and all nodes it holds.
+ *
+ * # Safety
+ *
+ * `a` must be null or a pointer returned by `clvm_allocator_new()` that has
+ * not already been freed.
+ */
+void clvm_allocator_free(struct ClvmAllocator *a);
+
+/**
+ * Deserialize `len` bytes from `buf` into a node, stored in `out`.
+ *
+ * # Safety
+ *
+ * `a` must be a valid allocator, `buf` must point to `len` readable bytes and
+ * `out` must be valid for writes.
+ */
+enum ClvmError clvm_deserialize(struct ClvmAllocator *a,
+                                const uint8_t *buf,
+                                size_t len,
+                                ClvmNode *out);
+
+/**
+ * Like `clvm_deserialize()`, but the serialization may contain
+ * back-references.
+ *
+ * # Safety
+ *
+ * `a` must be a valid allocator, `buf` must point to `len` readable bytes and
+ * `out` must be valid for writes.
+ */
+enum ClvmError clvm_deserialize_backrefs(struct ClvmAllocator *a,
+                                         const uint8_t *buf,
+                                         size_t len,
+                                         ClvmNode *out);
+
+/**
+ * Serialize `node`. On success, a pointer to the serialized bytes is stored
+ * in `out_buf` and its length in `out_len`. The buffer must be freed with
+ * `clvm_buffer_free()`.
+ *
+ * # Safety
+ *
+ * `a` must be a valid allocator and `out_buf` and `out_len` must be valid for
+ * writes.
+ */
+enum ClvmError clvm_serialize(struct ClvmAllocator *a,
+                              ClvmNode node,
+                              uint8_t **out_buf,
+                              size_t *out_len);
+
+/**
+ * Free a buffer returned by `clvm_serialize()`.
+ *
+ * # Safety
+ *
+ * `buf` must be null or a buffer returned by `clvm_serialize()`, together
+ * with its length, that has not already been freed.
+ */
+void clvm_buffer_free(uint8_t *buf, size_t len);
+
+/**
+ * Return a description of the most recent error reported by a function
+ * taking this allocator, or null if there hasn't been one (or it didn't have
+ * a description). The string is owned by the allocator and is valid until
+ * the next call that fails.
+ *
+ * # Safety
+ *
+ * `a` must be a valid allocator.
+ */
+const char *clvm_last_error(const struct ClvmAllocator *a);
+
+/**
+ * Run `program` with the environment `args` using the chia dialect, with the
+ * specified flags. The heap limit is the one of the allocator, see
+ * `clvm_allocator_new()`. `LIMIT_ATOM_SIZE` applies to this run only. On success, the cost is stored in `out_cost` and the
+ * result in `out_result`.
+ *
+ * # Safety
+ *
+ * `a` must be a valid allocator and `out_cost` and `out_result` must be valid
+ * for writes.
+ */
+enum ClvmError clvm_run_program(struct ClvmAllocator *a,
+                                ClvmNode program,
+                                ClvmNode args,
+                                uint64_t max_cost,
+                                uint32_t flags,
+                                uint64_t *out_cost,
+                                ClvmNode *out_result);
+
+/**
+ * Return a pointer to the bytes of an atom and store its length in
+ * `out_len`. Returns null if `node` is a pair or not a valid handle. Small
+ * atoms are written into `scratch`, which must have room for 4 bytes, and the
+ * returned pointer refers to it.
+ *
+ * The returned pointer is valid until the next call that creates nodes in
+ * the allocator, or until `scratch` is modified.
+ *
+ * # Safety
+ *
+ * `a` must be a valid allocator, `scratch` must be valid for writes of 4
+ * bytes and `out_len` must be valid for writes.
+ */
+const uint8_t *clvm_atom(const struct ClvmAllocator *a,
+                         ClvmNode node,
+                         uint8_t *scratch,
+                         size_t *out_len);
+
+/**
+ * If `node` is a pair, store its first and rest in `out_first` and
+ * `out_rest` and return true. Otherwise return false.
+ *
+ * # Safety
+ *
+ * `a` must be a valid allocator and `out_first` and `out_rest` must be valid
+ * for writes.
+ */
+bool clvm_pair(struct ClvmAllocator *a, ClvmNode node, ClvmNode *out_first, ClvmNode *out_rest);
+
+#endif  /* CLVM_H */
@@ -0,0 +1,589 @@
+//! C bindings for the CLVM interpreter, for embedders that can't use the rust
+//! crate, the python wheel or the wasm module directly. The header for these
+//! functions is in `include/clvm.h`, and is regenerated by building this crate
+//! with the `cbindgen` feature.
+//!
+//! A `ClvmAllocator` owns all nodes created through it. Nodes are referred to
+//! by `ClvmNode` handles, which are only meaningful together with the
+//! allocator that returned them, and stay valid until the allocator is freed.
+//! A node has a single handle, so looking up the same node again (e.g. with
+//! `clvm_pair()`) returns the handle it already has rather than a new one.
+//!
+//! Functions that can fail return a `ClvmError`. The numeric values of the
+//! error codes are stable. A human readable description of the most recent
+//! failure is available from `clvm_last_error()`.
+
+use std::collections::HashMap;
+use std::ffi::{c_char, CString};
+use std::ptr;
+use std::slice;
+
+use clvmr::allocator::{Allocator, NodePtr, SExp};
+use clvmr::chia_dialect::{ChiaDialect, LIMIT_ATOM_SIZE, LIMIT_HEAP, MAX_ATOM_SIZE};
+use clvmr::error::ErrorCode;
+use clvmr::reduction::EvalErr;
+use clvmr::run_program::run_program;
+use clvmr::serde::{node_from_bytes, node_from_bytes_backrefs, node_to_bytes};
+
+/// A handle to a node in a `ClvmAllocator`
+pub type ClvmNode = u32;
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ClvmError {
+    Ok = 0,
+    /// a required pointer argument was null
+    NullPointer = 1,
+    /// a `ClvmNode` handle that wasn't returned by this allocator
+    InvalidNode = 2,
+    /// the buffer is not a valid CLVM serialization
+    Deserialize = 3,
+    /// the program failed, for any other reason than the ones below
+    Eval = 4,
+    /// the program exceeded the maximum cost
+    CostExceeded = 5,
+    /// the allocator ran out of heap, atoms or pairs
+    OutOfMemory = 6,
+    /// the node could not be serialized
+    Serialize = 7,
+}
+
+impl From<&EvalErr> for ClvmError {
+    fn from(err: &EvalErr) -> Self {
+        match err.code() {
+            ErrorCode::CostExceeded => ClvmError::CostExceeded,
+            ErrorCode::OutOfMemory | ErrorCode::TooManyPairs | ErrorCode::TooManyAtoms => {
+                ClvmError::OutOfMemory
+            }
+            _ => ClvmError::Eval,
+        }
+    }
+}
+
+pub struct ClvmAllocator {
+    allocator: Allocator,
+    // the ClvmNode handles handed out are indices into this vector
+    nodes: Vec<NodePtr>,
+    // the handle of every node in `nodes`, so that a node is only added once
+    handles: HashMap<NodePtr, ClvmNode>,
+    last_error: Option<CString>,
+}
+
+impl ClvmAllocator {
+    fn node(&self, handle: ClvmNode) -> Result<NodePtr, ClvmError> {
+        self.nodes
+            .get(handle as usize)
+            .copied()
+            .ok_or(ClvmError::InvalidNode)
+    }
+
+    fn handle(&mut self, node: NodePtr) -> ClvmNode {
+        *self.handles.entry(node).or_insert_with(|| {
+            self.nodes.push(node);
+            (self.nodes.len() - 1) as ClvmNode
+        })
+    }
+
+    fn fail(&mut self, code: ClvmError, message: String) -> ClvmError {
+        // the messages we produce never contain nul bytes
+        self.last_error = CString::new(message).ok();
+        code
+    }
+}
+
+// the heap limit used when LIMIT_HEAP is set
+const HEAP_LIMIT: usize = 500_000_000;
+
+/// Create a new allocator. It must be freed with `clvm_allocator_free()`.
+/// `LIMIT_HEAP` and `LIMIT_ATOM_SIZE` in `flags` limit the allocator, the
+/// other flags are ignored. The heap limit is a property of the allocator, so
+/// `LIMIT_HEAP` only takes effect when passed here. `LIMIT_ATOM_SIZE` passed
+/// here applies to every atom created with this allocator (including by
+/// `clvm_deserialize()`), while passing it to `clvm_run_program()` only limits
+/// the atoms created by that program.
+#[no_mangle]
+pub extern "C" fn clvm_allocator_new(flags: u32) -> *mut ClvmAllocator {
+    let mut allocator = if (flags & LIMIT_HEAP) != 0 {
+        Allocator::new_limited(HEAP_LIMIT)
+    } else {
+        Allocator::new()
+    };
+    if (flags & LIMIT_ATOM_SIZE) != 0 {
+        allocator.set_max_atom_size(MAX_ATOM_SIZE);
+    }
+    Box::into_raw(Box::new(ClvmAllocator {
+        allocator,
+        nodes: Vec::new(),
+        handles: HashMap::new(),
+        last_error: None,
+    }))
+}
+
+/// Free an allocator and all nodes it holds.
+///
+/// # Safety
+///
+/// `a` must be null or a pointer returned by `clvm_allocator_new()` that has
+/// not already been freed.
+#[no_mangle]
+pub unsafe extern "C" fn clvm_allocator_free(a: *mut ClvmAllocator) {
+    if !a.is_null() {
+        drop(Box::from_raw(a));
+    }
+}
+
+/// Deserialize `len` bytes from `buf` into a node, stored in `out`.
+///
+/// # Safety
+///
+/// `a` must be a valid allocator, `buf` must point to `len` readable bytes and
+/// `out` must be valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn clvm_deserialize(
+    a: *mut ClvmAllocator,
+    buf: *const u8,
+    len: usize,
+    out: *mut ClvmNode,
+) -> ClvmError {
+    deserialize_impl(a, buf, len, out, node_from_bytes)
+}
+
+/// Like `clvm_deserialize()`, but the serialization may contain
+/// back-references.
+///
+/// # Safety
+///
+/// `a` must be a valid allocator, `buf` must point to `len` readable bytes and
+/// `out` must be valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn clvm_deserialize_backrefs(
+    a: *mut ClvmAllocator,
+    buf: *const u8,
+    len: usize,
+    out: *mut ClvmNode,
+) -> ClvmError {
+    deserialize_impl(a, buf, len, out, node_from_bytes_backrefs)
+}
+
+unsafe fn deserialize_impl(
+    a: *mut ClvmAllocator,
+    buf: *const u8,
+    len: usize,
+    out: *mut ClvmNode,
+    deserializer: fn(&mut Allocator, &[u8]) -> std::io::Result<NodePtr>,
+) -> ClvmError {
+    let (Some(a), false, false) = (a.as_mut(), buf.is_null(), out.is_null()) else {
+        return ClvmError::NullPointer;
+    };
+    match deserializer(&mut a.allocator, slice::from_raw_parts(buf, len)) {
+        Ok(node) => {
+            *out = a.handle(node);
+            ClvmError::Ok
+        }
+        Err(e) => a.fail(ClvmError::Deserialize, e.to_string()),
+    }
+}
+
+/// Serialize `node`. On success, a pointer to the serialized bytes is stored
+/// in `out_buf` and its length in `out_len`. The buffer must be freed with
+/// `clvm_buffer_free()`.
+///
+/// # Safety
+///
+/// `a` must be a valid allocator and `out_buf` and `out_len` must be valid for
+/// writes.
+#[no_mangle]
+pub unsafe extern "C" fn clvm_serialize(
+    a: *mut ClvmAllocator,
+    node: ClvmNode,
+    out_buf: *mut *mut u8,
+    out_len: *mut usize,
+) -> ClvmError {
+    let (Some(a), false, false) = (a.as_mut(), out_buf.is_null(), out_len.is_null()) else {
+        return ClvmError::NullPointer;
+    };
+    let node = match a.node(node) {
+        Ok(node) => node,
+        Err(e) => return e,
+    };
+    match node_to_bytes(&a.allocator, node) {
+        Ok(buf) => {
+            let buf = buf.into_boxed_slice();
+            *out_len = buf.len();
+            *out_buf = Box::into_raw(buf) as *mut u8;
+            ClvmError::Ok
+        }
+        Err(e) => a.fail(ClvmError::Serialize, e.to_string()),
+    }
+}
+
+/// Free a buffer returned by `clvm_serialize()`.
+///
+/// # Safety
+///
+/// `buf` must be null or a buffer returned by `clvm_serialize()`, together
+/// with its length, that has not already been freed.
+#[no_mangle]
+pub unsafe extern "C" fn clvm_buffer_free(buf: *mut u8, len: usize) {
+    if !buf.is_null() {
+        drop(Box::from_raw(ptr::slice_from_raw_parts_mut(buf, len)));
+    }
+}
+
+/// Return a description of the most recent error reported by a function
+/// taking this allocator, or null if there hasn't been one (or it didn't have
+/// a description). The string is owned by the allocator and is valid until
+/// the next call that fails.
+///
+/// # Safety
+///
+/// `a` must be a valid allocator.
+#[no_mangle]
+pub unsafe extern "C" fn clvm_last_error(a: *const ClvmAllocator) -> *const c_char {
+    match a.as_ref().and_then(|a| a.last_error.as_ref()) {
+        Some(msg) => msg.as_ptr(),
+        None => ptr::null(),
+    }
+}
+
+/// Run `program` with the environment `args` using the chia dialect, with the
+/// specified flags. The heap limit is the one of the allocator, see
+/// `clvm_allocator_new()`. `LIMIT_ATOM_SIZE` applies to this run only. On success, the cost is stored in `out_cost` and the
+/// result in `out_result`.
+///
+/// # Safety
+///
+/// `a` must be a valid allocator and `out_cost` and `out_result` must be valid
+/// for writes.
+#[no_mangle]
+pub unsafe extern "C" fn clvm_run_program(
+    a: *mut ClvmAllocator,
+    program: ClvmNode,
+    args: ClvmNode,
+    max_cost: u64,
+    flags: u32,
+    out_cost: *mut u64,
+    out_result: *mut ClvmNode,
+) -> ClvmError {
+    let (Some(a), false, false) = (a.as_mut(), out_cost.is_null(), out_result.is_null()) else {
+        return ClvmError::NullPointer;
+    };
+    let (program, args) = match (a.node(program), a.node(args)) {
+        (Ok(program), Ok(args)) => (program, args),
+        (Err(e), _) | (_, Err(e)) => return e,
+    };
+    let dialect = ChiaDialect::new(flags);
+    match run_program(&mut a.allocator, &dialect, program, args, max_cost) {
+        Ok(reduction) => {
+            *out_cost = reduction.0;
+            *out_result = a.handle(reduction.1);
+            ClvmError::Ok
+        }
+        Err(e) => a.fail(ClvmError::from(&e), e.1),
+    }
+}
+
+/// Return a pointer to the bytes of an atom and store its length in
+/// `out_len`. Returns null if `node` is a pair or not a valid handle. Small
+/// atoms are written into `scratch`, which must have room for 4 bytes, and the
+/// returned pointer refers to it.
+///
+/// The returned pointer is valid until the next call that creates nodes in
+/// the allocator, or until `scratch` is modified.
+///
+/// # Safety
+///
+/// `a` must be a valid allocator, `scratch` must be valid for writes of 4
+/// bytes and `out_len` must be valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn clvm_atom(
+    a: *const ClvmAllocator,
+    node: ClvmNode,
+    scratch: *mut u8,
+    out_len: *mut usize,
+) -> *const u8 {
+    let (Some(a), false, false) = (a.as_ref(), scratch.is_null(), out_len.is_null()) else {
+        return ptr::null();
+    };
+    let Ok(node) = a.node(node) else {
+        return ptr::null();
+    };
+    if !node.is_atom() {
+        return ptr::null();
+    }
+    let (atom, len) = a.allocator.atom_raw(node, &mut *(scratch as *mut [u8; 4]));
+    *out_len = len;
+    atom
+}
+
+/// If `node` is a pair, store its first and rest in `out_first` and
+/// `out_rest` and return true. Otherwise return false.
+///
+/// # Safety
+///
+/// `a` must be a valid allocator and `out_first` and `out_rest` must be valid
+/// for writes.
+#[no_mangle]
+pub unsafe extern "C" fn clvm_pair(
+    a: *mut ClvmAllocator,
+    node: ClvmNode,
+    out_first: *mut ClvmNode,
+    out_rest: *mut ClvmNode,
+) -> bool {
+    let (Some(a), false, false) = (a.as_mut(), out_first.is_null(), out_rest.is_null()) else {
+        return false;
+    };
+    let Ok(node) = a.node(node) else {
+        return false;
+    };
+    let SExp::Pair(first, rest) = a.allocator.sexp(node) else {
+        return false;
+    };
+    *out_first = a.handle(first);
+    *out_rest = a.handle(rest);
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn deserialize(a: *mut ClvmAllocator, buf: &[u8]) -> ClvmNode {
+        let mut node = 0;
+        assert_eq!(
+            unsafe { clvm_deserialize(a, buf.as_ptr(), buf.len(), &mut node) },
+            ClvmError::Ok
+        );
+        node
+    }
+
+    fn atom(a: *mut ClvmAllocator, node: ClvmNode) -> Option<Vec<u8>> {
+        let mut scratch = [0_u8; 4];
+        let mut len = 0;
+        let ptr = unsafe { clvm_atom(a, node, scratch.as_mut_ptr(), &mut len) };
+        if ptr.is_null() {
+            None
+        } else {
+            Some(unsafe { slice::from_raw_parts(ptr, len) }.to_vec())
+        }
+    }
+
+    #[test]
+    fn test_run_program() {
+        let a = clvm_allocator_new(0);
+        // (+ 2 3)
+        let program = deserialize(a, &[0xff, 0x10, 0xff, 0x02, 0xff, 0x03, 0x80]);
+        // (1000 . 337)
+        let args = deserialize(a, &[0xff, 0x82, 0x03, 0xe8, 0x82, 0x01, 0x51]);
+
+        let mut cost = 0;
+        let mut result = 0;
+        assert_eq!(
+            unsafe { clvm_run_program(a, program, args, 10000, 0, &mut cost, &mut result) },
+            ClvmError::Ok
+        );
+        assert_eq!(atom(a, result), Some(vec![0x05, 0x39]));
+        assert!(cost > 0);
+
+        // (x)
+        let program = deserialize(a, &[0xff, 0x08, 0x80]);
+        assert_eq!(
+            unsafe { clvm_run_program(a, program, args, 10000, 0, &mut cost, &mut result) },
+            ClvmError::Eval
+        );
+        assert_eq!(
+            unsafe { clvm_run_program(a, 1000, args, 10000, 0, &mut cost, &mut result) },
+            ClvmError::InvalidNode
+        );
+        unsafe { clvm_allocator_free(a) };
+    }
+
+    #[test]
+    fn test_atom_and_pair() {
+        let a = clvm_allocator_new(0);
+        // ("foobar" . 1)
+        let node = deserialize(a, &[0xff, 0x86, b'f', b'o', b'o', b'b', b'a', b'r', 0x01]);
+        assert_eq!(atom(a, node), None);
+
+        let mut first = 0;
+        let mut rest = 0;
+        assert!(unsafe { clvm_pair(a, node, &mut first, &mut rest) });
+        assert_eq!(atom(a, first), Some(b"foobar".to_vec()));
+        assert_eq!(atom(a, rest), Some(vec![1]));
+        assert!(!unsafe { clvm_pair(a, rest, &mut first, &mut rest) });
+
+        // visiting the same pair again returns the same handles, rather than
+        // adding new ones
+        let nodes = unsafe { (*a).nodes.len() };
+        let mut first2 = 0;
+        let mut rest2 = 0;
+        for _ in 0..10 {
+            assert!(unsafe { clvm_pair(a, node, &mut first2, &mut rest2) });
+        }
+        assert_eq!(first2, first);
+        assert_eq!(atom(a, rest2), Some(vec![1]));
+        assert_eq!(unsafe { (*a).nodes.len() }, nodes);
+        unsafe { clvm_allocator_free(a) };
+    }
+
+    #[test]
+    fn test_invalid_arguments() {
+        let a = clvm_allocator_new(0);
+        let mut node = 0;
+        assert_eq!(
+            unsafe { clvm_deserialize(a, [0xff, 0x01].as_ptr(), 2, &mut node) },
+            ClvmError::Deserialize
+        );
+        assert_eq!(
+            unsafe { clvm_deserialize(ptr::null_mut(), [0x01].as_ptr(), 1, &mut node) },
+            ClvmError::NullPointer
+        );
+        assert_eq!(atom(a, 0), None);
+        assert_eq!(
+            last_error(a).as_deref(),
+            Some("failed to fill whole buffer")
+        );
+        unsafe { clvm_allocator_free(a) };
+        unsafe { clvm_allocator_free(ptr::null_mut()) };
+    }
+
+    fn last_error(a: *mut ClvmAllocator) -> Option<String> {
+        let msg = unsafe { clvm_last_error(a) };
+        if msg.is_null() {
+            None
+        } else {
+            Some(
+                unsafe { std::ffi::CStr::from_ptr(msg) }
+                    .to_str()
+                    .unwrap()
+                    .to_string(),
+            )
+        }
+    }
+
+    #[test]
+    fn test_serialize() {
+        let a = clvm_allocator_new(0);
+        // ("foobar" "foobar"), with a back-reference
+        let buf = [0xff, 0x86, b'f', b'o', b'o', b'b', b'a', b'r', 0xfe, 0x01];
+        let mut node = 0;
+        assert_eq!(
+            unsafe { clvm_deserialize(a, buf.as_ptr(), buf.len(), &mut node) },
+            ClvmError::Deserialize
+        );
+        assert_eq!(
+            unsafe { clvm_deserialize_backrefs(a, buf.as_ptr(), buf.len(), &mut node) },
+            ClvmError::Ok
+        );
+
+        let mut out = ptr::null_mut();
+        let mut len = 0;
+        assert_eq!(
+            unsafe { clvm_serialize(a, node, &mut out, &mut len) },
+            ClvmError::Ok
+        );
+        assert_eq!(
+            unsafe { slice::from_raw_parts(out, len) },
+            b"\xff\x86foobar\xff\x86foobar\x80"
+        );
+        unsafe { clvm_buffer_free(out, len) };
+        unsafe { clvm_allocator_free(a) };
+    }
+
+    #[test]
+    fn test_error_codes() {
+        let a = clvm_allocator_new(0);
+        assert_eq!(last_error(a), None);
+        // (sha256 (q . "foobar"))
+        let program = deserialize(
+            a,
+            &[
+                0xff, 0x0b, 0xff, 0xff, 0x01, 0x86, b'f', b'o', b'o', b'b', b'a', b'r', 0x80,
+            ],
+        );
+        let args = deserialize(a, &[0x80]);
+
+        let mut cost = 0;
+        let mut result = 0;
+        assert_eq!(
+            unsafe { clvm_run_program(a, program, args, 100, 0, &mut cost, &mut result) },
+            ClvmError::CostExceeded
+        );
+        assert_eq!(last_error(a).as_deref(), Some("cost exceeded"));
+
+        // (x)
+        let program = deserialize(a, &[0xff, 0x08, 0x80]);
+        assert_eq!(
+            unsafe { clvm_run_program(a, program, args, 10000, 0, &mut cost, &mut result) },
+            ClvmError::Eval
+        );
+        assert_eq!(last_error(a).as_deref(), Some("clvm raise"));
+        unsafe { clvm_allocator_free(a) };
+    }
+
+    #[test]
+    fn test_allocator_flags() {
+        use clvmr::chia_dialect::MEMPOOL_MODE;
+
+        let a = clvm_allocator_new(MEMPOOL_MODE);
+        assert!(unsafe { (*a).allocator.remaining_heap() } <= HEAP_LIMIT);
+        unsafe { clvm_allocator_free(a) };
+        let a = clvm_allocator_new(0);
+        assert!(unsafe { (*a).allocator.remaining_heap() } > HEAP_LIMIT);
+        unsafe { clvm_allocator_free(a) };
+
+        // (concat (q . X) (q . X)), where X is 6 MB. The result is larger
+        // than MAX_ATOM_SIZE
+        let size = 6 * 1024 * 1024_u32;
+        let mut quoted = vec![0xff, 0x01];
+        quoted.extend_from_slice(&(0xf000_0000 | size).to_be_bytes());
+        quoted.resize(quoted.len() + size as usize, b'x');
+        let mut buf = vec![0xff, 0x0e, 0xff];
+        buf.extend_from_slice(&quoted);
+        buf.push(0xff);
+        buf.extend_from_slice(&quoted);
+        buf.push(0x80);
+
+        for (flags, expected) in [(0, ClvmError::Ok), (LIMIT_ATOM_SIZE, ClvmError::Eval)] {
+            let a = clvm_allocator_new(flags);
+            let program = deserialize(a, &buf);
+            let args = deserialize(a, &[0x80]);
+            let mut cost = 0;
+            let mut result = 0;
+            assert_eq!(
+                unsafe {
+                    clvm_run_program(a, program, args, 11_000_000_000, 0, &mut cost, &mut result)
+                },
+                expected
+            );
+            if expected == ClvmError::Eval {
+                assert_eq!(last_error(a).as_deref(), Some("atom too large"));
+            }
+            unsafe { clvm_allocator_free(a) };
+        }
+
+        // passed to clvm_run_program(), LIMIT_ATOM_SIZE only limits that run
+        let a = clvm_allocator_new(0);
+        let program = deserialize(a, &buf);
+        let args = deserialize(a, &[0x80]);
+        for (flags, expected) in [(LIMIT_ATOM_SIZE, ClvmError::Eval), (0, ClvmError::Ok)] {
+            let mut cost = 0;
+            let mut result = 0;
+            assert_eq!(
+                unsafe {
+                    clvm_run_program(
+                        a,
+                        program,
+                        args,
+                        11_000_000_000,
+                        flags,
+                        &mut cost,
+                        &mut result,
+                    )
+                },
+                expected
+            );
+        }
+        unsafe { clvm_allocator_free(a) };
+    }
+}
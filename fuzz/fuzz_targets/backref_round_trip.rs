@@ -0,0 +1,16 @@
+#![no_main]
+
+mod make_tree;
+mod node_eq;
+mod round_trip;
+
+use clvmr::allocator::Allocator;
+use libfuzzer_sys::fuzz_target;
+use round_trip::check_backref_round_trip;
+
+fuzz_target!(|data: &[u8]| {
+    let mut unstructured = arbitrary::Unstructured::new(data);
+    let mut allocator = Allocator::new();
+    let program = make_tree::make_tree(&mut allocator, &mut unstructured);
+    check_backref_round_trip(&mut allocator, program);
+});
@@ -0,0 +1,53 @@
+#![no_main]
+mod fuzzing_utils;
+mod make_tree;
+
+use clvmr::chia_dialect::ChiaDialect;
+use clvmr::curry_ops::{op_curry, op_uncurry};
+use clvmr::{parse_clvm_text, run_program, Allocator};
+use libfuzzer_sys::fuzz_target;
+
+use fuzzing_utils::tree_hash;
+
+// builds the environment (c (q . ARG1) (c (q . ARG2) ... 1)) recursively,
+// given (BUILD . ARGS) as its environment
+const BUILD: &str = "(a (i 3 (q c (q . 4) (c (c (q . 1) 5) (c (a 2 (c 2 7)) ()))) (q q . 1)) 1)";
+
+// a pure CLVM implementation of curry. It's run with (MOD . ARGS) as its
+// environment
+fn curry_program() -> String {
+    format!("(c (q . 2) (c (c (q . 1) 2) (c (a (q . {BUILD}) (c (q . {BUILD}) 3)) ())))")
+}
+
+fuzz_target!(|data: &[u8]| {
+    let mut unstructured = arbitrary::Unstructured::new(data);
+    let mut a = Allocator::new();
+    let input = make_tree::make_tree_limits(&mut a, &mut unstructured, 1000, 1000);
+
+    let program = parse_clvm_text(&mut a, &curry_program()).expect("parse_clvm_text");
+    let expected = run_program(&mut a, &ChiaDialect::new(0), program, input, 11_000_000_000);
+
+    // this fails if the input is an atom
+    let Ok(curried) = op_curry(&mut a, input, 11_000_000_000) else {
+        return;
+    };
+
+    // the operator ignores the terminator of the argument list, the pure
+    // CLVM implementation fails unless it's nil
+    if let Ok(expected) = expected {
+        assert_eq!(tree_hash(&a, curried.1), tree_hash(&a, expected.1));
+    }
+
+    let uncurry_args = a.new_pair(curried.1, a.nil()).unwrap();
+    let uncurried = op_uncurry(&mut a, uncurry_args, 11_000_000_000).expect("uncurry");
+    let (module, mut args) = a.next(uncurried.1).expect("curried program");
+    let (input_module, mut input_args) = a.next(input).expect("pair");
+    assert_eq!(module, input_module);
+    while let Some((arg, rest)) = a.next(args) {
+        let (input_arg, input_rest) = a.next(input_args).expect("argument");
+        assert_eq!(arg, input_arg);
+        args = rest;
+        input_args = input_rest;
+    }
+    assert!(a.next(input_args).is_none());
+});
@@ -2,7 +2,7 @@
 mod fuzzing_utils;
 mod make_tree;
 
-use clvmr::serde::{node_to_bytes, serialized_length, treehash, ObjectCache};
+use clvmr::serde::{node_to_bytes, serialized_length, treehash, ObjectCache, TreeHasher};
 use clvmr::{Allocator, NodePtr, SExp};
 use libfuzzer_sys::fuzz_target;
 
@@ -50,12 +50,14 @@ fuzz_target!(|data: &[u8]| {
 
     let mut hash_cache = ObjectCache::new(treehash);
     let mut length_cache = ObjectCache::new(serialized_length);
+    let mut hasher = TreeHasher::new();
     visit_tree(&allocator, program, |a, node| {
         let expect_hash = tree_hash(a, node);
         let expect_len = compute_serialized_len(a, node);
         let computed_hash = hash_cache.get_or_calculate(a, &node, None).unwrap();
         let computed_len = length_cache.get_or_calculate(a, &node, None).unwrap();
         assert_eq!(computed_hash, &expect_hash);
+        assert_eq!(hasher.tree_hash(a, node), expect_hash);
         assert_eq!(computed_len, &expect_len);
     });
 });
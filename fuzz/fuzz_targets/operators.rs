@@ -9,6 +9,8 @@ use clvmr::bls_ops::{
 };
 use clvmr::core_ops::{op_cons, op_eq, op_first, op_if, op_listp, op_raise, op_rest};
 use clvmr::cost::Cost;
+use clvmr::curry_ops::{op_curry, op_uncurry};
+use clvmr::hash_ops::{op_sha3_256, op_sha512_256};
 use clvmr::keccak256_ops::op_keccak256;
 use clvmr::more_ops::{
     op_add, op_all, op_any, op_ash, op_coinid, op_concat, op_div, op_divmod, op_gr, op_gr_bytes,
@@ -21,7 +23,7 @@ use clvmr::serde::node_from_bytes;
 
 type Opf = fn(&mut Allocator, NodePtr, Cost) -> Response;
 
-const FUNS: [Opf; 46] = [
+const FUNS: [Opf; 50] = [
     op_if as Opf,
     op_cons as Opf,
     op_first as Opf,
@@ -71,6 +73,12 @@ const FUNS: [Opf; 46] = [
     op_secp256r1_verify as Opf,
     // keccak operator
     op_keccak256 as Opf,
+    // hash operators (softfork extension 2)
+    op_sha3_256 as Opf,
+    op_sha512_256 as Opf,
+    // curry operators (softfork extension 3)
+    op_curry as Opf,
+    op_uncurry as Opf,
 ];
 
 fuzz_target!(|data: &[u8]| {
@@ -89,7 +97,7 @@ fuzz_target!(|data: &[u8]| {
         for max_cost in [11000000, 1100000, 110000, 10, 1, 0] {
             allocator.restore_checkpoint(&allocator_checkpoint);
             match op(&mut allocator, args, max_cost) {
-                Err(EvalErr(n, msg)) => {
+                Err(EvalErr(n, msg, _)) => {
                     assert!(!msg.contains("internal error"));
                     // make sure n is a valid node in the allocator
                     allocator.sexp(n);
@@ -0,0 +1,23 @@
+use clvmr::serde::{node_from_bytes_backrefs, node_to_bytes, node_to_bytes_backrefs};
+use clvmr::{Allocator, NodePtr};
+
+use crate::node_eq::node_eq;
+
+/// serialize the tree with back references, parse it back and make sure we get
+/// the same tree. Back references are only used when they save space, so the
+/// compressed form is never larger than the regular serialization. Panics on
+/// failure, returns the compressed serialization on success
+pub fn check_backref_round_trip(a: &mut Allocator, node: NodePtr) -> Vec<u8> {
+    let plain = node_to_bytes(a, node).expect("node_to_bytes");
+    let compressed = node_to_bytes_backrefs(a, node).expect("node_to_bytes_backrefs");
+    assert!(
+        compressed.len() <= plain.len(),
+        "compressed: {} plain: {}",
+        compressed.len(),
+        plain.len()
+    );
+
+    let round_trip = node_from_bytes_backrefs(a, &compressed).expect("node_from_bytes_backrefs");
+    assert!(node_eq(a, node, round_trip));
+    compressed
+}
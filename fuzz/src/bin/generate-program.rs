@@ -0,0 +1,205 @@
+//! Generates random CLVM programs from a seed, runs them and prints the
+//! program, its environment and the expected result (or error) as one line of
+//! JSON per program. This is meant for differential testing of other CLVM
+//! implementations. The same seed and options always produce the same
+//! programs.
+
+use clap::Parser;
+use clvmr::allocator::{Allocator, NodePtr};
+use clvmr::chia_dialect::ChiaDialect;
+use clvmr::dialect::{Dialect, OperatorSet};
+use clvmr::run_program::run_program;
+use clvmr::serde::node_to_bytes;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use serde_json::json;
+
+/// Generate random CLVM programs and their expected results
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+struct Args {
+    /// the seed of the first program
+    #[arg(short, long, default_value_t = 0)]
+    seed: u64,
+
+    /// the number of programs to generate. Program N uses the seed seed + N
+    #[arg(short, long, default_value_t = 1)]
+    count: u64,
+
+    /// the cost limit when running the programs
+    #[arg(long, default_value_t = 11_000_000_000)]
+    max_cost: u64,
+
+    /// the maximum nesting depth of operator calls
+    #[arg(long, default_value_t = 5)]
+    max_depth: u32,
+
+    /// the operators to use, and their relative weights. A comma separated
+    /// list of operator names, each optionally followed by "=<weight>", e.g.
+    /// "+=5,sha256,concat=2". By default, all operators except quote, apply
+    /// and softfork are used, with equal weights
+    #[arg(long)]
+    ops: Option<String>,
+
+    /// the flags to pass to the chia dialect
+    #[arg(long, default_value_t = 0)]
+    flags: u32,
+}
+
+const INTERESTING_ATOMS: [&[u8]; 10] = [
+    &[],
+    &[0],
+    &[1],
+    &[0x7f],
+    &[0x80],
+    &[0xff],
+    &[0, 0x80],
+    &[0xff, 0xff, 0xff, 0xff],
+    &[0x7f, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff],
+    &[0x80, 0, 0, 0, 0, 0, 0, 0],
+];
+
+struct Generator {
+    rng: ChaCha8Rng,
+    // opcodes and their cumulative weights
+    ops: Vec<(u32, u32)>,
+    max_depth: u32,
+}
+
+impl Generator {
+    fn atom(&mut self, a: &mut Allocator) -> NodePtr {
+        match self.rng.gen_range(0..4) {
+            0 => a
+                .new_small_number(self.rng.gen_range(0..256))
+                .expect("new_small_number"),
+            1 => {
+                let atom = INTERESTING_ATOMS[self.rng.gen_range(0..INTERESTING_ATOMS.len())];
+                a.new_atom(atom).expect("new_atom")
+            }
+            _ => {
+                let len = if self.rng.gen_bool(0.3) {
+                    32
+                } else {
+                    self.rng.gen_range(0..10)
+                };
+                let buf: Vec<u8> = (0..len).map(|_| self.rng.gen()).collect();
+                a.new_atom(&buf).expect("new_atom")
+            }
+        }
+    }
+
+    fn tree(&mut self, a: &mut Allocator, depth: u32) -> NodePtr {
+        if depth == 0 || self.rng.gen_bool(0.4) {
+            return self.atom(a);
+        }
+        let first = self.tree(a, depth - 1);
+        let rest = self.tree(a, depth - 1);
+        a.new_pair(first, rest).expect("new_pair")
+    }
+
+    fn operator(&mut self) -> u32 {
+        let total = self.ops.last().expect("no operators").1;
+        let pick = self.rng.gen_range(0..total);
+        self.ops
+            .iter()
+            .find(|(_, weight)| pick < *weight)
+            .expect("internal error")
+            .0
+    }
+
+    fn expression(&mut self, a: &mut Allocator, depth: u32) -> NodePtr {
+        if depth >= self.max_depth || self.rng.gen_bool(0.25) {
+            return if self.rng.gen_bool(0.5) {
+                // a path into the environment
+                a.new_small_number(self.rng.gen_range(1..32))
+                    .expect("new_small_number")
+            } else {
+                let quote = a.one();
+                let value = self.tree(a, 2);
+                a.new_pair(quote, value).expect("new_pair")
+            };
+        }
+        let opcode = self.operator();
+        let mut args = a.nil();
+        for _ in 0..self.rng.gen_range(0..=3) {
+            let arg = self.expression(a, depth + 1);
+            args = a.new_pair(arg, args).expect("new_pair");
+        }
+        let op = a.new_number(opcode.into()).expect("new_number");
+        a.new_pair(op, args).expect("new_pair")
+    }
+}
+
+fn parse_ops(dialect: &ChiaDialect, ops: Option<&str>) -> Vec<(u32, u32)> {
+    let table = dialect.op_table(OperatorSet::Default);
+    let mut weights = Vec::<(u32, u32)>::new();
+    match ops {
+        None => {
+            for (opcode, name) in table {
+                if ["q", "a", "softfork"].contains(name)
+                    || weights.iter().any(|(op, _)| op == opcode)
+                {
+                    continue;
+                }
+                weights.push((*opcode, 1));
+            }
+        }
+        Some(ops) => {
+            for entry in ops.split(',') {
+                let (name, weight) = match entry.split_once('=') {
+                    Some((name, weight)) => (name, weight.parse().expect("invalid weight")),
+                    None => (entry, 1),
+                };
+                let opcode = dialect
+                    .opcode_for_name(name, OperatorSet::Default)
+                    .unwrap_or_else(|| panic!("unknown operator \"{name}\""));
+                weights.push((opcode, weight));
+            }
+        }
+    }
+    // turn the weights into cumulative weights
+    let mut total = 0;
+    for (_, weight) in &mut weights {
+        total += *weight;
+        *weight = total;
+    }
+    assert!(total > 0, "the operator weights must not all be 0");
+    weights
+}
+
+fn main() {
+    let args = Args::parse();
+    let dialect = ChiaDialect::new(args.flags);
+    let ops = parse_ops(&dialect, args.ops.as_deref());
+
+    for seed in args.seed..args.seed + args.count {
+        let mut generator = Generator {
+            rng: ChaCha8Rng::seed_from_u64(seed),
+            ops: ops.clone(),
+            max_depth: args.max_depth,
+        };
+        let mut a = Allocator::new();
+        let program = generator.expression(&mut a, 0);
+        let env = generator.tree(&mut a, 4);
+
+        let mut line = json!({
+            "seed": seed,
+            "program": hex::encode(node_to_bytes(&a, program).expect("node_to_bytes")),
+            "env": hex::encode(node_to_bytes(&a, env).expect("node_to_bytes")),
+            "flags": args.flags,
+            "max_cost": args.max_cost,
+        });
+        match run_program(&mut a, &dialect, program, env, args.max_cost) {
+            Ok(reduction) => {
+                line["cost"] = json!(reduction.0);
+                line["result"] = json!(hex::encode(
+                    node_to_bytes(&a, reduction.1).expect("node_to_bytes")
+                ));
+            }
+            Err(err) => {
+                line["error"] = json!(err.1);
+            }
+        }
+        println!("{line}");
+    }
+}
@@ -0,0 +1,115 @@
+//! Native Node.js bindings, built with napi-rs. Unlike the wasm module, input
+//! `Buffer`s are read in place and results are handed to JavaScript without
+//! copying them across a wasm memory boundary, which matters for multi-MB
+//! block generators.
+
+use std::io;
+use std::io::Cursor;
+
+use clvmr::allocator::{Allocator, NodePtr};
+use clvmr::chia_dialect::ChiaDialect;
+use clvmr::run_program::run_program;
+use clvmr::serde::{
+    backref_stats_from_bytes, node_from_bytes, node_from_bytes_backrefs, node_to_bytes,
+    node_to_bytes_backrefs, serialized_length_from_bytes, tree_hash_from_stream,
+};
+use napi::bindgen_prelude::{BigInt, Buffer};
+use napi::{Error, Result};
+use napi_derive::napi;
+
+// when this flag is set, serializations are allowed to contain
+// back-references. This is the same value as the wasm module uses
+#[napi]
+pub const ALLOW_BACKREFS: u32 = 0x2000000;
+
+#[napi]
+pub const NO_UNKNOWN_OPS: u32 = clvmr::chia_dialect::NO_UNKNOWN_OPS;
+
+#[napi]
+pub const MEMPOOL_MODE: u32 = clvmr::chia_dialect::MEMPOOL_MODE;
+
+fn to_error(e: impl ToString) -> Error {
+    Error::from_reason(e.to_string())
+}
+
+fn deserialize(a: &mut Allocator, b: &[u8], flags: u32) -> io::Result<NodePtr> {
+    if (flags & ALLOW_BACKREFS) != 0 {
+        node_from_bytes_backrefs(a, b)
+    } else {
+        node_from_bytes(a, b)
+    }
+}
+
+#[napi(object)]
+pub struct RunOutput {
+    pub cost: BigInt,
+    /// the serialized result of the program
+    pub result: Buffer,
+}
+
+/// Runs a serialized program with serialized arguments and returns the cost
+/// and the serialized result.
+#[napi]
+pub fn run_chia_program(
+    program: Buffer,
+    args: Buffer,
+    max_cost: BigInt,
+    flags: u32,
+) -> Result<RunOutput> {
+    let (false, max_cost, true) = max_cost.get_u64() else {
+        return Err(to_error("max_cost must be an unsigned 64 bit integer"));
+    };
+    let mut allocator = Allocator::new();
+    let program = deserialize(&mut allocator, &program, flags).map_err(to_error)?;
+    let args = deserialize(&mut allocator, &args, flags).map_err(to_error)?;
+    let dialect = ChiaDialect::new(flags);
+
+    let reduction = run_program(&mut allocator, &dialect, program, args, max_cost)
+        .map_err(|e| to_error(e.1))?;
+    let result = node_to_bytes(&allocator, reduction.1).map_err(to_error)?;
+    Ok(RunOutput {
+        cost: reduction.0.into(),
+        result: result.into(),
+    })
+}
+
+// the length of the serialization at the start of the buffer. Back references
+// are supported
+#[napi]
+pub fn serialized_length(program: Buffer) -> Result<BigInt> {
+    Ok(serialized_length_from_bytes(&program)
+        .map_err(to_error)?
+        .into())
+}
+
+// computes the tree hash of a serialized CLVM structure, without building the
+// tree
+#[napi]
+pub fn tree_hash(program: Buffer, flags: u32) -> Result<Buffer> {
+    let hash = if (flags & ALLOW_BACKREFS) != 0 {
+        backref_stats_from_bytes(&program)
+            .map_err(to_error)?
+            .tree_hash
+    } else {
+        tree_hash_from_stream(&mut Cursor::new(&program)).map_err(to_error)?
+    };
+    Ok(hash.to_vec().into())
+}
+
+// re-serialize a CLVM structure using back references, to make it smaller
+#[napi]
+pub fn compress_serialization(program: Buffer, flags: u32) -> Result<Buffer> {
+    let mut allocator = Allocator::new();
+    let node = deserialize(&mut allocator, &program, flags).map_err(to_error)?;
+    Ok(node_to_bytes_backrefs(&allocator, node)
+        .map_err(to_error)?
+        .into())
+}
+
+// re-serialize a CLVM structure containing back references, without them
+#[napi]
+pub fn decompress_serialization(program: Buffer) -> Result<Buffer> {
+    let mut allocator = Allocator::new();
+    let node = node_from_bytes_backrefs(&mut allocator, &program).map_err(to_error)?;
+    Ok(node_to_bytes(&allocator, node).map_err(to_error)?.into())
+}
@@ -0,0 +1,83 @@
+const clvm = require("../clvm_napi.node");
+
+function expect_equal(challenge, expected) {
+  if (challenge !== expected) {
+    throw new Error(
+      `Assertion Error: Expected "${expected}" but actual value was "${challenge}"`,
+    );
+  }
+}
+
+function expect_throw(callback) {
+  let is_error = undefined;
+  try {
+    callback();
+  } catch (e) {
+    is_error = e;
+  }
+
+  if (!is_error) {
+    throw new Error("Expected an exception but it was not thrown");
+  }
+}
+
+let current_test_number = 0;
+function test_case(testTitle, test) {
+  const testNo = ++current_test_number;
+  console.log(`Case#${testNo} ${testTitle}`);
+  try {
+    test();
+    console.log(`✓ Successfully finished case#${testNo}`);
+  } catch (e) {
+    console.error(`❌ Failed Case#${testNo}`);
+    console.error(`${e.name}: ${e.message}`);
+    process.exit(1);
+  }
+}
+
+// ----------------------------------------------------- //
+
+test_case("Test '(+ 2 3)' '(1000 . 337)'", function () {
+  // (+ 2 3)
+  const prog = Buffer.from("ff10ff02ff0380", "hex");
+  // (1000 . 337)
+  const arg = Buffer.from("ff8203e8820151", "hex");
+  const { cost, result } = clvm.runChiaProgram(prog, arg, 100000n, 0);
+  expect_equal(result.toString("hex"), "820539");
+  expect_equal(cost, 868n);
+});
+
+test_case("Test '(x)' '()'", function () {
+  const prog = Buffer.from("ff0880", "hex");
+  const arg = Buffer.from("80", "hex");
+  expect_throw(function () {
+    clvm.runChiaProgram(prog, arg, 100000n, 0);
+  });
+});
+
+test_case("Test max_cost out of range", function () {
+  const prog = Buffer.from("ff017f", "hex");
+  const arg = Buffer.from("80", "hex");
+  expect_throw(function () {
+    clvm.runChiaProgram(prog, arg, -1n, 0);
+  });
+});
+
+test_case("Test back-references", function () {
+  // ("foobar" "foobar")
+  const compressed = Buffer.from("ff86666f6f626172fe01", "hex");
+  const plain = clvm.decompressSerialization(compressed);
+  expect_equal(plain.toString("hex"), "ff86666f6f626172ff86666f6f62617280");
+  expect_equal(
+    clvm.compressSerialization(plain, 0).toString("hex"),
+    compressed.toString("hex"),
+  );
+  expect_equal(clvm.serializedLength(compressed), 10n);
+  expect_equal(
+    clvm.treeHash(compressed, clvm.ALLOW_BACKREFS).toString("hex"),
+    clvm.treeHash(plain, 0).toString("hex"),
+  );
+  expect_throw(function () {
+    clvm.treeHash(compressed, 0);
+  });
+});
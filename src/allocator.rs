@@ -1,8 +1,11 @@
 use crate::err_utils::err;
+use crate::error::ErrorCode;
 use crate::number::{number_from_u8, Number};
 use crate::reduction::EvalErr;
 use chia_bls::{G1Element, G2Element};
 use std::borrow::Borrow;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
 use std::fmt;
 use std::hash::Hash;
 use std::hash::Hasher;
@@ -13,9 +16,27 @@ const MAX_NUM_PAIRS: usize = 62500000;
 const NODE_PTR_IDX_BITS: u32 = 26;
 const NODE_PTR_IDX_MASK: u32 = (1 << NODE_PTR_IDX_BITS) - 1;
 
+#[cfg(not(feature = "checked-nodeptr"))]
 #[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct NodePtr(u32);
 
+// with the "checked-nodeptr" feature, every NodePtr also carries the tag of
+// the Allocator that created it, so the Allocator can detect (and panic) when
+// it's passed a NodePtr that belongs to a different allocator, or one that was
+// created before the allocator was reset. Small atoms have the tag 0, since
+// they don't refer to any memory in the allocator
+#[cfg(feature = "checked-nodeptr")]
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct NodePtr(u32, u32);
+
+#[cfg(feature = "checked-nodeptr")]
+static NEXT_ALLOCATOR_TAG: std::sync::atomic::AtomicU32 = std::sync::atomic::AtomicU32::new(1);
+
+#[cfg(feature = "checked-nodeptr")]
+fn new_allocator_tag() -> u32 {
+    NEXT_ALLOCATOR_TAG.fetch_add(1, std::sync::atomic::Ordering::Relaxed)
+}
+
 impl fmt::Debug for NodePtr {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_tuple("NodePtr")
@@ -41,7 +62,17 @@ impl NodePtr {
 
     const fn new(object_type: ObjectType, index: usize) -> Self {
         debug_assert!(index <= NODE_PTR_IDX_MASK as usize);
-        NodePtr(((object_type as u32) << NODE_PTR_IDX_BITS) | (index as u32))
+        Self::from_raw(((object_type as u32) << NODE_PTR_IDX_BITS) | (index as u32))
+    }
+
+    #[cfg(not(feature = "checked-nodeptr"))]
+    const fn from_raw(v: u32) -> Self {
+        NodePtr(v)
+    }
+
+    #[cfg(feature = "checked-nodeptr")]
+    const fn from_raw(v: u32) -> Self {
+        NodePtr(v, 0)
     }
 
     pub fn is_atom(self) -> bool {
@@ -99,16 +130,114 @@ pub struct IntPair {
     rest: NodePtr,
 }
 
+/// An operation recorded in the allocator log, see `Allocator::enable_log()`.
+/// Only the sizes are recorded, not the contents or the resulting NodePtr, so
+/// logs from different versions can be compared even if they represent atoms
+/// differently.
+#[cfg(feature = "allocator-log")]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum AllocatorEvent {
+    /// an atom of this many bytes was created, by new_atom(), new_number()
+    /// and the like
+    Atom(usize),
+    Pair,
+    /// a list with this many elements was created by new_list() or
+    /// new_proper_list_from_iter()
+    List(usize),
+    /// a sub-string of this many bytes was created
+    Substr(usize),
+    /// this many atoms were concatenated into one of `len` bytes
+    Concat {
+        atoms: usize,
+        len: usize,
+    },
+    RestoreCheckpoint,
+    Reset,
+}
+
 // this represents a specific (former) state of an allocator. This can be used
 // to restore an allocator to a previous state. It cannot be used to re-create
 // the state from some other allocator.
 pub struct Checkpoint {
+    generation: u32,
     u8s: usize,
     pairs: usize,
     atoms: usize,
     small_atoms: usize,
 }
 
+/// A breakdown of the memory used by an `Allocator`, returned by
+/// `Allocator::memory_stats()`. The byte counts reflect memory in use, not
+/// memory reserved by the underlying vectors.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct MemoryStats {
+    // the number of bytes of atom contents
+    pub heap_bytes: usize,
+    // the number of bytes used by the table of (heap-allocated) atoms
+    pub atom_table_bytes: usize,
+    // the number of bytes used by the table of pairs
+    pub pair_table_bytes: usize,
+    // the number of heap-allocated atoms
+    pub atoms: usize,
+    // the number of atoms stored in the NodePtr itself. These don't use any
+    // memory but still count against the atom limit
+    pub small_atoms: usize,
+    pub pairs: usize,
+}
+
+impl MemoryStats {
+    pub fn total_bytes(&self) -> usize {
+        self.heap_bytes + self.atom_table_bytes + self.pair_table_bytes
+    }
+}
+
+/// The details of an allocation that would exceed the heap limit of an
+/// `Allocator`. When an allocation fails because of it, the `EvalErr` has the
+/// code `ErrorCode::OutOfMemory` and this error's message, which includes the
+/// sizes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HeapLimitError {
+    // the number of bytes of atom contents requested
+    pub requested: usize,
+    // the number of bytes of atom contents in use
+    pub used: usize,
+    pub limit: usize,
+}
+
+impl From<HeapLimitError> for EvalErr {
+    fn from(e: HeapLimitError) -> Self {
+        EvalErr(NodePtr::NIL, e.to_string(), ErrorCode::OutOfMemory)
+    }
+}
+
+impl fmt::Display for HeapLimitError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "out of memory: requested {} bytes with {} of {} bytes in use",
+            self.requested, self.used, self.limit
+        )
+    }
+}
+
+/// Counters of the atoms whose contents were shared with an existing atom, see
+/// `Allocator::enable_interning()`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct InternStats {
+    pub deduplicated_atoms: usize,
+    pub deduplicated_bytes: usize,
+}
+
+#[derive(Debug)]
+struct Interner {
+    // atoms at least this large are interned
+    threshold: usize,
+    // maps the hash of atom contents to the buffers with that hash
+    buffers: HashMap<u64, Vec<AtomBuf>>,
+    stats: InternStats,
+}
+
 pub enum NodeVisitor<'a> {
     Buffer(&'a [u8]),
     U32(u32),
@@ -177,6 +306,31 @@ pub struct Allocator {
     // the number of small atoms we've allocated. We keep track of these to ensure the limit on the
     // number of atoms is identical to what it was before the small-atom optimization
     small_atoms: usize,
+
+    // the total number of bytes used by u8_vec, atom_vec and pair_vec may not
+    // grow past this. This counts the elements in use, not the capacity of the
+    // vectors. usize::MAX means there's no budget
+    memory_budget: usize,
+
+    // new atoms may not be larger than this
+    max_atom_size: usize,
+
+    // incremented every time the allocator is reset(). Checkpoints from an
+    // earlier generation can't be restored
+    generation: u32,
+
+    // when interning is enabled, atoms with identical contents share the
+    // same bytes in u8_vec
+    interner: Option<Box<Interner>>,
+
+    // this is stamped into every NodePtr referring to a pair or a heap
+    // allocated atom. It's unique to this allocator, and changes on reset()
+    #[cfg(feature = "checked-nodeptr")]
+    tag: u32,
+
+    // when enabled, every successful allocation is recorded here
+    #[cfg(feature = "allocator-log")]
+    log: Option<Vec<AllocatorEvent>>,
 }
 
 impl Default for Allocator {
@@ -232,6 +386,15 @@ impl Allocator {
     }
 
     pub fn new_limited(heap_limit: usize) -> Self {
+        Self::new_with_memory_budget(heap_limit, usize::MAX)
+    }
+
+    /// In addition to the limit on the atom heap, the memory budget bounds
+    /// the total number of bytes used by the allocator, including the tables
+    /// of atoms and pairs. See `memory_stats()`. Only the bytes in use are
+    /// bounded, the memory reserved by the underlying vectors as they grow
+    /// may be larger (up to about twice as much).
+    pub fn new_with_memory_budget(heap_limit: usize, memory_budget: usize) -> Self {
         // we have a maximum of 4 GiB heap, because pointers are 32 bit unsigned
         assert!(heap_limit <= u32::MAX as usize);
 
@@ -244,6 +407,14 @@ impl Allocator {
             // initialize this to 2 to behave as if we had allocated atoms for
             // nil() and one(), like we used to
             small_atoms: 2,
+            memory_budget,
+            max_atom_size: usize::MAX,
+            generation: 0,
+            interner: None,
+            #[cfg(feature = "checked-nodeptr")]
+            tag: new_allocator_tag(),
+            #[cfg(feature = "allocator-log")]
+            log: None,
         };
         r.u8_vec.reserve(1024 * 1024);
         r.atom_vec.reserve(256);
@@ -256,6 +427,7 @@ impl Allocator {
     // to restore_checkpoint().
     pub fn checkpoint(&self) -> Checkpoint {
         Checkpoint {
+            generation: self.generation,
             u8s: self.u8_vec.len(),
             pairs: self.pair_vec.len(),
             atoms: self.atom_vec.len(),
@@ -268,6 +440,10 @@ impl Allocator {
         // a state that has already been "long-jumped" passed (via another
         // restore to an earlier state). You can only restore backwards in time,
         // not forwards.
+        assert!(
+            self.generation == cp.generation,
+            "checkpoint is from before the allocator was reset"
+        );
         assert!(self.u8_vec.len() >= cp.u8s);
         assert!(self.pair_vec.len() >= cp.pairs);
         assert!(self.atom_vec.len() >= cp.atoms);
@@ -275,23 +451,224 @@ impl Allocator {
         self.pair_vec.truncate(cp.pairs);
         self.atom_vec.truncate(cp.atoms);
         self.small_atoms = cp.small_atoms;
+        #[cfg(feature = "allocator-log")]
+        self.record(AllocatorEvent::RestoreCheckpoint);
+        if let Some(interner) = &mut self.interner {
+            interner.buffers.retain(|_, bufs| {
+                bufs.retain(|buf| buf.end as usize <= cp.u8s);
+                !bufs.is_empty()
+            });
+        }
+    }
+
+    // returns true if no node that existed when the checkpoint was taken has
+    // been freed since, i.e. the allocator hasn't been reset or restored to an
+    // earlier state
+    pub(crate) fn is_descendant_of(&self, cp: &Checkpoint) -> bool {
+        self.generation == cp.generation
+            && self.u8_vec.len() >= cp.u8s
+            && self.pair_vec.len() >= cp.pairs
+            && self.atom_vec.len() >= cp.atoms
+            && self.small_atoms >= cp.small_atoms
+    }
+
+    // free all atoms and pairs, but keep the memory that's been allocated for
+    // them, to be reused. This is cheaper than creating a new Allocator when
+    // running many programs in sequence. All NodePtr values (except the
+    // small atoms, like nil() and one()) created before the reset are
+    // invalid afterwards, as are any checkpoints.
+    pub fn reset(&mut self) {
+        self.u8_vec.clear();
+        self.pair_vec.clear();
+        self.atom_vec.clear();
+        self.small_atoms = 2;
+        self.generation = self.generation.wrapping_add(1);
+        if let Some(interner) = &mut self.interner {
+            interner.buffers.clear();
+        }
+        #[cfg(feature = "checked-nodeptr")]
+        {
+            self.tag = new_allocator_tag();
+        }
+        #[cfg(feature = "allocator-log")]
+        self.record(AllocatorEvent::Reset);
+    }
+
+    /// Starts recording every allocation, restore and reset, to compare the
+    /// behavior of different versions of the allocator (or of the operators
+    /// using it) on the same program. Any log recorded so far is discarded.
+    #[cfg(feature = "allocator-log")]
+    pub fn enable_log(&mut self) {
+        self.log = Some(Vec::new());
+    }
+
+    /// Returns the events recorded since the log was enabled, or since the
+    /// last call to take_log(). Returns an empty log if it's not enabled.
+    #[cfg(feature = "allocator-log")]
+    pub fn take_log(&mut self) -> Vec<AllocatorEvent> {
+        self.log.as_mut().map(std::mem::take).unwrap_or_default()
+    }
+
+    #[cfg(feature = "allocator-log")]
+    fn record(&mut self, event: AllocatorEvent) {
+        if let Some(log) = &mut self.log {
+            log.push(event);
+        }
+    }
+
+    // create a NodePtr referring to memory in this allocator
+    #[cfg(not(feature = "checked-nodeptr"))]
+    #[inline(always)]
+    fn mk_node(&self, object_type: ObjectType, index: usize) -> NodePtr {
+        NodePtr::new(object_type, index)
+    }
+
+    #[cfg(feature = "checked-nodeptr")]
+    fn mk_node(&self, object_type: ObjectType, index: usize) -> NodePtr {
+        let NodePtr(v, _) = NodePtr::new(object_type, index);
+        NodePtr(v, self.tag)
+    }
+
+    #[cfg(feature = "checked-nodeptr")]
+    fn validate(&self, node: NodePtr, len: usize) {
+        assert!(
+            node.1 == self.tag,
+            "NodePtr {node:?} belongs to a different allocator, or was created before the allocator was reset"
+        );
+        assert!(
+            (node.index() as usize) < len,
+            "NodePtr {node:?} was freed by restoring a checkpoint"
+        );
+    }
+
+    // look up the atom referred to by a NodePtr of type Bytes
+    #[inline(always)]
+    fn atom_buf(&self, node: NodePtr) -> AtomBuf {
+        #[cfg(feature = "checked-nodeptr")]
+        self.validate(node, self.atom_vec.len());
+        self.atom_vec[node.index() as usize]
+    }
+
+    // look up the pair referred to by a NodePtr of type Pair
+    #[inline(always)]
+    fn int_pair(&self, node: NodePtr) -> IntPair {
+        #[cfg(feature = "checked-nodeptr")]
+        self.validate(node, self.pair_vec.len());
+        self.pair_vec[node.index() as usize]
+    }
+
+    // the number of times this allocator has been reset()
+    pub fn generation(&self) -> u32 {
+        self.generation
+    }
+
+    /// Once enabled, new atoms (created by new_atom() and new_concat()) of at
+    /// least `threshold` bytes share their contents with any identical atom
+    /// that already exists, rather than growing the heap. The atoms are still
+    /// distinct nodes and count against the atom limit. Since this makes the
+    /// heap grow slower, programs may succeed that would otherwise run out of
+    /// memory. So this must not be used for consensus.
+    pub fn enable_interning(&mut self, threshold: usize) {
+        self.interner = Some(Box::new(Interner {
+            threshold: threshold.max(1),
+            buffers: HashMap::new(),
+            stats: InternStats::default(),
+        }));
+    }
+
+    /// The number of atoms and bytes that were deduplicated, or None if
+    /// interning isn't enabled
+    pub fn intern_stats(&self) -> Option<InternStats> {
+        self.interner.as_ref().map(|i| i.stats)
+    }
+
+    // the atom contents at u8_vec[start..] were just appended. If interning
+    // is enabled and identical contents already exist, the new bytes are
+    // removed and the existing buffer is returned instead
+    fn intern(&mut self, start: usize) -> AtomBuf {
+        let end = self.u8_vec.len();
+        let new_buf = AtomBuf {
+            start: start as u32,
+            end: end as u32,
+        };
+        let Some(interner) = &mut self.interner else {
+            return new_buf;
+        };
+        if end - start < interner.threshold {
+            return new_buf;
+        }
+        let mut hasher = DefaultHasher::new();
+        self.u8_vec[start..end].hash(&mut hasher);
+        let bufs = interner.buffers.entry(hasher.finish()).or_default();
+        for buf in bufs.iter() {
+            if self.u8_vec[buf.start as usize..buf.end as usize] == self.u8_vec[start..end] {
+                interner.stats.deduplicated_atoms += 1;
+                interner.stats.deduplicated_bytes += end - start;
+                let buf = *buf;
+                self.u8_vec.truncate(start);
+                return buf;
+            }
+        }
+        bufs.push(new_buf);
+        new_buf
+    }
+
+    /// The number of bytes of atom contents that can still be allocated
+    /// before reaching the heap limit. The memory budget (see
+    /// `new_with_memory_budget()`) may be reached sooner.
+    pub fn remaining_heap(&self) -> usize {
+        self.heap_limit - self.u8_vec.len()
+    }
+
+    /// Checks whether an atom of `requested` bytes can be allocated without
+    /// exceeding the heap limit. This lets callers report the sizes involved
+    /// before attempting a large allocation.
+    pub fn check_heap(&self, requested: usize) -> Result<(), HeapLimitError> {
+        if self.remaining_heap() < requested {
+            Err(HeapLimitError {
+                requested,
+                used: self.u8_vec.len(),
+                limit: self.heap_limit,
+            })
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Sets the max size of atoms created by new_atom() and new_concat().
+    /// Creating a larger atom fails with "atom too large" (atoms small enough
+    /// to be stored in the NodePtr itself are always allowed). This catches
+    /// single-atom memory bombs before they exhaust the heap.
+    pub fn set_max_atom_size(&mut self, max_atom_size: usize) {
+        self.max_atom_size = max_atom_size;
+    }
+
+    pub fn max_atom_size(&self) -> usize {
+        self.max_atom_size
     }
 
     pub fn new_atom(&mut self, v: &[u8]) -> Result<NodePtr, EvalErr> {
         let start = self.u8_vec.len() as u32;
-        if (self.heap_limit - start as usize) < v.len() {
-            return err(self.nil(), "out of memory");
+        let small_atom = fits_in_small_atom(v);
+        if small_atom.is_none() && v.len() > self.max_atom_size {
+            return err(self.nil(), ErrorCode::AtomTooLarge, "atom too large");
         }
+        self.check_heap(v.len())?;
         let idx = self.atom_vec.len();
         self.check_atom_limit()?;
-        if let Some(ret) = fits_in_small_atom(v) {
+        if let Some(ret) = small_atom {
             self.small_atoms += 1;
+            #[cfg(feature = "allocator-log")]
+            self.record(AllocatorEvent::Atom(v.len()));
             Ok(NodePtr::new(ObjectType::SmallAtom, ret as usize))
         } else {
+            self.check_memory_budget(v.len() + size_of::<AtomBuf>())?;
+            #[cfg(feature = "allocator-log")]
+            self.record(AllocatorEvent::Atom(v.len()));
             self.u8_vec.extend_from_slice(v);
-            let end = self.u8_vec.len() as u32;
-            self.atom_vec.push(AtomBuf { start, end });
-            Ok(NodePtr::new(ObjectType::Bytes, idx))
+            let buf = self.intern(start as usize);
+            self.atom_vec.push(buf);
+            Ok(self.mk_node(ObjectType::Bytes, idx))
         }
     }
 
@@ -299,6 +676,8 @@ impl Allocator {
         debug_assert!(v <= NODE_PTR_IDX_MASK);
         self.check_atom_limit()?;
         self.small_atoms += 1;
+        #[cfg(feature = "allocator-log")]
+        self.record(AllocatorEvent::Atom(len_for_value(v)));
         Ok(NodePtr::new(ObjectType::SmallAtom, v as usize))
     }
 
@@ -322,6 +701,26 @@ impl Allocator {
         self.new_atom(slice)
     }
 
+    /// equivalent to `new_number(v.into())`, without going via a bignum
+    pub fn new_i64(&mut self, v: i64) -> Result<NodePtr, EvalErr> {
+        if let Ok(val) = u32::try_from(v) {
+            if val <= NODE_PTR_IDX_MASK {
+                return self.new_small_number(val);
+            }
+        }
+        let bytes = v.to_be_bytes();
+        let mut slice = &bytes[..];
+
+        // make number minimal by removing redundant sign extension bytes
+        while slice.len() > 1
+            && ((slice[0] == 0 && slice[1] & 0x80 == 0)
+                || (slice[0] == 0xff && slice[1] & 0x80 == 0x80))
+        {
+            slice = &slice[1..];
+        }
+        self.new_atom(slice)
+    }
+
     pub fn new_g1(&mut self, g1: G1Element) -> Result<NodePtr, EvalErr> {
         self.new_atom(&g1.to_bytes())
     }
@@ -333,10 +732,67 @@ impl Allocator {
     pub fn new_pair(&mut self, first: NodePtr, rest: NodePtr) -> Result<NodePtr, EvalErr> {
         let idx = self.pair_vec.len();
         if idx == MAX_NUM_PAIRS {
-            return err(self.nil(), "too many pairs");
+            return err(self.nil(), ErrorCode::TooManyPairs, "too many pairs");
         }
+        self.check_memory_budget(size_of::<IntPair>())?;
+        #[cfg(feature = "allocator-log")]
+        self.record(AllocatorEvent::Pair);
         self.pair_vec.push(IntPair { first, rest });
-        Ok(NodePtr::new(ObjectType::Pair, idx))
+        Ok(self.mk_node(ObjectType::Pair, idx))
+    }
+
+    /// Reserves capacity for at least `n` more pairs, to avoid repeated
+    /// reallocation when building large structures. The reserved capacity
+    /// doesn't count against the heap limit until pairs are created.
+    pub fn reserve_pairs(&mut self, n: usize) {
+        let n = n.min(MAX_NUM_PAIRS.saturating_sub(self.pair_vec.len()));
+        self.pair_vec.reserve(n);
+    }
+
+    /// Creates a proper (nil terminated) list of `items`. This is equivalent
+    /// to calling new_pair() for each item, right-to-left, but allocates the
+    /// pairs in a single step. If the pair or heap limit is hit, no pairs are
+    /// created.
+    pub fn new_list(&mut self, items: &[NodePtr]) -> Result<NodePtr, EvalErr> {
+        if self.pair_vec.len() + items.len() > MAX_NUM_PAIRS {
+            return err(self.nil(), ErrorCode::TooManyPairs, "too many pairs");
+        }
+        self.check_memory_budget(items.len() * size_of::<IntPair>())?;
+        self.new_proper_list_from_iter(items.iter().copied())
+    }
+
+    /// Like new_list(), but the items are taken from an iterator. The list is
+    /// built front-to-back, with every pair referring to the one allocated
+    /// after it, so the items don't need to be collected first. If the pair or
+    /// heap limit is hit, the pairs created so far are released.
+    pub fn new_proper_list_from_iter(
+        &mut self,
+        items: impl IntoIterator<Item = NodePtr>,
+    ) -> Result<NodePtr, EvalErr> {
+        let items = items.into_iter();
+        self.reserve_pairs(items.size_hint().0);
+        let start = self.pair_vec.len();
+        for first in items {
+            let idx = self.pair_vec.len();
+            if idx == MAX_NUM_PAIRS {
+                self.pair_vec.truncate(start);
+                return err(self.nil(), ErrorCode::TooManyPairs, "too many pairs");
+            }
+            if let Err(e) = self.check_memory_budget(size_of::<IntPair>()) {
+                self.pair_vec.truncate(start);
+                return Err(e);
+            }
+            // the last pair is terminated below
+            let rest = self.mk_node(ObjectType::Pair, idx + 1);
+            self.pair_vec.push(IntPair { first, rest });
+        }
+        #[cfg(feature = "allocator-log")]
+        self.record(AllocatorEvent::List(self.pair_vec.len() - start));
+        if self.pair_vec.len() == start {
+            return Ok(self.nil());
+        }
+        self.pair_vec.last_mut().unwrap().rest = NodePtr::NIL;
+        Ok(self.mk_node(ObjectType::Pair, start))
     }
 
     pub fn new_substr(&mut self, node: NodePtr, start: u32, end: u32) -> Result<NodePtr, EvalErr> {
@@ -344,34 +800,53 @@ impl Allocator {
 
         fn bounds_check(node: NodePtr, start: u32, end: u32, len: u32) -> Result<(), EvalErr> {
             if start > len {
-                return err(node, "substr start out of bounds");
+                return err(
+                    node,
+                    ErrorCode::InvalidArgValue,
+                    "substr start out of bounds",
+                );
             }
             if end > len {
-                return err(node, "substr end out of bounds");
+                return err(node, ErrorCode::InvalidArgValue, "substr end out of bounds");
             }
             if end < start {
-                return err(node, "substr invalid bounds");
+                return err(node, ErrorCode::InvalidArgValue, "substr invalid bounds");
             }
             Ok(())
         }
 
         match node.object_type() {
-            ObjectType::Pair => err(node, "(internal error) substr expected atom, got pair"),
+            ObjectType::Pair => err(
+                node,
+                ErrorCode::InternalError,
+                "(internal error) substr expected atom, got pair",
+            ),
             ObjectType::Bytes => {
-                let atom = self.atom_vec[node.index() as usize];
+                let atom = self.atom_buf(node);
                 let atom_len = atom.end - atom.start;
                 bounds_check(node, start, end, atom_len)?;
+                #[cfg(feature = "allocator-log")]
+                self.record(AllocatorEvent::Substr((end - start) as usize));
+                let substr =
+                    &self.u8_vec[(atom.start + start) as usize..(atom.start + end) as usize];
+                if let Some(new_val) = fits_in_small_atom(substr) {
+                    self.small_atoms += 1;
+                    return Ok(NodePtr::new(ObjectType::SmallAtom, new_val as usize));
+                }
+                self.check_memory_budget(size_of::<AtomBuf>())?;
                 let idx = self.atom_vec.len();
                 self.atom_vec.push(AtomBuf {
                     start: atom.start + start,
                     end: atom.start + end,
                 });
-                Ok(NodePtr::new(ObjectType::Bytes, idx))
+                Ok(self.mk_node(ObjectType::Bytes, idx))
             }
             ObjectType::SmallAtom => {
                 let val = node.index();
                 let len = len_for_value(val) as u32;
                 bounds_check(node, start, end, len)?;
+                #[cfg(feature = "allocator-log")]
+                self.record(AllocatorEvent::Substr((end - start) as usize));
                 let buf: [u8; 4] = val.to_be_bytes();
                 let buf = &buf[4 - len as usize..];
                 let substr = &buf[start as usize..end as usize];
@@ -379,6 +854,7 @@ impl Allocator {
                     self.small_atoms += 1;
                     Ok(NodePtr::new(ObjectType::SmallAtom, new_val as usize))
                 } else {
+                    self.check_memory_budget(substr.len() + size_of::<AtomBuf>())?;
                     let start = self.u8_vec.len();
                     let end = start + substr.len();
                     self.u8_vec.extend_from_slice(substr);
@@ -387,7 +863,7 @@ impl Allocator {
                         start: start as u32,
                         end: end as u32,
                     });
-                    Ok(NodePtr::new(ObjectType::Bytes, idx))
+                    Ok(self.mk_node(ObjectType::Bytes, idx))
                 }
             }
         }
@@ -396,12 +872,23 @@ impl Allocator {
     pub fn new_concat(&mut self, new_size: usize, nodes: &[NodePtr]) -> Result<NodePtr, EvalErr> {
         self.check_atom_limit()?;
         let start = self.u8_vec.len();
-        if self.heap_limit - start < new_size {
-            return err(self.nil(), "out of memory");
+        if new_size > self.max_atom_size {
+            return err(self.nil(), ErrorCode::AtomTooLarge, "atom too large");
         }
+        self.check_heap(new_size)?;
+        self.check_memory_budget(new_size + size_of::<AtomBuf>())?;
+        #[cfg(feature = "allocator-log")]
+        self.record(AllocatorEvent::Concat {
+            atoms: nodes.len(),
+            len: new_size,
+        });
         // TODO: maybe it would make sense to have a special case where
         // nodes.len() == 1. We can just return the same node
 
+        if new_size <= 4 {
+            return self.new_small_concat(new_size, nodes);
+        }
+
         self.u8_vec.reserve(new_size);
 
         let mut counter: usize = 0;
@@ -409,13 +896,21 @@ impl Allocator {
             match node.object_type() {
                 ObjectType::Pair => {
                     self.u8_vec.truncate(start);
-                    return err(*node, "(internal error) concat expected atom, got pair");
+                    return err(
+                        *node,
+                        ErrorCode::InternalError,
+                        "(internal error) concat expected atom, got pair",
+                    );
                 }
                 ObjectType::Bytes => {
-                    let term = self.atom_vec[node.index() as usize];
+                    let term = self.atom_buf(*node);
                     if counter + term.len() > new_size {
                         self.u8_vec.truncate(start);
-                        return err(*node, "(internal error) concat passed invalid new_size");
+                        return err(
+                            *node,
+                            ErrorCode::InternalError,
+                            "(internal error) concat passed invalid new_size",
+                        );
                     }
                     self.u8_vec
                         .extend_from_within(term.start as usize..term.end as usize);
@@ -435,16 +930,60 @@ impl Allocator {
             self.u8_vec.truncate(start);
             return err(
                 self.nil(),
+                ErrorCode::InternalError,
                 "(internal error) concat passed invalid new_size",
             );
         }
-        let end = self.u8_vec.len() as u32;
+        let buf = self.intern(start);
         let idx = self.atom_vec.len();
-        self.atom_vec.push(AtomBuf {
-            start: (start as u32),
-            end,
-        });
-        Ok(NodePtr::new(ObjectType::Bytes, idx))
+        self.atom_vec.push(buf);
+        Ok(self.mk_node(ObjectType::Bytes, idx))
+    }
+
+    // concatenations of up to 4 bytes are assembled on the stack. If the
+    // result is a canonical small integer, it's stored in the NodePtr itself
+    // and the heap isn't touched
+    fn new_small_concat(&mut self, new_size: usize, nodes: &[NodePtr]) -> Result<NodePtr, EvalErr> {
+        let mut buf = [0_u8; 4];
+        let mut counter: usize = 0;
+        for node in nodes {
+            if node.is_pair() {
+                return err(
+                    *node,
+                    ErrorCode::InternalError,
+                    "(internal error) concat expected atom, got pair",
+                );
+            }
+            let term = self.atom(*node);
+            let term = term.as_ref();
+            if counter + term.len() > new_size {
+                return err(
+                    *node,
+                    ErrorCode::InternalError,
+                    "(internal error) concat passed invalid new_size",
+                );
+            }
+            buf[counter..counter + term.len()].copy_from_slice(term);
+            counter += term.len();
+        }
+        if counter != new_size {
+            return err(
+                self.nil(),
+                ErrorCode::InternalError,
+                "(internal error) concat passed invalid new_size",
+            );
+        }
+        let buf = &buf[..new_size];
+        if let Some(val) = fits_in_small_atom(buf) {
+            self.small_atoms += 1;
+            return Ok(NodePtr::new(ObjectType::SmallAtom, val as usize));
+        }
+        let start = self.u8_vec.len();
+        self.u8_vec.extend_from_slice(buf);
+        let buf = self.intern(start);
+        let idx = self.atom_vec.len();
+        self.atom_vec.push(buf);
+        Ok(self.mk_node(ObjectType::Bytes, idx))
     }
 
     pub fn atom_eq(&self, lhs: NodePtr, rhs: NodePtr) -> bool {
@@ -456,17 +995,17 @@ impl Allocator {
                 panic!("atom_eq() called on pair");
             }
             (ObjectType::Bytes, ObjectType::Bytes) => {
-                let lhs = self.atom_vec[lhs.index() as usize];
-                let rhs = self.atom_vec[rhs.index() as usize];
+                let lhs = self.atom_buf(lhs);
+                let rhs = self.atom_buf(rhs);
                 self.u8_vec[lhs.start as usize..lhs.end as usize]
                     == self.u8_vec[rhs.start as usize..rhs.end as usize]
             }
             (ObjectType::SmallAtom, ObjectType::SmallAtom) => lhs.index() == rhs.index(),
             (ObjectType::SmallAtom, ObjectType::Bytes) => {
-                self.bytes_eq_int(self.atom_vec[rhs.index() as usize], lhs.index())
+                self.bytes_eq_int(self.atom_buf(rhs), lhs.index())
             }
             (ObjectType::Bytes, ObjectType::SmallAtom) => {
-                self.bytes_eq_int(self.atom_vec[lhs.index() as usize], rhs.index())
+                self.bytes_eq_int(self.atom_buf(lhs), rhs.index())
             }
         }
     }
@@ -496,12 +1035,57 @@ impl Allocator {
         val == atom_val
     }
 
-    pub fn atom(&self, node: NodePtr) -> Atom {
+    /// Compares two trees structurally. Returns true if they have the same
+    /// shape and all atoms are equal. Shared sub trees are not descended
+    /// into, but the cost of comparing two distinct, but equal, trees is
+    /// proportional to their size.
+    pub fn tree_eq(&self, lhs: NodePtr, rhs: NodePtr) -> bool {
+        self.tree_eq_limited(lhs, rhs, usize::MAX)
+            .expect("tree_eq() is unlimited")
+    }
+
+    /// Like tree_eq(), but fails once more than max_nodes pairs of nodes have
+    /// been compared. This protects against adversarial (large) trees.
+    pub fn tree_eq_limited(
+        &self,
+        lhs: NodePtr,
+        rhs: NodePtr,
+        max_nodes: usize,
+    ) -> Result<bool, EvalErr> {
+        let mut budget = max_nodes;
+        let mut stack = vec![(lhs, rhs)];
+        while let Some((l, r)) = stack.pop() {
+            if l == r {
+                continue;
+            }
+            if budget == 0 {
+                return err(lhs, ErrorCode::LimitExceeded, "tree_eq node limit exceeded");
+            }
+            budget -= 1;
+            match (self.sexp(l), self.sexp(r)) {
+                (SExp::Pair(ll, lr), SExp::Pair(rl, rr)) => {
+                    stack.push((lr, rr));
+                    stack.push((ll, rl));
+                }
+                (SExp::Atom, SExp::Atom) => {
+                    if !self.atom_eq(l, r) {
+                        return Ok(false);
+                    }
+                }
+                _ => {
+                    return Ok(false);
+                }
+            }
+        }
+        Ok(true)
+    }
+
+    pub fn atom(&self, node: NodePtr) -> Atom<'_> {
         let index = node.index();
 
         match node.object_type() {
             ObjectType::Bytes => {
-                let atom = self.atom_vec[index as usize];
+                let atom = self.atom_buf(node);
                 Atom::Borrowed(&self.u8_vec[atom.start as usize..atom.end as usize])
             }
             ObjectType::SmallAtom => {
@@ -518,7 +1102,7 @@ impl Allocator {
 
         match node.object_type() {
             ObjectType::Bytes => {
-                let atom = self.atom_vec[index as usize];
+                let atom = self.atom_buf(node);
                 (atom.end - atom.start) as usize
             }
             ObjectType::SmallAtom => len_for_value(index),
@@ -528,11 +1112,36 @@ impl Allocator {
         }
     }
 
+    /// Returns a pointer to, and the length of, the bytes of an atom. This is
+    /// meant for foreign function interfaces, where the caller can't hold on
+    /// to an `Atom`. Atoms stored on the heap are returned without copying.
+    /// Small atoms don't have any backing storage, so their bytes are written
+    /// into `buf` and the returned pointer refers to it.
+    ///
+    /// The pointer is only valid for as long as both the allocator and `buf`
+    /// are not modified, moved or dropped. Any call that allocates may
+    /// reallocate the heap and invalidate it.
+    pub fn atom_raw(&self, node: NodePtr, buf: &mut [u8; 4]) -> (*const u8, usize) {
+        match node.object_type() {
+            ObjectType::Bytes => {
+                let atom = self.atom_buf(node);
+                let bytes = &self.u8_vec[atom.start as usize..atom.end as usize];
+                (bytes.as_ptr(), bytes.len())
+            }
+            ObjectType::SmallAtom => {
+                let len = len_for_value(node.index());
+                *buf = node.index().to_be_bytes();
+                (buf[4 - len..].as_ptr(), len)
+            }
+            _ => panic!("expected atom, got pair"),
+        }
+    }
+
     pub fn small_number(&self, node: NodePtr) -> Option<u32> {
         match node.object_type() {
             ObjectType::SmallAtom => Some(node.index()),
             ObjectType::Bytes => {
-                let atom = self.atom_vec[node.index() as usize];
+                let atom = self.atom_buf(node);
                 let buf = &self.u8_vec[atom.start as usize..atom.end as usize];
                 fits_in_small_atom(buf)
             }
@@ -545,7 +1154,7 @@ impl Allocator {
 
         match node.object_type() {
             ObjectType::Bytes => {
-                let atom = self.atom_vec[index as usize];
+                let atom = self.atom_buf(node);
                 number_from_u8(&self.u8_vec[atom.start as usize..atom.end as usize])
             }
             ObjectType::SmallAtom => Number::from(index),
@@ -556,63 +1165,97 @@ impl Allocator {
     }
 
     pub fn g1(&self, node: NodePtr) -> Result<G1Element, EvalErr> {
-        let idx = match node.object_type() {
-            ObjectType::Bytes => node.index(),
+        match node.object_type() {
+            ObjectType::Bytes => {}
             ObjectType::SmallAtom => {
-                return err(node, "atom is not G1 size, 48 bytes");
+                return err(
+                    node,
+                    ErrorCode::InvalidArgType,
+                    "atom is not G1 size, 48 bytes",
+                );
             }
             ObjectType::Pair => {
-                return err(node, "pair found, expected G1 point");
+                return err(
+                    node,
+                    ErrorCode::InvalidArgType,
+                    "pair found, expected G1 point",
+                );
             }
         };
-        let atom = self.atom_vec[idx as usize];
+        let atom = self.atom_buf(node);
         if atom.end - atom.start != 48 {
-            return err(node, "atom is not G1 size, 48 bytes");
+            return err(
+                node,
+                ErrorCode::InvalidArgType,
+                "atom is not G1 size, 48 bytes",
+            );
         }
 
         let array: &[u8; 48] = &self.u8_vec[atom.start as usize..atom.end as usize]
             .try_into()
             .expect("atom size is not 48 bytes");
-        G1Element::from_bytes(array)
-            .map_err(|_| EvalErr(node, "atom is not a G1 point".to_string()))
+        G1Element::from_bytes(array).map_err(|_| {
+            EvalErr(
+                node,
+                "atom is not a G1 point".to_string(),
+                ErrorCode::InvalidArgValue,
+            )
+        })
     }
 
     pub fn g2(&self, node: NodePtr) -> Result<G2Element, EvalErr> {
-        let idx = match node.object_type() {
-            ObjectType::Bytes => node.index(),
+        match node.object_type() {
+            ObjectType::Bytes => {}
             ObjectType::SmallAtom => {
-                return err(node, "atom is not G2 size, 96 bytes");
+                return err(
+                    node,
+                    ErrorCode::InvalidArgType,
+                    "atom is not G2 size, 96 bytes",
+                );
             }
             ObjectType::Pair => {
-                return err(node, "pair found, expected G2 point");
+                return err(
+                    node,
+                    ErrorCode::InvalidArgType,
+                    "pair found, expected G2 point",
+                );
             }
         };
 
-        let atom = self.atom_vec[idx as usize];
+        let atom = self.atom_buf(node);
         if atom.end - atom.start != 96 {
-            return err(node, "atom is not G2 size, 96 bytes");
+            return err(
+                node,
+                ErrorCode::InvalidArgType,
+                "atom is not G2 size, 96 bytes",
+            );
         }
 
         let array: &[u8; 96] = &self.u8_vec[atom.start as usize..atom.end as usize]
             .try_into()
             .expect("atom size is not 96 bytes");
 
-        G2Element::from_bytes(array)
-            .map_err(|_| EvalErr(node, "atom is not a G2 point".to_string()))
+        G2Element::from_bytes(array).map_err(|_| {
+            EvalErr(
+                node,
+                "atom is not a G2 point".to_string(),
+                ErrorCode::InvalidArgValue,
+            )
+        })
     }
 
-    pub fn node(&self, node: NodePtr) -> NodeVisitor {
+    pub fn node(&self, node: NodePtr) -> NodeVisitor<'_> {
         let index = node.index();
 
         match node.object_type() {
             ObjectType::Bytes => {
-                let atom = self.atom_vec[index as usize];
+                let atom = self.atom_buf(node);
                 let buf = &self.u8_vec[atom.start as usize..atom.end as usize];
                 NodeVisitor::Buffer(buf)
             }
             ObjectType::SmallAtom => NodeVisitor::U32(index),
             ObjectType::Pair => {
-                let pair = self.pair_vec[index as usize];
+                let pair = self.int_pair(node);
                 NodeVisitor::Pair(pair.first, pair.rest)
             }
         }
@@ -622,7 +1265,7 @@ impl Allocator {
         match node.object_type() {
             ObjectType::Bytes | ObjectType::SmallAtom => SExp::Atom,
             ObjectType::Pair => {
-                let pair = self.pair_vec[node.index() as usize];
+                let pair = self.int_pair(node);
                 SExp::Pair(pair.first, pair.rest)
             }
         }
@@ -651,12 +1294,41 @@ impl Allocator {
     #[inline]
     fn check_atom_limit(&self) -> Result<(), EvalErr> {
         if self.atom_vec.len() + self.small_atoms == MAX_NUM_ATOMS {
-            err(self.nil(), "too many atoms")
+            err(self.nil(), ErrorCode::TooManyAtoms, "too many atoms")
+        } else {
+            Ok(())
+        }
+    }
+
+    #[inline]
+    fn check_memory_budget(&self, additional_bytes: usize) -> Result<(), EvalErr> {
+        if self.memory_budget == usize::MAX {
+            return Ok(());
+        }
+        if self.memory_used().saturating_add(additional_bytes) > self.memory_budget {
+            err(self.nil(), ErrorCode::OutOfMemory, "out of memory")
         } else {
             Ok(())
         }
     }
 
+    fn memory_used(&self) -> usize {
+        self.u8_vec.len()
+            + self.atom_vec.len() * size_of::<AtomBuf>()
+            + self.pair_vec.len() * size_of::<IntPair>()
+    }
+
+    pub fn memory_stats(&self) -> MemoryStats {
+        MemoryStats {
+            heap_bytes: self.u8_vec.len(),
+            atom_table_bytes: self.atom_vec.len() * size_of::<AtomBuf>(),
+            pair_table_bytes: self.pair_vec.len() * size_of::<IntPair>(),
+            atoms: self.atom_vec.len(),
+            small_atoms: self.small_atoms,
+            pairs: self.pair_vec.len(),
+        }
+    }
+
     #[cfg(feature = "counters")]
     pub fn atom_count(&self) -> usize {
         self.atom_vec.len()
@@ -678,6 +1350,59 @@ impl Allocator {
     }
 }
 
+/// Copies the tree `node`, from the `src` allocator into `dst`, returning the
+/// root of the copy. This lets a value computed in a short-lived allocator be
+/// kept in a long-lived one, without serializing it. Only the nodes reachable
+/// from `node` are copied, and nodes that are shared in `src` (i.e. the same
+/// NodePtr appears more than once) are shared in the copy too.
+pub fn copy_tree(src: &Allocator, node: NodePtr, dst: &mut Allocator) -> Result<NodePtr, EvalErr> {
+    enum Op {
+        Copy(NodePtr),
+        // the copies of the first and rest of this pair are on top of the
+        // value stack
+        Cons(NodePtr),
+    }
+
+    let mut copied = HashMap::<NodePtr, NodePtr>::new();
+    let mut values = Vec::<NodePtr>::new();
+    let mut ops = vec![Op::Copy(node)];
+    while let Some(op) = ops.pop() {
+        match op {
+            Op::Copy(n) => {
+                if let Some(copy) = copied.get(&n) {
+                    values.push(*copy);
+                    continue;
+                }
+                match src.node(n) {
+                    NodeVisitor::Pair(first, rest) => {
+                        ops.push(Op::Cons(n));
+                        ops.push(Op::Copy(rest));
+                        ops.push(Op::Copy(first));
+                    }
+                    NodeVisitor::U32(val) => {
+                        // small atoms don't refer to any memory, but they
+                        // still count towards the atom limit of dst
+                        values.push(dst.new_small_number(val)?);
+                    }
+                    NodeVisitor::Buffer(buf) => {
+                        let copy = dst.new_atom(buf)?;
+                        copied.insert(n, copy);
+                        values.push(copy);
+                    }
+                }
+            }
+            Op::Cons(n) => {
+                let rest = values.pop().expect("copy_tree: value stack empty");
+                let first = values.pop().expect("copy_tree: value stack empty");
+                let copy = dst.new_pair(first, rest)?;
+                copied.insert(n, copy);
+                values.push(copy);
+            }
+        }
+    }
+    Ok(values.pop().expect("copy_tree: value stack empty"))
+}
+
 #[cfg(test)]
 mod tests {
     use rstest::rstest;
@@ -861,7 +1586,7 @@ mod tests {
     #[test]
     #[should_panic]
     fn test_invalid_node_ptr_type() {
-        let node = NodePtr(3 << NODE_PTR_IDX_BITS);
+        let node = NodePtr::from_raw(3 << NODE_PTR_IDX_BITS);
         // unknown NodePtr type
         let _ = node.object_type();
     }
@@ -937,7 +1662,10 @@ mod tests {
     fn test_allocate_heap_limit() {
         let mut a = Allocator::new_limited(6);
         // we can't allocate 6 bytes
-        assert_eq!(a.new_atom(b"foobar").unwrap_err().1, "out of memory");
+        assert_eq!(
+            a.new_atom(b"foobar").unwrap_err().1,
+            "out of memory: requested 6 bytes with 0 of 5 bytes in use"
+        );
         // but 5 is OK
         let _atom = a.new_atom(b"fooba").unwrap();
     }
@@ -1010,6 +1738,178 @@ mod tests {
         assert_eq!(a.new_pair(atom, atom).unwrap_err().1, "too many pairs");
     }
 
+    #[rstest]
+    #[case(0)]
+    #[case(1)]
+    #[case(2)]
+    #[case(100)]
+    fn test_new_list(#[case] len: usize) {
+        let mut a = Allocator::new();
+        let items: Vec<NodePtr> = (0..len)
+            .map(|i| a.new_atom(&[i as u8, 0xff]).unwrap())
+            .collect();
+
+        let mut expected = a.nil();
+        for item in items.iter().rev() {
+            expected = a.new_pair(*item, expected).unwrap();
+        }
+
+        let list = a.new_list(&items).unwrap();
+        assert!(a.tree_eq(list, expected));
+        assert_eq!(a.pair_vec.len(), len * 2);
+
+        // an iterator without a known length
+        let list = a
+            .new_proper_list_from_iter(items.iter().copied().filter(|_| true))
+            .unwrap();
+        assert!(a.tree_eq(list, expected));
+        assert_eq!(a.pair_vec.len(), len * 3);
+    }
+
+    #[test]
+    fn test_new_list_pair_limit() {
+        let mut a = Allocator::new();
+        let atom = a.new_atom(b"foo").unwrap();
+        for _ in 0..MAX_NUM_PAIRS - 2 {
+            a.new_pair(atom, atom).unwrap();
+        }
+        assert_eq!(
+            a.new_list(&[atom, atom, atom]).unwrap_err().1,
+            "too many pairs"
+        );
+        assert_eq!(
+            a.new_proper_list_from_iter([atom, atom, atom])
+                .unwrap_err()
+                .1,
+            "too many pairs"
+        );
+        // no pairs were leaked by the failed attempts
+        assert_eq!(a.pair_vec.len(), MAX_NUM_PAIRS - 2);
+        a.new_list(&[atom, atom]).unwrap();
+        assert_eq!(a.pair_vec.len(), MAX_NUM_PAIRS);
+    }
+
+    #[test]
+    fn test_new_list_memory_budget() {
+        let mut a = Allocator::new_with_memory_budget(u32::MAX as usize, size_of::<IntPair>() * 2);
+        assert_eq!(
+            a.new_list(&[NodePtr::NIL; 3]).unwrap_err().1,
+            "out of memory"
+        );
+        assert_eq!(
+            a.new_proper_list_from_iter([NodePtr::NIL; 3])
+                .unwrap_err()
+                .1,
+            "out of memory"
+        );
+        assert_eq!(a.pair_vec.len(), 0);
+        a.new_list(&[NodePtr::NIL; 2]).unwrap();
+    }
+
+    #[cfg(feature = "allocator-log")]
+    #[test]
+    fn test_allocator_log() {
+        let mut a = Allocator::new();
+        // nothing is recorded until the log is enabled
+        let foo = a.new_atom(b"foo").unwrap();
+        assert_eq!(a.take_log(), vec![]);
+
+        a.enable_log();
+        let cp = a.checkpoint();
+        let bar = a.new_atom(b"barbaz").unwrap();
+        let one = a.new_small_number(1).unwrap();
+        a.new_number(0x8000.into()).unwrap();
+        a.new_pair(foo, bar).unwrap();
+        a.new_list(&[foo, bar, one]).unwrap();
+        a.new_substr(bar, 1, 4).unwrap();
+        a.new_concat(9, &[foo, bar]).unwrap();
+        a.new_concat(2, &[one, one]).unwrap();
+        // failed allocations aren't recorded
+        a.new_substr(bar, 4, 1).unwrap_err();
+        a.restore_checkpoint(&cp);
+        a.reset();
+        assert_eq!(
+            a.take_log(),
+            vec![
+                AllocatorEvent::Atom(6),
+                AllocatorEvent::Atom(1),
+                AllocatorEvent::Atom(3),
+                AllocatorEvent::Pair,
+                AllocatorEvent::List(3),
+                AllocatorEvent::Substr(3),
+                AllocatorEvent::Concat { atoms: 2, len: 9 },
+                AllocatorEvent::Concat { atoms: 2, len: 2 },
+                AllocatorEvent::RestoreCheckpoint,
+                AllocatorEvent::Reset,
+            ]
+        );
+        assert_eq!(a.take_log(), vec![]);
+    }
+
+    #[rstest]
+    #[case("()")]
+    #[case("1337")]
+    #[case("\"foobar\"")]
+    #[case("(1 2 3)")]
+    #[case("((\"foo\" . 0x8000) (() . (-1 \"foobarbaz\")))")]
+    fn test_copy_tree(#[case] input: &str) {
+        use crate::serde::tree_hash;
+        use crate::test_ops::parse_exp;
+
+        let mut src = Allocator::new();
+        // allocate something first, so the NodePtrs in src and dst differ
+        src.new_atom(b"padding").unwrap();
+        let node = parse_exp(&mut src, input);
+
+        let mut dst = Allocator::new();
+        let copy = copy_tree(&src, node, &mut dst).unwrap();
+        assert_eq!(tree_hash(&src, node), tree_hash(&dst, copy));
+    }
+
+    #[test]
+    fn test_copy_tree_sharing() {
+        use crate::serde::tree_hash;
+
+        let mut src = Allocator::new();
+        let atom = src.new_atom(b"foobar").unwrap();
+        // a tree with 2^20 leaves, but only 21 distinct nodes
+        let mut node = src.new_pair(atom, atom).unwrap();
+        for _ in 0..20 {
+            node = src.new_pair(node, node).unwrap();
+        }
+        // garbage that isn't reachable from node
+        src.new_atom(b"unreachable").unwrap();
+        src.new_pair(atom, atom).unwrap();
+
+        let mut dst = Allocator::new();
+        let copy = copy_tree(&src, node, &mut dst).unwrap();
+        assert_eq!(dst.pair_vec.len(), 21);
+        assert_eq!(dst.atom_vec.len(), 1);
+        assert_eq!(dst.u8_vec.len(), 6);
+        assert_eq!(tree_hash(&src, node), tree_hash(&dst, copy));
+    }
+
+    #[test]
+    fn test_copy_tree_limit() {
+        use crate::test_ops::parse_exp;
+
+        let mut src = Allocator::new();
+        let node = parse_exp(&mut src, "(\"foo\" \"bar\")");
+        let mut dst = Allocator::new_with_memory_budget(u32::MAX as usize, 10);
+        assert_eq!(
+            copy_tree(&src, node, &mut dst).unwrap_err().1,
+            "out of memory"
+        );
+    }
+
+    #[test]
+    fn test_reserve_pairs() {
+        let mut a = Allocator::new();
+        a.reserve_pairs(10000);
+        assert!(a.pair_vec.capacity() >= 10000);
+        assert_eq!(a.pair_vec.len(), 0);
+    }
+
     #[test]
     fn test_substr() {
         let mut a = Allocator::new();
@@ -1209,7 +2109,7 @@ mod tests {
             a.new_concat(6, &[atom1, atom2, atom3, atom4, atom5, atom6])
                 .unwrap_err()
                 .1,
-            "out of memory"
+            "out of memory: requested 6 bytes with 0 of 5 bytes in use"
         );
         let cat = a.new_concat(2, &[atom1, atom2]).unwrap();
         assert_eq!(a.atom(cat).as_ref(), b"fo");
@@ -1242,6 +2142,355 @@ mod tests {
         assert_eq!(number_from_u8(expected), num);
     }
 
+    #[rstest]
+    #[case(0)]
+    #[case(1)]
+    #[case(-1)]
+    #[case(0x7f)]
+    #[case(0x80)]
+    #[case(-0x80)]
+    #[case(-0x81)]
+    #[case(0x3ffffff)]
+    #[case(0x4000000)]
+    #[case(0xffffffff)]
+    #[case(i64::MAX)]
+    #[case(i64::MIN)]
+    fn test_new_i64(#[case] num: i64) {
+        let mut a = Allocator::new();
+        let atom = a.new_i64(num).unwrap();
+        let expected = a.new_number(num.into()).unwrap();
+        assert_eq!(a.atom(atom).as_ref(), a.atom(expected).as_ref());
+        assert_eq!(atom.object_type(), expected.object_type());
+    }
+
+    #[test]
+    fn test_memory_stats() {
+        let mut a = Allocator::new();
+        let empty = a.memory_stats();
+        assert_eq!(empty.total_bytes(), 0);
+        assert_eq!(empty.small_atoms, 2);
+
+        let atom = a.new_atom(b"foobar").unwrap();
+        let _small = a.new_atom(&[1, 2]).unwrap();
+        let _pair = a.new_pair(atom, atom).unwrap();
+        let _substr = a.new_substr(atom, 1, 6).unwrap();
+
+        let stats = a.memory_stats();
+        assert_eq!(
+            stats,
+            MemoryStats {
+                heap_bytes: 6,
+                atom_table_bytes: 2 * size_of::<AtomBuf>(),
+                pair_table_bytes: size_of::<IntPair>(),
+                atoms: 2,
+                small_atoms: 3,
+                pairs: 1,
+            }
+        );
+        assert_eq!(
+            stats.total_bytes(),
+            6 + 2 * size_of::<AtomBuf>() + size_of::<IntPair>()
+        );
+
+        let checkpoint = a.checkpoint();
+        a.new_pair(atom, atom).unwrap();
+        a.restore_checkpoint(&checkpoint);
+        assert_eq!(a.memory_stats(), stats);
+    }
+
+    #[rstest]
+    // canonical integers of up to 4 bytes are stored in the NodePtr
+    #[case(&["66", "6f6f"], 0, 0)]
+    #[case(&["", ""], 0, 0)]
+    #[case(&["01", "0203"], 0, 0)]
+    // negative numbers, and numbers with redundant leading zeros, are not
+    #[case(&["ff", "01"], 2, 1)]
+    #[case(&["00", "01"], 2, 1)]
+    #[case(&["00"], 1, 1)]
+    // neither are values larger than 26 bits
+    #[case(&["0400", "0000"], 4, 1)]
+    #[case(&["666f6f", "626172"], 6, 1)]
+    fn test_concat_small_atoms(
+        #[case] terms: &[&str],
+        #[case] heap_bytes: usize,
+        #[case] atoms: usize,
+    ) {
+        let mut a = Allocator::new();
+        let terms: Vec<Vec<u8>> = terms.iter().map(|t| hex::decode(t).unwrap()).collect();
+        let nodes: Vec<NodePtr> = terms.iter().map(|t| a.new_atom(t).unwrap()).collect();
+        let expected: Vec<u8> = terms.concat();
+        let before = a.memory_stats();
+        let cat = a.new_concat(expected.len(), &nodes).unwrap();
+        assert_eq!(a.atom(cat).as_ref(), expected.as_slice());
+        let after = a.memory_stats();
+        assert_eq!(after.heap_bytes - before.heap_bytes, heap_bytes);
+        assert_eq!(after.atoms - before.atoms, atoms);
+        assert_eq!(after.small_atoms - before.small_atoms, 1 - atoms);
+    }
+
+    #[rstest]
+    #[case(0, 0, true)]
+    #[case(0, 3, true)]
+    #[case(1, 4, true)]
+    #[case(0, 6, false)]
+    // too large for 26 bits
+    #[case(1, 5, false)]
+    // \xff is negative
+    #[case(6, 7, false)]
+    // \x00\x01 has a redundant leading zero
+    #[case(7, 9, false)]
+    #[case(8, 9, true)]
+    fn test_substr_small_atoms(#[case] start: u32, #[case] end: u32, #[case] small: bool) {
+        let mut a = Allocator::new();
+        let buf = b"foobar\xff\x00\x01";
+        let atom = a.new_atom(buf).unwrap();
+        let before = a.memory_stats();
+        let sub = a.new_substr(atom, start, end).unwrap();
+        assert_eq!(a.atom(sub).as_ref(), &buf[start as usize..end as usize]);
+        let after = a.memory_stats();
+        // sub-strings of heap allocated atoms never use more heap
+        assert_eq!(after.heap_bytes, before.heap_bytes);
+        assert_eq!(after.atoms - before.atoms, usize::from(!small));
+        assert_eq!(after.small_atoms - before.small_atoms, usize::from(small));
+    }
+
+    #[test]
+    fn test_memory_budget() {
+        let budget = 6 + 2 * size_of::<AtomBuf>() + size_of::<IntPair>();
+        let mut a = Allocator::new_with_memory_budget(u32::MAX as usize, budget);
+        let atom = a.new_atom(b"foobar").unwrap();
+        let _pair = a.new_pair(atom, atom).unwrap();
+        // small atoms don't use any memory
+        let _small = a.new_small_number(1337).unwrap();
+        assert_eq!(
+            a.memory_stats().total_bytes(),
+            budget - size_of::<AtomBuf>()
+        );
+
+        // 7 bytes + the atom table entry is too much
+        assert_eq!(a.new_atom(b"1234567").unwrap_err().1, "out of memory");
+        assert_eq!(
+            a.new_concat(12, &[atom, atom]).unwrap_err().1,
+            "out of memory"
+        );
+        let _substr = a.new_substr(atom, 0, 5).unwrap();
+        assert_eq!(a.new_pair(atom, atom).unwrap_err().1, "out of memory");
+        assert_eq!(a.new_substr(atom, 0, 5).unwrap_err().1, "out of memory");
+        // short sub-strings that are canonical integers don't need any memory
+        let _substr = a.new_substr(atom, 0, 3).unwrap();
+        assert_eq!(a.memory_stats().total_bytes(), budget);
+    }
+
+    #[test]
+    fn test_no_memory_budget() {
+        // without a budget, the check never fails
+        let a = Allocator::new();
+        a.check_memory_budget(usize::MAX).unwrap();
+
+        // with one, a huge allocation fails rather than overflowing
+        let a = Allocator::new_with_memory_budget(u32::MAX as usize, 10);
+        assert_eq!(
+            a.check_memory_budget(usize::MAX).unwrap_err().1,
+            "out of memory"
+        );
+    }
+
+    #[test]
+    fn test_check_heap() {
+        let mut a = Allocator::new_limited(11);
+        assert_eq!(a.remaining_heap(), 10);
+        a.new_atom(b"foobar").unwrap();
+        assert_eq!(a.remaining_heap(), 4);
+        assert_eq!(a.check_heap(4), Ok(()));
+        let e = a.check_heap(5).unwrap_err();
+        assert_eq!(
+            e,
+            HeapLimitError {
+                requested: 5,
+                used: 6,
+                limit: 10,
+            }
+        );
+        assert_eq!(
+            e.to_string(),
+            "out of memory: requested 5 bytes with 6 of 10 bytes in use"
+        );
+        assert_eq!(
+            a.new_atom(b"fooba").unwrap_err().1,
+            "out of memory: requested 5 bytes with 6 of 10 bytes in use"
+        );
+        let atom = a.new_atom(b"foob").unwrap();
+        assert_eq!(a.remaining_heap(), 0);
+        assert_eq!(
+            a.new_concat(1, &[a.one()]).unwrap_err().1,
+            "out of memory: requested 1 bytes with 10 of 10 bytes in use"
+        );
+        assert_eq!(a.atom(atom).as_ref(), b"foob");
+    }
+
+    #[test]
+    fn test_max_atom_size() {
+        let mut a = Allocator::new();
+        assert_eq!(a.max_atom_size(), usize::MAX);
+        a.set_max_atom_size(6);
+        let atom = a.new_atom(b"foobar").unwrap();
+        assert_eq!(a.new_atom(b"foobar!").unwrap_err().1, "atom too large");
+        let small = a.new_small_number(1).unwrap();
+        assert_eq!(
+            a.new_concat(7, &[atom, small]).unwrap_err().1,
+            "atom too large"
+        );
+        let concat = a.new_concat(6, &[atom]).unwrap();
+        assert_eq!(a.atom(concat).as_ref(), b"foobar");
+        // small atoms are never too large
+        a.set_max_atom_size(0);
+        assert_eq!(
+            a.new_atom(&[1, 2, 3]).unwrap(),
+            a.new_small_number(0x10203).unwrap()
+        );
+        assert_eq!(a.new_atom(&[]).unwrap(), a.nil());
+
+        // the atom size limit is checked before the heap limit, in both
+        // new_atom() and new_concat()
+        let mut a = Allocator::new_limited(10);
+        a.set_max_atom_size(6);
+        assert_eq!(a.new_atom(&[1; 20]).unwrap_err().1, "atom too large");
+        let atom = a.new_atom(b"foobar").unwrap();
+        assert_eq!(
+            a.new_concat(12, &[atom, atom]).unwrap_err().1,
+            "atom too large"
+        );
+    }
+
+    #[test]
+    fn test_interning() {
+        let mut a = Allocator::new();
+        assert_eq!(a.intern_stats(), None);
+        a.enable_interning(6);
+
+        let hash = [0x42_u8; 32];
+        let first = a.new_atom(&hash).unwrap();
+        let second = a.new_atom(&hash).unwrap();
+        // the atoms are distinct nodes, sharing the same bytes
+        assert_ne!(first, second);
+        assert_eq!(a.atom(second).as_ref(), &hash);
+        assert_eq!(a.memory_stats().heap_bytes, 32);
+        assert_eq!(a.memory_stats().atoms, 2);
+
+        // concat producing the same bytes is deduplicated too
+        let half = a.new_atom(&hash[..16]).unwrap();
+        let concat = a.new_concat(32, &[half, half]).unwrap();
+        assert_eq!(a.atom(concat).as_ref(), &hash);
+        assert_eq!(a.memory_stats().heap_bytes, 48);
+
+        // atoms below the threshold aren't interned
+        a.new_atom(&[0xff, 0xff, 0xff]).unwrap();
+        a.new_atom(&[0xff, 0xff, 0xff]).unwrap();
+        assert_eq!(a.memory_stats().heap_bytes, 54);
+        assert_eq!(
+            a.intern_stats(),
+            Some(InternStats {
+                deduplicated_atoms: 2,
+                deduplicated_bytes: 64,
+            })
+        );
+
+        // interned buffers are forgotten when they're freed
+        let checkpoint = a.checkpoint();
+        a.new_atom(b"foobarbaz").unwrap();
+        a.restore_checkpoint(&checkpoint);
+        a.new_atom(b"barfoobaz").unwrap();
+        let other2 = a.new_atom(b"foobarbaz").unwrap();
+        assert_eq!(a.atom(other2).as_ref(), b"foobarbaz");
+        assert_eq!(a.memory_stats().heap_bytes, 72);
+
+        a.reset();
+        a.new_atom(b"barfoobaz").unwrap();
+        let atom = a.new_atom(b"foobarbaz").unwrap();
+        assert_eq!(a.atom(atom).as_ref(), b"foobarbaz");
+        assert_eq!(a.intern_stats().unwrap().deduplicated_atoms, 2);
+    }
+
+    #[test]
+    fn test_reset() {
+        let mut a = Allocator::new_limited(10);
+        let atom = a.new_atom(b"foobar").unwrap();
+        a.new_pair(atom, atom).unwrap();
+        a.new_small_number(1337).unwrap();
+        let capacity = (a.u8_vec.capacity(), a.pair_vec.capacity());
+        assert_eq!(a.generation(), 0);
+
+        a.reset();
+        assert_eq!(a.generation(), 1);
+        assert_eq!(a.memory_stats(), Allocator::new().memory_stats());
+        assert_eq!(capacity, (a.u8_vec.capacity(), a.pair_vec.capacity()));
+
+        // the heap limit still applies, but the old allocations no longer
+        // count against it
+        let atom2 = a.new_atom(b"foobar").unwrap();
+        assert_eq!(a.atom(atom2).as_ref(), b"foobar");
+        assert_eq!(
+            a.new_atom(b"foobar").unwrap_err().1,
+            "out of memory: requested 6 bytes with 6 of 9 bytes in use"
+        );
+    }
+
+    #[cfg(feature = "checked-nodeptr")]
+    #[test]
+    #[should_panic(expected = "belongs to a different allocator")]
+    fn test_checked_nodeptr_other_allocator() {
+        let mut a1 = Allocator::new();
+        let a2 = Allocator::new();
+        let atom = a1.new_atom(b"foobar").unwrap();
+        a2.atom(atom);
+    }
+
+    #[cfg(feature = "checked-nodeptr")]
+    #[test]
+    #[should_panic(expected = "was created before the allocator was reset")]
+    fn test_checked_nodeptr_reset() {
+        let mut a = Allocator::new();
+        let atom = a.new_atom(b"foobar").unwrap();
+        let pair = a.new_pair(atom, atom).unwrap();
+        a.reset();
+        a.new_pair(NodePtr::NIL, NodePtr::NIL).unwrap();
+        a.sexp(pair);
+    }
+
+    #[cfg(feature = "checked-nodeptr")]
+    #[test]
+    #[should_panic(expected = "was freed by restoring a checkpoint")]
+    fn test_checked_nodeptr_checkpoint() {
+        let mut a = Allocator::new();
+        let checkpoint = a.checkpoint();
+        let pair = a.new_pair(NodePtr::NIL, NodePtr::NIL).unwrap();
+        a.restore_checkpoint(&checkpoint);
+        a.sexp(pair);
+    }
+
+    #[cfg(feature = "checked-nodeptr")]
+    #[test]
+    fn test_checked_nodeptr_small_atoms() {
+        // small atoms are not tied to any allocator
+        let mut a1 = Allocator::new();
+        let a2 = Allocator::new();
+        let atom = a1.new_small_number(1337).unwrap();
+        assert_eq!(a2.small_number(atom), Some(1337));
+        assert_eq!(a1.nil(), a2.nil());
+    }
+
+    #[test]
+    #[should_panic(expected = "checkpoint is from before the allocator was reset")]
+    fn test_restore_checkpoint_after_reset() {
+        let mut a = Allocator::new();
+        a.new_atom(b"foobar").unwrap();
+        let checkpoint = a.checkpoint();
+        a.reset();
+        a.new_atom(b"foobar").unwrap();
+        a.new_atom(b"foobar").unwrap();
+        a.restore_checkpoint(&checkpoint);
+    }
+
     #[test]
     fn test_checkpoints() {
         let mut a = Allocator::new();
@@ -1596,6 +2845,23 @@ c6c886f6b57ec72a6178288c47c33577\
         assert_eq!(a.atom_len(atom), expected);
     }
 
+    #[rstest]
+    #[case("")]
+    #[case("01")]
+    #[case("7fffff")]
+    #[case("00ffffff")]
+    #[case("ff")]
+    #[case("0102030405060708090a")]
+    fn test_atom_raw(#[case] value: &str) {
+        let value = hex::decode(value).unwrap();
+        let mut a = Allocator::new();
+        let atom = a.new_atom(&value).unwrap();
+        let mut buf = [0; 4];
+        let (ptr, len) = a.atom_raw(atom, &mut buf);
+        let bytes = unsafe { std::slice::from_raw_parts(ptr, len) };
+        assert_eq!(bytes, value.as_slice());
+    }
+
     #[rstest]
     #[case(
         "\
@@ -1851,4 +3117,58 @@ c6c886f6b57ec72a6178288c47c33577\
         let ptr = a.new_number(num).unwrap();
         assert_eq!(a.atom(ptr).as_ref(), buf);
     }
+
+    #[rstest]
+    #[case("()", "()", true)]
+    #[case("()", "0", true)]
+    #[case("1", "0x01", true)]
+    #[case("1", "0x0001", false)]
+    #[case("\"foo\"", "0x666f6f", true)]
+    #[case("(1 2 3)", "(1 2 3)", true)]
+    #[case("(1 2 3)", "(1 2 4)", false)]
+    #[case("(1 2 3)", "(1 2 3 4)", false)]
+    #[case("(1 2 . 3)", "(1 2 3)", false)]
+    #[case("((1 . 2) . 3)", "((1 . 2) . 3)", true)]
+    #[case("((1 . 2) . 3)", "(1 . (2 . 3))", false)]
+    #[case("(())", "()", false)]
+    fn test_tree_eq(#[case] lhs: &str, #[case] rhs: &str, #[case] expected: bool) {
+        use crate::text::parse_clvm_text;
+        let mut a = Allocator::new();
+        let lhs = parse_clvm_text(&mut a, lhs).unwrap();
+        let rhs = parse_clvm_text(&mut a, rhs).unwrap();
+        assert_eq!(a.tree_eq(lhs, rhs), expected);
+        assert_eq!(a.tree_eq(rhs, lhs), expected);
+        assert!(a.tree_eq(lhs, lhs));
+    }
+
+    #[test]
+    fn test_tree_eq_limited() {
+        let mut a = Allocator::new();
+        // two distinct lists of 100 items each
+        let mut lhs = NodePtr::NIL;
+        let mut rhs = NodePtr::NIL;
+        for i in 0..100 {
+            let item = a.new_small_number(i).unwrap();
+            lhs = a.new_pair(item, lhs).unwrap();
+            rhs = a.new_pair(item, rhs).unwrap();
+        }
+        // every pair is compared, but the items (and the terminator) are shared
+        assert_eq!(a.tree_eq_limited(lhs, rhs, 100), Ok(true));
+        assert_eq!(
+            a.tree_eq_limited(lhs, rhs, 99).unwrap_err().1,
+            "tree_eq node limit exceeded"
+        );
+
+        // identical nodes don't count against the limit
+        assert_eq!(a.tree_eq_limited(lhs, lhs, 0), Ok(true));
+
+        // a deep tree doesn't overflow the stack
+        let mut deep1 = NodePtr::NIL;
+        let mut deep2 = a.one();
+        for _ in 0..1_000_000 {
+            deep1 = a.new_pair(deep1, NodePtr::NIL).unwrap();
+            deep2 = a.new_pair(deep2, NodePtr::NIL).unwrap();
+        }
+        assert!(!a.tree_eq(deep1, deep2));
+    }
 }
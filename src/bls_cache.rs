@@ -0,0 +1,215 @@
+use std::borrow::Borrow;
+use std::num::NonZeroUsize;
+use std::sync::Mutex;
+
+use crate::sha256::Sha256;
+use chia_bls::{aggregate_verify_gt, hash_to_g2, GTElement, PublicKey, Signature};
+use lru::LruCache;
+
+/// Counters of the lookups made in a `BlsCache`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BlsCacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    /// the number of pairings currently in the cache
+    pub len: usize,
+    pub capacity: usize,
+}
+
+#[derive(Debug)]
+struct Inner {
+    // sha256(pubkey + message) -> pairing
+    cache: LruCache<[u8; 32], GTElement>,
+    hits: u64,
+    misses: u64,
+}
+
+/// A least-recently-used cache of pairings of public keys and (augmented)
+/// messages. When the same (public key, message) pairs are verified
+/// repeatedly, e.g. when a mempool item is later included in a block, the
+/// pairings don't have to be computed again. Pass it to
+/// `ChiaDialect::with_bls_cache()` to have the bls_verify operator use it.
+#[derive(Debug)]
+pub struct BlsCache {
+    inner: Mutex<Inner>,
+}
+
+impl Default for BlsCache {
+    fn default() -> Self {
+        Self::new(NonZeroUsize::new(50000).unwrap())
+    }
+}
+
+impl BlsCache {
+    pub fn new(capacity: NonZeroUsize) -> Self {
+        Self {
+            inner: Mutex::new(Inner {
+                cache: LruCache::new(capacity),
+                hits: 0,
+                misses: 0,
+            }),
+        }
+    }
+
+    /// Verifies an aggregate signature of the (public key, message) pairs,
+    /// using the augmented scheme (i.e. the message is prefixed by the public
+    /// key). The result is the same as chia_bls::aggregate_verify()
+    pub fn aggregate_verify<Pk: Borrow<PublicKey>, Msg: AsRef<[u8]>>(
+        &self,
+        pks_msgs: impl IntoIterator<Item = (Pk, Msg)>,
+        sig: &Signature,
+    ) -> bool {
+        let iter = pks_msgs.into_iter().map(|(pk, msg)| -> GTElement {
+            let mut aug_msg = pk.borrow().to_bytes().to_vec();
+            aug_msg.extend_from_slice(msg.as_ref());
+            let mut hasher = Sha256::new();
+            hasher.update(&aug_msg);
+            let key: [u8; 32] = hasher.finalize();
+
+            {
+                let mut inner = self.inner.lock().expect("bls cache");
+                if let Some(pairing) = inner.cache.get(&key).cloned() {
+                    inner.hits += 1;
+                    return pairing;
+                }
+                inner.misses += 1;
+            }
+
+            // we don't hold the lock while computing the pairing
+            let pairing = hash_to_g2(&aug_msg).pair(pk.borrow());
+            self.inner
+                .lock()
+                .expect("bls cache")
+                .cache
+                .put(key, pairing.clone());
+            pairing
+        });
+        aggregate_verify_gt(sig, iter)
+    }
+
+    pub fn stats(&self) -> BlsCacheStats {
+        let inner = self.inner.lock().expect("bls cache");
+        BlsCacheStats {
+            hits: inner.hits,
+            misses: inner.misses,
+            len: inner.cache.len(),
+            capacity: inner.cache.cap().get(),
+        }
+    }
+
+    /// remove all pairings from the cache and reset the counters
+    pub fn clear(&self) {
+        let mut inner = self.inner.lock().expect("bls cache");
+        inner.cache.clear();
+        inner.hits = 0;
+        inner.misses = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::allocator::{Allocator, NodePtr};
+    use crate::chia_dialect::ChiaDialect;
+    use crate::run_program::run_program;
+    use chia_bls::{aggregate, sign, SecretKey};
+    use std::sync::Arc;
+
+    fn keys(count: u8) -> Vec<SecretKey> {
+        (0..count).map(|i| SecretKey::from_seed(&[i; 32])).collect()
+    }
+
+    #[test]
+    fn test_aggregate_verify() {
+        let cache = BlsCache::new(NonZeroUsize::new(3).unwrap());
+        let sks = keys(4);
+        let msgs: Vec<Vec<u8>> = (0..4_u8).map(|i| vec![i; 10]).collect();
+        let pks_msgs: Vec<(PublicKey, &[u8])> = sks
+            .iter()
+            .zip(&msgs)
+            .map(|(sk, msg)| (sk.public_key(), msg.as_slice()))
+            .collect();
+
+        let sig = aggregate(sks.iter().zip(&msgs).take(2).map(|(sk, msg)| sign(sk, msg)));
+        assert!(cache.aggregate_verify(pks_msgs[0..2].iter().cloned(), &sig));
+        assert_eq!(
+            cache.stats(),
+            BlsCacheStats {
+                hits: 0,
+                misses: 2,
+                len: 2,
+                capacity: 3
+            }
+        );
+
+        // the pairings are cached now
+        assert!(cache.aggregate_verify(pks_msgs[0..2].iter().cloned(), &sig));
+        assert_eq!(cache.stats().hits, 2);
+        assert_eq!(cache.stats().misses, 2);
+
+        // the wrong signature fails, whether the pairings are cached or not
+        assert!(!cache.aggregate_verify(pks_msgs[0..3].iter().cloned(), &sig));
+        assert_eq!(cache.stats().hits, 4);
+        assert_eq!(cache.stats().misses, 3);
+
+        // the least recently used pairing is evicted
+        let sig = sign(&sks[3], &msgs[3]);
+        assert!(cache.aggregate_verify(pks_msgs[3..4].iter().cloned(), &sig));
+        assert_eq!(cache.stats().len, 3);
+        let sig = sign(&sks[0], &msgs[0]);
+        assert!(cache.aggregate_verify(pks_msgs[0..1].iter().cloned(), &sig));
+        assert_eq!(cache.stats().misses, 5);
+
+        cache.clear();
+        assert_eq!(
+            cache.stats(),
+            BlsCacheStats {
+                hits: 0,
+                misses: 0,
+                len: 0,
+                capacity: 3
+            }
+        );
+    }
+
+    #[test]
+    fn test_bls_verify_operator() {
+        let cache = Arc::new(BlsCache::default());
+        let dialect = ChiaDialect::with_bls_cache(0, cache.clone());
+        let sk = SecretKey::from_seed(&[1; 32]);
+        let msg = b"foobar";
+        let sig = sign(&sk, msg);
+
+        let mut a = Allocator::new();
+        // (bls_verify 2 5 11), with the arguments in the environment
+        let program = crate::text::parse_clvm_text(&mut a, "(bls_verify 2 5 11)").unwrap();
+        let sig = a.new_g2(sig).unwrap();
+        let pk = a.new_g1(sk.public_key()).unwrap();
+        let msg = a.new_atom(msg).unwrap();
+        let mut env = a.new_pair(msg, NodePtr::NIL).unwrap();
+        env = a.new_pair(pk, env).unwrap();
+        env = a.new_pair(sig, env).unwrap();
+
+        let uncached =
+            run_program(&mut a, &ChiaDialect::new(0), program, env, 10_000_000_000).unwrap();
+        let first = run_program(&mut a, &dialect, program, env, 10_000_000_000).unwrap();
+        let second = run_program(&mut a, &dialect, program, env, 10_000_000_000).unwrap();
+        // caching doesn't affect the cost
+        assert_eq!(first.0, uncached.0);
+        assert_eq!(second.0, uncached.0);
+        assert_eq!(cache.stats().misses, 1);
+        assert_eq!(cache.stats().hits, 1);
+
+        // an invalid signature still fails
+        let wrong_msg = a.new_atom(b"barfoo").unwrap();
+        let mut env = a.new_pair(wrong_msg, NodePtr::NIL).unwrap();
+        env = a.new_pair(pk, env).unwrap();
+        env = a.new_pair(sig, env).unwrap();
+        assert_eq!(
+            run_program(&mut a, &dialect, program, env, 10_000_000_000)
+                .unwrap_err()
+                .1,
+            "bls_verify failed"
+        );
+    }
+}
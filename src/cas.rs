@@ -0,0 +1,154 @@
+// A content-addressed store of nodes, mapping tree hashes to nodes in an
+// Allocator. This lets callers that assemble many programs from a set of known
+// puzzles share those subtrees, rather than deserializing duplicates.
+
+use crate::allocator::{Allocator, NodePtr};
+use crate::serde::{parse_triples, tree_hash, Bytes32, ParsedTriple};
+use std::collections::HashMap;
+use std::io;
+use std::io::Cursor;
+
+/// Maps tree hashes to nodes that have been explicitly registered. The nodes
+/// refer to a specific Allocator, and the store must only be used with that
+/// allocator (and not after it's been reset or restored to an earlier
+/// checkpoint).
+#[derive(Debug, Default, Clone)]
+pub struct NodeStore {
+    nodes: HashMap<Bytes32, NodePtr>,
+}
+
+enum BuildOp {
+    Visit(usize),
+    Cons,
+}
+
+impl NodeStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers the node under its tree hash, and returns the hash. Only the
+    /// node itself is registered, not its subtrees.
+    pub fn register(&mut self, a: &Allocator, node: NodePtr) -> Bytes32 {
+        let hash = tree_hash(a, node);
+        self.nodes.insert(hash, node);
+        hash
+    }
+
+    pub fn get_by_hash(&self, hash: &Bytes32) -> Option<NodePtr> {
+        self.nodes.get(hash).copied()
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    /// Deserializes a CLVM tree (without back references). Any subtree whose
+    /// tree hash has been registered is replaced by the registered node,
+    /// without allocating it again.
+    pub fn deserialize(&self, a: &mut Allocator, blob: &[u8]) -> io::Result<NodePtr> {
+        let (triples, hashes) = parse_triples(&mut Cursor::new(blob), !self.nodes.is_empty())?;
+
+        let mut values = Vec::<NodePtr>::new();
+        let mut ops = vec![BuildOp::Visit(0)];
+        while let Some(op) = ops.pop() {
+            match op {
+                BuildOp::Visit(idx) => {
+                    if let Some(node) = hashes.as_ref().and_then(|h| self.get_by_hash(&h[idx])) {
+                        values.push(node);
+                        continue;
+                    }
+                    match triples[idx] {
+                        ParsedTriple::Atom {
+                            start,
+                            end,
+                            atom_offset,
+                        } => {
+                            let buf = &blob[(start as usize + atom_offset as usize)..end as usize];
+                            values.push(a.new_atom(buf)?);
+                        }
+                        ParsedTriple::Pair { right_index, .. } => {
+                            ops.push(BuildOp::Cons);
+                            ops.push(BuildOp::Visit(right_index as usize));
+                            ops.push(BuildOp::Visit(idx + 1));
+                        }
+                    }
+                }
+                BuildOp::Cons => {
+                    let rest = values.pop().expect("internal error, missing rest");
+                    let first = values.pop().expect("internal error, missing first");
+                    values.push(a.new_pair(first, rest)?);
+                }
+            }
+        }
+        Ok(values.pop().expect("internal error, missing root"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::allocator::SExp;
+    use crate::serde::{node_from_bytes, node_to_bytes};
+    use crate::text::parse_clvm_text;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case("()")]
+    #[case("\"foobar\"")]
+    #[case("(1 2 3)")]
+    #[case("((1 . 2) (\"foobar\" 4) 5 . 6)")]
+    fn test_deserialize_empty_store(#[case] text: &str) {
+        let mut a = Allocator::new();
+        let node = parse_clvm_text(&mut a, text).unwrap();
+        let blob = node_to_bytes(&a, node).unwrap();
+        let store = NodeStore::new();
+        let new_node = store.deserialize(&mut a, &blob).unwrap();
+        assert!(a.tree_eq(node, new_node));
+    }
+
+    #[test]
+    fn test_reuse() {
+        let mut a = Allocator::new();
+        let puzzle = parse_clvm_text(&mut a, "(a (q 2 (i 5 (q . 11) (q . 23)) 1) 1)").unwrap();
+        let mut store = NodeStore::new();
+        let hash = store.register(&a, puzzle);
+        assert_eq!(hash, tree_hash(&a, puzzle));
+        assert_eq!(store.get_by_hash(&hash), Some(puzzle));
+        assert_eq!(store.len(), 1);
+
+        // a list of spends, both using the puzzle
+        let mut b = Allocator::new();
+        let spends = parse_clvm_text(
+            &mut b,
+            "(((a (q 2 (i 5 (q . 11) (q . 23)) 1) 1) 1 2) ((a (q 2 (i 5 (q . 11) (q . 23)) 1) 1) 3 4))",
+        )
+        .unwrap();
+        let blob = node_to_bytes(&b, spends).unwrap();
+
+        let before = a.memory_stats().pairs;
+        let node = store.deserialize(&mut a, &blob).unwrap();
+        // the puzzle is shared, only the spine of the list and the spends
+        // (excluding the puzzles) were allocated
+        assert_eq!(a.memory_stats().pairs - before, 2 + 2 * 3);
+
+        let expected = node_from_bytes(&mut a, &blob).unwrap();
+        assert!(a.tree_eq(node, expected));
+        let SExp::Pair(first, rest) = a.sexp(node) else {
+            panic!("expected pair");
+        };
+        let SExp::Pair(second, _) = a.sexp(rest) else {
+            panic!("expected pair");
+        };
+        for spend in [first, second] {
+            let SExp::Pair(p, _) = a.sexp(spend) else {
+                panic!("expected pair");
+            };
+            assert_eq!(p, puzzle);
+        }
+    }
+}
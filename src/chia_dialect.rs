@@ -1,21 +1,44 @@
 use crate::allocator::{Allocator, NodePtr};
+#[cfg(feature = "bls-cache")]
+use crate::bls_cache::BlsCache;
+#[cfg(feature = "bls-cache")]
+use crate::bls_ops::op_bls_verify_cached;
 use crate::bls_ops::{
     op_bls_g1_multiply, op_bls_g1_negate, op_bls_g1_subtract, op_bls_g2_add, op_bls_g2_multiply,
     op_bls_g2_negate, op_bls_g2_subtract, op_bls_map_to_g1, op_bls_map_to_g2,
-    op_bls_pairing_identity, op_bls_verify,
+    op_bls_pairing_identity, op_bls_verify, BLS_G1_MULTIPLY_BASE_COST, BLS_G1_NEGATE_BASE_COST,
+    BLS_G1_SUBTRACT_BASE_COST, BLS_G2_ADD_BASE_COST, BLS_G2_MULTIPLY_BASE_COST,
+    BLS_G2_NEGATE_BASE_COST, BLS_G2_SUBTRACT_BASE_COST, BLS_MAP_TO_G1_BASE_COST,
+    BLS_MAP_TO_G2_BASE_COST, BLS_PAIRING_BASE_COST,
 };
-use crate::core_ops::{op_cons, op_eq, op_first, op_if, op_listp, op_raise, op_rest};
-use crate::cost::Cost;
+use crate::core_ops::{
+    op_cons, op_eq, op_first, op_if, op_listp, op_raise, op_rest, CONS_COST, EQ_BASE_COST,
+    FIRST_COST, IF_COST, LISTP_COST, REST_COST,
+};
+use crate::cost::{Cost, CostTable};
+use crate::curry_ops::{op_curry, op_uncurry, CURRY_BASE_COST, UNCURRY_BASE_COST};
 use crate::dialect::{Dialect, OperatorSet};
 use crate::err_utils::err;
-use crate::keccak256_ops::op_keccak256;
+use crate::error::ErrorCode;
+use crate::hash_ops::{op_sha3_256, op_sha512_256, SHA3_256_BASE_COST, SHA512_256_BASE_COST};
+use crate::keccak256_ops::{op_keccak256, KECCAK256_BASE_COST};
 use crate::more_ops::{
     op_add, op_all, op_any, op_ash, op_coinid, op_concat, op_div, op_divmod, op_gr, op_gr_bytes,
     op_logand, op_logior, op_lognot, op_logxor, op_lsh, op_mod, op_modpow, op_multiply, op_not,
     op_point_add, op_pubkey_for_exp, op_sha256, op_strlen, op_substr, op_subtract, op_unknown,
+    ARITH_BASE_COST, ASHIFT_BASE_COST, BOOL_BASE_COST, COINID_COST, CONCAT_BASE_COST,
+    DIVMOD_BASE_COST, DIV_BASE_COST, GRS_BASE_COST, GR_BASE_COST, LOGNOT_BASE_COST, LOG_BASE_COST,
+    LSHIFT_BASE_COST, MODPOW_BASE_COST, MUL_BASE_COST, POINT_ADD_BASE_COST, PUBKEY_BASE_COST,
+    SHA256_BASE_COST, STRLEN_BASE_COST, SUBSTR_COST,
 };
 use crate::reduction::Response;
-use crate::secp_ops::{op_secp256k1_verify, op_secp256r1_verify};
+use crate::run_program::{APPLY_COST, GUARD_COST, QUOTE_COST};
+use crate::secp_ops::{
+    op_secp256k1_verify, op_secp256r1_verify, SECP256K1_VERIFY_COST, SECP256R1_VERIFY_COST,
+};
+use lazy_static::lazy_static;
+#[cfg(feature = "bls-cache")]
+use std::sync::Arc;
 
 // unknown operators are disallowed
 // (otherwise they are no-ops with well defined cost)
@@ -33,10 +56,237 @@ pub const ENABLE_KECCAK_OPS_OUTSIDE_GUARD: u32 = 0x0100;
 // should be set for blocks past the activation height.
 pub const ENABLE_KECCAK: u32 = 0x0200;
 
+// enables softfork extension 2, with the sha3_256 and sha512_256 operators.
+// This is a soft-fork and should be set for blocks past the activation height.
+pub const ENABLE_HASH_OPS: u32 = 0x0400;
+
+// enables softfork extension 3, with the experimental curry and uncurry
+// operators. This is not scheduled to activate, it's meant for evaluating
+// proposals for native currying
+pub const ENABLE_CURRY_OPS: u32 = 0x1000;
+
+// When set, atoms may not be larger than MAX_ATOM_SIZE. This is enforced by
+// the Allocator; run_program() applies the limit to it when the dialect has
+// this flag set, and restores the allocator's previous limit when it returns
+pub const LIMIT_ATOM_SIZE: u32 = 0x0800;
+
+// the max atom size when LIMIT_ATOM_SIZE is set
+pub const MAX_ATOM_SIZE: usize = 10 * 1024 * 1024;
+
 // The default mode when running grnerators in mempool-mode (i.e. the stricter
 // mode)
 pub const MEMPOOL_MODE: u32 = NO_UNKNOWN_OPS | LIMIT_HEAP;
 
+/// The names of the operators in the Chia dialect, and their opcodes. When
+/// names alias the same opcode, the first one is canonical. This includes the
+/// operators only available in softfork extensions.
+pub const KEYWORDS: &[(u32, &str)] = &[
+    (1, "q"),
+    (2, "a"),
+    (3, "i"),
+    (4, "c"),
+    (5, "f"),
+    (6, "r"),
+    (7, "l"),
+    (8, "x"),
+    (9, "="),
+    (10, ">s"),
+    (11, "sha256"),
+    (12, "substr"),
+    (13, "strlen"),
+    (14, "concat"),
+    (16, "+"),
+    (17, "-"),
+    (18, "*"),
+    (19, "/"),
+    (20, "divmod"),
+    (21, ">"),
+    (22, "ash"),
+    (23, "lsh"),
+    (24, "logand"),
+    (25, "logior"),
+    (26, "logxor"),
+    (27, "lognot"),
+    (29, "point_add"),
+    (30, "pubkey_for_exp"),
+    (32, "not"),
+    (33, "any"),
+    (34, "all"),
+    (36, "softfork"),
+    (48, "coinid"),
+    (29, "g1_add"),
+    (49, "g1_subtract"),
+    (50, "g1_multiply"),
+    (51, "g1_negate"),
+    (52, "g2_add"),
+    (53, "g2_subtract"),
+    (54, "g2_multiply"),
+    (55, "g2_negate"),
+    (56, "g1_map"),
+    (57, "g2_map"),
+    (58, "bls_pairing_identity"),
+    (59, "bls_verify"),
+    (60, "modpow"),
+    (61, "%"),
+    (0x13d61f00, "secp256k1_verify"),
+    (0x1c3a8f00, "secp256r1_verify"),
+    // keccak256 is only available in softfork extension 1 (unless
+    // ENABLE_KECCAK_OPS_OUTSIDE_GUARD is set). The operators that depend on
+    // the extension are filtered out of OP_TABLES, see op_table()
+    (62, "keccak256"),
+    // these are only available in softfork extension 2
+    (63, "sha3_256"),
+    (64, "sha512_256"),
+    // these are only available in softfork extension 3
+    (65, "curry"),
+    (66, "uncurry"),
+];
+
+lazy_static! {
+    // the subsets of KEYWORDS available with different extensions. Indexed by
+    // whether keccak256 is enabled (bit 0), whether the hash operators are
+    // enabled (bit 1) and whether the curry operators are enabled (bit 2)
+    static ref OP_TABLES: [Vec<(u32, &'static str)>; 8] = std::array::from_fn(|i| {
+        KEYWORDS
+            .iter()
+            .filter(|(op, _)| match op {
+                62 => (i & 1) != 0,
+                63 | 64 => (i & 2) != 0,
+                65 | 66 => (i & 4) != 0,
+                _ => true,
+            })
+            .copied()
+            .collect()
+    });
+}
+
+type OpFn = fn(&mut Allocator, NodePtr, Cost) -> Response;
+
+// the operators with a single byte opcode that are always available, indexed
+// by opcode. Looking them up here is cheaper than a match on the opcode.
+// Operators that depend on the flags or the extension are not in the table,
+// see dispatch()
+static OP_FUNCTIONS: [Option<OpFn>; 256] = {
+    let mut t: [Option<OpFn>; 256] = [None; 256];
+    // 1 = quote
+    // 2 = apply
+    t[3] = Some(op_if);
+    t[4] = Some(op_cons);
+    t[5] = Some(op_first);
+    t[6] = Some(op_rest);
+    t[7] = Some(op_listp);
+    t[8] = Some(op_raise);
+    t[9] = Some(op_eq);
+    t[10] = Some(op_gr_bytes);
+    t[11] = Some(op_sha256);
+    t[12] = Some(op_substr);
+    t[13] = Some(op_strlen);
+    t[14] = Some(op_concat);
+    // 15 ---
+    t[16] = Some(op_add);
+    t[17] = Some(op_subtract);
+    t[18] = Some(op_multiply);
+    t[19] = Some(op_div);
+    t[20] = Some(op_divmod);
+    t[21] = Some(op_gr);
+    t[22] = Some(op_ash);
+    t[23] = Some(op_lsh);
+    t[24] = Some(op_logand);
+    t[25] = Some(op_logior);
+    t[26] = Some(op_logxor);
+    t[27] = Some(op_lognot);
+    // 28 ---
+    t[29] = Some(op_point_add);
+    t[30] = Some(op_pubkey_for_exp);
+    // 31 ---
+    t[32] = Some(op_not);
+    t[33] = Some(op_any);
+    t[34] = Some(op_all);
+    // 35 ---
+    // 36 = softfork
+    t[48] = Some(op_coinid);
+    t[49] = Some(op_bls_g1_subtract);
+    t[50] = Some(op_bls_g1_multiply);
+    t[51] = Some(op_bls_g1_negate);
+    t[52] = Some(op_bls_g2_add);
+    t[53] = Some(op_bls_g2_subtract);
+    t[54] = Some(op_bls_g2_multiply);
+    t[55] = Some(op_bls_g2_negate);
+    t[56] = Some(op_bls_map_to_g1);
+    t[57] = Some(op_bls_map_to_g2);
+    t[58] = Some(op_bls_pairing_identity);
+    t[59] = Some(op_bls_verify);
+    t[60] = Some(op_modpow);
+    t[61] = Some(op_mod);
+    t
+};
+
+/// An operator in the Chia dialect, see `ChiaDialect::operators()`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct OpInfo {
+    pub opcode: u32,
+    pub name: &'static str,
+    /// The fixed part of the cost of calling the operator. Most operators also
+    /// charge per argument, per byte of their arguments and for the atom they
+    /// return. For softfork, this is the cost of entering the guard, in
+    /// addition to the cost it declares.
+    pub base_cost: Cost,
+}
+
+// the fixed cost charged by each operator in KEYWORDS
+fn base_cost(opcode: u32) -> Cost {
+    match opcode {
+        1 => QUOTE_COST,
+        2 => APPLY_COST,
+        3 => IF_COST,
+        4 => CONS_COST,
+        5 => FIRST_COST,
+        6 => REST_COST,
+        7 => LISTP_COST,
+        // raise always fails, so it never charges anything
+        8 => 0,
+        9 => EQ_BASE_COST,
+        10 => GRS_BASE_COST,
+        11 => SHA256_BASE_COST,
+        12 => SUBSTR_COST,
+        13 => STRLEN_BASE_COST,
+        14 => CONCAT_BASE_COST,
+        16 | 17 => ARITH_BASE_COST,
+        18 => MUL_BASE_COST,
+        19 | 61 => DIV_BASE_COST,
+        20 => DIVMOD_BASE_COST,
+        21 => GR_BASE_COST,
+        22 => ASHIFT_BASE_COST,
+        23 => LSHIFT_BASE_COST,
+        24..=26 => LOG_BASE_COST,
+        27 => LOGNOT_BASE_COST,
+        29 => POINT_ADD_BASE_COST,
+        30 => PUBKEY_BASE_COST,
+        32..=34 => BOOL_BASE_COST,
+        36 => GUARD_COST,
+        48 => COINID_COST,
+        49 => BLS_G1_SUBTRACT_BASE_COST,
+        50 => BLS_G1_MULTIPLY_BASE_COST,
+        51 => BLS_G1_NEGATE_BASE_COST,
+        52 => BLS_G2_ADD_BASE_COST,
+        53 => BLS_G2_SUBTRACT_BASE_COST,
+        54 => BLS_G2_MULTIPLY_BASE_COST,
+        55 => BLS_G2_NEGATE_BASE_COST,
+        56 => BLS_MAP_TO_G1_BASE_COST,
+        57 => BLS_MAP_TO_G2_BASE_COST,
+        58 | 59 => BLS_PAIRING_BASE_COST,
+        60 => MODPOW_BASE_COST,
+        62 => KECCAK256_BASE_COST,
+        63 => SHA3_256_BASE_COST,
+        64 => SHA512_256_BASE_COST,
+        65 => CURRY_BASE_COST,
+        66 => UNCURRY_BASE_COST,
+        0x13d61f00 => SECP256K1_VERIFY_COST,
+        0x1c3a8f00 => SECP256R1_VERIFY_COST,
+        _ => unreachable!("no base cost for opcode {opcode}"),
+    }
+}
+
 fn unknown_operator(
     allocator: &mut Allocator,
     o: NodePtr,
@@ -45,7 +295,7 @@ fn unknown_operator(
     max_cost: Cost,
 ) -> Response {
     if (flags & NO_UNKNOWN_OPS) != 0 {
-        err(o, "unimplemented operator")
+        err(o, ErrorCode::InvalidOperator, "unimplemented operator")
     } else {
         op_unknown(allocator, o, args, max_cost)
     }
@@ -53,16 +303,74 @@ fn unknown_operator(
 
 pub struct ChiaDialect {
     flags: u32,
+    cost_table: Option<CostTable>,
+    #[cfg(feature = "bls-cache")]
+    bls_cache: Option<Arc<BlsCache>>,
 }
 
 impl ChiaDialect {
     pub fn new(flags: u32) -> ChiaDialect {
-        ChiaDialect { flags }
+        ChiaDialect {
+            flags,
+            cost_table: None,
+            #[cfg(feature = "bls-cache")]
+            bls_cache: None,
+        }
     }
-}
 
-impl Dialect for ChiaDialect {
-    fn op(
+    /// the costs of the operators in the cost table are overridden. This is
+    /// meant for simulations of alternative cost schedules
+    pub fn with_cost_table(flags: u32, cost_table: CostTable) -> ChiaDialect {
+        ChiaDialect {
+            flags,
+            cost_table: Some(cost_table),
+            #[cfg(feature = "bls-cache")]
+            bls_cache: None,
+        }
+    }
+
+    /// the bls_verify operator will look up (and store) pairings in the cache
+    #[cfg(feature = "bls-cache")]
+    pub fn with_bls_cache(flags: u32, cache: Arc<BlsCache>) -> ChiaDialect {
+        ChiaDialect {
+            flags,
+            cost_table: None,
+            bls_cache: Some(cache),
+        }
+    }
+
+    /// The softfork extensions enabled by the flags, and the operator sets
+    /// they enable. Other extensions are treated as unknown: they fail in
+    /// mempool mode and are skipped in consensus mode.
+    pub fn extensions(&self) -> Vec<(u32, OperatorSet)> {
+        (0..=3)
+            .map(|ext| (ext, self.softfork_extension(ext)))
+            .filter(|(_, set)| *set != OperatorSet::Default)
+            .collect()
+    }
+
+    /// The operators available with the specified extensions, like
+    /// `op_table()`, along with their costs. If this dialect has a cost table,
+    /// its costs take precedence.
+    pub fn operators(&self, extensions: OperatorSet) -> Vec<OpInfo> {
+        self.op_table(extensions)
+            .iter()
+            .map(|(opcode, name)| OpInfo {
+                opcode: *opcode,
+                name,
+                base_cost: match &self.cost_table {
+                    // quote, apply and softfork are charged by the
+                    // interpreter, the table doesn't affect them
+                    Some(table) if !matches!(opcode, 1 | 2 | 36) => table
+                        .get(*opcode)
+                        .map_or(base_cost(*opcode), |cost| cost.base),
+                    _ => base_cost(*opcode),
+                },
+            })
+            .collect()
+    }
+
+    fn dispatch(
         &self,
         allocator: &mut Allocator,
         o: NodePtr,
@@ -80,6 +388,12 @@ impl Dialect for ChiaDialect {
 
                 // Keccak is allowed as if it were a default operator, inside of the softfork guard.
                 OperatorSet::Keccak => ENABLE_KECCAK_OPS_OUTSIDE_GUARD,
+
+                // the hash operators are only enabled by the extension, see below
+                OperatorSet::Hash => 0,
+
+                // and so are the curry operators
+                OperatorSet::Curry => 0,
             };
 
         let op_len = allocator.atom_len(o);
@@ -116,64 +430,47 @@ impl Dialect for ChiaDialect {
         let Some(op) = allocator.small_number(o) else {
             return unknown_operator(allocator, o, argument_list, flags, max_cost);
         };
+        #[cfg(feature = "bls-cache")]
+        if op == 59 {
+            if let Some(cache) = &self.bls_cache {
+                return op_bls_verify_cached(allocator, argument_list, max_cost, cache);
+            }
+        }
+        // op is a single byte, so it's always in range of the table
+        if let Some(f) = OP_FUNCTIONS[op as usize] {
+            return f(allocator, argument_list, max_cost);
+        }
+        // the operators that depend on the flags or the extension
         let f = match op {
-            // 1 = quote
-            // 2 = apply
-            3 => op_if,
-            4 => op_cons,
-            5 => op_first,
-            6 => op_rest,
-            7 => op_listp,
-            8 => op_raise,
-            9 => op_eq,
-            10 => op_gr_bytes,
-            11 => op_sha256,
-            12 => op_substr,
-            13 => op_strlen,
-            14 => op_concat,
-            // 15 ---
-            16 => op_add,
-            17 => op_subtract,
-            18 => op_multiply,
-            19 => op_div,
-            20 => op_divmod,
-            21 => op_gr,
-            22 => op_ash,
-            23 => op_lsh,
-            24 => op_logand,
-            25 => op_logior,
-            26 => op_logxor,
-            27 => op_lognot,
-            // 28 ---
-            29 => op_point_add,
-            30 => op_pubkey_for_exp,
-            // 31 ---
-            32 => op_not,
-            33 => op_any,
-            34 => op_all,
-            // 35 ---
-            // 36 = softfork
-            48 => op_coinid,
-            49 => op_bls_g1_subtract,
-            50 => op_bls_g1_multiply,
-            51 => op_bls_g1_negate,
-            52 => op_bls_g2_add,
-            53 => op_bls_g2_subtract,
-            54 => op_bls_g2_multiply,
-            55 => op_bls_g2_negate,
-            56 => op_bls_map_to_g1,
-            57 => op_bls_map_to_g2,
-            58 => op_bls_pairing_identity,
-            59 => op_bls_verify,
-            60 => op_modpow,
-            61 => op_mod,
             62 if (flags & ENABLE_KECCAK_OPS_OUTSIDE_GUARD) != 0 => op_keccak256,
+            63 if extension == OperatorSet::Hash => op_sha3_256,
+            64 if extension == OperatorSet::Hash => op_sha512_256,
+            65 if extension == OperatorSet::Curry => op_curry,
+            66 if extension == OperatorSet::Curry => op_uncurry,
             _ => {
                 return unknown_operator(allocator, o, argument_list, flags, max_cost);
             }
         };
         f(allocator, argument_list, max_cost)
     }
+}
+
+impl Dialect for ChiaDialect {
+    fn op(
+        &self,
+        allocator: &mut Allocator,
+        o: NodePtr,
+        argument_list: NodePtr,
+        max_cost: Cost,
+        extension: OperatorSet,
+    ) -> Response {
+        match &self.cost_table {
+            Some(table) => table.apply(allocator, o, argument_list, max_cost, |a, max_cost| {
+                self.dispatch(a, o, argument_list, max_cost, extension)
+            }),
+            None => self.dispatch(allocator, o, argument_list, max_cost, extension),
+        }
+    }
 
     fn quote_kw(&self) -> u32 {
         1
@@ -199,8 +496,16 @@ impl Dialect for ChiaDialect {
             // This is to prevent submission of spends with keccak until the softfork activates.
             1 if (self.flags & ENABLE_KECCAK) != 0 => OperatorSet::Keccak,
 
-            // Extensions 2 and beyond are considered invalid by the mempool.
-            // However, all future extensions are valid in consensus mode and reserved for future softforks.
+            // Extension 2 is for the sha3_256 and sha512_256 operators.
+            2 if (self.flags & ENABLE_HASH_OPS) != 0 => OperatorSet::Hash,
+
+            // Extension 3 is for the experimental curry and uncurry operators.
+            3 if (self.flags & ENABLE_CURRY_OPS) != 0 => OperatorSet::Curry,
+
+            // Any other extension, including 1, 2 and 3 when their flags are
+            // not set, is unknown. Unknown extensions are considered invalid by
+            // the mempool (with NO_UNKNOWN_OPS). However, they are valid in
+            // consensus mode and reserved for future softforks.
             _ => OperatorSet::Default,
         }
     }
@@ -208,4 +513,302 @@ impl Dialect for ChiaDialect {
     fn allow_unknown_ops(&self) -> bool {
         (self.flags & NO_UNKNOWN_OPS) == 0
     }
+
+    fn max_atom_size(&self) -> Option<usize> {
+        ((self.flags & LIMIT_ATOM_SIZE) != 0).then_some(MAX_ATOM_SIZE)
+    }
+
+    fn op_table(&self, extensions: OperatorSet) -> &[(u32, &'static str)] {
+        let keccak = (self.flags & ENABLE_KECCAK_OPS_OUTSIDE_GUARD) != 0
+            || extensions == OperatorSet::Keccak;
+        let hash = extensions == OperatorSet::Hash;
+        let curry = extensions == OperatorSet::Curry;
+        &OP_TABLES[keccak as usize | ((hash as usize) << 1) | ((curry as usize) << 2)]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cost::OpCost;
+    use crate::reduction::Reduction;
+    use crate::test_ops::parse_exp;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case(0, OperatorSet::Default, false)]
+    #[case(0, OperatorSet::Bls, false)]
+    #[case(0, OperatorSet::Keccak, true)]
+    #[case(ENABLE_KECCAK, OperatorSet::Default, false)]
+    #[case(ENABLE_KECCAK_OPS_OUTSIDE_GUARD, OperatorSet::Default, true)]
+    #[case(ENABLE_HASH_OPS, OperatorSet::Hash, false)]
+    #[case(ENABLE_KECCAK_OPS_OUTSIDE_GUARD, OperatorSet::Hash, true)]
+    #[case(ENABLE_CURRY_OPS, OperatorSet::Curry, false)]
+    #[case(ENABLE_KECCAK_OPS_OUTSIDE_GUARD, OperatorSet::Curry, true)]
+    fn test_op_table(
+        #[case] flags: u32,
+        #[case] extensions: OperatorSet,
+        #[case] has_keccak: bool,
+    ) {
+        let dialect = ChiaDialect::new(flags);
+        assert_eq!(
+            dialect.opcode_for_name("keccak256", extensions),
+            has_keccak.then_some(62)
+        );
+        assert_eq!(
+            dialect.op_name(62, extensions),
+            has_keccak.then_some("keccak256")
+        );
+        let has_hash_ops = extensions == OperatorSet::Hash;
+        assert_eq!(
+            dialect.opcode_for_name("sha3_256", extensions),
+            has_hash_ops.then_some(63)
+        );
+        assert_eq!(
+            dialect.op_name(64, extensions),
+            has_hash_ops.then_some("sha512_256")
+        );
+        let has_curry_ops = extensions == OperatorSet::Curry;
+        assert_eq!(
+            dialect.opcode_for_name("curry", extensions),
+            has_curry_ops.then_some(65)
+        );
+        assert_eq!(
+            dialect.op_name(66, extensions),
+            has_curry_ops.then_some("uncurry")
+        );
+
+        // aliases map to the same opcode, but the opcode maps to the first name
+        assert_eq!(dialect.opcode_for_name("g1_add", extensions), Some(29));
+        assert_eq!(dialect.opcode_for_name("point_add", extensions), Some(29));
+        assert_eq!(dialect.op_name(29, extensions), Some("point_add"));
+
+        assert_eq!(
+            dialect.opcode_for_name("q", extensions),
+            Some(dialect.quote_kw())
+        );
+        assert_eq!(
+            dialect.opcode_for_name("a", extensions),
+            Some(dialect.apply_kw())
+        );
+        assert_eq!(
+            dialect.opcode_for_name("softfork", extensions),
+            Some(dialect.softfork_kw())
+        );
+        assert_eq!(
+            dialect.op_name(0x13d61f00, extensions),
+            Some("secp256k1_verify")
+        );
+        assert_eq!(dialect.op_name(15, extensions), None);
+        assert_eq!(dialect.opcode_for_name("foobar", extensions), None);
+    }
+
+    #[rstest]
+    #[case(0, &[(0, OperatorSet::Bls)])]
+    #[case(MEMPOOL_MODE, &[(0, OperatorSet::Bls)])]
+    #[case(ENABLE_KECCAK, &[(0, OperatorSet::Bls), (1, OperatorSet::Keccak)])]
+    #[case(
+        ENABLE_KECCAK | ENABLE_HASH_OPS | ENABLE_CURRY_OPS,
+        &[
+            (0, OperatorSet::Bls),
+            (1, OperatorSet::Keccak),
+            (2, OperatorSet::Hash),
+            (3, OperatorSet::Curry)
+        ]
+    )]
+    fn test_extensions(#[case] flags: u32, #[case] expected: &[(u32, OperatorSet)]) {
+        assert_eq!(ChiaDialect::new(flags).extensions(), expected);
+    }
+
+    #[rstest]
+    #[case(OperatorSet::Default)]
+    #[case(OperatorSet::Bls)]
+    #[case(OperatorSet::Keccak)]
+    #[case(OperatorSet::Hash)]
+    #[case(OperatorSet::Curry)]
+    fn test_operators(#[case] extensions: OperatorSet) {
+        let dialect = ChiaDialect::new(0);
+        let operators = dialect.operators(extensions);
+        let table: Vec<_> = operators.iter().map(|op| (op.opcode, op.name)).collect();
+        assert_eq!(table, dialect.op_table(extensions));
+        assert!(operators
+            .iter()
+            .all(|op| op.base_cost > 0 || op.name == "x"));
+    }
+
+    // these calls don't take any arguments or return any atoms that are
+    // charged for, so the base cost is the whole cost
+    #[rstest]
+    #[case("i", "(1 2 3)")]
+    #[case("c", "(1 2)")]
+    #[case("f", "((1 . 2))")]
+    #[case("r", "((1 . 2))")]
+    #[case("l", "(1)")]
+    #[case("=", "(() ())")]
+    #[case(">s", "(() ())")]
+    #[case("substr", "(\"foo\" 1)")]
+    #[case("strlen", "(())")]
+    #[case("concat", "()")]
+    #[case("+", "()")]
+    #[case("not", "(1)")]
+    #[case("any", "()")]
+    #[case("bls_pairing_identity", "()")]
+    fn test_base_cost(#[case] name: &str, #[case] args: &str) {
+        let dialect = ChiaDialect::new(0);
+        let info = dialect
+            .operators(OperatorSet::Default)
+            .into_iter()
+            .find(|op| op.name == name)
+            .expect("unknown operator");
+        let mut a = Allocator::new();
+        let op = a.new_number(info.opcode.into()).unwrap();
+        let args = parse_exp(&mut a, args);
+        let Reduction(cost, _) = dialect
+            .op(&mut a, op, args, 100_000_000, OperatorSet::Default)
+            .unwrap();
+        assert_eq!(cost, info.base_cost);
+    }
+
+    #[test]
+    fn test_operators_cost_table() {
+        let mut table = CostTable::new();
+        let cost = OpCost {
+            base: 1234,
+            per_arg: 0,
+            per_byte: 0,
+        };
+        table.set(1, cost);
+        table.set(11, cost);
+        let dialect = ChiaDialect::with_cost_table(0, table);
+        let operators = dialect.operators(OperatorSet::Default);
+        let base_cost = |opcode| {
+            operators
+                .iter()
+                .find(|op| op.opcode == opcode)
+                .unwrap()
+                .base_cost
+        };
+        // quote is charged by the interpreter, so it can't be overridden
+        assert_eq!(base_cost(1), 20);
+        assert_eq!(base_cost(11), 1234);
+        assert_eq!(base_cost(16), 99);
+    }
+
+    #[test]
+    fn test_op_functions() {
+        // the dispatch table has exactly the single byte operators that are
+        // always available, except for the ones the interpreter handles
+        let dialect = ChiaDialect::new(0);
+        let expected: Vec<u32> = dialect
+            .op_table(OperatorSet::Default)
+            .iter()
+            .map(|(op, _)| *op)
+            .filter(|op| *op < 256 && ![1, 2, 36].contains(op))
+            .collect();
+        for op in 0..256 {
+            assert_eq!(
+                OP_FUNCTIONS[op as usize].is_some(),
+                expected.contains(&op),
+                "opcode {op}"
+            );
+        }
+    }
+
+    // sha256 and + with the arguments "foobar" and "baz"
+    #[rstest]
+    #[case(11, 693, 100 + 20 + 9)]
+    #[case(16, 826, 100 + 20 + 9)]
+    fn test_cost_table(#[case] opcode: u8, #[case] default_cost: Cost, #[case] new_cost: Cost) {
+        let mut a = Allocator::new();
+        let op = a.new_atom(&[opcode]).unwrap();
+        let foobar = a.new_atom(b"foobar").unwrap();
+        let baz = a.new_atom(b"baz").unwrap();
+        let args = a.new_pair(baz, NodePtr::NIL).unwrap();
+        let args = a.new_pair(foobar, args).unwrap();
+
+        let Reduction(cost, expected) = ChiaDialect::new(0)
+            .op(&mut a, op, args, 100_000, OperatorSet::Default)
+            .unwrap();
+        assert_eq!(cost, default_cost);
+
+        let mut table = CostTable::new();
+        table.set(
+            opcode as u32,
+            OpCost {
+                base: 100,
+                per_arg: 10,
+                per_byte: 1,
+            },
+        );
+        let dialect = ChiaDialect::with_cost_table(0, table);
+        let Reduction(cost, result) = dialect
+            .op(&mut a, op, args, 100_000, OperatorSet::Default)
+            .unwrap();
+        assert_eq!(cost, new_cost);
+        assert!(a.tree_eq(result, expected));
+
+        // the operator's built-in cost checks still apply, against what's
+        // left of max_cost once the overridden cost has been charged. It's
+        // still only charged the overridden cost
+        assert!(new_cost < default_cost);
+        let Reduction(cost, _) = dialect
+            .op(
+                &mut a,
+                op,
+                args,
+                new_cost + default_cost,
+                OperatorSet::Default,
+            )
+            .unwrap();
+        assert_eq!(cost, new_cost);
+        assert_eq!(
+            dialect
+                .op(&mut a, op, args, new_cost, OperatorSet::Default)
+                .unwrap_err()
+                .1,
+            "cost exceeded"
+        );
+
+        // the overridden cost is checked against max_cost
+        assert_eq!(
+            dialect
+                .op(&mut a, op, args, new_cost - 1, OperatorSet::Default)
+                .unwrap_err()
+                .1,
+            "cost exceeded"
+        );
+
+        // operators not in the table keep their default cost
+        let concat = a.new_atom(&[14]).unwrap();
+        assert_eq!(
+            dialect
+                .op(&mut a, concat, args, 100_000, OperatorSet::Default)
+                .unwrap()
+                .0,
+            ChiaDialect::new(0)
+                .op(&mut a, concat, args, 100_000, OperatorSet::Default)
+                .unwrap()
+                .0
+        );
+
+        // a multi byte opcode is a different (unknown) operator, even if its
+        // value is the same
+        let long_op = a.new_atom(&[0, opcode]).unwrap();
+        assert_eq!(
+            dialect
+                .op(&mut a, long_op, args, 100_000, OperatorSet::Default)
+                .unwrap()
+                .0,
+            ChiaDialect::new(0)
+                .op(&mut a, long_op, args, 100_000, OperatorSet::Default)
+                .unwrap()
+                .0
+        );
+
+        // failures are passed through
+        let bad_args = a.new_pair(args, NodePtr::NIL).unwrap();
+        assert!(dialect
+            .op(&mut a, op, bad_args, 100_000, OperatorSet::Default)
+            .is_err());
+    }
 }
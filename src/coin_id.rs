@@ -0,0 +1,204 @@
+// A coin ID is sha256(parent_coin_id + puzzle_hash + amount), where the amount
+// is serialized as a CLVM integer, i.e. big-endian, with a leading zero only if
+// the most significant bit would otherwise be set. Any other encoding of the
+// amount produces a different (and invalid) coin ID.
+
+use crate::allocator::{Allocator, NodePtr};
+use crate::err_utils::err;
+use crate::error::ErrorCode;
+use crate::op_utils::atom;
+use crate::reduction::EvalErr;
+use crate::serde::Bytes32;
+use crate::sha256::Sha256;
+
+fn hash_coin(parent_coin_id: &[u8], puzzle_hash: &[u8], amount: &[u8]) -> Bytes32 {
+    let mut hasher = Sha256::new();
+    hasher.update(parent_coin_id);
+    hasher.update(puzzle_hash);
+    hasher.update(amount);
+    hasher.finalize()
+}
+
+/// Computes the ID of the coin with the specified parent, puzzle hash and
+/// amount.
+pub fn coin_id(parent_coin_id: &Bytes32, puzzle_hash: &Bytes32, amount: u64) -> Bytes32 {
+    let mut buf = [0_u8; 9];
+    buf[1..].copy_from_slice(&amount.to_be_bytes());
+    // keep one leading zero byte if the next one has its top bit set. The
+    // first byte is always zero, so there's always one to keep
+    let mut start = buf.iter().position(|b| *b != 0).unwrap_or(buf.len());
+    if start < buf.len() && (buf[start] & 0x80) != 0 {
+        start -= 1;
+    }
+    hash_coin(parent_coin_id, puzzle_hash, &buf[start..])
+}
+
+/// Computes the coin ID from atoms in the allocator, with the same rules as the
+/// `coinid` operator. The parent coin ID and puzzle hash must be 32 bytes, and
+/// the amount must be a canonical, non-negative integer that fits in a u64.
+pub fn coin_id_from_nodes(
+    a: &Allocator,
+    parent_coin_id: NodePtr,
+    puzzle_hash: NodePtr,
+    amount: NodePtr,
+) -> Result<Bytes32, EvalErr> {
+    let parent_coin = atom(a, parent_coin_id, "coinid")?;
+    if parent_coin.as_ref().len() != 32 {
+        return err(
+            parent_coin_id,
+            ErrorCode::InvalidArgValue,
+            "coinid: invalid parent coin id (must be 32 bytes)",
+        );
+    }
+    let puzzle = atom(a, puzzle_hash, "coinid")?;
+    if puzzle.as_ref().len() != 32 {
+        return err(
+            puzzle_hash,
+            ErrorCode::InvalidArgValue,
+            "coinid: invalid puzzle hash (must be 32 bytes)",
+        );
+    }
+    let amount_atom = atom(a, amount, "coinid")?;
+    let buf = amount_atom.as_ref();
+    if !buf.is_empty() {
+        if (buf[0] & 0x80) != 0 {
+            return err(
+                amount,
+                ErrorCode::InvalidArgValue,
+                "coinid: invalid amount (may not be negative",
+            );
+        }
+        if buf == [0_u8] || (buf.len() > 1 && buf[0] == 0 && (buf[1] & 0x80) == 0) {
+            return err(
+                amount,
+                ErrorCode::InvalidArgValue,
+                "coinid: invalid amount (may not have redundant leading zero)",
+            );
+        }
+        // the only valid coin value that's 9 bytes is when a leading zero is
+        // required to not have the value interpreted as negative
+        if buf.len() > 9 || (buf.len() == 9 && buf[0] != 0) {
+            return err(
+                amount,
+                ErrorCode::InvalidArgValue,
+                "coinid: invalid amount (may not exceed max coin amount)",
+            );
+        }
+    }
+    Ok(hash_coin(parent_coin.as_ref(), puzzle.as_ref(), buf))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hex_literal::hex;
+    use rstest::rstest;
+
+    const PARENT: Bytes32 =
+        hex!("1234500000000000000000000000000000000000000000000000000000000000");
+    const PUZZLE_HASH: Bytes32 =
+        hex!("6789abcdef000000000000000000000000000000000000000000000000000000");
+
+    // the same vectors as the coinid operator in op-tests/test-bls-ops.txt
+    #[rstest]
+    #[case(
+        [0; 32],
+        [0; 32],
+        0,
+        hex!("f5a5fd42d16a20302798ef6ed309979b43003d2320d9f0e8ea9831a92759fb4b")
+    )]
+    #[case(
+        [0; 32],
+        [0; 32],
+        123456789,
+        hex!("2f6c01d9205e70f55b940367dcdc1b518f077ee7308788ec4467447708a76e79")
+    )]
+    #[case(
+        PARENT,
+        PUZZLE_HASH,
+        123456789,
+        hex!("69bfe81b052bfc6bd7f3fb9167fec61793175b897c16a35827f947d5cc98e4bc")
+    )]
+    #[case(
+        PARENT,
+        PUZZLE_HASH,
+        u64::MAX,
+        hex!("609d2d5e3081fbc1106950950f3ea3dbb4eaec96a57a544ba83b8a762b457168")
+    )]
+    fn test_coin_id(
+        #[case] parent: Bytes32,
+        #[case] puzzle_hash: Bytes32,
+        #[case] amount: u64,
+        #[case] expected: Bytes32,
+    ) {
+        assert_eq!(coin_id(&parent, &puzzle_hash, amount), expected);
+
+        let mut a = Allocator::new();
+        let parent = a.new_atom(&parent).unwrap();
+        let puzzle_hash = a.new_atom(&puzzle_hash).unwrap();
+        let amount = a.new_number(amount.into()).unwrap();
+        assert_eq!(
+            coin_id_from_nodes(&a, parent, puzzle_hash, amount).unwrap(),
+            expected
+        );
+    }
+
+    #[rstest]
+    #[case(0x80)]
+    #[case(0x7f)]
+    #[case(0x8000)]
+    #[case(0x80_0000_0000_0000)]
+    #[case(0x7fff_ffff_ffff_ffff)]
+    fn test_amount_encoding(#[case] amount: u64) {
+        // the node based variant only accepts the canonical encoding, so it
+        // must agree with coin_id()
+        let mut a = Allocator::new();
+        let parent = a.new_atom(&PARENT).unwrap();
+        let puzzle_hash = a.new_atom(&PUZZLE_HASH).unwrap();
+        let amount_node = a.new_number(amount.into()).unwrap();
+        assert_eq!(
+            coin_id_from_nodes(&a, parent, puzzle_hash, amount_node).unwrap(),
+            coin_id(&PARENT, &PUZZLE_HASH, amount)
+        );
+    }
+
+    #[rstest]
+    #[case(&PARENT[1..], &PUZZLE_HASH, &[1], "coinid: invalid parent coin id (must be 32 bytes)")]
+    #[case(&PARENT, &[0; 33], &[1], "coinid: invalid puzzle hash (must be 32 bytes)")]
+    #[case(&PARENT, &PUZZLE_HASH, &[0xff], "coinid: invalid amount (may not be negative")]
+    #[case(
+        &PARENT,
+        &PUZZLE_HASH,
+        &[0],
+        "coinid: invalid amount (may not have redundant leading zero)"
+    )]
+    #[case(
+        &PARENT,
+        &PUZZLE_HASH,
+        &[0, 0x12, 0x34],
+        "coinid: invalid amount (may not have redundant leading zero)"
+    )]
+    #[case(
+        &PARENT,
+        &PUZZLE_HASH,
+        &[1, 0, 0, 0, 0, 0, 0, 0, 0],
+        "coinid: invalid amount (may not exceed max coin amount)"
+    )]
+    fn test_coin_id_from_nodes_invalid(
+        #[case] parent: &[u8],
+        #[case] puzzle_hash: &[u8],
+        #[case] amount: &[u8],
+        #[case] expected: &str,
+    ) {
+        let mut a = Allocator::new();
+        let parent = a.new_atom(parent).unwrap();
+        let puzzle_hash = a.new_atom(puzzle_hash).unwrap();
+        let amount = a.new_atom(amount).unwrap();
+        assert_eq!(
+            coin_id_from_nodes(&a, parent, puzzle_hash, amount)
+                .unwrap_err()
+                .1,
+            expected
+        );
+    }
+}
@@ -0,0 +1,60 @@
+// Checks that the programs in compat-tests/corpus.txt still serialize, hash
+// and run exactly the way they did when they were added. Any difference is
+// likely to be a consensus-affecting change.
+
+use crate::allocator::Allocator;
+use crate::chia_dialect::ChiaDialect;
+use crate::run_program::run_program;
+use crate::serde::{node_from_bytes, node_to_bytes, tree_hash};
+use std::fs::read_to_string;
+
+fn check_case(line: &str) {
+    let (inputs, outcome) = line.split_once("=>").expect("missing =>");
+    let inputs: Vec<&str> = inputs.split_whitespace().collect();
+    let [flags, max_cost, program, env, hash] = inputs[..] else {
+        panic!("expected 5 inputs, found {}", inputs.len());
+    };
+    let flags = u32::from_str_radix(flags.strip_prefix("0x").expect("flags"), 16).unwrap();
+    let max_cost = max_cost.parse().unwrap();
+    let program = hex::decode(program).unwrap();
+    let env = hex::decode(env).unwrap();
+
+    let mut a = Allocator::new();
+    let program_node = node_from_bytes(&mut a, &program).unwrap();
+    let env = node_from_bytes(&mut a, &env).unwrap();
+    assert_eq!(node_to_bytes(&a, program_node).unwrap(), program);
+    assert_eq!(hex::encode(tree_hash(&a, program_node)), hash);
+
+    let (expected, expected_cost) = outcome.split_once('|').expect("missing |");
+    let (expected, expected_cost) = (expected.trim(), expected_cost.trim());
+    let dialect = ChiaDialect::new(flags);
+    match run_program(&mut a, &dialect, program_node, env, max_cost) {
+        Ok(reduction) => {
+            assert_eq!(
+                hex::encode(node_to_bytes(&a, reduction.1).unwrap()),
+                expected
+            );
+            assert_eq!(reduction.0.to_string(), expected_cost);
+        }
+        Err(err) => {
+            assert_eq!(expected, "FAIL", "unexpected failure: {}", err.1);
+            assert_eq!(err.1, expected_cost);
+        }
+    }
+}
+
+#[test]
+fn test_compat_corpus() {
+    let corpus = read_to_string("compat-tests/corpus.txt").expect("corpus not found");
+    let mut count = 0;
+    for line in corpus.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with(';') {
+            continue;
+        }
+        println!("{line}");
+        check_case(line);
+        count += 1;
+    }
+    assert!(count > 0);
+}
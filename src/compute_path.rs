@@ -0,0 +1,119 @@
+// Computes environment paths, i.e. the inverse of traverse_path(). This lets
+// compilers and debuggers map values back to references into the environment.
+
+use crate::allocator::{Allocator, NodePtr, SExp};
+use crate::number::Number;
+use std::collections::{HashSet, VecDeque};
+
+/// Returns the shortest path from `root` to `target`, as the integer that
+/// traverse_path() (and the CLVM interpreter) accepts, or None if `target` is
+/// not in the tree. Nodes are compared by identity (i.e. NodePtr), so a
+/// structurally equal copy of a subtree won't be found. The search is
+/// breadth-first, visiting every node at most once.
+pub fn compute_path(a: &Allocator, root: NodePtr, target: NodePtr) -> Option<Number> {
+    // every visited pair, with the index of its parent and which side of the
+    // parent it is. The root has no parent
+    let mut visited: Vec<(Option<usize>, bool)> = vec![(None, false)];
+    let mut seen = HashSet::<NodePtr>::new();
+    let mut queue = VecDeque::<(NodePtr, usize)>::new();
+    queue.push_back((root, 0));
+
+    while let Some((node, idx)) = queue.pop_front() {
+        if node == target {
+            // walk back to the root to build the path. The sentinel bit is
+            // the most significant one, and the first step is the least
+            // significant one
+            let mut path = Number::from(1);
+            let mut current = idx;
+            while let (Some(parent), is_rest) = visited[current] {
+                path <<= 1;
+                if is_rest {
+                    path += 1;
+                }
+                current = parent;
+            }
+            return Some(path);
+        }
+        let SExp::Pair(first, rest) = a.sexp(node) else {
+            continue;
+        };
+        if !seen.insert(node) {
+            continue;
+        }
+        for (child, is_rest) in [(first, false), (rest, true)] {
+            visited.push((Some(idx), is_rest));
+            queue.push_back((child, visited.len() - 1));
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::text::parse_clvm_text;
+    use crate::traverse_path::traverse_path;
+    use rstest::rstest;
+
+    fn find(a: &Allocator, root: NodePtr, path: u32) -> NodePtr {
+        let path = path.to_be_bytes();
+        traverse_path(a, &path, root).unwrap().1
+    }
+
+    #[rstest]
+    #[case("(1 2 3)", 1)]
+    #[case("(1 2 3)", 2)]
+    #[case("(1 2 3)", 3)]
+    #[case("(1 2 3)", 5)]
+    #[case("(1 2 3)", 11)]
+    #[case("(1 2 3)", 15)]
+    #[case("((1 . 2) (3 . 4) 5)", 4)]
+    #[case("((1 . 2) (3 . 4) 5)", 6)]
+    #[case("((1 . 2) (3 . 4) 5)", 13)]
+    #[case("((1 . 2) (3 . 4) 5)", 11)]
+    #[case("(((((((((((1 . 2)))))))))))", 2048)]
+    #[case("(((((((((((1 . 2)))))))))))", 3072)]
+    fn test_compute_path(#[case] tree: &str, #[case] path: u32) {
+        let mut a = Allocator::new();
+        let root = parse_clvm_text(&mut a, tree).unwrap();
+        let target = find(&a, root, path);
+        assert_eq!(compute_path(&a, root, target), Some(path.into()));
+    }
+
+    #[test]
+    fn test_shortest_path() {
+        let mut a = Allocator::new();
+        let shared = a.new_atom(b"foobar").unwrap();
+        let deep = a.new_pair(NodePtr::NIL, shared).unwrap();
+        let deep = a.new_pair(deep, NodePtr::NIL).unwrap();
+        let root = a.new_pair(deep, shared).unwrap();
+        // shared is both at 3 and 12, the shortest path is returned
+        assert_eq!(find(&a, root, 12), shared);
+        assert_eq!(compute_path(&a, root, shared), Some(3.into()));
+    }
+
+    #[test]
+    fn test_not_found() {
+        let mut a = Allocator::new();
+        let root = parse_clvm_text(&mut a, "(\"foobar\" 2 3)").unwrap();
+        // an equal atom, but not the same node
+        let target = a.new_atom(b"foobar").unwrap();
+        assert_eq!(compute_path(&a, root, target), None);
+        let pair = a.new_pair(target, target).unwrap();
+        assert_eq!(compute_path(&a, root, pair), None);
+    }
+
+    #[test]
+    fn test_shared_subtrees() {
+        // a DAG with 2^100 paths, each node must only be visited once
+        let mut a = Allocator::new();
+        let target = a.new_atom(b"foobar").unwrap();
+        let mut root = NodePtr::NIL;
+        for _ in 0..100 {
+            root = a.new_pair(root, root).unwrap();
+        }
+        assert_eq!(compute_path(&a, root, target), None);
+        let root = a.new_pair(root, target).unwrap();
+        assert_eq!(compute_path(&a, root, target), Some(3.into()));
+    }
+}
@@ -1,18 +1,19 @@
 use crate::allocator::{Allocator, NodePtr, SExp};
 use crate::cost::Cost;
 use crate::err_utils::err;
+use crate::error::ErrorCode;
 use crate::op_utils::{first, get_args, nilp, rest};
 use crate::reduction::{EvalErr, Reduction, Response};
 
-const FIRST_COST: Cost = 30;
-const IF_COST: Cost = 33;
+pub(crate) const FIRST_COST: Cost = 30;
+pub(crate) const IF_COST: Cost = 33;
 // Cons cost lowered from 245. It only allocates a pair, which is small
-const CONS_COST: Cost = 50;
+pub(crate) const CONS_COST: Cost = 50;
 // Rest cost lowered from 77 since it doesn't allocate anything and it should be
 // the same as first
-const REST_COST: Cost = 30;
-const LISTP_COST: Cost = 19;
-const EQ_BASE_COST: Cost = 117;
+pub(crate) const REST_COST: Cost = 30;
+pub(crate) const LISTP_COST: Cost = 19;
+pub(crate) const EQ_BASE_COST: Cost = 117;
 const EQ_COST_PER_BYTE: Cost = 1;
 
 pub fn op_if(a: &mut Allocator, input: NodePtr, _max_cost: Cost) -> Response {
@@ -59,14 +60,18 @@ pub fn op_raise(a: &mut Allocator, input: NodePtr, _max_cost: Cost) -> Response
         input
     };
 
-    err(throw_value, "clvm raise")
+    err(throw_value, ErrorCode::Raise, "clvm raise")
 }
 
 fn ensure_atom(a: &Allocator, n: NodePtr, op: &str) -> Result<(), EvalErr> {
     if let SExp::Atom = a.sexp(n) {
         Ok(())
     } else {
-        Err(EvalErr(n, format!("{op} on list")))
+        Err(EvalErr(
+            n,
+            format!("{op} on list"),
+            ErrorCode::InvalidArgType,
+        ))
     }
 }
 
@@ -1,12 +1,261 @@
-use crate::allocator::Allocator;
-use crate::reduction::EvalErr;
+use crate::allocator::{Allocator, NodePtr, SExp};
+use crate::error::ErrorCode;
+use crate::reduction::{EvalErr, Reduction, Response};
+use std::collections::HashMap;
 
 pub type Cost = u64;
 
-pub fn check_cost(a: &Allocator, cost: Cost, max_cost: Cost) -> Result<(), EvalErr> {
-    if cost > max_cost {
-        Err(EvalErr(a.nil(), "cost exceeded".into()))
-    } else {
-        Ok(())
+pub fn check_cost(_a: &Allocator, cost: Cost, max_cost: Cost) -> Result<(), EvalErr> {
+    CostTracker::new(cost, max_cost).check()
+}
+
+/// Accumulates the cost of an operation and checks it against a limit. All
+/// additions are checked. If the cost overflows a `u64`, it saturates and is
+/// considered to exceed any limit, rather than wrapping around.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CostTracker {
+    cost: Cost,
+    max_cost: Cost,
+    overflow: bool,
+}
+
+impl CostTracker {
+    pub fn new(cost: Cost, max_cost: Cost) -> Self {
+        Self {
+            cost,
+            max_cost,
+            overflow: false,
+        }
+    }
+
+    /// the cost so far. If it has overflowed, this is `Cost::MAX`
+    pub fn cost(&self) -> Cost {
+        self.cost
+    }
+
+    pub fn max_cost(&self) -> Cost {
+        self.max_cost
+    }
+
+    pub fn set_max_cost(&mut self, max_cost: Cost) {
+        self.max_cost = max_cost;
+    }
+
+    /// the cost that can still be added without exceeding the limit
+    pub fn remaining(&self) -> Cost {
+        if self.overflow {
+            0
+        } else {
+            self.max_cost.saturating_sub(self.cost)
+        }
+    }
+
+    pub fn exceeded(&self) -> bool {
+        self.overflow || self.cost > self.max_cost
+    }
+
+    /// add to the cost, without checking the limit
+    pub fn add(&mut self, cost: Cost) {
+        match self.cost.checked_add(cost) {
+            Some(c) => self.cost = c,
+            None => {
+                self.cost = Cost::MAX;
+                self.overflow = true;
+            }
+        }
+    }
+
+    /// add `count * cost_per_item` to the cost, without checking the limit
+    pub fn add_per(&mut self, count: usize, cost_per_item: Cost) {
+        match (count as Cost).checked_mul(cost_per_item) {
+            Some(c) => self.add(c),
+            None => {
+                self.cost = Cost::MAX;
+                self.overflow = true;
+            }
+        }
+    }
+
+    /// fails with "cost exceeded" if the cost has exceeded the limit
+    pub fn check(&self) -> Result<(), EvalErr> {
+        if self.exceeded() {
+            Err(EvalErr(
+                NodePtr::NIL,
+                "cost exceeded".into(),
+                ErrorCode::CostExceeded,
+            ))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// add to the cost, then check the limit
+    pub fn charge(&mut self, cost: Cost) -> Result<(), EvalErr> {
+        self.add(cost);
+        self.check()
+    }
+
+    pub fn reduction(&self, node: NodePtr) -> Reduction {
+        Reduction(self.cost, node)
+    }
+}
+
+/// The cost of an operator, as a function of its arguments. The cost is
+/// base + per_arg * (number of arguments) + per_byte * (total size of the atom
+/// arguments)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct OpCost {
+    pub base: Cost,
+    pub per_arg: Cost,
+    pub per_byte: Cost,
+}
+
+/// Overrides of the costs of operators, for simulating alternative cost
+/// schedules. Operators not in the table keep their built-in cost. The cost
+/// of quote and apply is charged by the interpreter and can't be overridden.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CostTable {
+    overrides: HashMap<u32, OpCost>,
+}
+
+impl CostTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn set(&mut self, opcode: u32, cost: OpCost) {
+        self.overrides.insert(opcode, cost);
+    }
+
+    pub fn get(&self, opcode: u32) -> Option<OpCost> {
+        self.overrides.get(&opcode).copied()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.overrides.is_empty()
+    }
+
+    /// run an operator, charging it the cost from the table, if there is
+    /// one. The cost from the table is checked against `max_cost`, and the
+    /// overridden operator is run with what's left of it as its own limit.
+    /// This keeps the built-in cost checks in place, since those are what
+    /// stop expensive operators before they do the work. Operators without
+    /// an override are run with `max_cost`, unchanged
+    pub(crate) fn apply(
+        &self,
+        a: &mut Allocator,
+        op: NodePtr,
+        args: NodePtr,
+        max_cost: Cost,
+        run: impl FnOnce(&mut Allocator, Cost) -> Response,
+    ) -> Response {
+        let Some(cost) = opcode(a, op).and_then(|opcode| self.get(opcode)) else {
+            return run(a, max_cost);
+        };
+
+        let mut new_cost = CostTracker::new(cost.base, max_cost);
+        let mut next = args;
+        while let SExp::Pair(first, rest) = a.sexp(next) {
+            new_cost.add(cost.per_arg);
+            if let SExp::Atom = a.sexp(first) {
+                new_cost.add_per(a.atom_len(first), cost.per_byte);
+            }
+            next = rest;
+        }
+        new_cost.check()?;
+        let Reduction(_, result) = run(a, max_cost.saturating_sub(new_cost.cost()))?;
+        Ok(new_cost.reduction(result))
+    }
+}
+
+/// the opcode of an operator atom, following the same rules as the dialects'
+/// dispatch: only 1 and 4 byte atoms are operators that can be overridden. A 4
+/// byte atom with the value of a 1 byte opcode is a different operator, so it
+/// doesn't have an opcode in the table
+fn opcode(a: &Allocator, op: NodePtr) -> Option<u32> {
+    let buf = a.atom(op);
+    match buf.as_ref() {
+        [b] => Some(*b as u32),
+        b @ [_, _, _, _] => {
+            let opcode = u32::from_be_bytes(b.try_into().unwrap());
+            (opcode > 0xff).then_some(opcode)
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cost_tracker() {
+        let mut c = CostTracker::new(10, 100);
+        assert_eq!(c.cost(), 10);
+        assert_eq!(c.max_cost(), 100);
+        assert_eq!(c.remaining(), 90);
+
+        c.add(80);
+        c.add_per(5, 2);
+        assert_eq!(c.cost(), 100);
+        assert_eq!(c.remaining(), 0);
+        assert!(!c.exceeded());
+        c.check().unwrap();
+
+        // the limit is only checked on demand
+        c.add(1);
+        assert!(c.exceeded());
+        assert_eq!(c.remaining(), 0);
+        assert_eq!(c.check().unwrap_err().1, "cost exceeded");
+        assert_eq!(c.reduction(NodePtr::NIL), Reduction(101, NodePtr::NIL));
+
+        let mut c = CostTracker::new(0, 100);
+        c.charge(100).unwrap();
+        assert_eq!(c.charge(1).unwrap_err().1, "cost exceeded");
+    }
+
+    #[test]
+    fn test_cost_tracker_overflow() {
+        // overflowing exceeds even the highest limit
+        let mut c = CostTracker::new(Cost::MAX - 1, Cost::MAX);
+        c.charge(1).unwrap();
+        assert_eq!(c.remaining(), 0);
+        c.add(1);
+        assert_eq!(c.cost(), Cost::MAX);
+        assert!(c.exceeded());
+        assert!(c.check().is_err());
+
+        // and it sticks
+        let mut c = CostTracker::new(Cost::MAX, Cost::MAX);
+        c.add(1);
+        c.add(0);
+        assert!(c.check().is_err());
+
+        let mut c = CostTracker::new(0, Cost::MAX);
+        c.add_per(usize::MAX, 3);
+        assert!(c.check().is_err());
+        assert_eq!(c.cost(), Cost::MAX);
+
+        let mut c = CostTracker::new(1, Cost::MAX);
+        c.add_per(usize::MAX, 1);
+        assert!(c.check().is_err());
+    }
+
+    #[test]
+    fn test_opcode() {
+        let mut a = Allocator::new();
+        for (buf, expected) in [
+            (&[][..], None),
+            (&[11][..], Some(11)),
+            (&[0, 11][..], None),
+            (&[0, 0, 11][..], None),
+            (&[0x13, 0xd6, 0x1f, 0x00][..], Some(0x13d61f00)),
+            (&[0, 0, 0, 11][..], None),
+            (&[0, 0, 1, 0][..], Some(0x100)),
+            (&[0, 0, 0, 0, 11][..], None),
+        ] {
+            let op = a.new_atom(buf).unwrap();
+            assert_eq!(opcode(&a, op), expected, "{buf:?}");
+        }
     }
 }
@@ -0,0 +1,290 @@
+// Currying binds arguments to a program, by wrapping it in a program that
+// builds the environment. This is the structure used on chain:
+//
+//   (a (q . MOD) (c (q . ARG1) (c (q . ARG2) ... 1)))
+//
+// i.e. the curried arguments come first in the environment the module is run
+// with, followed by the environment the curried program is run with.
+//
+// wrap_apply() builds the simpler (a (q . PROGRAM) (q . ENV)), which ignores
+// the environment it's run with.
+
+use crate::allocator::{Allocator, NodePtr, SExp};
+use crate::reduction::EvalErr;
+use crate::serde::Bytes32;
+use crate::sha256::Sha256;
+use std::convert::Infallible;
+
+const OP_QUOTE: u8 = 1;
+const OP_APPLY: u8 = 2;
+const OP_CONS: u8 = 4;
+
+fn hash_atom(buf: &[u8]) -> Bytes32 {
+    let mut ctx = Sha256::new();
+    ctx.update([1_u8]);
+    ctx.update(buf);
+    ctx.finalize()
+}
+
+fn hash_pair(left: &Bytes32, right: &Bytes32) -> Bytes32 {
+    let mut ctx = Sha256::new();
+    ctx.update([2_u8]);
+    ctx.update(left);
+    ctx.update(right);
+    ctx.finalize()
+}
+
+/// Computes the tree hash of the module whose tree hash is `mod_hash`, curried
+/// with arguments whose tree hashes are `arg_hashes`. This is the same as
+/// `tree_hash(curry(mod, args))`, without having to build the program.
+pub fn curry_tree_hash(mod_hash: &Bytes32, arg_hashes: &[Bytes32]) -> Bytes32 {
+    let nil = hash_atom(&[]);
+    let op_q = hash_atom(&[OP_QUOTE]);
+    let op_a = hash_atom(&[OP_APPLY]);
+    let op_c = hash_atom(&[OP_CONS]);
+
+    // the environment is built back-to-front, starting with the terminating
+    // 1, which refers to the whole environment of the curried program
+    let mut env = hash_atom(&[1]);
+    for arg in arg_hashes.iter().rev() {
+        let quoted_arg = hash_pair(&op_q, arg);
+        env = hash_pair(&op_c, &hash_pair(&quoted_arg, &hash_pair(&env, &nil)));
+    }
+
+    let quoted_mod = hash_pair(&op_q, mod_hash);
+    hash_pair(&op_a, &hash_pair(&quoted_mod, &hash_pair(&env, &nil)))
+}
+
+/// Curries `args` into the program `module`.
+pub fn curry(a: &mut Allocator, module: NodePtr, args: &[NodePtr]) -> Result<NodePtr, EvalErr> {
+    let op_q = a.one();
+    let op_c = a.new_small_number(OP_CONS as u32)?;
+
+    let mut env = a.one();
+    for arg in args.iter().rev() {
+        let quoted_arg = a.new_pair(op_q, *arg)?;
+        let rest = a.new_pair(env, NodePtr::NIL)?;
+        let rest = a.new_pair(quoted_arg, rest)?;
+        env = a.new_pair(op_c, rest)?;
+    }
+
+    apply_quoted(a, module, env)
+}
+
+// builds (a (q . PROGRAM) ENV), where ENV is evaluated
+fn apply_quoted(a: &mut Allocator, program: NodePtr, env: NodePtr) -> Result<NodePtr, EvalErr> {
+    let op_q = a.one();
+    let op_a = a.new_small_number(OP_APPLY as u32)?;
+    let quoted_program = a.new_pair(op_q, program)?;
+    let rest = a.new_pair(env, NodePtr::NIL)?;
+    let rest = a.new_pair(quoted_program, rest)?;
+    a.new_pair(op_a, rest)
+}
+
+/// Wraps `program` and `env` in (a (q . PROGRAM) (q . ENV)), i.e. a program
+/// that runs `program` with the environment `env`, regardless of the
+/// environment it's run with itself. Both are quoted, so neither is evaluated
+/// before the call.
+pub fn wrap_apply(a: &mut Allocator, program: NodePtr, env: NodePtr) -> Result<NodePtr, EvalErr> {
+    let quoted_env = a.new_pair(a.one(), env)?;
+    apply_quoted(a, program, quoted_env)
+}
+
+/// The inverse of `wrap_apply()`. If `node` is (a (q . PROGRAM) (q . ENV)),
+/// returns PROGRAM and ENV. Otherwise returns None.
+pub fn unwrap_apply(a: &Allocator, node: NodePtr) -> Option<(NodePtr, NodePtr)> {
+    let (quoted_program, quoted_env) = match_call(a, node, OP_APPLY)?;
+    Some((match_quote(a, quoted_program)?, match_quote(a, quoted_env)?))
+}
+
+fn is_op(a: &Allocator, node: NodePtr, op: u8) -> bool {
+    match a.sexp(node) {
+        SExp::Atom => a.atom(node).as_ref() == [op],
+        SExp::Pair(..) => false,
+    }
+}
+
+// matches (OP X Y), returning X and Y
+fn match_call(a: &Allocator, node: NodePtr, op: u8) -> Option<(NodePtr, NodePtr)> {
+    let (head, rest) = a.next(node)?;
+    if !is_op(a, head, op) {
+        return None;
+    }
+    let (first, rest) = a.next(rest)?;
+    let (second, rest) = a.next(rest)?;
+    if a.next(rest).is_some() || !a.atom(rest).is_empty() {
+        return None;
+    }
+    Some((first, second))
+}
+
+// matches (q . X), returning X
+fn match_quote(a: &Allocator, node: NodePtr) -> Option<NodePtr> {
+    let (head, value) = a.next(node)?;
+    if !is_op(a, head, OP_QUOTE) {
+        return None;
+    }
+    Some(value)
+}
+
+/// The inverse of `curry()`. If `node` has the structure of a curried
+/// program, returns the module and the curried arguments. Otherwise returns
+/// None.
+pub fn uncurry(a: &Allocator, node: NodePtr) -> Option<(NodePtr, Vec<NodePtr>)> {
+    match try_uncurry(a, node, || Ok::<(), Infallible>(())) {
+        Ok(ret) => ret,
+        Err(never) => match never {},
+    }
+}
+
+/// Like `uncurry()`, but calls `visit_arg` before inspecting each level of the
+/// environment, whether or not it turns out to be a curried argument. If
+/// `visit_arg` fails, so does this, without inspecting the rest of the
+/// environment.
+pub fn try_uncurry<E>(
+    a: &Allocator,
+    node: NodePtr,
+    mut visit_arg: impl FnMut() -> Result<(), E>,
+) -> Result<Option<(NodePtr, Vec<NodePtr>)>, E> {
+    let Some((quoted_mod, mut env)) = match_call(a, node, OP_APPLY) else {
+        return Ok(None);
+    };
+    let Some(module) = match_quote(a, quoted_mod) else {
+        return Ok(None);
+    };
+
+    let mut args = Vec::new();
+    while !is_op(a, env, 1) {
+        visit_arg()?;
+        let Some((quoted_arg, rest)) = match_call(a, env, OP_CONS) else {
+            return Ok(None);
+        };
+        let Some(arg) = match_quote(a, quoted_arg) else {
+            return Ok(None);
+        };
+        args.push(arg);
+        env = rest;
+    }
+    Ok(Some((module, args)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::list_utils::proper_list;
+    use crate::serde::tree_hash;
+    use crate::test_ops::parse_exp;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case("(+ 2 5)", "()", "(a (q . (+ 2 5)) 1)")]
+    #[case("(+ 2 5)", "(1)", "(a (q . (+ 2 5)) (c (q . 1) 1))")]
+    #[case(
+        "(+ 2 5)",
+        "(1 (2 3) ())",
+        "(a (q . (+ 2 5)) (c (q . 1) (c (q . (2 3)) (c (q . ()) 1))))"
+    )]
+    #[case("()", "(0x00 0x0100)", "(a (q) (c (q . 0x00) (c (q . 0x0100) 1)))")]
+    fn test_curry(#[case] module: &str, #[case] args: &str, #[case] expected: &str) {
+        let mut a = Allocator::new();
+        let module = parse_exp(&mut a, module);
+        let args = parse_exp(&mut a, args);
+        let expected = parse_exp(&mut a, expected);
+
+        let arg_list = proper_list(&a, args, true).expect("list");
+
+        let curried = curry(&mut a, module, &arg_list).expect("curry");
+        assert_eq!(tree_hash(&a, curried), tree_hash(&a, expected));
+
+        let arg_hashes: Vec<Bytes32> = arg_list.iter().map(|n| tree_hash(&a, *n)).collect();
+        assert_eq!(
+            curry_tree_hash(&tree_hash(&a, module), &arg_hashes),
+            tree_hash(&a, expected)
+        );
+
+        // the expected program is parsed, rather than built by curry(), so
+        // this also covers atoms that aren't small numbers
+        let (uncurried_mod, uncurried_args) = uncurry(&a, expected).expect("uncurry");
+        assert_eq!(tree_hash(&a, uncurried_mod), tree_hash(&a, module));
+        assert_eq!(uncurried_args.len(), arg_list.len());
+        for (lhs, rhs) in uncurried_args.iter().zip(arg_list.iter()) {
+            assert_eq!(tree_hash(&a, *lhs), tree_hash(&a, *rhs));
+        }
+
+        let (uncurried_mod, uncurried_args) = uncurry(&a, curried).expect("uncurry");
+        assert_eq!(uncurried_mod, module);
+        assert_eq!(uncurried_args, arg_list);
+    }
+
+    #[rstest]
+    #[case("1")]
+    #[case("()")]
+    #[case("(a (q . 1))")]
+    #[case("(a (q . 1) 1 ())")]
+    #[case("(a (q . 1) 1 . 1)")]
+    #[case("(a 1 1)")]
+    #[case("(a (q . 1) 2)")]
+    #[case("(a (q . 1) (c 1 1))")]
+    #[case("(a (q . 1) (c (q . 1) 1 1))")]
+    #[case("(a (q . 1) (c (q . 1) 2))")]
+    #[case("(a (q . 1) (f (q . 1) 1))")]
+    #[case("(c (q . 1) 1)")]
+    #[case("(0x0002 (q . 1) 1)")]
+    #[case("(a (0x0001 . 1) 1)")]
+    fn test_uncurry_invalid(#[case] program: &str) {
+        let mut a = Allocator::new();
+        let program = parse_exp(&mut a, program);
+        assert_eq!(uncurry(&a, program), None);
+    }
+
+    #[rstest]
+    #[case("(+ 2 5)", "(40 2)")]
+    #[case("()", "()")]
+    #[case("1", "1")]
+    #[case("(q . 1)", "(q . 1)")]
+    fn test_wrap_apply(#[case] program: &str, #[case] env: &str) {
+        use crate::chia_dialect::ChiaDialect;
+        use crate::run_program::run_program;
+
+        let mut a = Allocator::new();
+        let program = parse_exp(&mut a, program);
+        let env = parse_exp(&mut a, env);
+        let wrapped = wrap_apply(&mut a, program, env).expect("wrap_apply");
+        assert_eq!(unwrap_apply(&a, wrapped), Some((program, env)));
+
+        // running the wrapped program, with any environment, is the same as
+        // running the program with env
+        let dialect = ChiaDialect::new(0);
+        let expected = run_program(&mut a, &dialect, program, env, 0).map(|r| r.1);
+        let result = run_program(&mut a, &dialect, wrapped, NodePtr::NIL, 0).map(|r| r.1);
+        match (expected, result) {
+            (Ok(expected), Ok(result)) => assert!(a.tree_eq(expected, result)),
+            (expected, result) => assert_eq!(expected.is_ok(), result.is_ok()),
+        }
+    }
+
+    #[rstest]
+    #[case("(a (q . 1) (q . 2) ())")]
+    #[case("(a (q . 1) 2)")]
+    #[case("(a 1 (q . 2))")]
+    #[case("(c (q . 1) (q . 2))")]
+    // a curried program isn't a wrapped one
+    #[case("(a (q . 1) (c (q . 2) 1))")]
+    fn test_unwrap_apply_invalid(#[case] program: &str) {
+        let mut a = Allocator::new();
+        let program = parse_exp(&mut a, program);
+        assert_eq!(unwrap_apply(&a, program), None);
+    }
+
+    #[rstest]
+    #[case(&[], "899eb615ab3e6971119e5c666980803efdd9bc6f9b1c4b045a3e404a0c0aa784")]
+    #[case(&[&[2], &b"foo"[..]], "7605f83bd2ed02a2c2fd23daaa8bf33613e6b1b1cff6381f5f4fbdf9b3157815")]
+    fn test_curry_tree_hash_vectors(#[case] args: &[&[u8]], #[case] expected: &str) {
+        // computed with the python CurryTreehasher, for the module `1`
+        let arg_hashes: Vec<Bytes32> = args.iter().map(|arg| hash_atom(arg)).collect();
+        assert_eq!(
+            hex::encode(curry_tree_hash(&hash_atom(&[1]), &arg_hashes)),
+            expected
+        );
+    }
+}
@@ -0,0 +1,95 @@
+use crate::allocator::{Allocator, NodePtr};
+use crate::cost::{Cost, CostTracker};
+use crate::curry::{curry, try_uncurry};
+use crate::err_utils::err;
+use crate::error::ErrorCode;
+use crate::op_utils::get_args;
+use crate::reduction::Response;
+
+// These operators are experimental, and only available in softfork extension
+// 3. Their costs are modeled on the cost of doing the same thing with the core
+// operators, i.e. every pair they create costs as much as `c` (50) and every
+// pair they inspect costs as much as `f` or `r` (30).
+
+// (a (q . MOD) ENV) is 3 pairs, plus the (q . MOD) pair
+pub(crate) const CURRY_BASE_COST: Cost = 4 * 50;
+// (c (q . ARG) REST) is 3 pairs, plus the (q . ARG) pair
+const CURRY_COST_PER_ARG: Cost = 4 * 50;
+
+// inspecting (a (q . MOD) ENV), the terminating 1, and creating the
+// (MOD . ARGS) pair
+pub(crate) const UNCURRY_BASE_COST: Cost = 5 * 30 + 50;
+// inspecting (c (q . ARG) REST), and creating a pair in the list of ARGS. This
+// is charged for every level of the environment that's inspected, even if it
+// turns out not to be a curried program
+const UNCURRY_COST_PER_ARG: Cost = 4 * 30 + 50;
+
+/// (curry MOD ARG1 ARG2 ...) returns MOD curried with the arguments, i.e.
+/// (a (q . MOD) (c (q . ARG1) (c (q . ARG2) ... 1)))
+pub fn op_curry(a: &mut Allocator, input: NodePtr, max_cost: Cost) -> Response {
+    let mut cost = CostTracker::new(CURRY_BASE_COST, max_cost);
+    let Some((module, mut args)) = a.next(input) else {
+        return err(
+            input,
+            ErrorCode::InvalidArgCount,
+            "curry takes at least 1 argument",
+        );
+    };
+    let mut curried_args = Vec::new();
+    while let Some((arg, rest)) = a.next(args) {
+        cost.charge(CURRY_COST_PER_ARG)?;
+        curried_args.push(arg);
+        args = rest;
+    }
+    Ok(cost.reduction(curry(a, module, &curried_args)?))
+}
+
+/// (uncurry PROGRAM) returns (MOD . ARGS) if PROGRAM is a curried program, or
+/// () otherwise
+pub fn op_uncurry(a: &mut Allocator, input: NodePtr, max_cost: Cost) -> Response {
+    let [program] = get_args::<1>(a, input, "uncurry")?;
+    let mut cost = CostTracker::new(UNCURRY_BASE_COST, max_cost);
+    let Some((module, args)) = try_uncurry(a, program, || cost.charge(UNCURRY_COST_PER_ARG))?
+    else {
+        return Ok(cost.reduction(NodePtr::NIL));
+    };
+
+    let list = a.new_list(&args)?;
+    Ok(cost.reduction(a.new_pair(module, list)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reduction::Reduction;
+
+    #[test]
+    fn test_uncurry_long_chain_cost() {
+        // (a (q . 1) (c (q . 2) (c (q . 2) ... 2))), with 100000 levels. It's
+        // not a curried program, since the environment doesn't end in 1, but
+        // uncurry has to pay for every level it inspects, so it runs out of
+        // cost long before it has inspected all of them
+        let mut a = Allocator::new();
+        let op_a = a.new_small_number(2).unwrap();
+        let op_q = a.one();
+        let op_c = a.new_small_number(4).unwrap();
+        let two = a.new_small_number(2).unwrap();
+        let quoted_two = a.new_pair(op_q, two).unwrap();
+        let mut env = two;
+        for _ in 0..100_000 {
+            env = a.new_list(&[op_c, quoted_two, env]).unwrap();
+        }
+        let quoted_mod = a.new_pair(op_q, op_q).unwrap();
+        let program = a.new_list(&[op_a, quoted_mod, env]).unwrap();
+        let input = a.new_list(&[program]).unwrap();
+
+        let max_cost = UNCURRY_BASE_COST + 10 * UNCURRY_COST_PER_ARG;
+        let e = op_uncurry(&mut a, input, max_cost).unwrap_err();
+        assert_eq!(e.code(), ErrorCode::CostExceeded);
+
+        let Reduction(cost, result) = op_uncurry(&mut a, input, Cost::MAX).unwrap();
+        assert_eq!(result, NodePtr::NIL);
+        // the terminating 2 is inspected too
+        assert_eq!(cost, UNCURRY_BASE_COST + 100_001 * UNCURRY_COST_PER_ARG);
+    }
+}
@@ -0,0 +1,207 @@
+// A dialect for developing and debugging programs. It must never be used to
+// validate anything, since it adds an operator that no other dialect has.
+
+use crate::allocator::{Allocator, NodePtr};
+use crate::cost::Cost;
+use crate::dialect::{Dialect, OperatorSet};
+use crate::op_utils::get_args;
+use crate::reduction::{Reduction, Response};
+
+/// The opcode of the print operator. It's not assigned in the Chia dialect.
+pub const PRINT_OPCODE: u32 = 0x7072;
+
+const ALL_OPERATOR_SETS: [OperatorSet; 5] = [
+    OperatorSet::Default,
+    OperatorSet::Bls,
+    OperatorSet::Keccak,
+    OperatorSet::Hash,
+    OperatorSet::Curry,
+];
+
+/// Wraps another dialect and adds a `print` operator, `(print X)`, which
+/// passes the value of X to a callback and returns it. This makes it possible
+/// to trace intermediate values while running a program, e.g.
+/// `(+ (print 2) (q . 1))`.
+///
+/// `print` doesn't add to the cost, so a program costs the same as it would
+/// with the `print` calls removed. All other operators are implemented by the
+/// wrapped dialect, including inside softfork guards.
+pub struct DevDialect<D: Dialect, F: Fn(&Allocator, NodePtr)> {
+    inner: D,
+    print: F,
+    // the op table of the inner dialect, plus print, for each OperatorSet
+    op_tables: [Vec<(u32, &'static str)>; 5],
+}
+
+impl<D: Dialect, F: Fn(&Allocator, NodePtr)> DevDialect<D, F> {
+    pub fn new(inner: D, print: F) -> Self {
+        let op_tables = ALL_OPERATOR_SETS.map(|extensions| {
+            let mut table = inner.op_table(extensions).to_vec();
+            table.push((PRINT_OPCODE, "print"));
+            table
+        });
+        Self {
+            inner,
+            print,
+            op_tables,
+        }
+    }
+
+    pub fn inner(&self) -> &D {
+        &self.inner
+    }
+}
+
+impl<D: Dialect, F: Fn(&Allocator, NodePtr)> Dialect for DevDialect<D, F> {
+    fn op(
+        &self,
+        allocator: &mut Allocator,
+        o: NodePtr,
+        argument_list: NodePtr,
+        max_cost: Cost,
+        extensions: OperatorSet,
+    ) -> Response {
+        if allocator.small_number(o) != Some(PRINT_OPCODE) {
+            return self
+                .inner
+                .op(allocator, o, argument_list, max_cost, extensions);
+        }
+        let [value] = get_args::<1>(allocator, argument_list, "print")?;
+        (self.print)(allocator, value);
+        Ok(Reduction(0, value))
+    }
+
+    fn quote_kw(&self) -> u32 {
+        self.inner.quote_kw()
+    }
+    fn apply_kw(&self) -> u32 {
+        self.inner.apply_kw()
+    }
+    fn softfork_kw(&self) -> u32 {
+        self.inner.softfork_kw()
+    }
+
+    fn softfork_extension(&self, ext: u32) -> OperatorSet {
+        self.inner.softfork_extension(ext)
+    }
+
+    fn allow_unknown_ops(&self) -> bool {
+        self.inner.allow_unknown_ops()
+    }
+
+    fn max_atom_size(&self) -> Option<usize> {
+        self.inner.max_atom_size()
+    }
+
+    fn op_table(&self, extensions: OperatorSet) -> &[(u32, &'static str)] {
+        &self.op_tables[extensions as usize]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chia_dialect::{ChiaDialect, ENABLE_CURRY_OPS, NO_UNKNOWN_OPS};
+    use crate::disasm::disassemble;
+    use crate::run_program::run_program;
+    use crate::test_ops::parse_exp;
+    use rstest::rstest;
+    use std::cell::RefCell;
+
+    // 0x7072 is the print operator
+    #[rstest]
+    #[case("(0x7072 1)", "(1000 2000)", "(1000 2000)", &["(1000 2000)"])]
+    // the arguments are evaluated last to first
+    #[case("(+ (0x7072 2) (0x7072 (q . 1)))", "(5)", "6", &["1", "5"])]
+    #[case("(0x7072 (0x7072 (q . \"foo\")))", "()", "\"foo\"", &["\"foo\"", "\"foo\""])]
+    #[case(
+        "(softfork (q . 582) (q . 3) (q . (0x7072 (curry (q . 1) (q . 2)))) (q . ()))",
+        "()",
+        "()",
+        &["(a (q . 1) (c (q . 2) 1))"]
+    )]
+    fn test_print(
+        #[case] program: &str,
+        #[case] env: &str,
+        #[case] expected: &str,
+        #[case] expected_prints: &[&str],
+    ) {
+        let mut a = Allocator::new();
+        let program = parse_exp(&mut a, program);
+        let env = parse_exp(&mut a, env);
+        let printed = RefCell::new(Vec::new());
+        let dialect = DevDialect::new(ChiaDialect::new(ENABLE_CURRY_OPS), |a, node| {
+            printed.borrow_mut().push(disassemble(a, node))
+        });
+        let Reduction(cost, result) = run_program(&mut a, &dialect, program, env, 100_000).unwrap();
+        assert_eq!(disassemble(&a, result), expected);
+        assert_eq!(printed.into_inner(), expected_prints);
+        assert!(cost > 0);
+    }
+
+    #[test]
+    fn test_print_cost() {
+        // print doesn't add to the cost, except for the interpreter's cost
+        // of evaluating the call
+        let mut a = Allocator::new();
+        let with_print = parse_exp(&mut a, "(+ (0x7072 2) (q . 1))");
+        let without_print = parse_exp(&mut a, "(+ 2 (q . 1))");
+        let env = parse_exp(&mut a, "(5)");
+        let dialect = DevDialect::new(ChiaDialect::new(0), |_, _| {});
+        let with_print = run_program(&mut a, &dialect, with_print, env, 100_000).unwrap();
+        let without_print = run_program(&mut a, &dialect, without_print, env, 100_000).unwrap();
+        assert!(a.tree_eq(with_print.1, without_print.1));
+        assert!(with_print.0 > without_print.0);
+        assert!(with_print.0 - without_print.0 < 10);
+    }
+
+    #[test]
+    fn test_print_not_in_chia_dialect() {
+        let mut a = Allocator::new();
+        let program = parse_exp(&mut a, "(0x7072 1)");
+        let dialect = ChiaDialect::new(NO_UNKNOWN_OPS);
+        assert_eq!(
+            run_program(&mut a, &dialect, program, NodePtr::NIL, 100_000)
+                .unwrap_err()
+                .1,
+            "unimplemented operator"
+        );
+    }
+
+    #[rstest]
+    #[case("()")]
+    #[case("(1 2)")]
+    fn test_print_invalid_args(#[case] args: &str) {
+        let mut a = Allocator::new();
+        let op = a.new_number(PRINT_OPCODE.into()).unwrap();
+        let args = parse_exp(&mut a, args);
+        let dialect = DevDialect::new(ChiaDialect::new(0), |_, _| panic!("unexpected print"));
+        assert_eq!(
+            dialect
+                .op(&mut a, op, args, 100_000, OperatorSet::Default)
+                .unwrap_err()
+                .1,
+            "print takes exactly 1 argument"
+        );
+    }
+
+    #[test]
+    fn test_op_table() {
+        let dialect = DevDialect::new(ChiaDialect::new(0), |_, _| {});
+        for extensions in ALL_OPERATOR_SETS {
+            assert_eq!(
+                dialect.opcode_for_name("print", extensions),
+                Some(PRINT_OPCODE)
+            );
+            assert_eq!(dialect.opcode_for_name("+", extensions), Some(16));
+        }
+        assert_eq!(
+            dialect.opcode_for_name("keccak256", OperatorSet::Keccak),
+            Some(62)
+        );
+        assert_eq!(
+            dialect.opcode_for_name("keccak256", OperatorSet::Default),
+            None
+        );
+    }
+}
@@ -4,7 +4,7 @@ use crate::reduction::Response;
 
 /// The set of operators that are available in the dialect.
 #[repr(u32)]
-#[derive(Clone, Copy, Eq, PartialEq)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
 pub enum OperatorSet {
     /// Any softfork extensions that are not added yet will be rejected.
     Default,
@@ -16,6 +16,14 @@ pub enum OperatorSet {
     /// The keccak256 operator, which is only available inside the softfork guard.
     /// This uses softfork extension 1, which does not conflict with the BLS fork.
     Keccak,
+
+    /// The sha3_256 and sha512_256 operators, which are only available inside
+    /// the softfork guard, using softfork extension 2.
+    Hash,
+
+    /// The experimental curry and uncurry operators, which are only available
+    /// inside the softfork guard, using softfork extension 3.
+    Curry,
 }
 
 pub trait Dialect {
@@ -32,4 +40,78 @@ pub trait Dialect {
         extensions: OperatorSet,
     ) -> Response;
     fn allow_unknown_ops(&self) -> bool;
+
+    /// The max size of the atoms a program may create, if the dialect limits
+    /// it. `run_program()` applies this limit to the allocator.
+    fn max_atom_size(&self) -> Option<usize> {
+        None
+    }
+
+    /// The opcodes and names of the operators available with the specified
+    /// extensions, including quote, apply and softfork. When an opcode has
+    /// more than one name, the first one is the canonical name. The default
+    /// is an empty table, for dialects whose operators don't have names.
+    fn op_table(&self, _extensions: OperatorSet) -> &[(u32, &'static str)] {
+        &[]
+    }
+
+    fn op_name(&self, opcode: u32, extensions: OperatorSet) -> Option<&'static str> {
+        self.op_table(extensions)
+            .iter()
+            .find(|(op, _)| *op == opcode)
+            .map(|(_, name)| *name)
+    }
+
+    fn opcode_for_name(&self, name: &str, extensions: OperatorSet) -> Option<u32> {
+        self.op_table(extensions)
+            .iter()
+            .find(|(_, n)| *n == name)
+            .map(|(op, _)| *op)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reduction::Reduction;
+
+    // a dialect that only implements the required methods
+    struct MinimalDialect;
+
+    impl Dialect for MinimalDialect {
+        fn quote_kw(&self) -> u32 {
+            1
+        }
+        fn apply_kw(&self) -> u32 {
+            2
+        }
+        fn softfork_kw(&self) -> u32 {
+            36
+        }
+        fn softfork_extension(&self, _ext: u32) -> OperatorSet {
+            OperatorSet::Default
+        }
+        fn op(
+            &self,
+            _allocator: &mut Allocator,
+            _op: NodePtr,
+            args: NodePtr,
+            _max_cost: Cost,
+            _extensions: OperatorSet,
+        ) -> Response {
+            Ok(Reduction(0, args))
+        }
+        fn allow_unknown_ops(&self) -> bool {
+            true
+        }
+    }
+
+    #[test]
+    fn test_default_op_table() {
+        let dialect = MinimalDialect;
+        assert!(dialect.op_table(OperatorSet::Default).is_empty());
+        assert_eq!(dialect.op_name(1, OperatorSet::Default), None);
+        assert_eq!(dialect.opcode_for_name("q", OperatorSet::Default), None);
+        assert_eq!(dialect.max_atom_size(), None);
+    }
 }
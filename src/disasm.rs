@@ -0,0 +1,202 @@
+// A disassembler, rendering CLVM trees as human readable programs, with
+// operator names substituted for their opcodes. Unlike to_clvm_text(), the
+// output is meant for humans (error messages and debuggers), and may be lossy
+// when truncation or a fixed atom format is requested.
+
+use crate::allocator::{Allocator, NodePtr, SExp};
+use crate::chia_dialect::KEYWORDS;
+use crate::number::number_from_u8;
+use crate::text::{atom_to_text, hex_atom};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum AtomFormat {
+    /// strings if printable, small integers in decimal, and hex otherwise
+    #[default]
+    Auto,
+    /// every (non-nil) atom as hex
+    Hex,
+    /// every (non-nil) atom as a signed, decimal, integer
+    Decimal,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DisasmOptions {
+    /// lists nested deeper than this are printed as "(...)"
+    pub max_depth: Option<usize>,
+    /// lists with more items than this are truncated, with the remaining items
+    /// printed as "..."
+    pub max_width: Option<usize>,
+    pub atom_format: AtomFormat,
+}
+
+// the opcode of an operator, if the atom is the canonical representation of
+// one
+fn opcode(buf: &[u8]) -> Option<u32> {
+    if buf.is_empty() || buf.len() > 4 || buf[0] == 0 || (buf[0] & 0x80) != 0 {
+        return None;
+    }
+    Some(buf.iter().fold(0, |acc, b| (acc << 8) | *b as u32))
+}
+
+fn format_atom(buf: &[u8], format: AtomFormat) -> String {
+    if buf.is_empty() {
+        return "()".to_string();
+    }
+    match format {
+        AtomFormat::Auto => atom_to_text(buf),
+        AtomFormat::Hex => hex_atom(buf),
+        AtomFormat::Decimal => number_from_u8(buf).to_string(),
+    }
+}
+
+enum PrintOp {
+    // the node, its depth and whether it's quoted (i.e. data, not code)
+    Visit(NodePtr, usize, bool),
+    Operator(NodePtr),
+    Text(&'static str),
+}
+
+/// Disassemble a CLVM program, using the default options
+pub fn disassemble(a: &Allocator, node: NodePtr) -> String {
+    disassemble_with_options(a, node, &DisasmOptions::default())
+}
+
+/// Disassemble a CLVM program. The first item of every list is printed as an
+/// operator name, if it's a known opcode. Quoted values are data, and printed
+/// without operator names.
+pub fn disassemble_with_options(a: &Allocator, node: NodePtr, options: &DisasmOptions) -> String {
+    let mut out = String::new();
+    let mut ops = vec![PrintOp::Visit(node, 0, false)];
+    while let Some(op) = ops.pop() {
+        match op {
+            PrintOp::Text(t) => out.push_str(t),
+            PrintOp::Operator(node) => {
+                let buf = a.atom(node);
+                let name =
+                    opcode(buf.as_ref()).and_then(|op| KEYWORDS.iter().find(|(kw, _)| *kw == op));
+                match name {
+                    Some((_, name)) => out.push_str(name),
+                    None => out.push_str(&format_atom(buf.as_ref(), options.atom_format)),
+                }
+            }
+            PrintOp::Visit(node, depth, quoted) => match a.sexp(node) {
+                SExp::Atom => {
+                    out.push_str(&format_atom(a.atom(node).as_ref(), options.atom_format))
+                }
+                SExp::Pair(_, _) if options.max_depth.is_some_and(|max| depth >= max) => {
+                    out.push_str("(...)");
+                }
+                SExp::Pair(first, rest) => {
+                    out.push('(');
+                    let mut items = vec![first];
+                    let mut tail = rest;
+                    while let SExp::Pair(f, r) = a.sexp(tail) {
+                        items.push(f);
+                        tail = r;
+                    }
+
+                    // the operands of quote are data
+                    let is_operator =
+                        !quoted && matches!(a.sexp(first), SExp::Atom) && a.atom_len(first) > 0;
+                    let rest_quoted =
+                        quoted || (is_operator && opcode(a.atom(first).as_ref()) == Some(1));
+
+                    // the operations are pushed in reverse order
+                    ops.push(PrintOp::Text(")"));
+                    let truncated = options.max_width.is_some_and(|max| items.len() > max);
+                    if truncated {
+                        items.truncate(options.max_width.unwrap());
+                        ops.push(PrintOp::Text("..."));
+                        if !items.is_empty() {
+                            ops.push(PrintOp::Text(" "));
+                        }
+                    } else if a.atom_len(tail) != 0 {
+                        ops.push(PrintOp::Visit(tail, depth + 1, rest_quoted));
+                        ops.push(PrintOp::Text(" . "));
+                    }
+                    for (i, item) in items.into_iter().enumerate().rev() {
+                        if i == 0 {
+                            if is_operator {
+                                ops.push(PrintOp::Operator(item));
+                            } else {
+                                ops.push(PrintOp::Visit(item, depth + 1, quoted));
+                            }
+                        } else {
+                            ops.push(PrintOp::Visit(item, depth + 1, rest_quoted));
+                            ops.push(PrintOp::Text(" "));
+                        }
+                    }
+                }
+            },
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::text::parse_clvm_text;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case("()", "()")]
+    #[case("(1 2 3)", "(q 2 3)")]
+    #[case("(q . 1)", "(q . 1)")]
+    #[case("(q 1 2 3)", "(q 1 2 3)")]
+    #[case("(q (16 2 3))", "(q (16 2 3))")]
+    #[case("(16 (q . 1) (q . 2))", "(+ (q . 1) (q . 2))")]
+    #[case("(11 (q . \"foobar\"))", "(sha256 (q . \"foobar\"))")]
+    #[case(
+        "(2 (q 2 2 (4 2 (4 5 ()))) (4 (q . 1) 1))",
+        "(a (q 2 2 (4 2 (4 5 ()))) (c (q . 1) 1))"
+    )]
+    #[case("(0x13d61f00 1 2 3)", "(secp256k1_verify 1 2 3)")]
+    #[case("(0x0010 1)", "(0x0010 1)")]
+    #[case("(1000 1)", "(1000 1)")]
+    #[case("((16 1 2) (17 1 2))", "((+ 1 2) (- 1 2))")]
+    #[case("(() 1)", "(() 1)")]
+    #[case("(29 . 30)", "(point_add . 30)")]
+    fn test_disassemble(#[case] program: &str, #[case] expected: &str) {
+        let mut a = Allocator::new();
+        let node = parse_clvm_text(&mut a, program).unwrap();
+        assert_eq!(disassemble(&a, node), expected);
+    }
+
+    #[rstest]
+    #[case(None, None, AtomFormat::Auto, "(+ (q . 1) (q 0x00 \"foobar\" -1))")]
+    #[case(
+        None,
+        None,
+        AtomFormat::Hex,
+        "(+ (q . 0x01) (q 0x00 0x666f6f626172 0xff))"
+    )]
+    #[case(
+        None,
+        None,
+        AtomFormat::Decimal,
+        "(+ (q . 1) (q 0 112628796121458 -1))"
+    )]
+    #[case(Some(0), None, AtomFormat::Auto, "(...)")]
+    #[case(Some(1), None, AtomFormat::Auto, "(+ (...) (...))")]
+    #[case(Some(2), None, AtomFormat::Auto, "(+ (q . 1) (q 0x00 \"foobar\" -1))")]
+    #[case(None, Some(0), AtomFormat::Auto, "(...)")]
+    #[case(None, Some(2), AtomFormat::Auto, "(+ (q . 1) ...)")]
+    #[case(None, Some(3), AtomFormat::Auto, "(+ (q . 1) (q 0x00 \"foobar\" ...))")]
+    #[case(Some(1), Some(1), AtomFormat::Hex, "(+ ...)")]
+    fn test_options(
+        #[case] max_depth: Option<usize>,
+        #[case] max_width: Option<usize>,
+        #[case] atom_format: AtomFormat,
+        #[case] expected: &str,
+    ) {
+        let mut a = Allocator::new();
+        let node = parse_clvm_text(&mut a, "(16 (1 . 1) (1 0x00 \"foobar\" -1))").unwrap();
+        let options = DisasmOptions {
+            max_depth,
+            max_width,
+            atom_format,
+        };
+        assert_eq!(disassemble_with_options(&a, node, &options), expected);
+    }
+}
@@ -1,6 +1,7 @@
 use crate::allocator::NodePtr;
+use crate::error::ErrorCode;
 use crate::reduction::EvalErr;
 
-pub fn err<T>(node: NodePtr, msg: &str) -> Result<T, EvalErr> {
-    Err(EvalErr(node, msg.into()))
+pub fn err<T>(node: NodePtr, code: ErrorCode, msg: &str) -> Result<T, EvalErr> {
+    Err(EvalErr(node, msg.into(), code))
 }
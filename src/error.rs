@@ -0,0 +1,124 @@
+use crate::reduction::EvalErr;
+
+/// The kind of error an `EvalErr` represents. The numeric values are stable,
+/// and may be passed across FFI boundaries. New kinds are only ever added with
+/// new values.
+///
+/// The code is set where the error is created, the message is only there to
+/// give context. Code that needs to handle errors differently depending on
+/// their kind should match on the `ErrorCode`, rather than comparing message
+/// strings.
+#[repr(u32)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ErrorCode {
+    /// an error not (yet) classified
+    Unknown = 0,
+    CostExceeded = 1,
+    /// the heap limit of the allocator was exceeded
+    OutOfMemory = 2,
+    TooManyAtoms = 3,
+    TooManyPairs = 4,
+    TooManyReductions = 5,
+    ValueStackLimit = 6,
+    EnvironmentStackLimit = 7,
+    /// the evaluation was aborted with a `Cancellation`
+    Cancelled = 8,
+    DeadlineExceeded = 9,
+    /// the program raised an error, with the `x` operator
+    Raise = 10,
+    /// the operator is invalid, reserved or not implemented
+    InvalidOperator = 11,
+    /// a path (or environment lookup) went past an atom
+    PathIntoAtom = 12,
+    FirstOfNonCons = 13,
+    RestOfNonCons = 14,
+    /// an operator was passed the wrong number of arguments
+    InvalidArgCount = 15,
+    /// an operator was passed an argument of the wrong type, e.g. a pair
+    /// where it expects an atom
+    InvalidArgType = 16,
+    /// an operator was passed an argument with an invalid value, e.g. a
+    /// division by zero or an invalid point
+    InvalidArgValue = 17,
+    /// a signature verification or pairing check failed
+    VerifyFailed = 18,
+    AtomTooLarge = 19,
+    /// a softfork guard is invalid or failed
+    Softfork = 20,
+    /// an invariant of the interpreter or allocator was violated
+    InternalError = 21,
+    /// a limit on the size of the inputs to an operation was exceeded
+    LimitExceeded = 22,
+}
+
+impl ErrorCode {
+    pub fn code(self) -> u32 {
+        self as u32
+    }
+}
+
+impl From<ErrorCode> for u32 {
+    fn from(code: ErrorCode) -> Self {
+        code.code()
+    }
+}
+
+impl EvalErr {
+    /// the kind of error this is
+    pub fn code(&self) -> ErrorCode {
+        self.2
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::allocator::{Allocator, NodePtr};
+    use crate::chia_dialect::{ChiaDialect, ENABLE_KECCAK_OPS_OUTSIDE_GUARD, NO_UNKNOWN_OPS};
+    use crate::run_program::run_program;
+    use crate::test_ops::parse_exp;
+    use rstest::rstest;
+
+    #[test]
+    fn test_stable_values() {
+        assert_eq!(ErrorCode::Unknown.code(), 0);
+        assert_eq!(ErrorCode::CostExceeded.code(), 1);
+        assert_eq!(u32::from(ErrorCode::InvalidArgValue), 17);
+        assert_eq!(ErrorCode::LimitExceeded as u32, 22);
+    }
+
+    // the errors of actual programs are classified
+    #[rstest]
+    #[case("(+ (q . (1 2)))", ErrorCode::InvalidArgType)]
+    #[case("(/ (q . 1) (q . 0))", ErrorCode::InvalidArgValue)]
+    #[case("(f (q . 1))", ErrorCode::FirstOfNonCons)]
+    #[case("(r (q . 1))", ErrorCode::RestOfNonCons)]
+    #[case("(f)", ErrorCode::InvalidArgCount)]
+    #[case("(x (q . 1))", ErrorCode::Raise)]
+    #[case("(i (q . 1))", ErrorCode::InvalidArgCount)]
+    #[case("(substr (q . 1) (q . 2))", ErrorCode::InvalidArgValue)]
+    #[case("(0xffff (q . 1))", ErrorCode::InvalidOperator)]
+    #[case("(sha256 (q . (1)))", ErrorCode::InvalidArgType)]
+    #[case("(g1_add (q . 1))", ErrorCode::InvalidArgType)]
+    #[case("(coinid (q . 1) (q . 2) (q . 3))", ErrorCode::InvalidArgValue)]
+    #[case("(c (q . 1))", ErrorCode::InvalidArgCount)]
+    #[case("(a (q . 5) (q . 1))", ErrorCode::PathIntoAtom)]
+    #[case("(softfork (q . 0))", ErrorCode::Softfork)]
+    #[case("(concat (q . 1) (q . 2) (q . 3) (q 4))", ErrorCode::InvalidArgType)]
+    fn test_program_errors(#[case] prg: &str, #[case] expected: ErrorCode) {
+        let mut a = Allocator::new();
+        let program = parse_exp(&mut a, prg);
+        let dialect = ChiaDialect::new(NO_UNKNOWN_OPS | ENABLE_KECCAK_OPS_OUTSIDE_GUARD);
+        let e = run_program(&mut a, &dialect, program, NodePtr::NIL, 11_000_000_000).unwrap_err();
+        assert_eq!(e.code(), expected, "{}", e.1);
+    }
+
+    #[test]
+    fn test_cost_exceeded() {
+        let mut a = Allocator::new();
+        let program = parse_exp(&mut a, "(sha256 (q . 1))");
+        let dialect = ChiaDialect::new(0);
+        let e = run_program(&mut a, &dialect, program, NodePtr::NIL, 10).unwrap_err();
+        assert_eq!(e.code(), ErrorCode::CostExceeded);
+    }
+}
@@ -0,0 +1,67 @@
+use crate::allocator::{Allocator, NodePtr};
+use crate::cost::{Cost, CostTracker};
+use crate::op_utils::atom;
+use crate::op_utils::new_atom_and_cost;
+use crate::reduction::Response;
+use sha2::Sha512_256;
+use sha3::{Digest, Sha3_256};
+
+// sha3_256 is the same construction as keccak256 (only the padding differs),
+// so it has the same cost
+pub(crate) const SHA3_256_BASE_COST: Cost = 50;
+const SHA3_256_COST_PER_ARG: Cost = 160;
+const SHA3_256_COST_PER_BYTE: Cost = 2;
+
+// SHA-512/256 is priced like sha256
+pub(crate) const SHA512_256_BASE_COST: Cost = 87;
+const SHA512_256_COST_PER_ARG: Cost = 134;
+const SHA512_256_COST_PER_BYTE: Cost = 2;
+
+fn hash_op<D: Digest>(
+    a: &mut Allocator,
+    mut input: NodePtr,
+    max_cost: Cost,
+    name: &str,
+    costs: (Cost, Cost, Cost),
+) -> Response {
+    let (base_cost, cost_per_arg, cost_per_byte) = costs;
+    let mut cost = CostTracker::new(base_cost, max_cost);
+
+    let mut hasher = D::new();
+    while let Some((arg, rest)) = a.next(input) {
+        input = rest;
+        cost.charge(cost_per_arg)?;
+        let blob = atom(a, arg, name)?;
+        cost.add_per(blob.as_ref().len(), cost_per_byte);
+        hasher.update(blob);
+    }
+    new_atom_and_cost(a, cost.cost(), &hasher.finalize())
+}
+
+pub fn op_sha3_256(a: &mut Allocator, input: NodePtr, max_cost: Cost) -> Response {
+    hash_op::<Sha3_256>(
+        a,
+        input,
+        max_cost,
+        "sha3_256",
+        (
+            SHA3_256_BASE_COST,
+            SHA3_256_COST_PER_ARG,
+            SHA3_256_COST_PER_BYTE,
+        ),
+    )
+}
+
+pub fn op_sha512_256(a: &mut Allocator, input: NodePtr, max_cost: Cost) -> Response {
+    hash_op::<Sha512_256>(
+        a,
+        input,
+        max_cost,
+        "sha512_256",
+        (
+            SHA512_256_BASE_COST,
+            SHA512_256_COST_PER_ARG,
+            SHA512_256_COST_PER_BYTE,
+        ),
+    )
+}
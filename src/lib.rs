@@ -1,41 +1,81 @@
 pub mod allocator;
+#[cfg(feature = "bls-cache")]
+pub mod bls_cache;
 pub mod bls_ops;
+pub mod cas;
 pub mod chia_dialect;
+pub mod coin_id;
+pub mod compute_path;
 pub mod core_ops;
 pub mod cost;
+pub mod curry;
+pub mod curry_ops;
+pub mod dev_dialect;
 pub mod dialect;
+pub mod disasm;
 pub mod err_utils;
+pub mod error;
 pub mod f_table;
+pub mod hash_ops;
 pub mod keccak256_ops;
+pub mod list_utils;
 pub mod more_ops;
 pub mod number;
 pub mod op_utils;
+pub mod optimize;
 pub mod reduction;
 pub mod run_program;
+pub mod run_serialized;
 pub mod runtime_dialect;
 pub mod secp_ops;
 pub mod serde;
+pub mod sha256;
+pub mod text;
 pub mod traverse_path;
 
-pub use allocator::{Allocator, Atom, NodePtr, SExp};
-pub use chia_dialect::ChiaDialect;
-pub use run_program::run_program;
+#[cfg(feature = "trace")]
+pub mod trace;
+
+pub use allocator::{
+    copy_tree, Allocator, Atom, HeapLimitError, InternStats, MemoryStats, NodePtr, SExp,
+};
+pub use chia_dialect::{ChiaDialect, OpInfo};
+pub use run_program::{
+    run_program, run_program_with_budget, run_program_with_cancellation, run_program_with_deadline,
+    run_program_with_max_reductions, run_program_with_op_usage, EvalState, OpUsage,
+    SuspendedEvaluation,
+};
+pub use run_serialized::run_serialized_program;
+pub use serde::{tree_hash, TreeHasher};
+pub use text::{parse_clvm_text, to_clvm_text};
 
 pub use chia_dialect::{
-    ENABLE_KECCAK, ENABLE_KECCAK_OPS_OUTSIDE_GUARD, LIMIT_HEAP, MEMPOOL_MODE, NO_UNKNOWN_OPS,
+    ENABLE_CURRY_OPS, ENABLE_HASH_OPS, ENABLE_KECCAK, ENABLE_KECCAK_OPS_OUTSIDE_GUARD,
+    LIMIT_ATOM_SIZE, LIMIT_HEAP, MAX_ATOM_SIZE, MEMPOOL_MODE, NO_UNKNOWN_OPS,
 };
 
 #[cfg(feature = "counters")]
-pub use run_program::run_program_with_counters;
+pub use run_program::{
+    run_program_with_cost_breakdown, run_program_with_counters, run_program_with_resource_profile,
+};
 
 #[cfg(feature = "pre-eval")]
 pub use run_program::run_program_with_pre_eval;
 
 #[cfg(feature = "counters")]
-pub use run_program::Counters;
+pub use run_program::{Counters, ResourceProfile};
+
+#[cfg(feature = "trace")]
+pub use run_program::run_program_with_trace;
+
+#[cfg(feature = "allocator-log")]
+pub use allocator::AllocatorEvent;
 
 #[cfg(test)]
 mod tests;
 
+#[cfg(test)]
+mod compat_tests;
+
 #[cfg(test)]
 mod test_ops;
@@ -0,0 +1,156 @@
+// Helpers for inspecting lists, for embedders that need to parse CLVM
+// structures (e.g. solutions and conditions). They are all iterative, so
+// arbitrarily long lists can't exhaust the stack, and the ones that collect
+// elements can be bounded with a `ListBudget`.
+
+use crate::allocator::{Allocator, NodePtr, SExp};
+
+/// Limits on the list accepted by `proper_list_with_budget()`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ListBudget {
+    /// The maximum number of elements in the list
+    pub max_items: usize,
+    /// The maximum total size of the elements that are atoms. Elements that
+    /// are pairs don't count towards this
+    pub max_atom_bytes: usize,
+}
+
+impl Default for ListBudget {
+    fn default() -> Self {
+        Self {
+            max_items: usize::MAX,
+            max_atom_bytes: usize::MAX,
+        }
+    }
+}
+
+/// Returns the elements of the list `node`. If `strict` is set, the list must
+/// be terminated by nil, otherwise any atom terminates it. Returns None if
+/// `node` is not a list.
+pub fn proper_list(a: &Allocator, node: NodePtr, strict: bool) -> Option<Vec<NodePtr>> {
+    proper_list_with_budget(a, node, strict, &ListBudget::default())
+}
+
+/// Like `proper_list()`, but also returns None if the list exceeds `budget`.
+/// The list is only traversed as far as the budget allows.
+pub fn proper_list_with_budget(
+    a: &Allocator,
+    mut node: NodePtr,
+    strict: bool,
+    budget: &ListBudget,
+) -> Option<Vec<NodePtr>> {
+    let mut items = Vec::new();
+    let mut atom_bytes: usize = 0;
+    loop {
+        match a.sexp(node) {
+            SExp::Pair(first, rest) => {
+                if items.len() == budget.max_items {
+                    return None;
+                }
+                if let SExp::Atom = a.sexp(first) {
+                    atom_bytes = atom_bytes.checked_add(a.atom_len(first))?;
+                    if atom_bytes > budget.max_atom_bytes {
+                        return None;
+                    }
+                }
+                items.push(first);
+                node = rest;
+            }
+            SExp::Atom => {
+                if strict && a.atom_len(node) != 0 {
+                    return None;
+                }
+                return Some(items);
+            }
+        }
+    }
+}
+
+/// Returns the number of elements in the list `node`, i.e. the number of
+/// pairs along its right-hand side. The terminator is not checked.
+pub fn list_len(a: &Allocator, mut node: NodePtr) -> usize {
+    let mut len = 0;
+    while let Some((_, rest)) = a.next(node) {
+        len += 1;
+        node = rest;
+    }
+    len
+}
+
+/// Returns the element at index `n` of the list `node`, or None if the list
+/// is shorter than that.
+pub fn nth(a: &Allocator, mut node: NodePtr, n: usize) -> Option<NodePtr> {
+    for _ in 0..n {
+        let (_, rest) = a.next(node)?;
+        node = rest;
+    }
+    a.next(node).map(|(first, _)| first)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_ops::parse_exp;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case("()", true, Some(0))]
+    #[case("()", false, Some(0))]
+    #[case("(1 2 3)", true, Some(3))]
+    #[case("(1 (2 3) ())", true, Some(3))]
+    #[case("(1 2 . 3)", true, None)]
+    #[case("(1 2 . 3)", false, Some(2))]
+    #[case("1", true, None)]
+    #[case("1", false, Some(0))]
+    fn test_proper_list(
+        #[case] input: &str,
+        #[case] strict: bool,
+        #[case] expected: Option<usize>,
+    ) {
+        let mut a = Allocator::new();
+        let node = parse_exp(&mut a, input);
+        let items = proper_list(&a, node, strict);
+        assert_eq!(items.as_ref().map(Vec::len), expected);
+        if let Some(items) = items {
+            for (i, item) in items.iter().enumerate() {
+                assert_eq!(nth(&a, node, i), Some(*item));
+            }
+            assert_eq!(nth(&a, node, items.len()), None);
+            assert_eq!(list_len(&a, node), items.len());
+        }
+    }
+
+    #[rstest]
+    #[case(3, 100, true)]
+    #[case(2, 100, false)]
+    // "foo" + 2 + the pair, which doesn't count
+    #[case(3, 4, true)]
+    #[case(3, 3, false)]
+    fn test_proper_list_with_budget(
+        #[case] max_items: usize,
+        #[case] max_atom_bytes: usize,
+        #[case] ok: bool,
+    ) {
+        let mut a = Allocator::new();
+        let node = parse_exp(&mut a, "(\"foo\" 2 (\"bar\" \"baz\"))");
+        let budget = ListBudget {
+            max_items,
+            max_atom_bytes,
+        };
+        assert_eq!(
+            proper_list_with_budget(&a, node, true, &budget).is_some(),
+            ok
+        );
+    }
+
+    #[test]
+    fn test_long_list() {
+        let mut a = Allocator::new();
+        let items = vec![NodePtr::NIL; 100_000];
+        let list = a.new_list(&items).unwrap();
+        assert_eq!(proper_list(&a, list, true), Some(items));
+        assert_eq!(list_len(&a, list), 100_000);
+        assert_eq!(nth(&a, list, 99_999), Some(NodePtr::NIL));
+        assert_eq!(nth(&a, list, 100_000), None);
+    }
+}
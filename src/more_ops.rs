@@ -6,70 +6,74 @@ use std::ops::BitOrAssign;
 use std::ops::BitXorAssign;
 
 use crate::allocator::{len_for_value, Allocator, NodePtr, NodeVisitor, SExp};
-use crate::cost::{check_cost, Cost};
+use crate::coin_id::coin_id_from_nodes;
+use crate::cost::{Cost, CostTracker};
 use crate::err_utils::err;
+use crate::error::ErrorCode;
 use crate::number::Number;
 use crate::op_utils::{
     atom, atom_len, get_args, get_varargs, i32_atom, int_atom, match_args, mod_group_order,
     new_atom_and_cost, nilp, u32_from_u8, MALLOC_COST_PER_BYTE,
 };
-use crate::reduction::{Reduction, Response};
+use crate::reduction::{EvalErr, Reduction, Response};
+use crate::sha256::Sha256;
 use chia_bls::G1Element;
-use chia_sha2::Sha256;
 
-const ARITH_BASE_COST: Cost = 99;
+pub(crate) const ARITH_BASE_COST: Cost = 99;
 const ARITH_COST_PER_ARG: Cost = 320;
 const ARITH_COST_PER_BYTE: Cost = 3;
 
-const LOG_BASE_COST: Cost = 100;
+pub(crate) const LOG_BASE_COST: Cost = 100;
 const LOG_COST_PER_ARG: Cost = 264;
 const LOG_COST_PER_BYTE: Cost = 3;
 
-const LOGNOT_BASE_COST: Cost = 331;
+pub(crate) const LOGNOT_BASE_COST: Cost = 331;
 const LOGNOT_COST_PER_BYTE: Cost = 3;
 
-const MUL_BASE_COST: Cost = 92;
+pub(crate) const MUL_BASE_COST: Cost = 92;
 const MUL_COST_PER_OP: Cost = 885;
 const MUL_LINEAR_COST_PER_BYTE: Cost = 6;
 const MUL_SQUARE_COST_PER_BYTE_DIVIDER: Cost = 128;
 
-const GR_BASE_COST: Cost = 498;
+pub(crate) const GR_BASE_COST: Cost = 498;
 const GR_COST_PER_BYTE: Cost = 2;
 
-const GRS_BASE_COST: Cost = 117;
+pub(crate) const GRS_BASE_COST: Cost = 117;
 const GRS_COST_PER_BYTE: Cost = 1;
 
-const STRLEN_BASE_COST: Cost = 173;
+pub(crate) const SUBSTR_COST: Cost = 1;
+
+pub(crate) const STRLEN_BASE_COST: Cost = 173;
 const STRLEN_COST_PER_BYTE: Cost = 1;
 
-const CONCAT_BASE_COST: Cost = 142;
+pub(crate) const CONCAT_BASE_COST: Cost = 142;
 const CONCAT_COST_PER_ARG: Cost = 135;
 const CONCAT_COST_PER_BYTE: Cost = 3;
 
-const DIVMOD_BASE_COST: Cost = 1116;
+pub(crate) const DIVMOD_BASE_COST: Cost = 1116;
 const DIVMOD_COST_PER_BYTE: Cost = 6;
 
-const DIV_BASE_COST: Cost = 988;
+pub(crate) const DIV_BASE_COST: Cost = 988;
 const DIV_COST_PER_BYTE: Cost = 4;
 
-const SHA256_BASE_COST: Cost = 87;
+pub(crate) const SHA256_BASE_COST: Cost = 87;
 const SHA256_COST_PER_ARG: Cost = 134;
 const SHA256_COST_PER_BYTE: Cost = 2;
 
-const ASHIFT_BASE_COST: Cost = 596;
+pub(crate) const ASHIFT_BASE_COST: Cost = 596;
 const ASHIFT_COST_PER_BYTE: Cost = 3;
 
-const LSHIFT_BASE_COST: Cost = 277;
+pub(crate) const LSHIFT_BASE_COST: Cost = 277;
 const LSHIFT_COST_PER_BYTE: Cost = 3;
 
-const BOOL_BASE_COST: Cost = 200;
+pub(crate) const BOOL_BASE_COST: Cost = 200;
 const BOOL_COST_PER_ARG: Cost = 300;
 
 // Raspberry PI 4 is about 7.679960 / 1.201742 = 6.39 times slower
 // in the point_add benchmark
 
 // increased from 31592 to better model Raspberry PI
-const POINT_ADD_BASE_COST: Cost = 101094;
+pub(crate) const POINT_ADD_BASE_COST: Cost = 101094;
 // increased from 419994 to better model Raspberry PI
 const POINT_ADD_COST_PER_ARG: Cost = 1343980;
 
@@ -77,17 +81,17 @@ const POINT_ADD_COST_PER_ARG: Cost = 1343980;
 // in the pubkey benchmark
 
 // increased from 419535 to better model Raspberry PI
-const PUBKEY_BASE_COST: Cost = 1325730;
+pub(crate) const PUBKEY_BASE_COST: Cost = 1325730;
 // increased from 12 to closer model Raspberry PI
 const PUBKEY_COST_PER_BYTE: Cost = 38;
 
 // the new coinid operator
 // we subtract 153 cost as a discount, to incentivize using this operator rather
 // than "naked" sha256
-const COINID_COST: Cost =
+pub(crate) const COINID_COST: Cost =
     SHA256_BASE_COST + SHA256_COST_PER_ARG * 3 + SHA256_COST_PER_BYTE * (32 + 32 + 8) - 153;
 
-const MODPOW_BASE_COST: Cost = 17000;
+pub(crate) const MODPOW_BASE_COST: Cost = 17000;
 const MODPOW_COST_PER_BYTE_BASE_VALUE: Cost = 38;
 // the cost for exponent and modular scale by the square of the size of the
 // respective operands
@@ -95,7 +99,7 @@ const MODPOW_COST_PER_BYTE_EXPONENT: Cost = 3;
 const MODPOW_COST_PER_BYTE_MOD: Cost = 21;
 
 fn limbs_for_int(v: &Number) -> usize {
-    ((v.bits() + 7) / 8) as usize
+    v.bits().div_ceil(8) as usize
 }
 
 #[cfg(test)]
@@ -149,11 +153,65 @@ fn test_limbs_for_int() {
     limb_test_helper(&[0x80, 0, 0, 0, 0, 0, 0, 0]);
 }
 
+fn limbs_for_i64(v: i64) -> usize {
+    (64 - v.unsigned_abs().leading_zeros()).div_ceil(8) as usize
+}
+
+// lets tests force the arithmetic operators onto their bignum paths, to
+// compare them against the fast paths
+#[cfg(test)]
+thread_local! {
+    static BIGNUM_ONLY: std::cell::Cell<bool> = const { std::cell::Cell::new(false) };
+}
+
+/// returns the value of an atom that fits in an i64, along with its length in
+/// bytes (including redundant leading bytes, since they count towards the
+/// cost). The arithmetic operators use this to avoid converting small operands
+/// to `Number`. Pairs and larger atoms return `None`.
+fn small_int_atom(a: &Allocator, n: NodePtr) -> Option<(i64, usize)> {
+    #[cfg(test)]
+    if BIGNUM_ONLY.with(|v| v.get()) {
+        return None;
+    }
+    match a.node(n) {
+        NodeVisitor::U32(val) => Some((val as i64, len_for_value(val))),
+        NodeVisitor::Buffer(buf) if buf.len() <= 8 => {
+            let mut ret: i64 = if !buf.is_empty() && (buf[0] & 0x80) != 0 {
+                -1
+            } else {
+                0
+            };
+            for b in buf {
+                ret = (ret << 8) | *b as i64;
+            }
+            Some((ret, buf.len()))
+        }
+        _ => None,
+    }
+}
+
+/// returns both operands if they fit in an i64 and can be divided without
+/// overflowing (or dividing by zero)
+fn small_div_args(a: &Allocator, v0: NodePtr, v1: NodePtr) -> Option<((i64, usize), (i64, usize))> {
+    let lhs = small_int_atom(a, v0)?;
+    let rhs = small_int_atom(a, v1)?;
+    if rhs.0 == 0 || (lhs.0 == i64::MIN && rhs.0 == -1) {
+        None
+    } else {
+        Some((lhs, rhs))
+    }
+}
+
 fn malloc_cost(a: &Allocator, cost: Cost, ptr: NodePtr) -> Reduction {
     let c = a.atom_len(ptr) as Cost * MALLOC_COST_PER_BYTE;
     Reduction(cost + c, ptr)
 }
 
+fn malloc_cost_tracked(a: &Allocator, mut cost: CostTracker, ptr: NodePtr) -> Reduction {
+    cost.add_per(a.atom_len(ptr), MALLOC_COST_PER_BYTE);
+    cost.reduction(ptr)
+}
+
 pub fn op_unknown(
     allocator: &mut Allocator,
     o: NodePtr,
@@ -192,81 +250,72 @@ pub fn op_unknown(
     let op = op_atom.as_ref();
 
     if op.is_empty() || (op.len() >= 2 && op[0] == 0xff && op[1] == 0xff) {
-        return err(o, "reserved operator");
+        return err(o, ErrorCode::InvalidOperator, "reserved operator");
     }
 
     let cost_function = (op[op.len() - 1] & 0b11000000) >> 6;
     let cost_multiplier: u64 = match u32_from_u8(&op[0..op.len() - 1]) {
         Some(v) => v as u64,
         None => {
-            return err(o, "invalid operator");
+            return err(o, ErrorCode::InvalidOperator, "invalid operator");
         }
     };
 
-    let mut cost = match cost_function {
-        0 => 1,
+    let cost = match cost_function {
+        0 => CostTracker::new(1, max_cost),
         1 => {
-            let mut cost = ARITH_BASE_COST;
-            let mut byte_count: u64 = 0;
+            let mut cost = CostTracker::new(ARITH_BASE_COST, max_cost);
             while let Some((arg, rest)) = allocator.next(args) {
                 args = rest;
-                cost += ARITH_COST_PER_ARG;
+                cost.add(ARITH_COST_PER_ARG);
                 let len = atom_len(allocator, arg, "unknown op")?;
-                byte_count += len as u64;
-                check_cost(
-                    allocator,
-                    cost + (byte_count as Cost * ARITH_COST_PER_BYTE),
-                    max_cost,
-                )?;
+                cost.add_per(len, ARITH_COST_PER_BYTE);
+                cost.check()?;
             }
-            cost + (byte_count * ARITH_COST_PER_BYTE)
+            cost
         }
         2 => {
-            let mut cost = MUL_BASE_COST;
+            let mut cost = CostTracker::new(MUL_BASE_COST, max_cost);
             let mut first_iter: bool = true;
-            let mut l0: u64 = 0;
+            let mut l0: usize = 0;
             while let Some((arg, rest)) = allocator.next(args) {
                 args = rest;
                 let len = atom_len(allocator, arg, "unknown op")?;
                 if first_iter {
-                    l0 = len as u64;
+                    l0 = len;
                     first_iter = false;
                     continue;
                 }
-                let l1 = len as u64;
-                cost += MUL_COST_PER_OP;
-                cost += (l0 + l1) * MUL_LINEAR_COST_PER_BYTE;
-                cost += (l0 * l1) / MUL_SQUARE_COST_PER_BYTE_DIVIDER;
+                let l1 = len;
+                cost.add(mul_cost(l0, l1));
                 l0 += l1;
-                check_cost(allocator, cost, max_cost)?;
+                cost.check()?;
             }
             cost
         }
         3 => {
-            let mut cost = CONCAT_BASE_COST;
-            let mut total_size: u64 = 0;
+            let mut cost = CostTracker::new(CONCAT_BASE_COST, max_cost);
             while let Some((arg, rest)) = allocator.next(args) {
                 args = rest;
-                cost += CONCAT_COST_PER_ARG;
+                cost.add(CONCAT_COST_PER_ARG);
                 let len = atom_len(allocator, arg, "unknown op")?;
-                total_size += len as u64;
-                check_cost(
-                    allocator,
-                    cost + total_size as Cost * CONCAT_COST_PER_BYTE,
-                    max_cost,
-                )?;
+                cost.add_per(len, CONCAT_COST_PER_BYTE);
+                cost.check()?;
             }
-            cost + total_size * CONCAT_COST_PER_BYTE
+            cost
         }
-        _ => 1,
+        _ => CostTracker::new(1, max_cost),
     };
 
-    assert!(cost > 0);
+    assert!(cost.cost() > 0);
 
-    check_cost(allocator, cost, max_cost)?;
-    cost *= cost_multiplier + 1;
+    cost.check()?;
+    // the multiplication wraps around on overflow. This is consensus
+    // behavior: unknown operators are allowed outside of mempool mode, so
+    // changing how this overflows would change which programs are valid
+    let cost = cost.cost().wrapping_mul(cost_multiplier + 1);
     if cost > u32::MAX as u64 {
-        err(o, "invalid operator")
+        err(o, ErrorCode::InvalidOperator, "invalid operator")
     } else {
         Ok(Reduction(cost as Cost, allocator.nil()))
     }
@@ -316,6 +365,25 @@ fn test_unknown_op_reserved() {
     );
 }
 
+#[test]
+fn test_unknown_op_cost_wraps() {
+    let mut a = Allocator::new();
+
+    // a mul-like unknown operator (cost function 2) with these operand sizes
+    // has a cost of exactly 2^33. The multiplier is 2^31 - 1, so the total
+    // cost is 2^64, which wraps around to 0
+    let op = a.new_atom(&[0x7f, 0xff, 0xff, 0xff, 0x80]).unwrap();
+    let a0 = a.new_atom(&vec![1_u8; 1_001_321]).unwrap();
+    let a1 = a.new_atom(&vec![1_u8; 1_096_452]).unwrap();
+    let args = a.new_pair(a1, NodePtr::NIL).unwrap();
+    let args = a.new_pair(a0, args).unwrap();
+    assert_eq!(MUL_BASE_COST + mul_cost(1_001_321, 1_096_452), 1 << 33);
+    assert_eq!(
+        op_unknown(&mut a, op, args, Cost::MAX),
+        Ok(Reduction(0, NodePtr::NIL))
+    );
+}
+
 #[test]
 fn test_lenient_mode_last_bits() {
     let mut a = crate::allocator::Allocator::new();
@@ -397,101 +465,153 @@ pub fn op_sha256(a: &mut Allocator, mut input: NodePtr, max_cost: Cost) -> Respo
         }
     }
 
-    let mut byte_count: usize = 0;
+    let mut cost = CostTracker::new(cost, max_cost);
     let mut hasher = Sha256::new();
     while let Some((arg, rest)) = a.next(input) {
         input = rest;
-        cost += SHA256_COST_PER_ARG;
-        check_cost(
-            a,
-            cost + byte_count as Cost * SHA256_COST_PER_BYTE,
-            max_cost,
-        )?;
+        cost.charge(SHA256_COST_PER_ARG)?;
         let blob = atom(a, arg, "sha256")?;
-        byte_count += blob.as_ref().len();
+        cost.add_per(blob.as_ref().len(), SHA256_COST_PER_BYTE);
         hasher.update(blob);
     }
-    cost += byte_count as Cost * SHA256_COST_PER_BYTE;
-    new_atom_and_cost(a, cost, &hasher.finalize())
+    new_atom_and_cost(a, cost.cost(), &hasher.finalize())
 }
 
 pub fn op_add(a: &mut Allocator, mut input: NodePtr, max_cost: Cost) -> Response {
-    let mut cost = ARITH_BASE_COST;
-    let mut byte_count: usize = 0;
+    let mut cost = CostTracker::new(ARITH_BASE_COST, max_cost);
+    // the sum is kept in an i64 until an argument or the sum doesn't fit
+    let mut small_total: Option<i64> = Some(0);
     let mut total: Number = 0.into();
     while let Some((arg, rest)) = a.next(input) {
         input = rest;
-        cost += ARITH_COST_PER_ARG;
-        check_cost(
-            a,
-            cost + (byte_count as Cost * ARITH_COST_PER_BYTE),
-            max_cost,
-        )?;
+        cost.charge(ARITH_COST_PER_ARG)?;
+
+        if let Some(t) = small_total {
+            if let Some((val, len)) = small_int_atom(a, arg) {
+                if let Some(sum) = t.checked_add(val) {
+                    small_total = Some(sum);
+                    cost.add_per(len, ARITH_COST_PER_BYTE);
+                    continue;
+                }
+            }
+            total = t.into();
+            small_total = None;
+        }
 
         match a.node(arg) {
             NodeVisitor::Buffer(buf) => {
                 use crate::number::number_from_u8;
                 total += number_from_u8(buf);
-                byte_count += buf.len();
+                cost.add_per(buf.len(), ARITH_COST_PER_BYTE);
             }
             NodeVisitor::U32(val) => {
                 total += val;
-                byte_count += len_for_value(val);
+                cost.add_per(len_for_value(val), ARITH_COST_PER_BYTE);
             }
             NodeVisitor::Pair(_, _) => {
-                return err(arg, "+ requires int args");
+                return err(arg, ErrorCode::InvalidArgType, "+ requires int args");
             }
         }
     }
-    let total = a.new_number(total)?;
-    cost += byte_count as Cost * ARITH_COST_PER_BYTE;
-    Ok(malloc_cost(a, cost, total))
+    let total = match small_total {
+        Some(t) => a.new_i64(t)?,
+        None => a.new_number(total)?,
+    };
+    Ok(malloc_cost_tracked(a, cost, total))
 }
 
 pub fn op_subtract(a: &mut Allocator, mut input: NodePtr, max_cost: Cost) -> Response {
-    let mut cost = ARITH_BASE_COST;
-    let mut byte_count: usize = 0;
+    let mut cost = CostTracker::new(ARITH_BASE_COST, max_cost);
+    let mut small_total: Option<i64> = Some(0);
     let mut total: Number = 0.into();
     let mut is_first = true;
     while let Some((arg, rest)) = a.next(input) {
         input = rest;
-        cost += ARITH_COST_PER_ARG;
-        check_cost(a, cost + byte_count as Cost * ARITH_COST_PER_BYTE, max_cost)?;
+        cost.charge(ARITH_COST_PER_ARG)?;
+        if let Some(t) = small_total {
+            if let Some((val, len)) = small_int_atom(a, arg) {
+                let diff = if is_first {
+                    Some(val)
+                } else {
+                    t.checked_sub(val)
+                };
+                if let Some(diff) = diff {
+                    small_total = Some(diff);
+                    cost.add_per(len, ARITH_COST_PER_BYTE);
+                    is_first = false;
+                    continue;
+                }
+            }
+            total = t.into();
+            small_total = None;
+        }
         if is_first {
             let (v, len) = int_atom(a, arg, "-")?;
-            byte_count = len;
+            cost.add_per(len, ARITH_COST_PER_BYTE);
             total = v;
         } else {
             match a.node(arg) {
                 NodeVisitor::Buffer(buf) => {
                     use crate::number::number_from_u8;
                     total -= number_from_u8(buf);
-                    byte_count += buf.len();
+                    cost.add_per(buf.len(), ARITH_COST_PER_BYTE);
                 }
                 NodeVisitor::U32(val) => {
                     total -= val;
-                    byte_count += len_for_value(val);
+                    cost.add_per(len_for_value(val), ARITH_COST_PER_BYTE);
                 }
                 NodeVisitor::Pair(_, _) => {
-                    return err(arg, "- requires int args");
+                    return err(arg, ErrorCode::InvalidArgType, "- requires int args");
                 }
             }
         };
         is_first = false;
     }
-    let total = a.new_number(total)?;
-    cost += byte_count as Cost * ARITH_COST_PER_BYTE;
-    Ok(malloc_cost(a, cost, total))
+    let total = match small_total {
+        Some(t) => a.new_i64(t)?,
+        None => a.new_number(total)?,
+    };
+    Ok(malloc_cost_tracked(a, cost, total))
+}
+
+// the cost of multiplying two integers of l0 and l1 bytes
+fn mul_cost(l0: usize, l1: usize) -> Cost {
+    let (l0, l1) = (l0 as Cost, l1 as Cost);
+    MUL_COST_PER_OP
+        .saturating_add(
+            l0.saturating_add(l1)
+                .saturating_mul(MUL_LINEAR_COST_PER_BYTE),
+        )
+        .saturating_add(l0.saturating_mul(l1) / MUL_SQUARE_COST_PER_BYTE_DIVIDER)
 }
 
 pub fn op_multiply(a: &mut Allocator, mut input: NodePtr, max_cost: Cost) -> Response {
-    let mut cost: Cost = MUL_BASE_COST;
+    let mut cost = CostTracker::new(MUL_BASE_COST, max_cost);
     let mut first_iter: bool = true;
+    let mut small_total: Option<i64> = Some(1);
     let mut total: Number = 1.into();
     let mut l0: usize = 0;
     while let Some((arg, rest)) = a.next(input) {
         input = rest;
-        check_cost(a, cost, max_cost)?;
+        cost.check()?;
+        if let Some(t) = small_total {
+            if let Some((val, l1)) = small_int_atom(a, arg) {
+                if first_iter {
+                    small_total = Some(val);
+                    l0 = l1;
+                    first_iter = false;
+                    continue;
+                }
+                if let Some(product) = t.checked_mul(val) {
+                    cost.add(mul_cost(l0, l1));
+                    l0 = limbs_for_i64(product);
+                    small_total = Some(product);
+                    continue;
+                }
+            }
+            total = t.into();
+            small_total = None;
+        }
         if first_iter {
             (total, l0) = int_atom(a, arg, "*")?;
             first_iter = false;
@@ -509,26 +629,32 @@ pub fn op_multiply(a: &mut Allocator, mut input: NodePtr, max_cost: Cost) -> Res
                 len_for_value(val)
             }
             NodeVisitor::Pair(_, _) => {
-                return err(arg, "* requires int args");
+                return err(arg, ErrorCode::InvalidArgType, "* requires int args");
             }
         };
 
-        cost += MUL_COST_PER_OP;
-        cost += (l0 + l1) as Cost * MUL_LINEAR_COST_PER_BYTE;
-        cost += (l0 * l1) as Cost / MUL_SQUARE_COST_PER_BYTE_DIVIDER;
+        cost.add(mul_cost(l0, l1));
         l0 = limbs_for_int(&total);
     }
-    let total = a.new_number(total)?;
-    Ok(malloc_cost(a, cost, total))
+    let total = match small_total {
+        Some(t) => a.new_i64(t)?,
+        None => a.new_number(total)?,
+    };
+    Ok(malloc_cost_tracked(a, cost, total))
 }
 
 pub fn op_div(a: &mut Allocator, input: NodePtr, _max_cost: Cost) -> Response {
     let [v0, v1] = get_args::<2>(a, input, "/")?;
+    if let Some(((a0, a0_len), (a1, a1_len))) = small_div_args(a, v0, v1) {
+        let cost = DIV_BASE_COST + ((a0_len + a1_len) as Cost) * DIV_COST_PER_BYTE;
+        let q = a.new_i64(Integer::div_floor(&a0, &a1))?;
+        return Ok(malloc_cost(a, cost, q));
+    }
     let (a0, a0_len) = int_atom(a, v0, "/")?;
     let (a1, a1_len) = int_atom(a, v1, "/")?;
     let cost = DIV_BASE_COST + ((a0_len + a1_len) as Cost) * DIV_COST_PER_BYTE;
     if a1.sign() == Sign::NoSign {
-        err(input, "div with 0")
+        err(input, ErrorCode::InvalidArgValue, "div with 0")
     } else {
         let q = a0.div_floor(&a1);
         let q = a.new_number(q)?;
@@ -538,11 +664,21 @@ pub fn op_div(a: &mut Allocator, input: NodePtr, _max_cost: Cost) -> Response {
 
 pub fn op_divmod(a: &mut Allocator, input: NodePtr, _max_cost: Cost) -> Response {
     let [v0, v1] = get_args::<2>(a, input, "divmod")?;
+    if let Some(((a0, a0_len), (a1, a1_len))) = small_div_args(a, v0, v1) {
+        let cost = DIVMOD_BASE_COST + ((a0_len + a1_len) as Cost) * DIVMOD_COST_PER_BYTE;
+        let (q, r) = a0.div_mod_floor(&a1);
+        let q1 = a.new_i64(q)?;
+        let r1 = a.new_i64(r)?;
+
+        let c = (a.atom_len(q1) + a.atom_len(r1)) as Cost * MALLOC_COST_PER_BYTE;
+        let r: NodePtr = a.new_pair(q1, r1)?;
+        return Ok(Reduction(cost + c, r));
+    }
     let (a0, a0_len) = int_atom(a, v0, "divmod")?;
     let (a1, a1_len) = int_atom(a, v1, "divmod")?;
     let cost = DIVMOD_BASE_COST + ((a0_len + a1_len) as Cost) * DIVMOD_COST_PER_BYTE;
     if a1.sign() == Sign::NoSign {
-        err(input, "divmod with 0")
+        err(input, ErrorCode::InvalidArgValue, "divmod with 0")
     } else {
         let (q, r) = a0.div_mod_floor(&a1);
         let q1 = a.new_number(q)?;
@@ -556,11 +692,17 @@ pub fn op_divmod(a: &mut Allocator, input: NodePtr, _max_cost: Cost) -> Response
 
 pub fn op_mod(a: &mut Allocator, input: NodePtr, _max_cost: Cost) -> Response {
     let [v0, v1] = get_args::<2>(a, input, "mod")?;
+    if let Some(((a0, a0_len), (a1, a1_len))) = small_div_args(a, v0, v1) {
+        let cost = DIV_BASE_COST + ((a0_len + a1_len) as Cost) * DIV_COST_PER_BYTE;
+        let q = a.new_i64(a0.mod_floor(&a1))?;
+        let c = a.atom_len(q) as Cost * MALLOC_COST_PER_BYTE;
+        return Ok(Reduction(cost + c, q));
+    }
     let (a0, a0_len) = int_atom(a, v0, "mod")?;
     let (a1, a1_len) = int_atom(a, v1, "mod")?;
     let cost = DIV_BASE_COST + ((a0_len + a1_len) as Cost) * DIV_COST_PER_BYTE;
     if a1.sign() == Sign::NoSign {
-        err(input, "mod with 0")
+        err(input, ErrorCode::InvalidArgValue, "mod with 0")
     } else {
         let q = a.new_number(a0.mod_floor(&a1))?;
         let c = a.atom_len(q) as Cost * MALLOC_COST_PER_BYTE;
@@ -571,10 +713,9 @@ pub fn op_mod(a: &mut Allocator, input: NodePtr, _max_cost: Cost) -> Response {
 pub fn op_gr(a: &mut Allocator, input: NodePtr, _max_cost: Cost) -> Response {
     let [v0, v1] = get_args::<2>(a, input, ">")?;
 
-    match (a.small_number(v0), a.small_number(v1)) {
-        (Some(lhs), Some(rhs)) => {
-            let cost =
-                GR_BASE_COST + (len_for_value(lhs) + len_for_value(rhs)) as Cost * GR_COST_PER_BYTE;
+    match (small_int_atom(a, v0), small_int_atom(a, v1)) {
+        (Some((lhs, lhs_len)), Some((rhs, rhs_len))) => {
+            let cost = GR_BASE_COST + (lhs_len + rhs_len) as Cost * GR_COST_PER_BYTE;
             Ok(Reduction(cost, if lhs > rhs { a.one() } else { a.nil() }))
         }
         _ => {
@@ -607,7 +748,11 @@ pub fn op_strlen(a: &mut Allocator, input: NodePtr, _max_cost: Cost) -> Response
 pub fn op_substr(a: &mut Allocator, input: NodePtr, _max_cost: Cost) -> Response {
     let ([a0, start, end], argc) = get_varargs::<3>(a, input, "substr")?;
     if !(2..=3).contains(&argc) {
-        return err(input, "substr takes exactly 2 or 3 arguments");
+        return err(
+            input,
+            ErrorCode::InvalidArgCount,
+            "substr takes exactly 2 or 3 arguments",
+        );
     }
     let size = atom_len(a, a0, "substr")?;
     let start = i32_atom(a, start, "substr")?;
@@ -618,38 +763,39 @@ pub fn op_substr(a: &mut Allocator, input: NodePtr, _max_cost: Cost) -> Response
         size as i32
     };
     if end < 0 || start < 0 || end as usize > size || end < start {
-        err(input, "invalid indices for substr")
+        err(
+            input,
+            ErrorCode::InvalidArgValue,
+            "invalid indices for substr",
+        )
     } else {
         let r = a.new_substr(a0, start as u32, end as u32)?;
-        let cost: Cost = 1;
-        Ok(Reduction(cost, r))
+        Ok(Reduction(SUBSTR_COST, r))
     }
 }
 
 pub fn op_concat(a: &mut Allocator, mut input: NodePtr, max_cost: Cost) -> Response {
-    let mut cost = CONCAT_BASE_COST;
+    let mut cost = CostTracker::new(CONCAT_BASE_COST, max_cost);
     let mut total_size: usize = 0;
     let mut terms = Vec::<NodePtr>::new();
     while let Some((arg, rest)) = a.next(input) {
         input = rest;
-        cost += CONCAT_COST_PER_ARG;
-        check_cost(
-            a,
-            cost + total_size as Cost * CONCAT_COST_PER_BYTE,
-            max_cost,
-        )?;
+        cost.charge(CONCAT_COST_PER_ARG)?;
         match a.sexp(arg) {
-            SExp::Pair(_, _) => return err(arg, "concat on list"),
-            SExp::Atom => total_size += a.atom_len(arg),
+            SExp::Pair(_, _) => return err(arg, ErrorCode::InvalidArgType, "concat on list"),
+            SExp::Atom => {
+                let len = a.atom_len(arg);
+                total_size += len;
+                cost.add_per(len, CONCAT_COST_PER_BYTE);
+            }
         };
         terms.push(arg);
     }
 
-    cost += total_size as Cost * CONCAT_COST_PER_BYTE;
-    cost += total_size as Cost * MALLOC_COST_PER_BYTE;
-    check_cost(a, cost, max_cost)?;
+    cost.add_per(total_size, MALLOC_COST_PER_BYTE);
+    cost.check()?;
     let new_atom = a.new_concat(total_size, &terms)?;
-    Ok(Reduction(cost, new_atom))
+    Ok(cost.reduction(new_atom))
 }
 
 pub fn op_ash(a: &mut Allocator, input: NodePtr, _max_cost: Cost) -> Response {
@@ -657,7 +803,7 @@ pub fn op_ash(a: &mut Allocator, input: NodePtr, _max_cost: Cost) -> Response {
     let (i0, l0) = int_atom(a, n0, "ash")?;
     let a1 = i32_atom(a, n1, "ash")?;
     if !(-65535..=65535).contains(&a1) {
-        return err(n1, "shift too large");
+        return err(n1, ErrorCode::InvalidArgValue, "shift too large");
     }
 
     let v: Number = if a1 > 0 { i0 << a1 } else { i0 >> -a1 };
@@ -731,7 +877,7 @@ pub fn op_lsh(a: &mut Allocator, input: NodePtr, _max_cost: Cost) -> Response {
     let b0 = b0_atom.as_ref();
     let a1 = i32_atom(a, n1, "lsh")?;
     if !(-65535..=65535).contains(&a1) {
-        return err(n1, "shift too large");
+        return err(n1, ErrorCode::InvalidArgValue, "shift too large");
     }
     let i0 = BigUint::from_bytes_be(b0);
     let l0 = b0.len();
@@ -797,19 +943,16 @@ fn binop_reduction(
     op_f: fn(&mut Number, &Number) -> (),
 ) -> Response {
     let mut total = initial_value;
-    let mut arg_size: usize = 0;
-    let mut cost = LOG_BASE_COST;
+    let mut cost = CostTracker::new(LOG_BASE_COST, max_cost);
     while let Some((arg, rest)) = a.next(input) {
         input = rest;
         let (n0, len) = int_atom(a, arg, op_name)?;
         op_f(&mut total, &n0);
-        arg_size += len;
-        cost += LOG_COST_PER_ARG;
-        check_cost(a, cost + (arg_size as Cost * LOG_COST_PER_BYTE), max_cost)?;
+        cost.add_per(len, LOG_COST_PER_BYTE);
+        cost.charge(LOG_COST_PER_ARG)?;
     }
-    cost += arg_size as Cost * LOG_COST_PER_BYTE;
     let total = a.new_number(total)?;
-    Ok(malloc_cost(a, cost, total))
+    Ok(malloc_cost_tracked(a, cost, total))
 }
 
 fn logand_op(a: &mut Number, b: &Number) {
@@ -856,27 +999,25 @@ pub fn op_not(a: &mut Allocator, input: NodePtr, _max_cost: Cost) -> Response {
 }
 
 pub fn op_any(a: &mut Allocator, mut input: NodePtr, max_cost: Cost) -> Response {
-    let mut cost = BOOL_BASE_COST;
+    let mut cost = CostTracker::new(BOOL_BASE_COST, max_cost);
     let mut is_any = false;
     while let Some((arg, rest)) = a.next(input) {
         input = rest;
-        cost += BOOL_COST_PER_ARG;
-        check_cost(a, cost, max_cost)?;
+        cost.charge(BOOL_COST_PER_ARG)?;
         is_any = is_any || !nilp(a, arg);
     }
-    Ok(Reduction(cost, if is_any { a.one() } else { a.nil() }))
+    Ok(cost.reduction(if is_any { a.one() } else { a.nil() }))
 }
 
 pub fn op_all(a: &mut Allocator, mut input: NodePtr, max_cost: Cost) -> Response {
-    let mut cost = BOOL_BASE_COST;
+    let mut cost = CostTracker::new(BOOL_BASE_COST, max_cost);
     let mut is_all = true;
     while let Some((arg, rest)) = a.next(input) {
         input = rest;
-        cost += BOOL_COST_PER_ARG;
-        check_cost(a, cost, max_cost)?;
+        cost.charge(BOOL_COST_PER_ARG)?;
         is_all = is_all && !nilp(a, arg);
     }
-    Ok(Reduction(cost, if is_all { a.one() } else { a.nil() }))
+    Ok(cost.reduction(if is_all { a.one() } else { a.nil() }))
 }
 
 pub fn op_pubkey_for_exp(a: &mut Allocator, input: NodePtr, _max_cost: Cost) -> Response {
@@ -894,97 +1035,96 @@ pub fn op_pubkey_for_exp(a: &mut Allocator, input: NodePtr, _max_cost: Cost) ->
 }
 
 pub fn op_point_add(a: &mut Allocator, mut input: NodePtr, max_cost: Cost) -> Response {
-    let mut cost = POINT_ADD_BASE_COST;
+    let mut cost = CostTracker::new(POINT_ADD_BASE_COST, max_cost);
     let mut total = G1Element::default();
     while let Some((arg, rest)) = a.next(input) {
         input = rest;
         let point = a.g1(arg)?;
-        cost += POINT_ADD_COST_PER_ARG;
-        check_cost(a, cost, max_cost)?;
+        cost.charge(POINT_ADD_COST_PER_ARG)?;
         total += &point;
     }
-    Ok(Reduction(
-        cost + 48 * MALLOC_COST_PER_BYTE,
-        a.new_g1(total)?,
-    ))
+    cost.add(48 * MALLOC_COST_PER_BYTE);
+    Ok(cost.reduction(a.new_g1(total)?))
 }
 
 pub fn op_coinid(a: &mut Allocator, input: NodePtr, _max_cost: Cost) -> Response {
     let [parent_coin, puzzle_hash, amount] = get_args::<3>(a, input, "coinid")?;
-
-    let parent_coin = atom(a, parent_coin, "coinid")?;
-    if parent_coin.as_ref().len() != 32 {
-        return err(input, "coinid: invalid parent coin id (must be 32 bytes)");
-    }
-    let puzzle_hash = atom(a, puzzle_hash, "coinid")?;
-    if puzzle_hash.as_ref().len() != 32 {
-        return err(input, "coinid: invalid puzzle hash (must be 32 bytes)");
-    }
-    let amount_atom = atom(a, amount, "coinid")?;
-    let amount = amount_atom.as_ref();
-    if !amount.is_empty() {
-        if (amount[0] & 0x80) != 0 {
-            return err(input, "coinid: invalid amount (may not be negative");
-        }
-        if amount == [0_u8] || (amount.len() > 1 && amount[0] == 0 && (amount[1] & 0x80) == 0) {
-            return err(
-                input,
-                "coinid: invalid amount (may not have redundant leading zero)",
-            );
-        }
-        // the only valid coin value that's 9 bytes is when a leading zero is
-        // required to not have the value interpreted as negative
-        if amount.len() > 9 || (amount.len() == 9 && amount[0] != 0) {
-            return err(
-                input,
-                "coinid: invalid amount (may not exceed max coin amount)",
-            );
+    // coin_id_from_nodes() reports the invalid argument, but the operator has
+    // always reported invalid values on the argument list
+    let ret = coin_id_from_nodes(a, parent_coin, puzzle_hash, amount).map_err(|e| {
+        if e.2 == ErrorCode::InvalidArgValue {
+            EvalErr(input, e.1, e.2)
+        } else {
+            e
         }
-    }
-
-    let mut hasher = Sha256::new();
-    hasher.update(parent_coin);
-    hasher.update(puzzle_hash);
-    hasher.update(amount);
-    let ret: [u8; 32] = hasher
-        .finalize()
-        .as_slice()
-        .try_into()
-        .expect("sha256 hash is not 32 bytes");
-
+    })?;
     new_atom_and_cost(a, COINID_COST, &ret)
 }
 
 pub fn op_modpow(a: &mut Allocator, input: NodePtr, max_cost: Cost) -> Response {
     let [base, exponent, modulus] = get_args::<3>(a, input, "modpow")?;
 
-    let mut cost = MODPOW_BASE_COST;
+    let mut cost = CostTracker::new(MODPOW_BASE_COST, max_cost);
     let (base, bsize) = int_atom(a, base, "modpow")?;
-    cost += bsize as Cost * MODPOW_COST_PER_BYTE_BASE_VALUE;
+    cost.add_per(bsize, MODPOW_COST_PER_BYTE_BASE_VALUE);
+    // the cost is quadratic in the size of the exponent and modulus
     let (exponent, esize) = int_atom(a, exponent, "modpow")?;
-    cost += (esize * esize) as Cost * MODPOW_COST_PER_BYTE_EXPONENT;
-    check_cost(a, cost, max_cost)?;
+    cost.add_per(
+        esize,
+        (esize as Cost).saturating_mul(MODPOW_COST_PER_BYTE_EXPONENT),
+    );
+    cost.check()?;
     let (modulus, msize) = int_atom(a, modulus, "modpow")?;
-    cost += (msize * msize) as Cost * MODPOW_COST_PER_BYTE_MOD;
-    check_cost(a, cost, max_cost)?;
+    cost.add_per(
+        msize,
+        (msize as Cost).saturating_mul(MODPOW_COST_PER_BYTE_MOD),
+    );
+    cost.check()?;
 
     if exponent.sign() == Sign::Minus {
-        return err(input, "modpow with negative exponent");
+        return err(
+            input,
+            ErrorCode::InvalidArgValue,
+            "modpow with negative exponent",
+        );
     }
 
     if modulus.sign() == Sign::NoSign {
-        return err(input, "modpow with 0 modulus");
+        return err(input, ErrorCode::InvalidArgValue, "modpow with 0 modulus");
     }
 
     let ret = base.modpow(&exponent, &modulus);
     let ret = a.new_number(ret)?;
-    Ok(malloc_cost(a, cost, ret))
+    Ok(malloc_cost_tracked(a, cost, ret))
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_coinid_error_node() {
+        let mut a = Allocator::new();
+        let coin_id = a.new_atom(&[0; 32]).unwrap();
+        let short = a.new_atom(&[0; 31]).unwrap();
+        let amount = a.new_small_number(1).unwrap();
+        let list = a.new_pair(amount, NodePtr::NIL).unwrap();
+        for (args, error_node) in [
+            ([short, coin_id, amount], None),
+            ([coin_id, short, amount], None),
+            ([coin_id, coin_id, coin_id], None),
+            // passing a list is reported on the argument itself
+            ([coin_id, coin_id, list], Some(list)),
+        ] {
+            let mut input = a.nil();
+            for arg in args.iter().rev() {
+                input = a.new_pair(*arg, input).unwrap();
+            }
+            let e = op_coinid(&mut a, input, 100_000).unwrap_err();
+            assert_eq!(e.0, error_node.unwrap_or(input), "{}", e.1);
+        }
+    }
+
     fn test_sha256_atom(buf: &[u8]) {
         let mut a = Allocator::new();
         let mut args = a.nil();
@@ -1025,4 +1165,95 @@ mod tests {
             test_sha256_atom(&[0xff, val]);
         }
     }
+
+    type Op = fn(&mut Allocator, NodePtr, Cost) -> Response;
+
+    // operands are given as raw bytes, to cover redundant leading zeros and
+    // sign bytes, and atoms on either side of the 8 byte limit of the fast path
+    const OPERANDS: &[&[u8]] = &[
+        &[],
+        &[0],
+        &[1],
+        &[0x7f],
+        &[0x80],
+        &[0xff],
+        &[0, 0x80],
+        &[0xff, 0x7f],
+        &[0, 0, 1],
+        &[0x03, 0xff, 0xff, 0xff],
+        &[0x04, 0, 0, 0],
+        &[0x7f, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff],
+        &[0x80, 0, 0, 0, 0, 0, 0, 0],
+        &[0x80, 0, 0, 0, 0, 0, 0, 1],
+        &[0, 0x80, 0, 0, 0, 0, 0, 0, 0],
+        &[0xff, 0x7f, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff],
+    ];
+
+    fn run_op(
+        a: &mut Allocator,
+        op: Op,
+        args: NodePtr,
+        max_cost: Cost,
+        bignum_only: bool,
+    ) -> Result<(Cost, Vec<u8>), String> {
+        BIGNUM_ONLY.with(|v| v.set(bignum_only));
+        let ret = op(a, args, max_cost);
+        BIGNUM_ONLY.with(|v| v.set(false));
+        match ret {
+            Ok(Reduction(cost, node)) => Ok((
+                cost,
+                crate::serde::node_to_bytes(a, node).expect("node_to_bytes"),
+            )),
+            Err(e) => Err(e.1),
+        }
+    }
+
+    fn check_fast_path(a: &mut Allocator, op: Op, args: NodePtr) {
+        let fast = run_op(a, op, args, Cost::MAX, false);
+        let slow = run_op(a, op, args, Cost::MAX, true);
+        assert_eq!(fast, slow);
+
+        // running out of cost must fail the same way
+        if let Ok((cost, _)) = fast {
+            for max_cost in [cost - 1, cost / 2, 0] {
+                let fast = run_op(a, op, args, max_cost, false);
+                let slow = run_op(a, op, args, max_cost, true);
+                assert_eq!(fast, slow);
+            }
+        }
+    }
+
+    #[rstest::rstest]
+    #[case(op_add)]
+    #[case(op_subtract)]
+    #[case(op_multiply)]
+    #[case(op_div)]
+    #[case(op_divmod)]
+    #[case(op_mod)]
+    #[case(op_gr)]
+    fn small_int_fast_path(#[case] op: Op) {
+        let mut a = Allocator::new();
+        let mut operands: Vec<NodePtr> = OPERANDS
+            .iter()
+            .map(|buf| a.new_atom(buf).unwrap())
+            .collect();
+        operands.push(a.new_small_number(1337).unwrap());
+        let pair = a.new_pair(operands[1], operands[2]).unwrap();
+        operands.push(pair);
+
+        for lhs in &operands {
+            for rhs in &operands {
+                let nil = a.nil();
+                let args = a.new_pair(*rhs, nil).unwrap();
+                let args = a.new_pair(*lhs, args).unwrap();
+                check_fast_path(&mut a, op, args);
+                // the variadic operators switch to the bignum path half-way
+                // through the argument list, when the result overflows
+                for extra in &operands {
+                    let args = a.new_pair(*extra, args).unwrap();
+                    check_fast_path(&mut a, op, args);
+                }
+            }
+        }
+    }
 }
@@ -1,6 +1,7 @@
 use crate::allocator::{Allocator, Atom, NodePtr, NodeVisitor, SExp};
 use crate::cost::Cost;
 use crate::err_utils::err;
+use crate::error::ErrorCode;
 use crate::number::Number;
 use crate::reduction::EvalErr;
 use crate::reduction::{Reduction, Response};
@@ -23,6 +24,7 @@ pub fn get_args<const N: usize>(
                 "{name} takes exactly {N} argument{}",
                 if N == 1 { "" } else { "s" }
             ),
+            ErrorCode::InvalidArgCount,
         )
     })
 }
@@ -51,7 +53,11 @@ pub fn match_args<const N: usize>(a: &Allocator, args: NodePtr) -> Option<[NodeP
 pub fn atom_len(a: &Allocator, args: NodePtr, op_name: &str) -> Result<usize, EvalErr> {
     match a.sexp(args) {
         SExp::Atom => Ok(a.atom_len(args)),
-        _ => err(args, &format!("{op_name} requires an atom")),
+        _ => err(
+            args,
+            ErrorCode::InvalidArgType,
+            &format!("{op_name} requires an atom"),
+        ),
     }
 }
 
@@ -67,7 +73,11 @@ pub fn uint_atom<const SIZE: usize>(
             }
 
             if (bytes[0] & 0x80) != 0 {
-                return err(args, &format!("{op_name} requires positive int arg"));
+                return err(
+                    args,
+                    ErrorCode::InvalidArgType,
+                    &format!("{op_name} requires positive int arg"),
+                );
             }
 
             // strip leading zeros
@@ -77,7 +87,11 @@ pub fn uint_atom<const SIZE: usize>(
             }
 
             if buf.len() > SIZE {
-                return err(args, &format!("{op_name} requires u{} arg", SIZE * 8));
+                return err(
+                    args,
+                    ErrorCode::InvalidArgType,
+                    &format!("{op_name} requires u{} arg", SIZE * 8),
+                );
             }
 
             let mut ret = 0;
@@ -88,13 +102,17 @@ pub fn uint_atom<const SIZE: usize>(
             Ok(ret)
         }
         NodeVisitor::U32(val) => Ok(val as u64),
-        NodeVisitor::Pair(_, _) => err(args, &format!("{op_name} requires int arg")),
+        NodeVisitor::Pair(_, _) => err(
+            args,
+            ErrorCode::InvalidArgType,
+            &format!("{op_name} requires int arg"),
+        ),
     }
 }
 
 pub fn atom<'a>(a: &'a Allocator, n: NodePtr, op_name: &str) -> Result<Atom<'a>, EvalErr> {
     if n.is_pair() {
-        return err(n, &format!("{op_name} on list"));
+        return err(n, ErrorCode::InvalidArgType, &format!("{op_name} on list"));
     }
     Ok(a.atom(n))
 }
@@ -105,11 +123,16 @@ pub fn i32_atom(a: &Allocator, args: NodePtr, op_name: &str) -> Result<i32, Eval
             Some(v) => Ok(v),
             _ => err(
                 args,
+                ErrorCode::InvalidArgType,
                 &format!("{op_name} requires int32 args (with no leading zeros)"),
             ),
         },
         NodeVisitor::U32(val) => Ok(val as i32),
-        NodeVisitor::Pair(_, _) => err(args, &format!("{op_name} requires int32 args")),
+        NodeVisitor::Pair(_, _) => err(
+            args,
+            ErrorCode::InvalidArgType,
+            &format!("{op_name} requires int32 args"),
+        ),
     }
 }
 
@@ -153,6 +176,68 @@ pub fn u64_from_bytes(buf: &[u8]) -> u64 {
     ret
 }
 
+// the number of leading bytes of the integer `buf` that can be removed without
+// changing its value
+fn redundant_prefix_len(buf: &[u8]) -> usize {
+    let mut start = 0;
+    while start + 1 < buf.len()
+        && ((buf[start] == 0 && buf[start + 1] & 0x80 == 0)
+            || (buf[start] == 0xff && buf[start + 1] & 0x80 != 0))
+    {
+        start += 1;
+    }
+    // 0 is represented by the empty atom
+    if buf[start..] == [0] {
+        start += 1;
+    }
+    start
+}
+
+/// Returns true if `buf` is the minimal encoding of the integer it represents.
+/// i.e. it doesn't have redundant leading 0x00 or 0xff bytes. Zero is encoded
+/// as the empty atom, so a single 0x00 byte is not canonical either.
+pub fn is_canonical_atom_encoding(buf: &[u8]) -> bool {
+    redundant_prefix_len(buf) == 0
+}
+
+/// Returns an atom with the same integer value as `node`, but in its minimal
+/// encoding (see `is_canonical_atom_encoding()`). If `node` already is
+/// canonical, it's returned as is. Otherwise the result refers to a sub-string
+/// of `node`, without copying it.
+pub fn normalize_number_atom(a: &mut Allocator, node: NodePtr) -> Result<NodePtr, EvalErr> {
+    let SExp::Atom = a.sexp(node) else {
+        return err(
+            node,
+            ErrorCode::InvalidArgType,
+            "normalize_number_atom expected atom, got pair",
+        );
+    };
+    let atom = a.atom(node);
+    let start = redundant_prefix_len(atom.as_ref());
+    let end = atom.as_ref().len();
+    if start == 0 {
+        return Ok(node);
+    }
+    a.new_substr(node, start as u32, end as u32)
+}
+
+/// Compares two byte strings in time that only depends on their lengths, not
+/// on where they differ. This is meant for comparing secrets, like keys and
+/// hashes that are checked against expected values. The signature verification
+/// operators don't need it, since the libraries they use compare in constant
+/// time already.
+pub fn constant_time_eq(lhs: &[u8], rhs: &[u8]) -> bool {
+    if lhs.len() != rhs.len() {
+        return false;
+    }
+    let diff = lhs
+        .iter()
+        .zip(rhs.iter())
+        .fold(0_u8, |acc, (l, r)| acc | (l ^ r));
+    // prevent the compiler from turning this into an early exit
+    std::hint::black_box(diff) == 0
+}
+
 pub fn new_atom_and_cost(a: &mut Allocator, cost: Cost, buf: &[u8]) -> Response {
     let c = buf.len() as Cost * MALLOC_COST_PER_BYTE;
     Ok(Reduction(cost + c, a.new_atom(buf)?))
@@ -193,6 +278,7 @@ pub fn get_varargs<const N: usize>(
         if counter == N {
             return err(
                 args,
+                ErrorCode::InvalidArgCount,
                 &format!(
                     "{name} takes no more than {N} argument{}",
                     if N == 1 { "" } else { "s" }
@@ -216,21 +302,25 @@ pub fn nilp(a: &Allocator, n: NodePtr) -> bool {
 pub fn first(a: &Allocator, n: NodePtr) -> Result<NodePtr, EvalErr> {
     match a.sexp(n) {
         SExp::Pair(first, _) => Ok(first),
-        _ => err(n, "first of non-cons"),
+        _ => err(n, ErrorCode::FirstOfNonCons, "first of non-cons"),
     }
 }
 
 pub fn rest(a: &Allocator, n: NodePtr) -> Result<NodePtr, EvalErr> {
     match a.sexp(n) {
         SExp::Pair(_, rest) => Ok(rest),
-        _ => err(n, "rest of non-cons"),
+        _ => err(n, ErrorCode::RestOfNonCons, "rest of non-cons"),
     }
 }
 
 pub fn int_atom(a: &Allocator, args: NodePtr, op_name: &str) -> Result<(Number, usize), EvalErr> {
     match a.sexp(args) {
         SExp::Atom => Ok((a.number(args), a.atom_len(args))),
-        _ => err(args, &format!("{op_name} requires int args")),
+        _ => err(
+            args,
+            ErrorCode::InvalidArgType,
+            &format!("{op_name} requires int args"),
+        ),
     }
 }
 
@@ -432,7 +522,7 @@ mod tests {
         use crate::allocator::Allocator;
         let mut a = Allocator::new();
         let n = a.new_atom(buf).unwrap();
-        assert!(uint_atom::<4>(&a, n, "test") == err(n, expected));
+        assert!(uint_atom::<4>(&a, n, "test") == err(n, ErrorCode::InvalidArgType, expected));
     }
 
     #[test]
@@ -441,7 +531,10 @@ mod tests {
         let mut a = Allocator::new();
         let n = a.new_atom(&[0, 0]).unwrap();
         let p = a.new_pair(n, n).unwrap();
-        assert!(uint_atom::<4>(&a, p, "test") == err(p, "test requires int arg"));
+        assert!(
+            uint_atom::<4>(&a, p, "test")
+                == err(p, ErrorCode::InvalidArgType, "test requires int arg")
+        );
     }
 
     // u64, 8 bytes
@@ -478,7 +571,7 @@ mod tests {
         use crate::allocator::Allocator;
         let mut a = Allocator::new();
         let n = a.new_atom(buf).unwrap();
-        assert!(uint_atom::<8>(&a, n, "test") == err(n, expected));
+        assert!(uint_atom::<8>(&a, n, "test") == err(n, ErrorCode::InvalidArgType, expected));
     }
 
     #[test]
@@ -487,7 +580,10 @@ mod tests {
         let mut a = Allocator::new();
         let n = a.new_atom(&[0, 0]).unwrap();
         let p = a.new_pair(n, n).unwrap();
-        assert!(uint_atom::<8>(&a, p, "test") == err(p, "test requires int arg"));
+        assert!(
+            uint_atom::<8>(&a, p, "test")
+                == err(p, ErrorCode::InvalidArgType, "test requires int arg")
+        );
     }
 
     #[test]
@@ -543,6 +639,79 @@ mod tests {
         assert_eq!(i32_from_u8(&[0x7d, 0xcc, 0x55, 0x88, 0xf3]), None);
     }
 
+    #[rstest]
+    #[case(&[], &[])]
+    #[case(&[], &[0])]
+    #[case(&[0], &[0])]
+    #[case(&[1, 2, 3], &[1, 2, 3])]
+    #[case(&[1, 2, 3], &[1, 2, 4])]
+    #[case(&[1, 2, 3], &[0, 2, 3])]
+    #[case(&[1, 2, 3], &[1, 2])]
+    #[case(&[0xff; 32], &[0xff; 32])]
+    #[case(&[0xff; 32], &[0x7f; 32])]
+    fn test_constant_time_eq(#[case] lhs: &[u8], #[case] rhs: &[u8]) {
+        assert_eq!(constant_time_eq(lhs, rhs), lhs == rhs);
+        assert_eq!(constant_time_eq(rhs, lhs), lhs == rhs);
+    }
+
+    #[rstest]
+    #[case(&[], true)]
+    #[case(&[0], false)]
+    #[case(&[1], true)]
+    #[case(&[0x7f], true)]
+    #[case(&[0x80], true)]
+    #[case(&[0xff], true)]
+    #[case(&[0, 0], false)]
+    #[case(&[0, 1], false)]
+    #[case(&[0, 0x80], true)]
+    #[case(&[0, 0, 0x80], false)]
+    #[case(&[0xff, 0xff], false)]
+    #[case(&[0xff, 0x7f], true)]
+    #[case(&[0xff, 0x80], false)]
+    #[case(&[0xff, 0xff, 0x7f], false)]
+    #[case(&[1, 0, 0], true)]
+    #[case(&[0x80, 0, 0], true)]
+    fn test_is_canonical_atom_encoding(#[case] buf: &[u8], #[case] expected: bool) {
+        assert_eq!(is_canonical_atom_encoding(buf), expected);
+    }
+
+    #[rstest]
+    #[case(&[], &[])]
+    #[case(&[0], &[])]
+    #[case(&[0, 0, 0], &[])]
+    #[case(&[1], &[1])]
+    #[case(&[0, 1], &[1])]
+    #[case(&[0, 0, 0, 0, 0, 1], &[1])]
+    #[case(&[0, 0x80], &[0, 0x80])]
+    #[case(&[0, 0, 0x80], &[0, 0x80])]
+    #[case(&[0, 0, 0x12, 0x34, 0x56, 0x78, 0x9a], &[0x12, 0x34, 0x56, 0x78, 0x9a])]
+    #[case(&[0xff], &[0xff])]
+    #[case(&[0xff, 0xff, 0xff], &[0xff])]
+    #[case(&[0xff, 0xff, 0x7f], &[0xff, 0x7f])]
+    #[case(&[0xff, 0x80, 0], &[0x80, 0])]
+    #[case(&[0x80, 0, 0], &[0x80, 0, 0])]
+    fn test_normalize_number_atom(#[case] buf: &[u8], #[case] expected: &[u8]) {
+        let mut a = Allocator::new();
+        let node = a.new_atom(buf).unwrap();
+        let normalized = normalize_number_atom(&mut a, node).unwrap();
+        assert_eq!(a.atom(normalized).as_ref(), expected);
+        assert!(is_canonical_atom_encoding(a.atom(normalized).as_ref()));
+        assert_eq!(a.number(normalized), a.number(node));
+        if buf == expected {
+            assert_eq!(normalized, node);
+        }
+    }
+
+    #[test]
+    fn test_normalize_number_atom_pair() {
+        let mut a = Allocator::new();
+        let pair = a.new_pair(NodePtr::NIL, NodePtr::NIL).unwrap();
+        assert_eq!(
+            normalize_number_atom(&mut a, pair).unwrap_err().1,
+            "normalize_number_atom expected atom, got pair"
+        );
+    }
+
     #[test]
     fn test_u64_from_bytes() {
         assert_eq!(u64_from_bytes(&[]), 0);
@@ -0,0 +1,194 @@
+// Optimizations applied to a program before running it. They never change
+// what the program computes, or its cost, only how it's represented in the
+// allocator. None of them are applied unless the caller asks for them.
+
+use crate::allocator::{Allocator, NodePtr, SExp};
+use crate::dialect::Dialect;
+use crate::reduction::EvalErr;
+use crate::serde::{Bytes32, TreeHasher};
+use std::collections::HashMap;
+
+enum Op {
+    Visit(NodePtr),
+    // the optimized arguments of this call are on top of the value stack
+    Call(NodePtr, usize),
+}
+
+/// Returns a program equivalent to `program`, where identical quoted
+/// constants, (q . X), are all the same node. Programs produced by naive
+/// compilers often repeat large constants. Since the result shares them, it
+/// takes less memory when copied to another allocator with `copy_tree()`, and
+/// less time to tree hash or to serialize with back-references.
+///
+/// Only the program itself is scanned, i.e. the arguments of operators. The
+/// contents of constants are left alone, even if they're run with `a`. Nodes
+/// in `program` are only re-created on the path to a constant that was
+/// replaced.
+///
+/// CLVM can't tell two nodes with the same structure apart, so the result
+/// runs with the same cost and produces the same result as `program`.
+pub fn share_constants<D: Dialect>(
+    a: &mut Allocator,
+    dialect: &D,
+    program: NodePtr,
+) -> Result<NodePtr, EvalErr> {
+    let mut hasher = TreeHasher::new();
+    let mut constants = HashMap::<Bytes32, NodePtr>::new();
+    let mut values = Vec::<NodePtr>::new();
+    let mut ops = vec![Op::Visit(program)];
+    while let Some(op) = ops.pop() {
+        match op {
+            Op::Visit(node) => {
+                let SExp::Pair(operator, args) = a.sexp(node) else {
+                    // a path into the environment
+                    values.push(node);
+                    continue;
+                };
+                if operator.is_pair() {
+                    // the ((X) ...) syntax. This is an error when run, so
+                    // there's no point optimizing it
+                    values.push(node);
+                } else if a.small_number(operator) == Some(dialect.quote_kw()) {
+                    let canonical = *constants.entry(hasher.tree_hash(a, node)).or_insert(node);
+                    // the hash can't collide in practice, but this makes
+                    // sure the program is never changed
+                    values.push(if a.tree_eq(canonical, node) {
+                        canonical
+                    } else {
+                        node
+                    });
+                } else {
+                    ops.push(Op::Call(node, 0));
+                    let call_op = ops.len() - 1;
+                    let mut arg_count = 0;
+                    let mut iter = args;
+                    while let Some((arg, rest)) = a.next(iter) {
+                        ops.push(Op::Visit(arg));
+                        arg_count += 1;
+                        iter = rest;
+                    }
+                    ops[call_op] = Op::Call(node, arg_count);
+                    // visit the arguments in order
+                    ops[call_op + 1..].reverse();
+                }
+            }
+            Op::Call(node, arg_count) => {
+                let new_args = values.split_off(values.len() - arg_count);
+                values.push(rebuild_call(a, node, &new_args)?);
+            }
+        }
+    }
+    Ok(values.pop().expect("share_constants: value stack empty"))
+}
+
+// returns the call `node`, with its arguments replaced by `new_args`. If
+// they're all the same as the old ones, `node` itself is returned. The
+// terminator of the argument list is preserved, even if it's not nil
+fn rebuild_call(
+    a: &mut Allocator,
+    node: NodePtr,
+    new_args: &[NodePtr],
+) -> Result<NodePtr, EvalErr> {
+    let (operator, args) = a.next(node).expect("rebuild_call: expected a call");
+    let mut old_args = Vec::with_capacity(new_args.len());
+    let mut iter = args;
+    while let Some((arg, rest)) = a.next(iter) {
+        old_args.push(arg);
+        iter = rest;
+    }
+    if old_args == new_args {
+        return Ok(node);
+    }
+    let mut list = iter;
+    for arg in new_args.iter().rev() {
+        list = a.new_pair(*arg, list)?;
+    }
+    a.new_pair(operator, list)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chia_dialect::ChiaDialect;
+    use crate::op_utils::get_args;
+    use crate::run_program::run_program;
+    use crate::serde::tree_hash;
+    use crate::test_ops::parse_exp;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case("1", "(1 2)")]
+    #[case("(q . 1)", "()")]
+    #[case("(+ (q . 1) (q . 1))", "()")]
+    #[case("(c (q . (1 2 3)) (c (q . (1 2 3)) (q . (1 2 3))))", "()")]
+    #[case(
+        "(sha256 (q . \"foobar\") 2 (q . \"foobar\") (f (q . (\"foobar\"))))",
+        "(1)"
+    )]
+    // the program run by a is a constant, and is not scanned
+    #[case("(a (q + (q . 5) (q . 5)) (q . 5))", "()")]
+    #[case("(a (q . (+ 2 5)) (c (q . 5) (c (q . 5) ())))", "()")]
+    #[case("(i 2 (q . (x)) (q . (x)))", "(1)")]
+    #[case("(softfork (q . 1000) (q . 0) (q . (q . 1)) (q . ()))", "()")]
+    // invalid programs fail the same way
+    #[case("(+ (q . 1) (q . 1) . 1)", "()")]
+    #[case("((q . 1) (q . 1))", "()")]
+    #[case("(f (q . 1) (q . 1))", "()")]
+    fn test_share_constants(#[case] program: &str, #[case] env: &str) {
+        let mut a = Allocator::new();
+        let program = parse_exp(&mut a, program);
+        let env = parse_exp(&mut a, env);
+        let dialect = ChiaDialect::new(0);
+        let optimized = share_constants(&mut a, &dialect, program).expect("share_constants");
+        assert_eq!(tree_hash(&a, optimized), tree_hash(&a, program));
+
+        let expected = run_program(&mut a, &dialect, program, env, 0);
+        let result = run_program(&mut a, &dialect, optimized, env, 0);
+        match (expected, result) {
+            (Ok(expected), Ok(result)) => {
+                assert_eq!(expected.0, result.0);
+                assert!(a.tree_eq(expected.1, result.1));
+            }
+            (Err(expected), Err(result)) => assert_eq!(expected.1, result.1),
+            (expected, result) => panic!("{expected:?} != {result:?}"),
+        }
+    }
+
+    #[test]
+    fn test_constants_are_shared() {
+        let mut a = Allocator::new();
+        let program = parse_exp(&mut a, "(c (q . (1 2 3)) (c 2 (q . (1 2 3))))");
+        let optimized = share_constants(&mut a, &ChiaDialect::new(0), program).unwrap();
+        assert_ne!(optimized, program);
+        let [_, first, rest] = get_args::<3>(&a, optimized, "c").unwrap();
+        let [_, _, second] = get_args::<3>(&a, rest, "c").unwrap();
+        assert_eq!(first, second);
+    }
+
+    #[test]
+    fn test_unchanged() {
+        // when nothing is shared, the program is returned as is, without
+        // allocating anything
+        let mut a = Allocator::new();
+        let program = parse_exp(&mut a, "(c (q . 1) (c 2 (q . 3)))");
+        let before = a.memory_stats();
+        let optimized = share_constants(&mut a, &ChiaDialect::new(0), program).unwrap();
+        assert_eq!(optimized, program);
+        assert_eq!(a.memory_stats(), before);
+    }
+
+    #[test]
+    fn test_deep_program() {
+        // this would overflow the stack if the program was traversed
+        // recursively
+        let mut a = Allocator::new();
+        let mut program = NodePtr::NIL;
+        let constant = parse_exp(&mut a, "(q . \"foobar\")");
+        let op_c = a.new_small_number(4).unwrap();
+        for _ in 0..100_000 {
+            program = a.new_list(&[op_c, constant, program]).unwrap();
+        }
+        let optimized = share_constants(&mut a, &ChiaDialect::new(0), program).unwrap();
+        assert_eq!(optimized, program);
+    }
+}
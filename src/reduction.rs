@@ -2,9 +2,12 @@ use std::{fmt, io};
 
 use crate::allocator::NodePtr;
 use crate::cost::Cost;
+use crate::error::ErrorCode;
 
+/// An error from running a program: the node the error refers to, a message
+/// describing it and the kind of error.
 #[derive(Debug, Clone, PartialEq, Eq)]
-pub struct EvalErr(pub NodePtr, pub String);
+pub struct EvalErr(pub NodePtr, pub String, pub ErrorCode);
 
 #[derive(Debug, PartialEq, Eq)]
 pub struct Reduction(pub Cost, pub NodePtr);
@@ -21,6 +24,6 @@ impl std::error::Error for EvalErr {}
 
 impl From<EvalErr> for io::Error {
     fn from(v: EvalErr) -> Self {
-        Self::new(io::ErrorKind::Other, v.1)
+        Self::other(v.1)
     }
 }
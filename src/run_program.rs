@@ -1,17 +1,30 @@
 use super::traverse_path::{traverse_path, traverse_path_fast};
 use crate::allocator::{Allocator, Checkpoint, NodePtr, NodeVisitor, SExp};
-use crate::cost::Cost;
+use crate::cost::{Cost, CostTracker};
 use crate::dialect::{Dialect, OperatorSet};
 use crate::err_utils::err;
+use crate::error::ErrorCode;
 use crate::op_utils::{first, get_args, uint_atom};
 use crate::reduction::{EvalErr, Reduction, Response};
 
+#[cfg(feature = "trace")]
+use crate::allocator::copy_tree;
+#[cfg(feature = "trace")]
+use crate::trace::{Trace, TraceStep};
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Instant;
+
+#[cfg(feature = "counters")]
+use crate::op_utils::u32_from_u8;
+
 // lowered from 46
-const QUOTE_COST: Cost = 20;
+pub(crate) const QUOTE_COST: Cost = 20;
 // lowered from 138
-const APPLY_COST: Cost = 90;
+pub(crate) const APPLY_COST: Cost = 90;
 // the cost of entering a softfork guard
-const GUARD_COST: Cost = 140;
+pub(crate) const GUARD_COST: Cost = 140;
 // mandatory base cost for every operator we execute
 const OP_COST: Cost = 1;
 
@@ -19,6 +32,10 @@ const OP_COST: Cost = 1;
 // exceeded
 const STACK_SIZE_LIMIT: usize = 20000000;
 
+// The number of operations between checks of the cancellation token or the
+// deadline
+const CANCEL_CHECK_INTERVAL: u32 = 1000;
+
 #[cfg(feature = "pre-eval")]
 pub type PreEval =
     Box<dyn Fn(&mut Allocator, NodePtr, NodePtr) -> Result<Option<Box<PostEval>>, EvalErr>>;
@@ -35,18 +52,52 @@ enum Operation {
 
     #[cfg(feature = "pre-eval")]
     PostEval,
+
+    #[cfg(feature = "trace")]
+    TraceResult,
 }
 
 #[cfg(feature = "counters")]
 #[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Counters {
     pub val_stack_usage: usize,
     pub env_stack_usage: usize,
     pub op_stack_usage: usize,
+    // the max number of nested softfork guards
+    pub softfork_stack_usage: usize,
+    // the number of times the allocator was restored to a checkpoint when
+    // exiting a softfork guard, and the number of atom bytes freed by it
+    pub checkpoint_restores: u32,
+    pub heap_bytes_reclaimed: u64,
     pub atom_count: u32,
     pub small_atom_count: u32,
     pub pair_count: u32,
     pub heap_size: u32,
+    // when enabled (see run_program_with_cost_breakdown()), maps each operator
+    // to the number of times it was invoked and the total cost it incurred.
+    // Operators whose atom doesn't fit in 4 bytes are all recorded under
+    // u32::MAX
+    pub op_costs: Option<HashMap<u32, (u64, Cost)>>,
+    // when enabled (see run_program_with_resource_profile()), the largest
+    // values the program produced
+    pub resource_profile: Option<ResourceProfile>,
+}
+
+/// The largest values a program produced and the deepest path it looked up,
+/// see run_program_with_resource_profile(). These inform what limits future
+/// consensus rules could impose without affecting existing programs.
+#[cfg(feature = "counters")]
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ResourceProfile {
+    /// the length of the largest atom returned by an operator
+    pub max_atom_len: usize,
+    /// the number of elements in the longest list returned by an operator,
+    /// e.g. built by `c`. The terminator of the list is not checked
+    pub max_list_len: usize,
+    /// the number of steps into the environment of the deepest path lookup
+    pub max_path_depth: usize,
 }
 
 #[cfg(feature = "counters")]
@@ -56,14 +107,34 @@ impl Counters {
             val_stack_usage: 0,
             env_stack_usage: 0,
             op_stack_usage: 0,
+            softfork_stack_usage: 0,
+            checkpoint_restores: 0,
+            heap_bytes_reclaimed: 0,
             atom_count: 0,
             small_atom_count: 0,
             pair_count: 0,
             heap_size: 0,
+            op_costs: None,
+            resource_profile: None,
         }
     }
 }
 
+/// The softfork extensions and unknown operators a program used, see
+/// run_program_with_op_usage(). Mempool policy can use this to flag programs
+/// relying on extensions that haven't activated yet, or on unknown operators.
+/// Only operators that succeeded are recorded.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct OpUsage {
+    /// the number of times a softfork guard was entered, by extension. This
+    /// includes extensions the dialect doesn't know about
+    pub softfork_extensions: HashMap<u32, u32>,
+    /// the number of times an unknown operator was invoked, and the total
+    /// cost charged for it, by its first 4 bytes. Operators longer than that
+    /// are counted together by prefix
+    pub unknown_ops: HashMap<Vec<u8>, (u32, Cost)>,
+}
+
 // this represents the state we were in before entering a soft-fork guard. We
 // may need this to long-jump out of the guard, and also to validate the cost
 // when exiting the guard
@@ -80,10 +151,46 @@ struct SoftforkGuard {
     // this specifies which new operators are available
     operator_set: OperatorSet,
 
+    // when tracing, the number of steps recorded when entering the guard. The
+    // steps after this may refer to nodes allocated inside the guard
+    #[cfg(feature = "trace")]
+    trace_start: usize,
+
     #[cfg(test)]
     start_cost: Cost,
 }
 
+// A way to abort a program before it has exhausted its cost limit
+#[derive(Clone, Copy)]
+enum Cancel<'a> {
+    Token(&'a AtomicBool),
+    Deadline(Instant),
+}
+
+impl Cancel<'_> {
+    fn check(&self) -> Result<(), EvalErr> {
+        match self {
+            Cancel::Token(token) if token.load(Ordering::Relaxed) => {
+                err(NodePtr::NIL, ErrorCode::Cancelled, "cancelled")
+            }
+            Cancel::Deadline(deadline) if Instant::now() >= *deadline => err(
+                NodePtr::NIL,
+                ErrorCode::DeadlineExceeded,
+                "deadline exceeded",
+            ),
+            _ => Ok(()),
+        }
+    }
+}
+
+// the result of running the interpreter loop
+enum Progress {
+    Done(Reduction),
+    // the cost budget was used up before the program completed. This is the
+    // cost so far
+    Paused(Cost),
+}
+
 // `run_program` has three stacks:
 // 1. the operand stack of `NodePtr` objects. val_stack
 // 2. the operator stack of Operation. op_stack
@@ -92,30 +199,71 @@ struct SoftforkGuard {
 
 struct RunProgramContext<'a, D> {
     allocator: &'a mut Allocator,
+    // the allocator's atom size limit before the dialect's limit was applied
+    prev_max_atom_size: usize,
     dialect: &'a D,
     val_stack: Vec<NodePtr>,
     env_stack: Vec<NodePtr>,
     op_stack: Vec<Operation>,
     softfork_stack: Vec<SoftforkGuard>,
+    cancel: Option<Cancel<'a>>,
+    // the max number of operations to execute
+    max_reductions: u64,
+    // when set, softfork extensions and unknown operators are recorded
+    op_usage: Option<OpUsage>,
     #[cfg(feature = "counters")]
     pub counters: Counters,
+    // the length of lists returned by operators, and of their tails, when
+    // collecting a resource profile. This keeps the cost of measuring lists
+    // built one `c` at a time linear
+    #[cfg(feature = "counters")]
+    list_lengths: HashMap<NodePtr, usize>,
 
     #[cfg(feature = "pre-eval")]
     pre_eval: Option<PreEval>,
     #[cfg(feature = "pre-eval")]
     posteval_stack: Vec<Box<PostEval>>,
+
+    // when tracing, every evaluation is recorded in trace. trace_stack holds
+    // the index of the step and the cost when the evaluation started, for
+    // every evaluation that hasn't completed yet
+    #[cfg(feature = "trace")]
+    trace: Option<Trace>,
+    #[cfg(feature = "trace")]
+    trace_stack: Vec<(usize, Cost)>,
+    #[cfg(feature = "trace")]
+    current_cost: Cost,
 }
 
 fn augment_cost_errors(r: Result<Cost, EvalErr>, max_cost: NodePtr) -> Result<Cost, EvalErr> {
     r.map_err(|e| {
-        if &e.1 != "cost exceeded" {
+        if e.2 != ErrorCode::CostExceeded {
             e
         } else {
-            EvalErr(max_cost, e.1)
+            EvalErr(max_cost, e.1, e.2)
         }
     })
 }
 
+/// apply the dialect's atom size limit to the allocator, unless the allocator
+/// already has a stricter one. Returns the allocator's previous limit, which
+/// is restored when the RunProgramContext is dropped
+fn limit_atom_size<D: Dialect>(allocator: &mut Allocator, dialect: &D) -> usize {
+    let prev_max_atom_size = allocator.max_atom_size();
+    if let Some(max_atom_size) = dialect.max_atom_size() {
+        if max_atom_size < prev_max_atom_size {
+            allocator.set_max_atom_size(max_atom_size);
+        }
+    }
+    prev_max_atom_size
+}
+
+impl<D> Drop for RunProgramContext<'_, D> {
+    fn drop(&mut self) {
+        self.allocator.set_max_atom_size(self.prev_max_atom_size);
+    }
+}
+
 impl<'a, D: Dialect> RunProgramContext<'a, D> {
     #[cfg(feature = "counters")]
     #[inline(always)]
@@ -138,6 +286,86 @@ impl<'a, D: Dialect> RunProgramContext<'a, D> {
             std::cmp::max(self.counters.op_stack_usage, self.op_stack.len());
     }
 
+    #[cfg(feature = "counters")]
+    #[inline(always)]
+    fn account_softfork_push(&mut self) {
+        self.counters.softfork_stack_usage = std::cmp::max(
+            self.counters.softfork_stack_usage,
+            self.softfork_stack.len(),
+        );
+    }
+
+    #[cfg(feature = "counters")]
+    #[inline(always)]
+    fn account_restore(&mut self, heap_size_before: usize) {
+        self.counters.checkpoint_restores += 1;
+        self.counters.heap_bytes_reclaimed +=
+            (heap_size_before - self.allocator.heap_size()) as u64;
+        // the pairs allocated inside the softfork guard were freed, and their
+        // NodePtrs may be reused
+        self.list_lengths.clear();
+    }
+
+    #[cfg(feature = "counters")]
+    fn account_op_result(&mut self, result: NodePtr) {
+        let Some(profile) = &mut self.counters.resource_profile else {
+            return;
+        };
+        if let SExp::Atom = self.allocator.sexp(result) {
+            profile.max_atom_len = profile.max_atom_len.max(self.allocator.atom_len(result));
+            return;
+        }
+        // walk the list until we reach its end, or a tail we've already
+        // measured, then record the lengths of all the tails we passed
+        let mut tails = Vec::new();
+        let mut node = result;
+        let mut len = 0;
+        while let SExp::Pair(_, rest) = self.allocator.sexp(node) {
+            if let Some(known) = self.list_lengths.get(&node) {
+                len = *known;
+                break;
+            }
+            tails.push(node);
+            node = rest;
+        }
+        for tail in tails.into_iter().rev() {
+            len += 1;
+            self.list_lengths.insert(tail, len);
+        }
+        profile.max_list_len = profile.max_list_len.max(len);
+    }
+
+    #[cfg(feature = "counters")]
+    fn account_path(&mut self, path: NodePtr) {
+        let Some(profile) = &mut self.counters.resource_profile else {
+            return;
+        };
+        // every bit below the most significant 1 bit is a step into the
+        // environment. Leading zero bytes are ignored
+        let atom = self.allocator.atom(path);
+        let buf = atom.as_ref();
+        let Some(pos) = buf.iter().position(|b| *b != 0) else {
+            return;
+        };
+        let depth = (buf.len() - pos - 1) * 8 + (7 - buf[pos].leading_zeros() as usize);
+        profile.max_path_depth = profile.max_path_depth.max(depth);
+    }
+
+    #[cfg(feature = "counters")]
+    fn account_op_cost(&mut self, operator: NodePtr, cost: Cost) {
+        let Some(op_costs) = &mut self.counters.op_costs else {
+            return;
+        };
+        let opcode = match self.allocator.node(operator) {
+            NodeVisitor::U32(val) => val,
+            NodeVisitor::Buffer(buf) => u32_from_u8(buf).unwrap_or(u32::MAX),
+            NodeVisitor::Pair(_, _) => u32::MAX,
+        };
+        let entry = op_costs.entry(opcode).or_insert((0, 0));
+        entry.0 += 1;
+        entry.1 += cost;
+    }
+
     #[cfg(not(feature = "counters"))]
     #[inline(always)]
     fn account_val_push(&mut self) {}
@@ -150,19 +378,84 @@ impl<'a, D: Dialect> RunProgramContext<'a, D> {
     #[inline(always)]
     fn account_op_push(&mut self) {}
 
+    #[cfg(not(feature = "counters"))]
+    #[inline(always)]
+    fn account_softfork_push(&mut self) {}
+
+    #[cfg(not(feature = "counters"))]
+    #[inline(always)]
+    fn account_op_cost(&mut self, _operator: NodePtr, _cost: Cost) {}
+
+    #[cfg(not(feature = "counters"))]
+    #[inline(always)]
+    fn account_op_result(&mut self, _result: NodePtr) {}
+
+    #[cfg(not(feature = "counters"))]
+    #[inline(always)]
+    fn account_path(&mut self, _path: NodePtr) {}
+
+    fn record_softfork(&mut self, operand_list: NodePtr) {
+        let Some(usage) = &mut self.op_usage else {
+            return;
+        };
+        // (softfork cost extension program env)
+        let Some(ext) = self
+            .allocator
+            .next(operand_list)
+            .and_then(|(_, rest)| self.allocator.next(rest))
+            .and_then(|(ext, _)| uint_atom::<4>(self.allocator, ext, "softfork").ok())
+        else {
+            return;
+        };
+        *usage.softfork_extensions.entry(ext as u32).or_default() += 1;
+    }
+
+    fn record_operator(&mut self, operator: NodePtr, extensions: OperatorSet, cost: Cost) {
+        let Some(usage) = &mut self.op_usage else {
+            return;
+        };
+        let atom = self.allocator.atom(operator);
+        let buf = atom.as_ref();
+        // only the canonical representation of an opcode is known
+        let known = !buf.is_empty()
+            && buf.len() <= 4
+            && buf[0] != 0
+            && self
+                .dialect
+                .op_name(
+                    buf.iter().fold(0, |acc, b| (acc << 8) | *b as u32),
+                    extensions,
+                )
+                .is_some();
+        if !known {
+            let prefix = buf[..buf.len().min(4)].to_vec();
+            let entry = usage.unknown_ops.entry(prefix).or_default();
+            entry.0 += 1;
+            entry.1 += cost;
+        }
+    }
+
     pub fn pop(&mut self) -> Result<NodePtr, EvalErr> {
         let v: Option<NodePtr> = self.val_stack.pop();
         match v {
             None => {
                 let node: NodePtr = self.allocator.nil();
-                err(node, "runtime error: value stack empty")
+                err(
+                    node,
+                    ErrorCode::InternalError,
+                    "runtime error: value stack empty",
+                )
             }
             Some(k) => Ok(k),
         }
     }
     pub fn push(&mut self, node: NodePtr) -> Result<(), EvalErr> {
         if self.val_stack.len() == STACK_SIZE_LIMIT {
-            return err(node, "value stack limit reached");
+            return err(
+                node,
+                ErrorCode::ValueStackLimit,
+                "value stack limit reached",
+            );
         }
         self.val_stack.push(node);
         self.account_val_push();
@@ -171,7 +464,11 @@ impl<'a, D: Dialect> RunProgramContext<'a, D> {
 
     pub fn push_env(&mut self, env: NodePtr) -> Result<(), EvalErr> {
         if self.env_stack.len() == STACK_SIZE_LIMIT {
-            return err(env, "environment stack limit reached");
+            return err(
+                env,
+                ErrorCode::EnvironmentStackLimit,
+                "environment stack limit reached",
+            );
         }
         self.env_stack.push(env);
         self.account_env_push();
@@ -184,34 +481,60 @@ impl<'a, D: Dialect> RunProgramContext<'a, D> {
         dialect: &'a D,
         pre_eval: Option<PreEval>,
     ) -> Self {
+        let prev_max_atom_size = limit_atom_size(allocator, dialect);
         RunProgramContext {
             allocator,
+            prev_max_atom_size,
             dialect,
             val_stack: Vec::new(),
             env_stack: Vec::new(),
             op_stack: Vec::new(),
             softfork_stack: Vec::new(),
+            cancel: None,
+            max_reductions: u64::MAX,
+            op_usage: None,
             #[cfg(feature = "counters")]
             counters: Counters::new(),
+            #[cfg(feature = "counters")]
+            list_lengths: HashMap::new(),
             pre_eval,
             posteval_stack: Vec::new(),
+            #[cfg(feature = "trace")]
+            trace: None,
+            #[cfg(feature = "trace")]
+            trace_stack: Vec::new(),
+            #[cfg(feature = "trace")]
+            current_cost: 0,
         }
     }
 
     fn new(allocator: &'a mut Allocator, dialect: &'a D) -> Self {
+        let prev_max_atom_size = limit_atom_size(allocator, dialect);
         RunProgramContext {
             allocator,
+            prev_max_atom_size,
             dialect,
             val_stack: Vec::new(),
             env_stack: Vec::new(),
             op_stack: Vec::new(),
             softfork_stack: Vec::new(),
+            cancel: None,
+            max_reductions: u64::MAX,
+            op_usage: None,
             #[cfg(feature = "counters")]
             counters: Counters::new(),
+            #[cfg(feature = "counters")]
+            list_lengths: HashMap::new(),
             #[cfg(feature = "pre-eval")]
             pre_eval: None,
             #[cfg(feature = "pre-eval")]
             posteval_stack: Vec::new(),
+            #[cfg(feature = "trace")]
+            trace: None,
+            #[cfg(feature = "trace")]
+            trace_stack: Vec::new(),
+            #[cfg(feature = "trace")]
+            current_cost: 0,
         }
     }
 
@@ -259,7 +582,7 @@ impl<'a, D: Dialect> RunProgramContext<'a, D> {
             }
             // ensure a correct nil terminator
             if self.allocator.atom_len(operands) != 0 {
-                err(operand_list, "bad operand list")
+                err(operand_list, ErrorCode::Unknown, "bad operand list")
             } else {
                 self.push(self.allocator.nil())?;
                 Ok(OP_COST)
@@ -276,9 +599,23 @@ impl<'a, D: Dialect> RunProgramContext<'a, D> {
             }
         };
 
+        #[cfg(feature = "trace")]
+        if let Some(trace) = &mut self.trace {
+            self.trace_stack
+                .push((trace.steps.len(), self.current_cost));
+            trace.steps.push(TraceStep {
+                program,
+                env,
+                result: None,
+                cost: 0,
+            });
+            self.op_stack.push(Operation::TraceResult);
+        }
+
         // put a bunch of ops on op_stack
         let SExp::Pair(op_node, op_list) = self.allocator.sexp(program) else {
             // the program is just a bitfield path through the env tree
+            self.account_path(program);
             let r = match self.allocator.node(program) {
                 NodeVisitor::Buffer(buf) => traverse_path(self.allocator, buf, env)?,
                 NodeVisitor::U32(val) => traverse_path_fast(self.allocator, val, env)?,
@@ -298,7 +635,11 @@ impl<'a, D: Dialect> RunProgramContext<'a, D> {
                     "in the ((X)...) syntax, the inner list",
                 )?;
                 if let SExp::Pair(_, _) = self.allocator.sexp(inner) {
-                    return err(program, "in ((X)...) syntax X must be lone atom");
+                    return err(
+                        program,
+                        ErrorCode::InvalidOperator,
+                        "in ((X)...) syntax X must be lone atom",
+                    );
                 }
                 self.push_env(env)?;
                 self.push(new_operator)?;
@@ -314,10 +655,13 @@ impl<'a, D: Dialect> RunProgramContext<'a, D> {
     fn swap_eval_op(&mut self) -> Result<Cost, EvalErr> {
         let v2 = self.pop()?;
         let program: NodePtr = self.pop()?;
-        let env: NodePtr = *self
-            .env_stack
-            .last()
-            .ok_or_else(|| EvalErr(program, "runtime error: env stack empty".into()))?;
+        let env: NodePtr = *self.env_stack.last().ok_or_else(|| {
+            EvalErr(
+                program,
+                "runtime error: env stack empty".into(),
+                ErrorCode::InternalError,
+            )
+        })?;
         self.push(v2)?;
 
         // on the way back, build a list from the values
@@ -337,7 +681,7 @@ impl<'a, D: Dialect> RunProgramContext<'a, D> {
             self.dialect
                 .softfork_extension(uint_atom::<4>(self.allocator, extension, "softfork")? as u32);
         if extension == OperatorSet::Default {
-            err(args, "unknown softfork extension")
+            err(args, ErrorCode::Softfork, "unknown softfork extension")
         } else {
             Ok((extension, program, env))
         }
@@ -347,7 +691,11 @@ impl<'a, D: Dialect> RunProgramContext<'a, D> {
         let operand_list = self.pop()?;
         let operator = self.pop()?;
         if self.env_stack.pop().is_none() {
-            return err(operator, "runtime error: env stack empty");
+            return err(
+                operator,
+                ErrorCode::InternalError,
+                "runtime error: env stack empty",
+            );
         }
         let op_atom = self.allocator.small_number(operator);
 
@@ -361,11 +709,12 @@ impl<'a, D: Dialect> RunProgramContext<'a, D> {
                 "softfork",
             )?;
             if expected_cost > max_cost {
-                return err(operand_list, "cost exceeded");
+                return err(operand_list, ErrorCode::CostExceeded, "cost exceeded");
             }
             if expected_cost == 0 {
-                return err(operand_list, "cost must be > 0");
+                return err(operand_list, ErrorCode::Softfork, "cost must be > 0");
             }
+            self.record_softfork(operand_list);
 
             // we can't blindly propagate errors here, since we handle errors
             // differently depending on whether we allow unknown ops or not
@@ -388,9 +737,12 @@ impl<'a, D: Dialect> RunProgramContext<'a, D> {
                 expected_cost: current_cost + expected_cost,
                 allocator_state: self.allocator.checkpoint(),
                 operator_set: ext,
+                #[cfg(feature = "trace")]
+                trace_start: self.trace.as_ref().map_or(0, |t| t.steps.len()),
                 #[cfg(test)]
                 start_cost: current_cost,
             });
+            self.account_softfork_push();
 
             // once the softfork guard exits, we need to ensure the cost that was
             // specified match the true cost. We also free heap allocations
@@ -411,11 +763,61 @@ impl<'a, D: Dialect> RunProgramContext<'a, D> {
                 max_cost,
                 current_extensions,
             )?;
+            self.record_operator(operator, current_extensions, r.0);
+            self.account_op_cost(operator, r.0);
+            self.account_op_result(r.1);
             self.push(r.1)?;
             Ok(r.0)
         }
     }
 
+    // copies the nodes of the trace steps, starting at index start, into a
+    // separate allocator. Returns None when not tracing
+    #[cfg(feature = "trace")]
+    fn save_trace_nodes(&mut self, start: usize) -> Result<Option<(Allocator, NodePtr)>, EvalErr> {
+        let Some(trace) = &self.trace else {
+            return Ok(None);
+        };
+        // all nodes are collected into a single list, so nodes shared between
+        // steps are only copied once
+        let mut list = self.allocator.nil();
+        for step in trace.steps[start..].iter().rev() {
+            for node in [step.result.unwrap_or(NodePtr::NIL), step.env, step.program] {
+                list = self.allocator.new_pair(node, list)?;
+            }
+        }
+        let mut saved = Allocator::new();
+        let list = copy_tree(self.allocator, list, &mut saved)?;
+        Ok(Some((saved, list)))
+    }
+
+    // the reverse of save_trace_nodes(). Copies the nodes back into the
+    // allocator and updates the trace steps to refer to the copies
+    #[cfg(feature = "trace")]
+    fn restore_trace_nodes(
+        &mut self,
+        start: usize,
+        saved: Option<(Allocator, NodePtr)>,
+    ) -> Result<(), EvalErr> {
+        let (Some(trace), Some((saved, list))) = (&mut self.trace, saved) else {
+            return Ok(());
+        };
+        let mut list = copy_tree(&saved, list, self.allocator)?;
+        let mut nodes = Vec::new();
+        while let SExp::Pair(first, rest) = self.allocator.sexp(list) {
+            nodes.push(first);
+            list = rest;
+        }
+        for (step, nodes) in trace.steps[start..].iter_mut().zip(nodes.chunks_exact(3)) {
+            step.program = nodes[0];
+            step.env = nodes[1];
+            if step.result.is_some() {
+                step.result = Some(nodes[2]);
+            }
+        }
+        Ok(())
+    }
+
     fn exit_guard(&mut self, current_cost: Cost) -> Result<Cost, EvalErr> {
         // this is called when we are done executing a softfork program.
         // This is when we have to validate the cost
@@ -431,7 +833,11 @@ impl<'a, D: Dialect> RunProgramContext<'a, D> {
                 current_cost - guard.start_cost,
                 guard.expected_cost - guard.start_cost
             );
-            return err(self.allocator.nil(), "softfork specified cost mismatch");
+            return err(
+                self.allocator.nil(),
+                ErrorCode::Softfork,
+                "softfork specified cost mismatch",
+            );
         }
 
         // restore the allocator to the state when we entered the softfork guard
@@ -439,7 +845,18 @@ impl<'a, D: Dialect> RunProgramContext<'a, D> {
         // softfork program. Since the softfork always return nil, no value can
         // escape the softfork program, and it's therefore safe to restore the
         // heap
+        // When tracing, the recorded steps may refer to nodes allocated inside
+        // the softfork guard, so they are copied out before restoring, and
+        // copied back in afterwards
+        #[cfg(feature = "trace")]
+        let traced = self.save_trace_nodes(guard.trace_start)?;
+        #[cfg(feature = "counters")]
+        let heap_size = self.allocator.heap_size();
         self.allocator.restore_checkpoint(&guard.allocator_state);
+        #[cfg(feature = "counters")]
+        self.account_restore(heap_size);
+        #[cfg(feature = "trace")]
+        self.restore_trace_nodes(guard.trace_start, traced)?;
 
         // the softfork always returns nil, pop the value pushed by the
         // evaluation of the program and push nil instead
@@ -452,6 +869,22 @@ impl<'a, D: Dialect> RunProgramContext<'a, D> {
     }
 
     pub fn run_program(&mut self, program: NodePtr, env: NodePtr, max_cost: Cost) -> Response {
+        let (cost, max_cost, max_cost_ptr) = self.start(program, env, max_cost)?;
+        match self.run_loop(cost, max_cost, max_cost_ptr, Cost::MAX)? {
+            Progress::Done(reduction) => Ok(reduction),
+            Progress::Paused(_) => unreachable!("the budget is unlimited"),
+        }
+    }
+
+    // clears the stacks and evaluates the program, leaving the operations to
+    // execute on the op_stack. Returns the cost so far and the max cost (as a
+    // number and as a node)
+    fn start(
+        &mut self,
+        program: NodePtr,
+        env: NodePtr,
+        max_cost: Cost,
+    ) -> Result<(Cost, Cost, NodePtr), EvalErr> {
         self.val_stack = vec![];
         self.op_stack = vec![];
 
@@ -460,35 +893,71 @@ impl<'a, D: Dialect> RunProgramContext<'a, D> {
         let max_cost = if max_cost == 0 { Cost::MAX } else { max_cost };
         let max_cost_ptr = self.allocator.new_number(max_cost.into())?;
 
-        let mut cost: Cost = 0;
-
-        cost += self.eval_pair(program, env)?;
+        let cost = self.eval_pair(program, env)?;
+        Ok((cost, max_cost, max_cost_ptr))
+    }
 
+    // executes the operations on the op_stack until it's empty, or until the
+    // cost reaches pause_cost. In the latter case the stacks are left as they
+    // are, and the returned cost can be passed back in to continue.
+    fn run_loop(
+        &mut self,
+        cost: Cost,
+        max_cost: Cost,
+        max_cost_ptr: NodePtr,
+        pause_cost: Cost,
+    ) -> Result<Progress, EvalErr> {
+        let mut cost = CostTracker::new(cost, max_cost);
+        let mut steps: u32 = 0;
+        let mut reductions: u64 = 0;
         loop {
+            if let Some(cancel) = &self.cancel {
+                steps += 1;
+                if steps == CANCEL_CHECK_INTERVAL {
+                    steps = 0;
+                    cancel.check()?;
+                }
+            }
+
             // if we are in a softfork guard, temporarily use the guard's
             // expected cost as the upper limit. This lets us fail early in case
             // it's wrong. It's guaranteed to be <= max_cost, because we check
             // that when entering the softfork guard
-            let effective_max_cost = if let Some(sf) = self.softfork_stack.last() {
+            cost.set_max_cost(if let Some(sf) = self.softfork_stack.last() {
                 sf.expected_cost
             } else {
                 max_cost
-            };
+            });
 
-            if cost > effective_max_cost {
-                return err(max_cost_ptr, "cost exceeded");
+            if cost.exceeded() {
+                return err(max_cost_ptr, ErrorCode::CostExceeded, "cost exceeded");
+            }
+            // we always make some progress before pausing
+            if cost.cost() >= pause_cost && reductions > 0 && !self.op_stack.is_empty() {
+                return Ok(Progress::Paused(cost.cost()));
+            }
+            #[cfg(feature = "trace")]
+            {
+                self.current_cost = cost.cost();
             }
             let top = self.op_stack.pop();
             let op = match top {
                 Some(f) => f,
                 None => break,
             };
-            cost += match op {
-                Operation::Apply => augment_cost_errors(
-                    self.apply_op(cost, effective_max_cost - cost),
-                    max_cost_ptr,
-                )?,
-                Operation::ExitGuard => self.exit_guard(cost)?,
+            reductions += 1;
+            if reductions > self.max_reductions {
+                return err(
+                    NodePtr::NIL,
+                    ErrorCode::TooManyReductions,
+                    "too many reductions",
+                );
+            }
+            cost.add(match op {
+                Operation::Apply => {
+                    augment_cost_errors(self.apply_op(cost.cost(), cost.remaining()), max_cost_ptr)?
+                }
+                Operation::ExitGuard => self.exit_guard(cost.cost())?,
                 Operation::Cons => self.cons_op()?,
                 Operation::SwapEval => augment_cost_errors(self.swap_eval_op(), max_cost_ptr)?,
                 #[cfg(feature = "pre-eval")]
@@ -498,9 +967,17 @@ impl<'a, D: Dialect> RunProgramContext<'a, D> {
                     f(self.allocator, peek);
                     0
                 }
-            };
+                #[cfg(feature = "trace")]
+                Operation::TraceResult => {
+                    let (idx, start_cost) = self.trace_stack.pop().unwrap();
+                    let step = &mut self.trace.as_mut().unwrap().steps[idx];
+                    step.result = self.val_stack.last().copied();
+                    step.cost = cost.cost() - start_cost;
+                    0
+                }
+            });
         }
-        Ok(Reduction(cost, self.pop()?))
+        Ok(Progress::Done(cost.reduction(self.pop()?)))
     }
 }
 
@@ -515,6 +992,167 @@ pub fn run_program<'a, D: Dialect>(
     rpc.run_program(program, env, max_cost)
 }
 
+/// Like run_program(), but fails with "cancelled" once `token` is set. The
+/// token is checked periodically, so the program may run for a short while
+/// after it's set. This lets a server abort evaluations from another thread.
+pub fn run_program_with_cancellation<'a, D: Dialect>(
+    allocator: &'a mut Allocator,
+    dialect: &'a D,
+    program: NodePtr,
+    env: NodePtr,
+    max_cost: Cost,
+    token: &'a AtomicBool,
+) -> Response {
+    let mut rpc = RunProgramContext::new(allocator, dialect);
+    rpc.cancel = Some(Cancel::Token(token));
+    rpc.run_program(program, env, max_cost)
+}
+
+/// Like run_program(), but fails with "deadline exceeded" if the program is
+/// still running at `deadline`. The cost limit doesn't tightly bound the
+/// wall-clock time, e.g. when the allocator is under pressure.
+pub fn run_program_with_deadline<'a, D: Dialect>(
+    allocator: &'a mut Allocator,
+    dialect: &'a D,
+    program: NodePtr,
+    env: NodePtr,
+    max_cost: Cost,
+    deadline: Instant,
+) -> Response {
+    let mut rpc = RunProgramContext::new(allocator, dialect);
+    rpc.cancel = Some(Cancel::Deadline(deadline));
+    rpc.run_program(program, env, max_cost)
+}
+
+/// The state of a program whose evaluation was suspended, see
+/// run_program_with_budget(). It refers to nodes in the allocator the program
+/// was run with. That allocator must be passed to resume(), and must not be
+/// reset or restored to an earlier checkpoint in the meantime. Since exiting a
+/// softfork guard frees everything allocated inside it, suspended evaluations
+/// that are interleaved should not share an allocator.
+pub struct SuspendedEvaluation {
+    val_stack: Vec<NodePtr>,
+    env_stack: Vec<NodePtr>,
+    op_stack: Vec<Operation>,
+    softfork_stack: Vec<SoftforkGuard>,
+    cost: Cost,
+    max_cost: Cost,
+    max_cost_ptr: NodePtr,
+    allocator_state: Checkpoint,
+}
+
+pub enum EvalState {
+    Done(Reduction),
+    Suspended(SuspendedEvaluation),
+}
+
+impl SuspendedEvaluation {
+    /// the cost spent so far
+    pub fn cost(&self) -> Cost {
+        self.cost
+    }
+
+    /// Continue the evaluation until it completes, or until another `budget`
+    /// of cost has been spent. The dialect should be the same as the program
+    /// was started with.
+    pub fn resume<D: Dialect>(
+        self,
+        allocator: &mut Allocator,
+        dialect: &D,
+        budget: Cost,
+    ) -> Result<EvalState, EvalErr> {
+        if !allocator.is_descendant_of(&self.allocator_state) {
+            return err(NodePtr::NIL, ErrorCode::Unknown, "allocator was reset");
+        }
+        let mut rpc = RunProgramContext::new(allocator, dialect);
+        rpc.val_stack = self.val_stack;
+        rpc.env_stack = self.env_stack;
+        rpc.op_stack = self.op_stack;
+        rpc.softfork_stack = self.softfork_stack;
+        let pause_cost = self.cost.saturating_add(budget);
+        run_until(
+            &mut rpc,
+            self.cost,
+            self.max_cost,
+            self.max_cost_ptr,
+            pause_cost,
+        )
+    }
+}
+
+fn run_until<D: Dialect>(
+    rpc: &mut RunProgramContext<'_, D>,
+    cost: Cost,
+    max_cost: Cost,
+    max_cost_ptr: NodePtr,
+    pause_cost: Cost,
+) -> Result<EvalState, EvalErr> {
+    match rpc.run_loop(cost, max_cost, max_cost_ptr, pause_cost)? {
+        Progress::Done(reduction) => Ok(EvalState::Done(reduction)),
+        Progress::Paused(cost) => Ok(EvalState::Suspended(SuspendedEvaluation {
+            val_stack: std::mem::take(&mut rpc.val_stack),
+            env_stack: std::mem::take(&mut rpc.env_stack),
+            op_stack: std::mem::take(&mut rpc.op_stack),
+            softfork_stack: std::mem::take(&mut rpc.softfork_stack),
+            cost,
+            max_cost,
+            max_cost_ptr,
+            allocator_state: rpc.allocator.checkpoint(),
+        })),
+    }
+}
+
+/// Like run_program(), but the evaluation is suspended once `budget` cost has
+/// been spent (the check is made between operations, so the budget may be
+/// overrun by one operation, and at least one operation is always executed).
+/// The returned SuspendedEvaluation can be resumed later. This allows many
+/// programs to be interleaved on a single thread. max_cost is the limit for
+/// the whole evaluation, across all resumptions.
+pub fn run_program_with_budget<'a, D: Dialect>(
+    allocator: &'a mut Allocator,
+    dialect: &'a D,
+    program: NodePtr,
+    env: NodePtr,
+    max_cost: Cost,
+    budget: Cost,
+) -> Result<EvalState, EvalErr> {
+    let mut rpc = RunProgramContext::new(allocator, dialect);
+    let (cost, max_cost, max_cost_ptr) = rpc.start(program, env, max_cost)?;
+    run_until(&mut rpc, cost, max_cost, max_cost_ptr, budget)
+}
+
+/// Like run_program(), but fails with "too many reductions" once more than
+/// `max_reductions` operations have been executed. Unlike the cost, this bound
+/// doesn't depend on the cost model, which may change across forks.
+pub fn run_program_with_max_reductions<'a, D: Dialect>(
+    allocator: &'a mut Allocator,
+    dialect: &'a D,
+    program: NodePtr,
+    env: NodePtr,
+    max_cost: Cost,
+    max_reductions: u64,
+) -> Response {
+    let mut rpc = RunProgramContext::new(allocator, dialect);
+    rpc.max_reductions = max_reductions;
+    rpc.run_program(program, env, max_cost)
+}
+
+/// Like run_program(), but also returns the softfork extensions and unknown
+/// operators the program used. The usage is returned even if the program
+/// fails.
+pub fn run_program_with_op_usage<'a, D: Dialect>(
+    allocator: &'a mut Allocator,
+    dialect: &'a D,
+    program: NodePtr,
+    env: NodePtr,
+    max_cost: Cost,
+) -> (OpUsage, Response) {
+    let mut rpc = RunProgramContext::new(allocator, dialect);
+    rpc.op_usage = Some(OpUsage::default());
+    let ret = rpc.run_program(program, env, max_cost);
+    (rpc.op_usage.take().unwrap_or_default(), ret)
+}
+
 #[cfg(feature = "pre-eval")]
 pub fn run_program_with_pre_eval<'a, D: Dialect>(
     allocator: &'a mut Allocator,
@@ -528,6 +1166,22 @@ pub fn run_program_with_pre_eval<'a, D: Dialect>(
     rpc.run_program(program, env, max_cost)
 }
 
+// runs the program and records every evaluation (program, environment,
+// result and cost) in a Trace. The trace is returned even if the program fails
+#[cfg(feature = "trace")]
+pub fn run_program_with_trace<'a, D: Dialect>(
+    allocator: &'a mut Allocator,
+    dialect: &'a D,
+    program: NodePtr,
+    env: NodePtr,
+    max_cost: Cost,
+) -> (Trace, Response) {
+    let mut rpc = RunProgramContext::new(allocator, dialect);
+    rpc.trace = Some(Trace::default());
+    let ret = rpc.run_program(program, env, max_cost);
+    (rpc.trace.take().unwrap(), ret)
+}
+
 #[cfg(feature = "counters")]
 pub fn run_program_with_counters<'a, D: Dialect>(
     allocator: &'a mut Allocator,
@@ -537,19 +1191,62 @@ pub fn run_program_with_counters<'a, D: Dialect>(
     max_cost: Cost,
 ) -> (Counters, Response) {
     let mut rpc = RunProgramContext::new(allocator, dialect);
+    run_with_counters(&mut rpc, program, env, max_cost)
+}
+
+// like run_program_with_counters(), but also collects the number of
+// invocations and the total cost of each operator, in Counters::op_costs
+#[cfg(feature = "counters")]
+pub fn run_program_with_cost_breakdown<'a, D: Dialect>(
+    allocator: &'a mut Allocator,
+    dialect: &'a D,
+    program: NodePtr,
+    env: NodePtr,
+    max_cost: Cost,
+) -> (Counters, Response) {
+    let mut rpc = RunProgramContext::new(allocator, dialect);
+    rpc.counters.op_costs = Some(HashMap::new());
+    run_with_counters(&mut rpc, program, env, max_cost)
+}
+
+// like run_program_with_counters(), but also collects the largest atom and
+// list produced, and the deepest path looked up, in Counters::resource_profile
+#[cfg(feature = "counters")]
+pub fn run_program_with_resource_profile<'a, D: Dialect>(
+    allocator: &'a mut Allocator,
+    dialect: &'a D,
+    program: NodePtr,
+    env: NodePtr,
+    max_cost: Cost,
+) -> (Counters, Response) {
+    let mut rpc = RunProgramContext::new(allocator, dialect);
+    rpc.counters.resource_profile = Some(ResourceProfile::default());
+    run_with_counters(&mut rpc, program, env, max_cost)
+}
+
+#[cfg(feature = "counters")]
+fn run_with_counters<D: Dialect>(
+    rpc: &mut RunProgramContext<'_, D>,
+    program: NodePtr,
+    env: NodePtr,
+    max_cost: Cost,
+) -> (Counters, Response) {
     let ret = rpc.run_program(program, env, max_cost);
     rpc.counters.atom_count = rpc.allocator.atom_count() as u32;
     rpc.counters.small_atom_count = rpc.allocator.small_atom_count() as u32;
     rpc.counters.pair_count = rpc.allocator.pair_count() as u32;
     rpc.counters.heap_size = rpc.allocator.heap_size() as u32;
-    (rpc.counters, ret)
+    (std::mem::replace(&mut rpc.counters, Counters::new()), ret)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
-    use crate::chia_dialect::{ENABLE_KECCAK, ENABLE_KECCAK_OPS_OUTSIDE_GUARD, NO_UNKNOWN_OPS};
+    use crate::chia_dialect::{
+        ENABLE_CURRY_OPS, ENABLE_HASH_OPS, ENABLE_KECCAK, ENABLE_KECCAK_OPS_OUTSIDE_GUARD,
+        LIMIT_ATOM_SIZE, MAX_ATOM_SIZE, NO_UNKNOWN_OPS,
+    };
     use crate::test_ops::parse_exp;
 
     use rstest::rstest;
@@ -1213,6 +1910,108 @@ mod tests {
             err: "",
         },
 
+        // sha3_256 and sha512_256 are available in extension 2, when the softfork
+        // has activated
+        RunProgramTest {
+            prg: "(softfork (q . 1134) (q . 2) (q a (i (= (sha3_256 (q . \"foobar\")) (q . 0x09234807e4af85f17c66b48ee3bca89dffd1f1233659f9f940a2b17b0b8c6bc5)) (q . 0) (q x)) (q . ())) (q . ()))",
+            args: "()",
+            flags: ENABLE_HASH_OPS,
+            result: Some("()"),
+            cost: 1215,
+            err: "",
+        },
+        RunProgramTest {
+            prg: "(softfork (q . 1145) (q . 2) (q a (i (= (sha512_256 (q . \"foobar\")) (q . 0xd014c752bc2be868e16330f47e0c316a5967bcbc9c286a457761d7055b9214ce)) (q . 0) (q x)) (q . ())) (q . ()))",
+            args: "()",
+            flags: ENABLE_HASH_OPS,
+            result: Some("()"),
+            cost: 1226,
+            err: "",
+        },
+        // make sure sha3_256 is actually executed, by comparing with the wrong output
+        RunProgramTest {
+            prg: "(softfork (q . 1134) (q . 2) (q a (i (= (sha3_256 (q . \"foobar\")) (q . 0x19234807e4af85f17c66b48ee3bca89dffd1f1233659f9f940a2b17b0b8c6bc5)) (q . 0) (q x)) (q . ())) (q . ()))",
+            args: "()",
+            flags: ENABLE_HASH_OPS,
+            result: None,
+            cost: 1215,
+            err: "clvm raise",
+        },
+        // the hash operators are not available in the keccak extension
+        RunProgramTest {
+            prg: "(softfork (q . 1134) (q . 1) (q a (i (= (sha3_256 (q . \"foobar\")) (q . 0x09234807e4af85f17c66b48ee3bca89dffd1f1233659f9f940a2b17b0b8c6bc5)) (q . 0) (q x)) (q . ())) (q . ()))",
+            args: "()",
+            flags: ENABLE_KECCAK | ENABLE_HASH_OPS | NO_UNKNOWN_OPS,
+            result: None,
+            cost: 1215,
+            err: "unimplemented operator",
+        },
+        // nor outside the guard
+        RunProgramTest {
+            prg: "(sha3_256 (q . \"foobar\"))",
+            args: "()",
+            flags: ENABLE_HASH_OPS | NO_UNKNOWN_OPS,
+            result: None,
+            cost: 1215,
+            err: "unimplemented operator",
+        },
+        // the hash operators are ignored when the softfork has not activated
+        RunProgramTest {
+            prg: "(softfork (q . 1134) (q . 2) (q a (i (= (sha3_256 (q . \"foobar\")) (q . 0x19234807e4af85f17c66b48ee3bca89dffd1f1233659f9f940a2b17b0b8c6bc5)) (q . 0) (q x)) (q . ())) (q . ()))",
+            args: "()",
+            flags: 0,
+            result: Some("()"),
+            cost: 1215,
+            err: "",
+        },
+
+        // the experimental curry and uncurry operators are available in
+        // extension 3, when enabled
+        RunProgramTest {
+            prg: "(softfork (q . 1773) (q . 3) (q a (i (= (f (r (uncurry (curry (q . 1) (q . 2) (q . 3))))) (q . 2)) (q . 0) (q x)) (q . ())) (q . ()))",
+            args: "()",
+            flags: ENABLE_CURRY_OPS,
+            result: Some("()"),
+            cost: 1854,
+            err: "",
+        },
+        // make sure uncurry is actually executed, by comparing with the wrong output
+        RunProgramTest {
+            prg: "(softfork (q . 1773) (q . 3) (q a (i (= (f (r (uncurry (curry (q . 1) (q . 2) (q . 3))))) (q . 3)) (q . 0) (q x)) (q . ())) (q . ()))",
+            args: "()",
+            flags: ENABLE_CURRY_OPS,
+            result: None,
+            cost: 1854,
+            err: "clvm raise",
+        },
+        // the curried program can be run
+        RunProgramTest {
+            prg: "(softfork (q . 2003) (q . 3) (q a (i (a (curry (q + 2 5) (q . 40)) (q 2)) (q . 0) (q x)) (q . ())) (q . ()))",
+            args: "()",
+            flags: ENABLE_CURRY_OPS,
+            result: Some("()"),
+            cost: 2084,
+            err: "",
+        },
+        // the curry operators are not available outside the guard
+        RunProgramTest {
+            prg: "(curry (q . 1) (q . 2))",
+            args: "()",
+            flags: ENABLE_CURRY_OPS | NO_UNKNOWN_OPS,
+            result: None,
+            cost: 0,
+            err: "unimplemented operator",
+        },
+        // and extension 3 is unknown unless enabled
+        RunProgramTest {
+            prg: "(softfork (q . 1773) (q . 3) (q a (i (= (f (r (uncurry (curry (q . 1) (q . 2) (q . 3))))) (q . 2)) (q . 0) (q x)) (q . ())) (q . ()))",
+            args: "()",
+            flags: NO_UNKNOWN_OPS,
+            result: None,
+            cost: 0,
+            err: "unknown softfork extension",
+        },
+
         // === HARD FORK ===
         // new operators *outside* the softfork guard
 
@@ -1309,19 +2108,14 @@ mod tests {
         },
     ];
 
-    fn check(res: (NodePtr, &str)) -> NodePtr {
-        assert_eq!(res.1, "");
-        res.0
-    }
-
     fn run_test_case(t: &RunProgramTest) {
         use crate::chia_dialect::ChiaDialect;
         use crate::test_ops::node_eq;
         let mut allocator = Allocator::new();
 
-        let program = check(parse_exp(&mut allocator, t.prg));
-        let args = check(parse_exp(&mut allocator, t.args));
-        let expected_result = &t.result.map(|v| check(parse_exp(&mut allocator, v)));
+        let program = parse_exp(&mut allocator, t.prg);
+        let args = parse_exp(&mut allocator, t.args);
+        let expected_result = &t.result.map(|v| parse_exp(&mut allocator, v));
 
         let dialect = ChiaDialect::new(t.flags);
         println!("prg: {}", t.prg);
@@ -1556,6 +2350,271 @@ mod tests {
         run_test_case(&t);
     }
 
+    // a program that never terminates (other than by running out of cost)
+    const INFINITE_LOOP: &str = "(a (q 2 2 (c 2 ())) (c (q 2 2 (c 2 ())) ()))";
+
+    #[rstest]
+    #[case(false, Some("cost exceeded"))]
+    #[case(true, Some("cancelled"))]
+    fn test_cancellation(#[case] cancelled: bool, #[case] expected_err: Option<&str>) {
+        use crate::chia_dialect::ChiaDialect;
+        let mut a = Allocator::new();
+        let program = parse_exp(&mut a, INFINITE_LOOP);
+        let token = AtomicBool::new(cancelled);
+        let dialect = ChiaDialect::new(0);
+        let result = run_program_with_cancellation(
+            &mut a,
+            &dialect,
+            program,
+            NodePtr::NIL,
+            10_000_000,
+            &token,
+        );
+        assert_eq!(
+            result.err().map(|e| e.1),
+            expected_err.map(|e| e.to_string())
+        );
+
+        // a program that terminates isn't affected by the token
+        let program = parse_exp(&mut a, "(+ (q . 1) (q . 2))");
+        let token = AtomicBool::new(cancelled);
+        let result = run_program_with_cancellation(
+            &mut a,
+            &dialect,
+            program,
+            NodePtr::NIL,
+            10_000_000,
+            &token,
+        )
+        .unwrap();
+        assert_eq!(a.number(result.1), 3.into());
+    }
+
+    #[rstest]
+    #[case("(q . 1)", 0, false)]
+    #[case("(+ (q . 1) (q . 2))", 5, false)]
+    #[case("(+ (q . 1) (q . 2))", 4, true)]
+    #[case("(+ (q . 1) (+ (q . 2) (q . 3)))", 10, false)]
+    #[case("(+ (q . 1) (+ (q . 2) (q . 3)))", 9, true)]
+    #[case(INFINITE_LOOP, 10000, true)]
+    fn test_max_reductions(#[case] prg: &str, #[case] max_reductions: u64, #[case] exceeded: bool) {
+        use crate::chia_dialect::ChiaDialect;
+        let mut a = Allocator::new();
+        let program = parse_exp(&mut a, prg);
+        let result = run_program_with_max_reductions(
+            &mut a,
+            &ChiaDialect::new(0),
+            program,
+            NodePtr::NIL,
+            10_000_000,
+            max_reductions,
+        );
+        if exceeded {
+            assert_eq!(result.unwrap_err().1, "too many reductions");
+        } else {
+            assert!(result.is_ok());
+        }
+    }
+
+    #[rstest]
+    #[case(0)]
+    #[case(1)]
+    #[case(1000)]
+    #[case(100000)]
+    #[case(Cost::MAX)]
+    fn test_suspend_resume(#[case] budget: Cost) {
+        use crate::chia_dialect::ChiaDialect;
+        let mut a = Allocator::new();
+        // this is the program from test_counters()
+        let program = parse_exp(&mut a, "(a (q 2 2 (c 2 (c 5 (c 11 ())))) (c (q 2 (i (= 11 ()) (q 1 . 1) (q 18 5 (a 2 (c 2 (c 5 (c (- 11 (q . 1)) ())))))) 1) 1))");
+        let args = parse_exp(&mut a, "(5033 100)");
+        let dialect = ChiaDialect::new(0);
+        let expected = run_program(&mut a, &dialect, program, args, 0).unwrap();
+
+        let mut state =
+            run_program_with_budget(&mut a, &dialect, program, args, 0, budget).unwrap();
+        let mut suspensions = 0;
+        let result = loop {
+            match state {
+                EvalState::Done(r) => break r,
+                EvalState::Suspended(s) => {
+                    assert!(s.cost() >= budget * (suspensions + 1));
+                    suspensions += 1;
+                    state = s.resume(&mut a, &dialect, budget).unwrap();
+                }
+            }
+        };
+        assert_eq!(result.0, expected.0);
+        assert!(a.tree_eq(result.1, expected.1));
+        if budget < expected.0 {
+            assert!(suspensions > 0);
+        } else {
+            assert_eq!(suspensions, 0);
+        }
+
+        // max_cost applies to the whole evaluation
+        let mut state =
+            run_program_with_budget(&mut a, &dialect, program, args, expected.0 - 1, budget)
+                .map_err(|e| e.1);
+        while let Ok(EvalState::Suspended(s)) = state {
+            state = s.resume(&mut a, &dialect, budget).map_err(|e| e.1);
+        }
+        assert_eq!(state.err().unwrap(), "cost exceeded");
+    }
+
+    #[test]
+    fn test_resume_after_reset() {
+        use crate::chia_dialect::ChiaDialect;
+        let mut a = Allocator::new();
+        let program = parse_exp(&mut a, INFINITE_LOOP);
+        let dialect = ChiaDialect::new(0);
+        let EvalState::Suspended(s) =
+            run_program_with_budget(&mut a, &dialect, program, NodePtr::NIL, 0, 1000).unwrap()
+        else {
+            panic!("expected the program to be suspended");
+        };
+        a.reset();
+        assert_eq!(
+            s.resume(&mut a, &dialect, 1000).err().unwrap().1,
+            "allocator was reset"
+        );
+    }
+
+    #[rstest]
+    #[case("(+ (q . 1) (q . 2))", 0, &[], &[])]
+    #[case("(c (0x1000 (q . 1)) (0x0100ff (q . 2)))", 0, &[], &[("1000", 1, 17), ("0100ff", 1, 71960)])]
+    #[case("(c (0x1000 (q . 1)) (0x1000 (q . 1)))", 0, &[], &[("1000", 2, 34)])]
+    #[case("(c (0x0010 (q . 1)) (0x0000000001 (q . 1)))", 0, &[], &[("0010", 1, 1), ("00000000", 1, 1)])]
+    #[case("(softfork (q . 1134) (q . 1) (q a (i (= (keccak256 (q . \"foobar\")) (q . 0x38d18acb67d25c8bb9942764b62f18e17054f66a817bd4295423adf9ed98873e)) (q . 0) (q x)) (q . ())) (q . ()))", ENABLE_KECCAK, &[(1, 1)], &[])]
+    #[case("(softfork (q . 1134) (q . 1) (q a (i (= (keccak256 (q . \"foobar\")) (q . 0x38d18acb67d25c8bb9942764b62f18e17054f66a817bd4295423adf9ed98873e)) (q . 0) (q x)) (q . ())) (q . ()))", 0, &[(1, 1)], &[])]
+    #[case("(softfork (q . 1000) (q . 1337) (q . 0) (q . ()))", 0, &[(1337, 1)], &[])]
+    fn test_op_usage(
+        #[case] prg: &str,
+        #[case] flags: u32,
+        #[case] extensions: &[(u32, u32)],
+        #[case] unknown_ops: &[(&str, u32, Cost)],
+    ) {
+        use crate::chia_dialect::ChiaDialect;
+        let mut a = Allocator::new();
+        let program = parse_exp(&mut a, prg);
+        let (usage, result) = run_program_with_op_usage(
+            &mut a,
+            &ChiaDialect::new(flags),
+            program,
+            NodePtr::NIL,
+            10_000_000,
+        );
+        result.unwrap();
+        assert_eq!(
+            usage,
+            OpUsage {
+                softfork_extensions: extensions.iter().copied().collect(),
+                unknown_ops: unknown_ops
+                    .iter()
+                    .map(|(op, n, cost)| (hex::decode(op).unwrap(), (*n, *cost)))
+                    .collect(),
+            }
+        );
+    }
+
+    #[test]
+    fn test_deadline() {
+        use crate::chia_dialect::ChiaDialect;
+        use std::time::Duration;
+        let mut a = Allocator::new();
+        let program = parse_exp(&mut a, INFINITE_LOOP);
+        let dialect = ChiaDialect::new(0);
+
+        // no cost limit, so the program only stops at the deadline
+        let deadline = Instant::now() + Duration::from_millis(50);
+        let result =
+            run_program_with_deadline(&mut a, &dialect, program, NodePtr::NIL, 0, deadline);
+        assert_eq!(result.unwrap_err().1, "deadline exceeded");
+        assert!(Instant::now() >= deadline);
+
+        let deadline = Instant::now() + Duration::from_secs(3600);
+        let result = run_program_with_deadline(
+            &mut a,
+            &dialect,
+            program,
+            NodePtr::NIL,
+            10_000_000,
+            deadline,
+        );
+        assert_eq!(result.unwrap_err().1, "cost exceeded");
+    }
+
+    // the bytes in use are the atoms of the program, plus 5 bytes for the
+    // max cost, which the interpreter allocates
+    #[rstest]
+    // new_concat() needs 12 bytes
+    #[case(
+        "(concat (q . \"foobar\") (q . \"foobar\"))",
+        "out of memory: requested 12 bytes with 17 of 24 bytes in use"
+    )]
+    // new_atom() needs 32 bytes for the hash
+    #[case(
+        "(sha256 (q . \"foobar\"))",
+        "out of memory: requested 32 bytes with 11 of 24 bytes in use"
+    )]
+    fn test_heap_limit_error(#[case] prg: &str, #[case] expected: &str) {
+        use crate::chia_dialect::ChiaDialect;
+        use crate::error::ErrorCode;
+        let mut a = Allocator::new_limited(25);
+        let program = parse_exp(&mut a, prg);
+        let dialect = ChiaDialect::new(0);
+        let e = run_program(&mut a, &dialect, program, NodePtr::NIL, 11_000_000_000).unwrap_err();
+        assert_eq!(e.code(), ErrorCode::OutOfMemory);
+        assert_eq!(e.1, expected);
+    }
+
+    #[rstest]
+    #[case(0, None)]
+    #[case(LIMIT_ATOM_SIZE, Some("atom too large"))]
+    fn test_dialect_max_atom_size(#[case] flags: u32, #[case] expected: Option<&str>) {
+        use crate::chia_dialect::ChiaDialect;
+        use crate::error::ErrorCode;
+        // the result is twice as large as MAX_ATOM_SIZE
+        let mut a = Allocator::new();
+        let atom = a.new_atom(&vec![1_u8; MAX_ATOM_SIZE]).unwrap();
+        let program = parse_exp(&mut a, "(concat 1 1)");
+        let dialect = ChiaDialect::new(flags);
+        let result = run_program(&mut a, &dialect, program, atom, 11_000_000_000);
+        match expected {
+            None => {
+                let Reduction(_, result) = result.unwrap();
+                assert_eq!(a.atom_len(result), MAX_ATOM_SIZE * 2);
+                assert_eq!(a.max_atom_size(), usize::MAX);
+            }
+            Some(msg) => {
+                let e = result.unwrap_err();
+                assert_eq!(e.code(), ErrorCode::AtomTooLarge);
+                assert_eq!(e.1, msg);
+            }
+        }
+        // the dialect's limit only applies while the program runs
+        assert_eq!(a.max_atom_size(), usize::MAX);
+    }
+
+    #[test]
+    fn test_dialect_max_atom_size_restored() {
+        use crate::chia_dialect::ChiaDialect;
+        // a stricter limit set on the allocator is kept, both during and
+        // after the run
+        let mut a = Allocator::new();
+        a.set_max_atom_size(1000);
+        let atom = a.new_atom(&[1_u8; 600]).unwrap();
+        let program = parse_exp(&mut a, "(concat 1 1)");
+        let dialect = ChiaDialect::new(LIMIT_ATOM_SIZE);
+        let e = run_program(&mut a, &dialect, program, atom, 11_000_000_000).unwrap_err();
+        assert_eq!(e.code(), ErrorCode::AtomTooLarge);
+        assert_eq!(a.max_atom_size(), 1000);
+
+        let program = parse_exp(&mut a, "(q . 1)");
+        run_program(&mut a, &dialect, program, atom, 11_000_000_000).unwrap();
+        assert_eq!(a.max_atom_size(), 1000);
+    }
+
     #[cfg(feature = "counters")]
     #[test]
     fn test_counters() {
@@ -1563,8 +2622,8 @@ mod tests {
 
         let mut a = Allocator::new();
 
-        let program = check(parse_exp(&mut a, "(a (q 2 2 (c 2 (c 5 (c 11 ())))) (c (q 2 (i (= 11 ()) (q 1 . 1) (q 18 5 (a 2 (c 2 (c 5 (c (- 11 (q . 1)) ())))))) 1) 1))"));
-        let args = check(parse_exp(&mut a, "(5033 1000)"));
+        let program = parse_exp(&mut a, "(a (q 2 2 (c 2 (c 5 (c 11 ())))) (c (q 2 (i (= 11 ()) (q 1 . 1) (q 18 5 (a 2 (c 2 (c 5 (c (- 11 (q . 1)) ())))))) 1) 1))");
+        let args = parse_exp(&mut a, "(5033 1000)");
         let cost = 15073165;
 
         let (counters, result) =
@@ -1573,11 +2632,190 @@ mod tests {
         assert_eq!(counters.val_stack_usage, 3015);
         assert_eq!(counters.env_stack_usage, 1005);
         assert_eq!(counters.op_stack_usage, 3014);
+        assert_eq!(counters.softfork_stack_usage, 0);
+        assert_eq!(counters.checkpoint_restores, 0);
+        assert_eq!(counters.heap_bytes_reclaimed, 0);
         assert_eq!(counters.atom_count, 998);
         assert_eq!(counters.small_atom_count, 1042);
         assert_eq!(counters.pair_count, 22077);
         assert_eq!(counters.heap_size, 769963);
+        assert!(counters.op_costs.is_none());
 
         assert_eq!(result.unwrap().0, cost);
     }
+
+    #[cfg(feature = "counters")]
+    #[test]
+    fn test_softfork_counters() {
+        use crate::chia_dialect::ChiaDialect;
+
+        let mut a = Allocator::new();
+
+        // two nested softfork guards, each allocating an atom
+        let program = parse_exp(&mut a, "(softfork (q . 935) (q . 1) (q softfork (q . 714) (q . 1) (q sha256 (q . \"foobar\")) (q . ())) (q . ()))");
+        let (counters, result) = run_program_with_counters(
+            &mut a,
+            &ChiaDialect::new(ENABLE_KECCAK),
+            program,
+            NodePtr::NIL,
+            0,
+        );
+        assert_eq!(counters.softfork_stack_usage, 2);
+        assert_eq!(counters.checkpoint_restores, 2);
+        assert_eq!(counters.heap_bytes_reclaimed, 32);
+        assert_eq!(result.unwrap().1, NodePtr::NIL);
+    }
+
+    #[cfg(feature = "counters")]
+    #[rstest]
+    #[case("(q . 1)", "()", 0, 0, 0)]
+    #[case(
+        "(c (concat (q . \"foo\") (q . \"barbaz\")) (c 11 (c 5 ())))",
+        "(1 2 3)",
+        9,
+        3,
+        3
+    )]
+    #[case("(c (q . 1) (r (r 2)))", "((1 2 3 4))", 0, 3, 1)]
+    // leading zero bytes don't make the path deeper
+    #[case("(f 0x0005)", "(1 (2))", 1, 0, 2)]
+    fn test_resource_profile(
+        #[case] program: &str,
+        #[case] env: &str,
+        #[case] max_atom_len: usize,
+        #[case] max_list_len: usize,
+        #[case] max_path_depth: usize,
+    ) {
+        use crate::chia_dialect::ChiaDialect;
+
+        let mut a = Allocator::new();
+        let program = parse_exp(&mut a, program);
+        let env = parse_exp(&mut a, env);
+        let (counters, result) =
+            run_program_with_resource_profile(&mut a, &ChiaDialect::new(0), program, env, 0);
+        result.unwrap();
+        assert_eq!(
+            counters.resource_profile,
+            Some(ResourceProfile {
+                max_atom_len,
+                max_list_len,
+                max_path_depth,
+            })
+        );
+    }
+
+    #[cfg(feature = "trace")]
+    #[test]
+    fn test_trace() {
+        use crate::chia_dialect::ChiaDialect;
+        use crate::test_ops::node_eq;
+
+        let mut a = Allocator::new();
+
+        let program = parse_exp(&mut a, "(+ 1 (q . 3))");
+        let args = parse_exp(&mut a, "4");
+
+        let (trace, result) =
+            run_program_with_trace(&mut a, &ChiaDialect::new(0), program, args, 0);
+        let result = result.unwrap();
+
+        // note that arguments are evaluated last-to-first
+        let expected = [("(+ 1 (q . 3))", "7"), ("(q . 3)", "3"), ("1", "4")];
+        assert_eq!(trace.steps.len(), expected.len());
+        for (step, (prg, res)) in trace.steps.iter().zip(expected) {
+            let prg = parse_exp(&mut a, prg);
+            let res = parse_exp(&mut a, res);
+            assert!(node_eq(&a, step.program, prg));
+            assert!(node_eq(&a, step.env, args));
+            assert!(node_eq(&a, step.result.unwrap(), res));
+        }
+        // the outermost evaluation accounts for the whole cost
+        assert_eq!(trace.steps[0].cost, result.0);
+        assert_eq!(trace.steps[1].cost, QUOTE_COST);
+
+        // a failing program still returns the steps leading up to the failure
+        let program = parse_exp(&mut a, "(f (q . 3))");
+        let (trace, result) =
+            run_program_with_trace(&mut a, &ChiaDialect::new(0), program, args, 0);
+        assert!(result.is_err());
+        assert_eq!(trace.steps.len(), 2);
+        assert_eq!(trace.steps[0].result, None);
+        assert!(trace.steps[1].result.is_some());
+    }
+
+    #[cfg(feature = "trace")]
+    #[test]
+    fn test_trace_softfork() {
+        use crate::chia_dialect::ChiaDialect;
+        use crate::test_ops::node_eq;
+
+        // the heap allocated inside the softfork guard is reclaimed when
+        // tracing too, but the trace steps inside the guard still refer to
+        // valid nodes
+        let prg = "(softfork (q . 671) (q . 0) (q concat (q . \"foo\") (q . \"bar\")) (q . ()))";
+        let mut a = Allocator::new();
+        let program = parse_exp(&mut a, prg);
+        let expected = [
+            (prg, "()"),
+            ("(q . ())", "()"),
+            (
+                "(q concat (q . \"foo\") (q . \"bar\"))",
+                "(concat (q . \"foo\") (q . \"bar\"))",
+            ),
+            ("(q . 0)", "0"),
+            ("(q . 671)", "671"),
+            ("(concat (q . \"foo\") (q . \"bar\"))", "\"foobar\""),
+            ("(q . \"bar\")", "\"bar\""),
+            ("(q . \"foo\")", "\"foo\""),
+        ];
+        // parse the expected nodes first, so they can't end up in the heap
+        // that's reclaimed when exiting the guard
+        let expected: Vec<_> = expected
+            .iter()
+            .map(|(prg, res)| (parse_exp(&mut a, prg), parse_exp(&mut a, res)))
+            .collect();
+        let (trace, result) =
+            run_program_with_trace(&mut a, &ChiaDialect::new(0), program, NodePtr::NIL, 0);
+        let result = result.unwrap();
+        assert!(a.atom(result.1).as_ref().is_empty());
+
+        assert_eq!(trace.steps.len(), expected.len());
+        for (step, (prg, res)) in trace.steps.iter().zip(expected) {
+            assert!(node_eq(&a, step.program, prg));
+            assert!(node_eq(&a, step.result.unwrap(), res));
+        }
+    }
+
+    #[cfg(feature = "counters")]
+    #[test]
+    fn test_cost_breakdown() {
+        use crate::chia_dialect::ChiaDialect;
+
+        let mut a = Allocator::new();
+
+        // (+ (* 2 3) (strlen (sha256 "foo")) 7)
+        let program = parse_exp(
+            &mut a,
+            "(+ (* (q . 2) (q . 3)) (strlen (sha256 (q . \"foo\"))) (q . 7))",
+        );
+        let args = a.nil();
+
+        let (counters, result) =
+            run_program_with_cost_breakdown(&mut a, &ChiaDialect::new(0), program, args, 0);
+        let total = result.unwrap().0;
+
+        let op_costs = counters.op_costs.expect("op_costs");
+        assert_eq!(op_costs.len(), 4);
+        for op in [16, 18, 11, 13] {
+            assert_eq!(op_costs[&op].0, 1);
+        }
+        assert_eq!(op_costs[&18].1, 999);
+        assert_eq!(op_costs[&13].1, 215);
+
+        // the operator costs plus what the interpreter charges add up to the
+        // total cost. There are 4 operator calls and 4 quotes, and no
+        // environment lookups
+        let op_total: Cost = op_costs.values().map(|v| v.1).sum();
+        assert_eq!(op_total + 4 * OP_COST + 4 * QUOTE_COST, total);
+    }
 }
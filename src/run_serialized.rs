@@ -0,0 +1,121 @@
+// Running programs given in serialized form, for embedders that don't want to
+// deal with an Allocator (e.g. command line tools and language bindings).
+
+use crate::allocator::{Allocator, NodePtr};
+use crate::chia_dialect::{ChiaDialect, LIMIT_ATOM_SIZE, LIMIT_HEAP, MAX_ATOM_SIZE};
+use crate::cost::Cost;
+use crate::error::ErrorCode;
+use crate::reduction::EvalErr;
+use crate::run_program::run_program;
+use crate::serde::{node_from_bytes_backrefs, node_to_bytes};
+
+// the heap limit used when LIMIT_HEAP is set
+const HEAP_LIMIT: usize = 500_000_000;
+
+fn serde_err(e: std::io::Error) -> EvalErr {
+    let code = if e.kind() == std::io::ErrorKind::OutOfMemory {
+        ErrorCode::OutOfMemory
+    } else {
+        ErrorCode::Unknown
+    };
+    EvalErr(NodePtr::NIL, e.to_string(), code)
+}
+
+/// Deserializes `program` and `env` (which may use back-references), runs the
+/// program in the Chia dialect with `flags`, and returns the cost and the
+/// serialized result. `LIMIT_HEAP` and `LIMIT_ATOM_SIZE` in `flags` limit the
+/// allocator, just like they do for callers that create their own.
+///
+/// The allocator doesn't outlive the call, so the node in a returned
+/// `EvalErr` is always nil. Deserialization errors are returned as an
+/// `EvalErr` too.
+pub fn run_serialized_program(
+    program: &[u8],
+    env: &[u8],
+    max_cost: Cost,
+    flags: u32,
+) -> Result<(Cost, Vec<u8>), EvalErr> {
+    let mut a = if (flags & LIMIT_HEAP) != 0 {
+        Allocator::new_limited(HEAP_LIMIT)
+    } else {
+        Allocator::new()
+    };
+    if (flags & LIMIT_ATOM_SIZE) != 0 {
+        a.set_max_atom_size(MAX_ATOM_SIZE);
+    }
+
+    let program = node_from_bytes_backrefs(&mut a, program).map_err(serde_err)?;
+    let env = node_from_bytes_backrefs(&mut a, env).map_err(serde_err)?;
+    let dialect = ChiaDialect::new(flags);
+    let reduction = run_program(&mut a, &dialect, program, env, max_cost)
+        .map_err(|EvalErr(_, msg, code)| EvalErr(NodePtr::NIL, msg, code))?;
+    let result = node_to_bytes(&a, reduction.1).map_err(serde_err)?;
+    Ok((reduction.0, result))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chia_dialect::NO_UNKNOWN_OPS;
+    use crate::serde::node_to_bytes_backrefs;
+    use crate::test_ops::parse_exp;
+    use rstest::rstest;
+
+    #[rstest]
+    // (+ 2 5) with the environment (40 2)
+    #[case("ff10ff02ff0580", "ff28ff0280", 0, Ok((856, "2a")))]
+    // (q . (1 2)) returns a pair
+    #[case("ff01ff01ff0280", "80", 0, Ok((20, "ff01ff0280")))]
+    // (x (q . 1))
+    #[case("ff08ffff010180", "80", 0, Err("clvm raise"))]
+    // an unknown operator
+    #[case("ff7f80", "80", 0, Ok((100, "80")))]
+    #[case("ff7f80", "80", NO_UNKNOWN_OPS, Err("unimplemented operator"))]
+    // truncated program and environment
+    #[case("ff", "80", 0, Err("failed to fill whole buffer"))]
+    #[case("80", "ff80", 0, Err("failed to fill whole buffer"))]
+    fn test_run_serialized_program(
+        #[case] program: &str,
+        #[case] env: &str,
+        #[case] flags: u32,
+        #[case] expected: Result<(Cost, &str), &str>,
+    ) {
+        let program = hex::decode(program).unwrap();
+        let env = hex::decode(env).unwrap();
+        let result = run_serialized_program(&program, &env, 11_000_000_000, flags);
+        match expected {
+            Ok((cost, output)) => {
+                assert_eq!(result.unwrap(), (cost, hex::decode(output).unwrap()));
+            }
+            Err(msg) => {
+                let err = result.unwrap_err();
+                assert_eq!(err.0, NodePtr::NIL);
+                assert_eq!(err.1, msg);
+            }
+        }
+    }
+
+    #[test]
+    fn test_backrefs() {
+        // (concat 2 5) with an environment that repeats an atom
+        let mut a = Allocator::new();
+        let program = parse_exp(&mut a, "(concat 2 5)");
+        let env = parse_exp(&mut a, "(\"foobar\" \"foobar\")");
+        let program = node_to_bytes_backrefs(&a, program).unwrap();
+        let env = node_to_bytes_backrefs(&a, env).unwrap();
+        assert!(env.contains(&0xfe));
+
+        let (_, result) = run_serialized_program(&program, &env, 11_000_000_000, 0).unwrap();
+        // a 12 byte atom
+        assert_eq!(result, b"\x8cfoobarfoobar");
+    }
+
+    #[test]
+    fn test_cost_exceeded() {
+        // (+ 2 5)
+        let program = hex::decode("ff10ff02ff0580").unwrap();
+        let env = hex::decode("ff28ff0280").unwrap();
+        let err = run_serialized_program(&program, &env, 800, 0).unwrap_err();
+        assert_eq!(err.code(), ErrorCode::CostExceeded);
+    }
+}
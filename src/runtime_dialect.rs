@@ -1,19 +1,22 @@
 use crate::allocator::{Allocator, NodePtr};
 use crate::chia_dialect::NO_UNKNOWN_OPS;
-use crate::cost::Cost;
+use crate::cost::{Cost, CostTable};
 use crate::dialect::{Dialect, OperatorSet};
 use crate::err_utils::err;
-use crate::f_table::{f_lookup_for_hashmap, FLookup};
+use crate::error::ErrorCode;
+use crate::f_table::{f_lookup_for_hashmap, op_table_for_hashmap, FLookup};
 use crate::more_ops::op_unknown;
 use crate::reduction::Response;
 use std::collections::HashMap;
 
 pub struct RuntimeDialect {
     f_lookup: FLookup,
+    op_table: Vec<(u32, &'static str)>,
     quote_kw: Vec<u8>,
     apply_kw: Vec<u8>,
     softfork_kw: Vec<u8>,
     flags: u32,
+    cost_table: Option<CostTable>,
 }
 
 impl RuntimeDialect {
@@ -23,24 +26,34 @@ impl RuntimeDialect {
         apply_kw: Vec<u8>,
         flags: u32,
     ) -> RuntimeDialect {
+        let mut op_table = op_table_for_hashmap(&op_map);
+        op_table.push((quote_kw[0] as u32, "q"));
+        op_table.push((apply_kw[0] as u32, "a"));
+        op_table.push((36, "softfork"));
+        op_table.sort();
         RuntimeDialect {
             f_lookup: f_lookup_for_hashmap(op_map),
+            op_table,
             quote_kw,
             apply_kw,
             softfork_kw: vec![36], // softfork opcode
             flags,
+            cost_table: None,
         }
     }
-}
 
-impl Dialect for RuntimeDialect {
-    fn op(
+    /// override the costs of operators, e.g. to simulate a different cost
+    /// schedule
+    pub fn set_cost_table(&mut self, cost_table: CostTable) {
+        self.cost_table = Some(cost_table);
+    }
+
+    fn dispatch(
         &self,
         allocator: &mut Allocator,
         o: NodePtr,
         argument_list: NodePtr,
         max_cost: Cost,
-        _extensions: OperatorSet,
     ) -> Response {
         let atom = allocator.atom(o);
         let b = atom.as_ref();
@@ -51,11 +64,29 @@ impl Dialect for RuntimeDialect {
             }
         }
         if (self.flags & NO_UNKNOWN_OPS) != 0 {
-            err(o, "unimplemented operator")
+            err(o, ErrorCode::InvalidOperator, "unimplemented operator")
         } else {
             op_unknown(allocator, o, argument_list, max_cost)
         }
     }
+}
+
+impl Dialect for RuntimeDialect {
+    fn op(
+        &self,
+        allocator: &mut Allocator,
+        o: NodePtr,
+        argument_list: NodePtr,
+        max_cost: Cost,
+        _extensions: OperatorSet,
+    ) -> Response {
+        match &self.cost_table {
+            Some(table) => table.apply(allocator, o, argument_list, max_cost, |a, max_cost| {
+                self.dispatch(a, o, argument_list, max_cost)
+            }),
+            None => self.dispatch(allocator, o, argument_list, max_cost),
+        }
+    }
 
     fn quote_kw(&self) -> u32 {
         self.quote_kw[0] as u32
@@ -74,4 +105,8 @@ impl Dialect for RuntimeDialect {
     fn allow_unknown_ops(&self) -> bool {
         (self.flags & NO_UNKNOWN_OPS) == 0
     }
+
+    fn op_table(&self, _extensions: OperatorSet) -> &[(u32, &'static str)] {
+        &self.op_table
+    }
 }
@@ -1,4 +1,4 @@
-use chia_sha2::Sha256;
+use crate::sha256::Sha256;
 
 pub type Bytes32 = [u8; 32];
 
@@ -1,12 +1,129 @@
 use std::io;
-use std::io::{Cursor, Read};
+use std::io::Cursor;
 
 use crate::allocator::{Allocator, NodePtr};
 
+use super::errors::{limit_exceeded, non_canonical};
 use super::parse_atom::parse_atom;
+use super::slice_reader::SliceReader;
 
 const CONS_BOX_MARKER: u8 = 0xff;
 
+/// Bounds on the size and shape of a tree being deserialized. These are checked
+/// as the input is parsed, so deserialization fails before the allocator
+/// fills up. The default has no limits (other than the allocator's own).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DeserializationLimits {
+    /// the max number of pairs (cons boxes) in the tree
+    pub max_pairs: usize,
+    /// the max number of atoms in the tree
+    pub max_atoms: usize,
+    /// the max nesting of pairs. A lone atom has depth 0, (1 . 2) has depth 1
+    pub max_depth: usize,
+    /// the max length, in bytes, of any single atom
+    pub max_atom_len: usize,
+}
+
+impl Default for DeserializationLimits {
+    fn default() -> Self {
+        Self {
+            max_pairs: usize::MAX,
+            max_atoms: usize::MAX,
+            max_depth: usize::MAX,
+            max_atom_len: usize::MAX,
+        }
+    }
+}
+
+/// The size and shape of a tree that was deserialized, as measured against
+/// `DeserializationLimits`. This lets callers apply policy that's stricter
+/// than the limits, without traversing the tree again.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DeserializationStats {
+    /// the number of pairs (cons boxes) in the serialization
+    pub pairs: usize,
+    /// the number of atoms (and back references) in the serialization
+    pub atoms: usize,
+    /// the deepest nesting of pairs in the serialization
+    pub max_depth: usize,
+}
+
+/// keeps track of how much of the `DeserializationLimits` has been used up
+pub(crate) struct LimitsTracker {
+    limits: DeserializationLimits,
+    pairs: usize,
+    atoms: usize,
+    depth: usize,
+    max_depth: usize,
+}
+
+impl LimitsTracker {
+    pub fn new(limits: DeserializationLimits) -> Self {
+        Self {
+            limits,
+            pairs: 0,
+            atoms: 0,
+            depth: 0,
+            max_depth: 0,
+        }
+    }
+
+    pub fn stats(&self) -> DeserializationStats {
+        DeserializationStats {
+            pairs: self.pairs,
+            atoms: self.atoms,
+            max_depth: self.max_depth,
+        }
+    }
+
+    /// called when we encounter a cons box marker
+    pub fn enter_pair(&mut self) -> io::Result<()> {
+        self.pairs += 1;
+        if self.pairs > self.limits.max_pairs {
+            return Err(limit_exceeded("too many pairs"));
+        }
+        self.depth += 1;
+        if self.depth > self.limits.max_depth {
+            return Err(limit_exceeded("max depth exceeded"));
+        }
+        self.max_depth = self.max_depth.max(self.depth);
+        Ok(())
+    }
+
+    /// called once both children of a pair have been parsed
+    pub fn exit_pair(&mut self) {
+        self.depth -= 1;
+    }
+
+    fn count_atom(&mut self) -> io::Result<()> {
+        self.atoms += 1;
+        if self.atoms > self.limits.max_atoms {
+            return Err(limit_exceeded("too many atoms"));
+        }
+        Ok(())
+    }
+
+    /// back references are counted as atoms, but the sub tree they refer to
+    /// (`depth` levels of pairs deep) is nested at the current depth
+    pub fn back_reference(&mut self, depth: usize) -> io::Result<()> {
+        self.count_atom()?;
+        let depth = self.depth.saturating_add(depth);
+        if depth > self.limits.max_depth {
+            return Err(limit_exceeded("max depth exceeded"));
+        }
+        self.max_depth = self.max_depth.max(depth);
+        Ok(())
+    }
+
+    pub fn atom(&mut self, allocator: &Allocator, atom: NodePtr) -> io::Result<()> {
+        self.count_atom()?;
+        if allocator.atom_len(atom) > self.limits.max_atom_len {
+            return Err(limit_exceeded("atom too large"));
+        }
+        Ok(())
+    }
+}
+
 #[repr(u8)]
 enum ParseOp {
     SExp,
@@ -15,34 +132,282 @@ enum ParseOp {
 
 /// deserialize a clvm node from a `std::io::Cursor`
 pub fn node_from_stream(allocator: &mut Allocator, f: &mut Cursor<&[u8]>) -> io::Result<NodePtr> {
+    node_from_stream_with_limits(allocator, f, DeserializationLimits::default())
+}
+
+/// deserialize a clvm node from a `std::io::Cursor`, failing if the tree
+/// exceeds any of the specified limits
+pub fn node_from_stream_with_limits(
+    allocator: &mut Allocator,
+    f: &mut Cursor<&[u8]>,
+    limits: DeserializationLimits,
+) -> io::Result<NodePtr> {
+    with_slice_reader(f, |reader| {
+        node_from_slice_impl(allocator, reader, limits, false)
+    })
+    .map(|(node, _)| node)
+}
+
+/// run `parse` over the remaining bytes of the cursor, and advance the cursor
+/// past the bytes it read
+pub(crate) fn with_slice_reader<T>(
+    f: &mut Cursor<&[u8]>,
+    parse: impl FnOnce(&mut SliceReader<'_>) -> io::Result<T>,
+) -> io::Result<T> {
+    let start = f.position();
+    let buf = usize::try_from(start)
+        .ok()
+        .and_then(|start| f.get_ref().get(start..))
+        .unwrap_or_default();
+    let mut reader = SliceReader::new(buf);
+    let ret = parse(&mut reader);
+    f.set_position(start + reader.position() as u64);
+    ret
+}
+
+fn node_from_slice_impl(
+    allocator: &mut Allocator,
+    f: &mut SliceReader<'_>,
+    limits: DeserializationLimits,
+    canonical: bool,
+) -> io::Result<(NodePtr, DeserializationStats)> {
+    let mut tracker = LimitsTracker::new(limits);
     let mut values: Vec<NodePtr> = Vec::new();
     let mut ops = vec![ParseOp::SExp];
 
-    let mut b = [0; 1];
     while let Some(op) = ops.pop() {
         match op {
             ParseOp::SExp => {
-                f.read_exact(&mut b)?;
-                if b[0] == CONS_BOX_MARKER {
+                let b = f.read_u8()?;
+                if b == CONS_BOX_MARKER {
+                    tracker.enter_pair()?;
                     ops.push(ParseOp::Cons);
                     ops.push(ParseOp::SExp);
                     ops.push(ParseOp::SExp);
                 } else {
-                    values.push(parse_atom(allocator, b[0], f)?);
+                    let atom = parse_atom(allocator, b, f, canonical)?;
+                    tracker.atom(allocator, atom)?;
+                    values.push(atom);
                 }
             }
             ParseOp::Cons => {
                 // cons
+                tracker.exit_pair();
                 let v2 = values.pop();
                 let v1 = values.pop();
                 values.push(allocator.new_pair(v1.unwrap(), v2.unwrap())?);
             }
         }
     }
-    Ok(values.pop().unwrap())
+    Ok((values.pop().unwrap(), tracker.stats()))
 }
 
 pub fn node_from_bytes(allocator: &mut Allocator, b: &[u8]) -> io::Result<NodePtr> {
-    let mut buffer = Cursor::new(b);
-    node_from_stream(allocator, &mut buffer)
+    node_from_bytes_with_limits(allocator, b, DeserializationLimits::default())
+}
+
+pub fn node_from_bytes_with_limits(
+    allocator: &mut Allocator,
+    b: &[u8],
+    limits: DeserializationLimits,
+) -> io::Result<NodePtr> {
+    node_from_bytes_with_stats(allocator, b, limits).map(|(node, _)| node)
+}
+
+/// like `node_from_bytes_with_limits()`, but also returns how much of the
+/// limits the tree used up
+pub fn node_from_bytes_with_stats(
+    allocator: &mut Allocator,
+    b: &[u8],
+    limits: DeserializationLimits,
+) -> io::Result<(NodePtr, DeserializationStats)> {
+    node_from_slice_impl(allocator, &mut SliceReader::new(b), limits, false)
+}
+
+/// like `node_from_bytes()`, but fails if any atom in the serialization is
+/// not using the shortest possible encoding, or if there are trailing bytes
+/// after the serialized tree
+pub fn node_from_bytes_canonical(allocator: &mut Allocator, b: &[u8]) -> io::Result<NodePtr> {
+    let mut buffer = SliceReader::new(b);
+    let (ret, _) = node_from_slice_impl(
+        allocator,
+        &mut buffer,
+        DeserializationLimits::default(),
+        true,
+    )?;
+    if buffer.position() != b.len() {
+        return Err(non_canonical());
+    }
+    Ok(ret)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::serde::{is_non_canonical, node_to_bytes};
+    use hex::FromHex;
+    use rstest::rstest;
+
+    const NO_LIMITS: DeserializationLimits = DeserializationLimits {
+        max_pairs: usize::MAX,
+        max_atoms: usize::MAX,
+        max_depth: usize::MAX,
+        max_atom_len: usize::MAX,
+    };
+
+    #[rstest]
+    // 1
+    #[case("01", 0, 1, 0, 1)]
+    // "foobar"
+    #[case("86666f6f626172", 0, 1, 0, 6)]
+    // (foo . bar)
+    #[case("ff83666f6f83626172", 1, 2, 1, 3)]
+    // (1 2 3 4 5 6)
+    #[case("ff01ff02ff03ff04ff05ff0680", 6, 7, 6, 1)]
+    // ((1 . 2) . (3 . 4))
+    #[case("ffff0102ff0304", 3, 4, 2, 1)]
+    // (((1 . 2) . 3) . "foobar")
+    #[case("ffffff01020386666f6f626172", 3, 4, 3, 6)]
+    fn test_limits(
+        #[case] input: &str,
+        #[case] pairs: usize,
+        #[case] atoms: usize,
+        #[case] depth: usize,
+        #[case] atom_len: usize,
+    ) {
+        let buf = Vec::from_hex(input).unwrap();
+        let mut a = Allocator::new();
+
+        // the exact limits are fine
+        let limits = DeserializationLimits {
+            max_pairs: pairs,
+            max_atoms: atoms,
+            max_depth: depth,
+            max_atom_len: atom_len,
+        };
+        let node = node_from_bytes_with_limits(&mut a, &buf, limits).unwrap();
+        let expected = node_from_bytes(&mut a, &buf).unwrap();
+        let (_, stats) = node_from_bytes_with_stats(&mut a, &buf, NO_LIMITS).unwrap();
+        assert_eq!(
+            stats,
+            DeserializationStats {
+                pairs,
+                atoms,
+                max_depth: depth,
+            }
+        );
+        assert_eq!(
+            node_to_bytes(&a, node).unwrap(),
+            node_to_bytes(&a, expected).unwrap()
+        );
+
+        // but lowering any one of them fails
+        let check = |limits: DeserializationLimits, msg: &str| {
+            let mut a = Allocator::new();
+            let e = node_from_bytes_with_limits(&mut a, &buf, limits).unwrap_err();
+            assert_eq!(e.kind(), io::ErrorKind::InvalidInput);
+            assert_eq!(e.to_string(), msg);
+        };
+        if pairs > 0 {
+            check(
+                DeserializationLimits {
+                    max_pairs: pairs - 1,
+                    ..NO_LIMITS
+                },
+                "too many pairs",
+            );
+        }
+        check(
+            DeserializationLimits {
+                max_atoms: atoms - 1,
+                ..NO_LIMITS
+            },
+            "too many atoms",
+        );
+        if depth > 0 {
+            check(
+                DeserializationLimits {
+                    max_depth: depth - 1,
+                    ..NO_LIMITS
+                },
+                "max depth exceeded",
+            );
+        }
+        check(
+            DeserializationLimits {
+                max_atom_len: atom_len - 1,
+                ..NO_LIMITS
+            },
+            "atom too large",
+        );
+    }
+
+    #[test]
+    fn test_deep_list() {
+        // a long list nests as deep as it is long
+        let mut a = Allocator::new();
+        let list = a.new_list(&vec![NodePtr::NIL; 100_000]).unwrap();
+        let buf = node_to_bytes(&a, list).unwrap();
+        let (_, stats) = node_from_bytes_with_stats(&mut a, &buf, NO_LIMITS).unwrap();
+        assert_eq!(stats.max_depth, 100_000);
+
+        let limits = DeserializationLimits {
+            max_depth: 99_999,
+            ..NO_LIMITS
+        };
+        let e = node_from_bytes_with_limits(&mut a, &buf, limits).unwrap_err();
+        assert_eq!(e.to_string(), "max depth exceeded");
+    }
+
+    #[rstest]
+    #[case("ff0180", true)]
+    #[case("ff8400000001ff81ff80", true)]
+    #[case("ff8101ff81ff80", false)]
+    #[case("ff01ffc0018080", false)]
+    #[case("80", true)]
+    // trailing garbage
+    #[case("ff018000", false)]
+    fn test_canonical(#[case] input: &str, #[case] canonical: bool) {
+        let buf = Vec::from_hex(input).unwrap();
+        let mut a = Allocator::new();
+        let lenient = node_from_bytes(&mut a, &buf).unwrap();
+        let strict = node_from_bytes_canonical(&mut a, &buf);
+        if canonical {
+            assert_eq!(
+                node_to_bytes(&a, strict.unwrap()).unwrap(),
+                node_to_bytes(&a, lenient).unwrap()
+            );
+        } else {
+            let e = strict.unwrap_err();
+            assert!(is_non_canonical(&e));
+            assert_eq!(e.to_string(), "non-canonical encoding");
+        }
+    }
+
+    #[test]
+    fn test_node_from_stream() {
+        // two serializations back to back, starting part-way into the buffer
+        let buf = Vec::from_hex("00ff018083666f6f").unwrap();
+        let mut a = Allocator::new();
+        let mut cursor = Cursor::new(&buf[..]);
+        cursor.set_position(1);
+        let first = node_from_stream(&mut a, &mut cursor).unwrap();
+        assert_eq!(node_to_bytes(&a, first).unwrap(), &buf[1..4]);
+        assert_eq!(cursor.position(), 4);
+        let second = node_from_stream(&mut a, &mut cursor).unwrap();
+        assert_eq!(a.atom(second).as_ref(), b"foo");
+        assert_eq!(cursor.position(), buf.len() as u64);
+
+        // the input is truncated by 1 byte
+        let e = node_from_stream(&mut a, &mut Cursor::new(&buf[4..7])).unwrap_err();
+        assert_eq!(e.to_string(), "bad encoding");
+        let e = node_from_stream(&mut a, &mut cursor).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::UnexpectedEof);
+
+        // the cursor may point past the end of the buffer
+        cursor.set_position(100);
+        let e = node_from_stream(&mut a, &mut cursor).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::UnexpectedEof);
+        assert_eq!(cursor.position(), 100);
+    }
 }
@@ -1,11 +1,15 @@
 use std::collections::HashSet;
 use std::io;
-use std::io::{Cursor, Read};
+use std::io::Cursor;
 
 use crate::allocator::{Allocator, NodePtr, SExp};
 use crate::traverse_path::traverse_path;
 
+use super::de::{with_slice_reader, DeserializationLimits, DeserializationStats, LimitsTracker};
+use super::errors::non_canonical;
+use super::object_cache::{tree_depth, ObjectCache};
 use super::parse_atom::{parse_atom, parse_path};
+use super::slice_reader::SliceReader;
 
 const BACK_REFERENCE: u8 = 0xfe;
 const CONS_BOX_MARKER: u8 = 0xff;
@@ -20,28 +24,92 @@ enum ParseOp {
 pub fn node_from_stream_backrefs(
     allocator: &mut Allocator,
     f: &mut Cursor<&[u8]>,
-    mut backref_callback: impl FnMut(NodePtr),
+    backref_callback: impl FnMut(NodePtr),
 ) -> io::Result<NodePtr> {
+    node_from_stream_backrefs_with_limits(
+        allocator,
+        f,
+        backref_callback,
+        DeserializationLimits::default(),
+    )
+}
+
+/// deserialize a clvm node from a `std::io::Cursor`, failing if the
+/// serialization exceeds any of the specified limits. The pair, atom and atom
+/// length limits apply to the serialized form; a back reference counts as a
+/// single atom, regardless of the size of the sub tree it refers to. The depth
+/// limit applies to the expanded tree, including back referenced sub trees
+pub fn node_from_stream_backrefs_with_limits(
+    allocator: &mut Allocator,
+    f: &mut Cursor<&[u8]>,
+    backref_callback: impl FnMut(NodePtr),
+    limits: DeserializationLimits,
+) -> io::Result<NodePtr> {
+    with_slice_reader(f, |reader| {
+        node_from_slice_backrefs_impl(
+            allocator,
+            reader,
+            backref_callback,
+            limits,
+            false,
+            false,
+            None,
+        )
+    })
+    .map(|(node, _)| node)
+}
+
+// when `want_stats` is false, the returned stats are only meaningful for the
+// limits that were set. In particular, the depth of back referenced sub trees
+// is only computed when there's a depth limit, or when the stats are wanted
+fn node_from_slice_backrefs_impl(
+    allocator: &mut Allocator,
+    f: &mut SliceReader<'_>,
+    mut backref_callback: impl FnMut(NodePtr),
+    limits: DeserializationLimits,
+    want_stats: bool,
+    canonical: bool,
+    dictionary: Option<NodePtr>,
+) -> io::Result<(NodePtr, DeserializationStats)> {
+    let track_depth = want_stats || limits.max_depth != usize::MAX;
+    let mut tracker = LimitsTracker::new(limits);
+    // the depth of back referenced sub trees. Nodes are immutable, so this
+    // stays valid for the whole parse, and each node is only visited once
+    let mut depths = ObjectCache::new(tree_depth);
     let mut values = allocator.nil();
+    if let Some(dictionary) = dictionary {
+        // back references can point into the dictionary, as if it had been
+        // deserialized just before this tree
+        values = allocator.new_pair(dictionary, values)?;
+    }
     let mut ops = vec![ParseOp::SExp];
 
-    let mut b = [0; 1];
     while let Some(op) = ops.pop() {
         match op {
             ParseOp::SExp => {
-                f.read_exact(&mut b)?;
-                if b[0] == CONS_BOX_MARKER {
+                let b = f.read_u8()?;
+                if b == CONS_BOX_MARKER {
+                    tracker.enter_pair()?;
                     ops.push(ParseOp::Cons);
                     ops.push(ParseOp::SExp);
                     ops.push(ParseOp::SExp);
-                } else if b[0] == BACK_REFERENCE {
-                    let path = parse_path(f)?;
+                } else if b == BACK_REFERENCE {
+                    let path = parse_path(f, canonical)?;
                     let reduction = traverse_path(allocator, path, values)?;
                     let back_reference = reduction.1;
+                    let depth = if track_depth {
+                        *depths
+                            .get_or_calculate(allocator, &back_reference, None)
+                            .expect("depth of back reference")
+                    } else {
+                        0
+                    };
+                    tracker.back_reference(depth)?;
                     backref_callback(back_reference);
                     values = allocator.new_pair(back_reference, values)?;
                 } else {
-                    let new_atom = parse_atom(allocator, b[0], f)?;
+                    let new_atom = parse_atom(allocator, b, f, canonical)?;
+                    tracker.atom(allocator, new_atom)?;
                     values = allocator.new_pair(new_atom, values)?;
                 }
             }
@@ -49,6 +117,7 @@ pub fn node_from_stream_backrefs(
                 // cons
                 // pop left and right values off of the "values" stack, then
                 // push the new pair onto it
+                tracker.exit_pair();
                 let SExp::Pair(right, rest) = allocator.sexp(values) else {
                     panic!("internal error");
                 };
@@ -61,25 +130,112 @@ pub fn node_from_stream_backrefs(
         }
     }
     match allocator.sexp(values) {
-        SExp::Pair(v1, _v2) => Ok(v1),
+        SExp::Pair(v1, _v2) => Ok((v1, tracker.stats())),
         _ => panic!("unexpected atom"),
     }
 }
 
 pub fn node_from_bytes_backrefs(allocator: &mut Allocator, b: &[u8]) -> io::Result<NodePtr> {
-    let mut buffer = Cursor::new(b);
-    node_from_stream_backrefs(allocator, &mut buffer, |_node| {})
+    node_from_bytes_backrefs_with_limits(allocator, b, DeserializationLimits::default())
+}
+
+pub fn node_from_bytes_backrefs_with_limits(
+    allocator: &mut Allocator,
+    b: &[u8],
+    limits: DeserializationLimits,
+) -> io::Result<NodePtr> {
+    node_from_slice_backrefs_impl(
+        allocator,
+        &mut SliceReader::new(b),
+        |_node| {},
+        limits,
+        false,
+        false,
+        None,
+    )
+    .map(|(node, _)| node)
+}
+
+/// like `node_from_bytes_backrefs_with_limits()`, but also returns how much of
+/// the limits the serialization used up. Like the limits, `pairs` and `atoms`
+/// describe the serialized form, while `max_depth` is the depth of the tree it
+/// expands to, including back referenced sub trees
+pub fn node_from_bytes_backrefs_with_stats(
+    allocator: &mut Allocator,
+    b: &[u8],
+    limits: DeserializationLimits,
+) -> io::Result<(NodePtr, DeserializationStats)> {
+    node_from_slice_backrefs_impl(
+        allocator,
+        &mut SliceReader::new(b),
+        |_node| {},
+        limits,
+        true,
+        false,
+        None,
+    )
+}
+
+/// like `node_from_bytes_backrefs()`, but back references may also point into
+/// `dictionary`, a tree that's not part of the serialization. It must be the
+/// same tree the serializer was given, see
+/// `node_to_bytes_backrefs_with_dictionary()`
+pub fn node_from_bytes_backrefs_with_dictionary(
+    allocator: &mut Allocator,
+    b: &[u8],
+    dictionary: NodePtr,
+) -> io::Result<NodePtr> {
+    node_from_slice_backrefs_impl(
+        allocator,
+        &mut SliceReader::new(b),
+        |_node| {},
+        DeserializationLimits::default(),
+        false,
+        false,
+        Some(dictionary),
+    )
+    .map(|(node, _)| node)
+}
+
+/// like `node_from_bytes_backrefs()`, but fails if any atom or back reference
+/// path in the serialization is not using the shortest possible encoding, or
+/// if there are trailing bytes after the serialized tree
+pub fn node_from_bytes_backrefs_canonical(
+    allocator: &mut Allocator,
+    b: &[u8],
+) -> io::Result<NodePtr> {
+    let mut buffer = SliceReader::new(b);
+    let (ret, _) = node_from_slice_backrefs_impl(
+        allocator,
+        &mut buffer,
+        |_node| {},
+        DeserializationLimits::default(),
+        false,
+        true,
+        None,
+    )?;
+    if buffer.position() != b.len() {
+        return Err(non_canonical());
+    }
+    Ok(ret)
 }
 
 pub fn node_from_bytes_backrefs_record(
     allocator: &mut Allocator,
     b: &[u8],
 ) -> io::Result<(NodePtr, HashSet<NodePtr>)> {
-    let mut buffer = Cursor::new(b);
     let mut backrefs = HashSet::<NodePtr>::new();
-    let ret = node_from_stream_backrefs(allocator, &mut buffer, |node| {
-        backrefs.insert(node);
-    })?;
+    let (ret, _) = node_from_slice_backrefs_impl(
+        allocator,
+        &mut SliceReader::new(b),
+        |node| {
+            backrefs.insert(node);
+        },
+        DeserializationLimits::default(),
+        false,
+        false,
+        None,
+    )?;
     Ok((ret, backrefs))
 }
 
@@ -175,4 +331,140 @@ mod tests {
 
         assert_eq!(backrefs, expected_backrefs);
     }
+
+    #[rstest]
+    // ("foobar" "foobar")
+    // the back reference is to the list ("foobar"), so it adds to the depth
+    #[case("ff86666f6f626172fe01", 1, 2, 2, 6)]
+    // ((1 2 3 4) 1 2 3 4)
+    #[case("ffff01ff02ff03ff0480fe02", 5, 6, 5, 1)]
+    fn test_deserialize_with_limits(
+        #[case] serialization_as_hex: &str,
+        #[case] pairs: usize,
+        #[case] atoms: usize,
+        #[case] depth: usize,
+        #[case] atom_len: usize,
+    ) {
+        let buf = Vec::from_hex(serialization_as_hex).unwrap();
+        let limits = DeserializationLimits {
+            max_pairs: pairs,
+            max_atoms: atoms,
+            max_depth: depth,
+            max_atom_len: atom_len,
+        };
+        let mut allocator = Allocator::new();
+        node_from_bytes_backrefs_with_limits(&mut allocator, &buf, limits).unwrap();
+        let (_, stats) = node_from_bytes_backrefs_with_stats(
+            &mut allocator,
+            &buf,
+            DeserializationLimits::default(),
+        )
+        .unwrap();
+        assert_eq!(
+            stats,
+            DeserializationStats {
+                pairs,
+                atoms,
+                max_depth: depth,
+            }
+        );
+
+        for (limits, msg) in [
+            (
+                DeserializationLimits {
+                    max_pairs: pairs - 1,
+                    ..limits
+                },
+                "too many pairs",
+            ),
+            (
+                DeserializationLimits {
+                    max_atoms: atoms - 1,
+                    ..limits
+                },
+                "too many atoms",
+            ),
+            (
+                DeserializationLimits {
+                    max_depth: depth - 1,
+                    ..limits
+                },
+                "max depth exceeded",
+            ),
+            (
+                DeserializationLimits {
+                    max_atom_len: atom_len - 1,
+                    ..limits
+                },
+                "atom too large",
+            ),
+        ] {
+            let mut allocator = Allocator::new();
+            let e = node_from_bytes_backrefs_with_limits(&mut allocator, &buf, limits).unwrap_err();
+            assert_eq!(e.to_string(), msg);
+        }
+    }
+
+    #[test]
+    fn test_back_reference_depth() {
+        use crate::serde::{node_from_bytes_with_stats, node_to_bytes, node_to_bytes_backrefs};
+        let mut a = Allocator::new();
+        // a sub tree 100 pairs deep, ((((... . 1) . 1) . 1) . 1)
+        let one = a.one();
+        let mut deep = a.nil();
+        for _ in 0..100 {
+            deep = a.new_pair(deep, one).unwrap();
+        }
+        // (deep deep deep), the last one is nested 3 pairs deep
+        let mut tree = a.nil();
+        for _ in 0..3 {
+            tree = a.new_pair(deep, tree).unwrap();
+        }
+        let plain = node_to_bytes(&a, tree).unwrap();
+        let (_, expected) =
+            node_from_bytes_with_stats(&mut a, &plain, DeserializationLimits::default()).unwrap();
+        assert_eq!(expected.max_depth, 103);
+
+        let buf = node_to_bytes_backrefs(&a, tree).unwrap();
+        assert!(buf.len() < plain.len());
+        let (_, stats) =
+            node_from_bytes_backrefs_with_stats(&mut a, &buf, DeserializationLimits::default())
+                .unwrap();
+        assert_eq!(stats.max_depth, expected.max_depth);
+
+        let limits = DeserializationLimits {
+            max_depth: 103,
+            ..Default::default()
+        };
+        node_from_bytes_backrefs_with_limits(&mut a, &buf, limits).unwrap();
+        let limits = DeserializationLimits {
+            max_depth: 102,
+            ..Default::default()
+        };
+        let e = node_from_bytes_backrefs_with_limits(&mut a, &buf, limits).unwrap_err();
+        assert_eq!(e.to_string(), "max depth exceeded");
+    }
+
+    #[rstest]
+    #[case("ff86666f6f626172fe01", true)]
+    #[case("ffff01ff02ff03ff0480fe02", true)]
+    // the back reference path has a redundant leading zero
+    #[case("ff86666f6f626172fe820001", false)]
+    // the back reference path has a redundant length prefix
+    #[case("ff86666f6f626172fe8101", false)]
+    // a non-canonical atom
+    #[case("ff8101fe01", false)]
+    // trailing garbage
+    #[case("ff86666f6f626172fe0100", false)]
+    fn test_canonical(#[case] input: &str, #[case] canonical: bool) {
+        let buf = Vec::from_hex(input).unwrap();
+        let mut allocator = Allocator::new();
+        node_from_bytes_backrefs(&mut allocator, &buf).unwrap();
+        let ret = node_from_bytes_backrefs_canonical(&mut allocator, &buf);
+        if canonical {
+            ret.unwrap();
+        } else {
+            assert_eq!(ret.unwrap_err().to_string(), "non-canonical encoding");
+        }
+    }
 }
@@ -1,6 +1,7 @@
-use std::io::{Error, Read, Result, Write};
+use std::io::{Cursor, Error, Read, Result, Write};
+use std::ops::Range;
 
-use chia_sha2::Sha256;
+use crate::sha256::Sha256;
 
 use super::parse_atom::decode_size_with_offset;
 use super::utils::{copy_exactly, skip_bytes};
@@ -218,6 +219,86 @@ pub fn parse_triples<R: Read>(
     ))
 }
 
+/// A reference to a node in the tree of a `LazyReader`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LazyNode(u32);
+
+/// `LazyReader` navigates a serialized clvm object (without back references)
+/// in place, without building it in an `Allocator`. It's built on
+/// `parse_triples()`, so the whole serialization is validated up-front, but
+/// only a small array of offsets is kept. Navigating is constant time and
+/// atoms are returned as slices of the input buffer. This is useful when only
+/// a few fields are needed from a large structure.
+///
+/// Any bytes following the serialized object are ignored.
+pub struct LazyReader<'a> {
+    buf: &'a [u8],
+    triples: Vec<ParsedTriple>,
+}
+
+impl<'a> LazyReader<'a> {
+    pub fn new(buf: &'a [u8]) -> Result<Self> {
+        let (triples, _) = parse_triples(&mut Cursor::new(buf), false)?;
+        Ok(Self { buf, triples })
+    }
+
+    /// the node the whole serialization represents
+    pub fn root(&self) -> LazyNode {
+        LazyNode(0)
+    }
+
+    /// the left side of a pair, or `None` if the node is an atom
+    pub fn first(&self, node: LazyNode) -> Option<LazyNode> {
+        match self.triples[node.0 as usize] {
+            ParsedTriple::Pair { .. } => Some(LazyNode(node.0 + 1)),
+            ParsedTriple::Atom { .. } => None,
+        }
+    }
+
+    /// the right side of a pair, or `None` if the node is an atom
+    pub fn rest(&self, node: LazyNode) -> Option<LazyNode> {
+        match self.triples[node.0 as usize] {
+            ParsedTriple::Pair { right_index, .. } => Some(LazyNode(right_index)),
+            ParsedTriple::Atom { .. } => None,
+        }
+    }
+
+    /// the contents of an atom, or `None` if the node is a pair
+    pub fn atom_bytes(&self, node: LazyNode) -> Option<&'a [u8]> {
+        match self.triples[node.0 as usize] {
+            ParsedTriple::Atom {
+                start,
+                end,
+                atom_offset,
+            } => Some(&self.buf[start as usize + atom_offset as usize..end as usize]),
+            ParsedTriple::Pair { .. } => None,
+        }
+    }
+
+    /// the item at index `n` of the list `node`, or `None` if the list has
+    /// fewer items than that
+    pub fn nth(&self, mut node: LazyNode, n: usize) -> Option<LazyNode> {
+        for _ in 0..n {
+            node = self.rest(node)?;
+        }
+        self.first(node)
+    }
+
+    /// the range of bytes, in the input buffer, of the node's serialization
+    pub fn byte_range(&self, node: LazyNode) -> Range<usize> {
+        match self.triples[node.0 as usize] {
+            ParsedTriple::Atom { start, end, .. } | ParsedTriple::Pair { start, end, .. } => {
+                start as usize..end as usize
+            }
+        }
+    }
+
+    /// the serialization of the sub tree rooted in `node`
+    pub fn serialized(&self, node: LazyNode) -> &'a [u8] {
+        &self.buf[self.byte_range(node)]
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -351,4 +432,49 @@ mod tests {
             "d1c109981a9c5a3bbe2d98795a186a0f057dc9a3a7f5e1eb4dfb63a1636efa2d",
         );
     }
+
+    #[test]
+    fn test_lazy_reader() {
+        // (foo (bar . 100) "" baz) followed by a trailing byte
+        let buf = Vec::from_hex("ff83666f6fffff8362617264ff80ff8362617a8001").unwrap();
+        let r = LazyReader::new(&buf).unwrap();
+
+        let root = r.root();
+        assert_eq!(r.byte_range(root), 0..20);
+        assert_eq!(r.atom_bytes(root), None);
+
+        let foo = r.first(root).unwrap();
+        assert_eq!(r.atom_bytes(foo), Some(&b"foo"[..]));
+        assert_eq!(r.first(foo), None);
+        assert_eq!(r.rest(foo), None);
+        assert_eq!(r.nth(root, 0), Some(foo));
+
+        let pair = r.nth(root, 1).unwrap();
+        assert_eq!(r.serialized(pair), Vec::from_hex("ff8362617264").unwrap());
+        let bar = r.first(pair).unwrap();
+        assert_eq!(r.atom_bytes(bar), Some(&b"bar"[..]));
+        let hundred = r.rest(pair).unwrap();
+        assert_eq!(r.atom_bytes(hundred), Some(&[100_u8][..]));
+        assert_eq!(r.byte_range(hundred), 11..12);
+
+        let empty = r.nth(root, 2).unwrap();
+        assert_eq!(r.atom_bytes(empty), Some(&[][..]));
+        assert_eq!(r.serialized(empty), [0x80]);
+
+        let baz = r.nth(root, 3).unwrap();
+        assert_eq!(r.atom_bytes(baz), Some(&b"baz"[..]));
+
+        // the end of the list
+        assert_eq!(r.nth(root, 4), None);
+        assert_eq!(r.nth(root, 100), None);
+        // "foo" is not a list
+        assert_eq!(r.nth(foo, 0), None);
+    }
+
+    #[test]
+    fn test_lazy_reader_truncated() {
+        let buf = Vec::from_hex("ff83666f6fff836261").unwrap();
+        assert!(LazyReader::new(&buf).is_err());
+        assert!(LazyReader::new(&[]).is_err());
+    }
 }
@@ -0,0 +1,266 @@
+// Structural diffs between two CLVM trees. A diff is a list of patches, each
+// replacing the sub tree at a path (in the same encoding as CLVM environment
+// paths) with a new, serialized, sub tree.
+
+use std::io;
+use std::io::Cursor;
+
+use super::de_br::node_from_bytes_backrefs;
+use super::errors::bad_encoding;
+use super::object_cache::TreeHasher;
+use super::parse_atom::parse_atom;
+use super::ser_br::node_to_bytes_backrefs;
+use super::slice_reader::SliceReader;
+use super::write_atom::write_atom;
+use crate::allocator::{Allocator, NodePtr, SExp};
+
+const CONS_BOX_MARKER: u8 = 0xff;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Patch {
+    /// the path to the sub tree to replace. `1` is the root, and every bit
+    /// below the most significant set bit, starting with the least significant
+    /// one, selects the first (0) or rest (1) of a pair
+    pub path: Vec<u8>,
+    /// the replacement sub tree, serialized with back references
+    pub node: Vec<u8>,
+}
+
+/// A list of patches that turn one CLVM tree into another. The patches are
+/// applied in order, by `apply_diff()`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TreeDiff {
+    pub patches: Vec<Patch>,
+}
+
+impl TreeDiff {
+    pub fn is_empty(&self) -> bool {
+        self.patches.is_empty()
+    }
+
+    /// serialize the diff as a CLVM list of `(path . node)` pairs, where node
+    /// is the replacement's serialization, stored as an atom
+    pub fn to_bytes(&self) -> io::Result<Vec<u8>> {
+        let mut f = Cursor::new(Vec::new());
+        for p in &self.patches {
+            io::Write::write_all(&mut f, &[CONS_BOX_MARKER, CONS_BOX_MARKER])?;
+            write_atom(&mut f, &p.path)?;
+            write_atom(&mut f, &p.node)?;
+        }
+        write_atom(&mut f, &[])?;
+        Ok(f.into_inner())
+    }
+
+    pub fn from_bytes(buf: &[u8]) -> io::Result<Self> {
+        let mut a = Allocator::new();
+        let mut f = SliceReader::new(buf);
+        let mut patches = Vec::new();
+        loop {
+            let b = f.read_u8()?;
+            if b != CONS_BOX_MARKER {
+                let terminator = parse_atom(&mut a, b, &mut f, false)?;
+                if a.atom_len(terminator) != 0 {
+                    return Err(bad_encoding());
+                }
+                break;
+            }
+            if f.read_u8()? != CONS_BOX_MARKER {
+                return Err(bad_encoding());
+            }
+            let mut atom = || -> io::Result<Vec<u8>> {
+                let b = f.read_u8()?;
+                if b == CONS_BOX_MARKER {
+                    return Err(bad_encoding());
+                }
+                let node = parse_atom(&mut a, b, &mut f, false)?;
+                Ok(a.atom(node).as_ref().to_vec())
+            };
+            let path = atom()?;
+            let node = atom()?;
+            patches.push(Patch { path, node });
+        }
+        if f.position() != buf.len() {
+            return Err(bad_encoding());
+        }
+        Ok(Self { patches })
+    }
+}
+
+/// encode a list of directions (false = first, true = rest) as a path atom
+fn encode_path(directions: &[bool]) -> Vec<u8> {
+    let byte_count = (directions.len() + 1).div_ceil(8);
+    let mut ret = vec![0_u8; byte_count];
+    let mut set_bit = |bit: usize| ret[byte_count - 1 - bit / 8] |= 1 << (bit % 8);
+    for (i, d) in directions.iter().enumerate() {
+        if *d {
+            set_bit(i);
+        }
+    }
+    set_bit(directions.len());
+    ret
+}
+
+/// decode a path atom into a list of directions (false = first, true = rest)
+fn decode_path(path: &[u8]) -> io::Result<Vec<bool>> {
+    let Some(first_byte) = path.iter().position(|b| *b != 0) else {
+        return Err(bad_encoding());
+    };
+    let path = &path[first_byte..];
+    let total_bits = path.len() * 8 - path[0].leading_zeros() as usize - 1;
+    Ok((0..total_bits)
+        .map(|bit| (path[path.len() - 1 - bit / 8] & (1 << (bit % 8))) != 0)
+        .collect())
+}
+
+enum DiffOp {
+    Compare(NodePtr, NodePtr),
+    Descend(bool),
+    Ascend,
+}
+
+/// compute the patches needed to turn `old` into `new`. Sub trees with the same
+/// tree hash are left alone, so the diff only contains the parts of `new` that
+/// differ from `old`
+pub fn diff(a: &Allocator, old: NodePtr, new: NodePtr) -> io::Result<TreeDiff> {
+    let mut hasher = TreeHasher::new();
+    let mut patches = Vec::new();
+    let mut path = Vec::<bool>::new();
+    let mut ops = vec![DiffOp::Compare(old, new)];
+    while let Some(op) = ops.pop() {
+        match op {
+            DiffOp::Descend(d) => path.push(d),
+            DiffOp::Ascend => {
+                path.pop();
+            }
+            DiffOp::Compare(old, new) => {
+                if hasher.tree_hash(a, old) == hasher.tree_hash(a, new) {
+                    continue;
+                }
+                match (a.sexp(old), a.sexp(new)) {
+                    (SExp::Pair(old_first, old_rest), SExp::Pair(new_first, new_rest)) => {
+                        // these are executed in reverse order
+                        ops.push(DiffOp::Ascend);
+                        ops.push(DiffOp::Compare(old_rest, new_rest));
+                        ops.push(DiffOp::Descend(true));
+                        ops.push(DiffOp::Ascend);
+                        ops.push(DiffOp::Compare(old_first, new_first));
+                        ops.push(DiffOp::Descend(false));
+                    }
+                    _ => {
+                        patches.push(Patch {
+                            path: encode_path(&path),
+                            node: node_to_bytes_backrefs(a, new)?,
+                        });
+                    }
+                }
+            }
+        }
+    }
+    Ok(TreeDiff { patches })
+}
+
+/// apply the patches in `diff` to `root`, returning the new tree. The original
+/// tree is left intact; only the spine leading to each patched sub tree is
+/// copied
+pub fn apply_diff(a: &mut Allocator, root: NodePtr, diff: &TreeDiff) -> io::Result<NodePtr> {
+    let mut root = root;
+    for patch in &diff.patches {
+        let directions = decode_path(&patch.path)?;
+        let mut replacement = node_from_bytes_backrefs(a, &patch.node)?;
+
+        // walk down to the sub tree to replace, remembering the siblings
+        let mut spine = Vec::with_capacity(directions.len());
+        let mut node = root;
+        for d in &directions {
+            let SExp::Pair(first, rest) = a.sexp(node) else {
+                return Err(bad_encoding());
+            };
+            spine.push((*d, first, rest));
+            node = if *d { rest } else { first };
+        }
+
+        // then rebuild the spine on the way back up
+        for (d, first, rest) in spine.into_iter().rev() {
+            replacement = if d {
+                a.new_pair(first, replacement)?
+            } else {
+                a.new_pair(replacement, rest)?
+            };
+        }
+        root = replacement;
+    }
+    Ok(root)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::serde::{node_from_bytes, node_to_bytes};
+    use hex::FromHex;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case(&[], "01")]
+    #[case(&[false], "02")]
+    #[case(&[true], "03")]
+    #[case(&[true, false], "05")]
+    #[case(&[false, true, true], "0e")]
+    #[case(&[false; 7], "80")]
+    #[case(&[false; 8], "0100")]
+    #[case(&[true; 8], "01ff")]
+    fn test_paths(#[case] directions: &[bool], #[case] expected: &str) {
+        let path = encode_path(directions);
+        assert_eq!(hex::encode(&path), expected);
+        assert_eq!(decode_path(&path).unwrap(), directions);
+    }
+
+    #[rstest]
+    // identical trees
+    #[case("ff01ff02ff0380", "ff01ff02ff0380", 0)]
+    // (1 2 3) -> (1 4 3)
+    #[case("ff01ff02ff0380", "ff01ff04ff0380", 1)]
+    // (1 2 3) -> (5 2 6)
+    #[case("ff01ff02ff0380", "ff05ff02ff0680", 2)]
+    // (1 2 3) -> "foobar"
+    #[case("ff01ff02ff0380", "86666f6f626172", 1)]
+    // "foobar" -> ((1 . 2) . (3 . 4))
+    #[case("86666f6f626172", "ffff0102ff0304", 1)]
+    // ((1 . 2) . (3 . 4)) -> ((1 . 2) . (3 . (5 6)))
+    #[case("ffff0102ff0304", "ffff0102ff03ff05ff0680", 1)]
+    fn test_diff(#[case] old: &str, #[case] new: &str, #[case] num_patches: usize) {
+        let mut a = Allocator::new();
+        let old = node_from_bytes(&mut a, &Vec::from_hex(old).unwrap()).unwrap();
+        let new = node_from_bytes(&mut a, &Vec::from_hex(new).unwrap()).unwrap();
+
+        let d = diff(&a, old, new).unwrap();
+        assert_eq!(d.patches.len(), num_patches);
+        assert_eq!(d.is_empty(), num_patches == 0);
+
+        let d = TreeDiff::from_bytes(&d.to_bytes().unwrap()).unwrap();
+        let old_bytes = node_to_bytes(&a, old).unwrap();
+        let patched = apply_diff(&mut a, old, &d).unwrap();
+        assert_eq!(
+            node_to_bytes(&a, patched).unwrap(),
+            node_to_bytes(&a, new).unwrap()
+        );
+        // the original is not modified
+        assert_eq!(node_to_bytes(&a, old).unwrap(), old_bytes);
+    }
+
+    #[rstest]
+    // path 0
+    #[case("ffff000180")]
+    // the path leads through an atom
+    #[case("ffff040180")]
+    // truncated
+    #[case("ffff0381")]
+    // trailing garbage
+    #[case("ffff038101808000")]
+    fn test_invalid_diff(#[case] input: &str) {
+        let mut a = Allocator::new();
+        let root = node_from_bytes(&mut a, &Vec::from_hex("ff01ff0280").unwrap()).unwrap();
+        let r = TreeDiff::from_bytes(&Vec::from_hex(input).unwrap())
+            .and_then(|d| apply_diff(&mut a, root, &d));
+        assert!(r.is_err());
+    }
+}
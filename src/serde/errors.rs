@@ -1,9 +1,47 @@
+use std::fmt;
 use std::io::{Error, ErrorKind};
 
+/// The error (wrapped in a `std::io::Error`) returned by the canonical
+/// deserializers when the input isn't using the shortest possible encoding.
+/// Use `is_non_canonical()` to tell it apart from other errors.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NonCanonicalEncoding;
+
+impl fmt::Display for NonCanonicalEncoding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("non-canonical encoding")
+    }
+}
+
+impl std::error::Error for NonCanonicalEncoding {}
+
 pub fn bad_encoding() -> Error {
     Error::new(ErrorKind::InvalidInput, "bad encoding")
 }
 
+pub fn non_canonical() -> Error {
+    Error::new(ErrorKind::InvalidInput, NonCanonicalEncoding)
+}
+
+/// returns true if the error was caused by a non-canonical encoding
+pub fn is_non_canonical(e: &Error) -> bool {
+    e.get_ref()
+        .is_some_and(|inner| inner.is::<NonCanonicalEncoding>())
+}
+
+pub fn invalid_path() -> Error {
+    Error::new(ErrorKind::InvalidInput, "invalid path")
+}
+
 pub fn internal_error() -> Error {
     Error::new(ErrorKind::InvalidInput, "internal error")
 }
+
+pub fn limit_exceeded(msg: &str) -> Error {
+    Error::new(ErrorKind::InvalidInput, msg)
+}
+
+/// the error `std::io::Cursor` returns when reading past the end of the input
+pub fn unexpected_eof() -> Error {
+    Error::new(ErrorKind::UnexpectedEof, "failed to fill whole buffer")
+}
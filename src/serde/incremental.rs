@@ -6,7 +6,7 @@ use std::io::{Cursor, Write};
 use super::bytes32::Bytes32;
 use super::object_cache::{serialized_length, treehash, ObjectCache};
 use super::read_cache_lookup::ReadCacheLookup;
-use super::write_atom::write_atom;
+use super::write_atom::{write_atom, write_atom_node};
 use crate::allocator::{Allocator, NodePtr, SExp};
 
 const BACK_REFERENCE: u8 = 0xfe;
@@ -29,6 +29,10 @@ pub struct Serializer {
 
     sentinel: Option<NodePtr>,
     output: Cursor<Vec<u8>>,
+
+    // the stack of outstanding checkpoints, see push_checkpoint()
+    checkpoints: Vec<(CheckpointId, UndoState)>,
+    next_checkpoint: u64,
 }
 
 #[derive(Clone)]
@@ -39,6 +43,10 @@ pub struct UndoState {
     output_position: u64,
 }
 
+/// Identifies a checkpoint created by `Serializer::push_checkpoint()`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct CheckpointId(u64);
+
 /// The state to allow incrementally serializing CLVM structures with back-refs
 /// The compression cannot "see through" the sentinel node, so some compression
 /// opportunities may be missed when serializing and compressing incrementally.
@@ -52,6 +60,17 @@ impl Serializer {
             slc: ObjectCache::new(serialized_length),
             sentinel,
             output: Cursor::new(vec![]),
+            checkpoints: vec![],
+            next_checkpoint: 0,
+        }
+    }
+
+    fn undo_state(&self) -> UndoState {
+        UndoState {
+            read_op_stack: self.read_op_stack.clone(),
+            write_stack: self.write_stack.clone(),
+            read_cache_lookup: self.read_cache_lookup.clone(),
+            output_position: self.output.position(),
         }
     }
 
@@ -74,12 +93,7 @@ impl Serializer {
         // call to add()), we can't resume
         assert!(!self.read_op_stack.is_empty());
 
-        let undo_state = UndoState {
-            read_op_stack: self.read_op_stack.clone(),
-            write_stack: self.write_stack.clone(),
-            read_cache_lookup: self.read_cache_lookup.clone(),
-            output_position: self.output.position(),
-        };
+        let undo_state = self.undo_state();
         self.write_stack.push(node);
 
         while let Some(node_to_write) = self.write_stack.pop() {
@@ -112,8 +126,7 @@ impl Serializer {
                             self.serialize_pair(left, right)?;
                         }
                         SExp::Atom => {
-                            let atom = a.atom(node_to_write);
-                            write_atom(&mut self.output, atom.as_ref())?;
+                            write_atom_node(&mut self.output, a, node_to_write)?;
                             self.read_cache_lookup.push(*node_tree_hash);
                         }
                     },
@@ -124,8 +137,7 @@ impl Serializer {
                         self.serialize_pair(left, right)?;
                     }
                     SExp::Atom => {
-                        let atom = a.atom(node_to_write);
-                        write_atom(&mut self.output, atom.as_ref())?;
+                        write_atom_node(&mut self.output, a, node_to_write)?;
                     }
                 }
             }
@@ -149,6 +161,45 @@ impl Serializer {
             .truncate(state.output_position as usize);
     }
 
+    /// Record the current state of the serializer on the checkpoint stack.
+    /// Checkpoints nest; rolling back to (or committing) a checkpoint also
+    /// discards all checkpoints pushed after it.
+    pub fn push_checkpoint(&mut self) -> CheckpointId {
+        let id = CheckpointId(self.next_checkpoint);
+        self.next_checkpoint += 1;
+        self.checkpoints.push((id, self.undo_state()));
+        id
+    }
+
+    fn checkpoint_index(&self, id: CheckpointId) -> usize {
+        self.checkpoints
+            .iter()
+            .rposition(|(cp, _)| *cp == id)
+            .expect("unknown or discarded checkpoint")
+    }
+
+    /// Undo everything added since the checkpoint `id` was pushed. The
+    /// checkpoint, and any checkpoints pushed after it, are discarded.
+    /// Panics if `id` is not on the checkpoint stack.
+    pub fn rollback_to(&mut self, id: CheckpointId) {
+        let idx = self.checkpoint_index(id);
+        let (_, state) = self.checkpoints.drain(idx..).next().unwrap();
+        self.restore(state);
+    }
+
+    /// Keep everything added since the checkpoint `id` was pushed. The
+    /// checkpoint, and any checkpoints pushed after it, are discarded.
+    /// Panics if `id` is not on the checkpoint stack.
+    pub fn commit(&mut self, id: CheckpointId) {
+        let idx = self.checkpoint_index(id);
+        self.checkpoints.truncate(idx);
+    }
+
+    /// the number of outstanding checkpoints
+    pub fn checkpoint_depth(&self) -> usize {
+        self.checkpoints.len()
+    }
+
     pub fn size(&self) -> u64 {
         self.output.position()
     }
@@ -328,6 +379,67 @@ mod tests {
         assert_eq!(hex::encode(&output), "ffffff0102ff0304820539");
     }
 
+    #[test]
+    fn test_checkpoints() {
+        let mut a = Allocator::new();
+
+        let sentinel = a.new_pair(NodePtr::NIL, NodePtr::NIL).unwrap();
+        let mut items = vec![];
+        for i in 1..=4 {
+            let v = a.new_small_number(i).unwrap();
+            items.push(a.new_pair(v, sentinel).unwrap());
+        }
+
+        let mut ser = Serializer::new(Some(sentinel));
+        ser.add(&a, items[0]).unwrap();
+        assert_eq!(hex::encode(ser.get_ref()), "ff01");
+
+        let cp1 = ser.push_checkpoint();
+        ser.add(&a, items[1]).unwrap();
+        let cp2 = ser.push_checkpoint();
+        ser.add(&a, items[2]).unwrap();
+        let cp3 = ser.push_checkpoint();
+        ser.add(&a, items[3]).unwrap();
+        assert_eq!(ser.checkpoint_depth(), 3);
+        assert_eq!(hex::encode(ser.get_ref()), "ff01ff02ff03ff04");
+
+        // keep item 3 and 4
+        ser.commit(cp3);
+        assert_eq!(ser.checkpoint_depth(), 2);
+        assert_eq!(hex::encode(ser.get_ref()), "ff01ff02ff03ff04");
+
+        // eject item 3 (and 4, which was added after it)
+        ser.rollback_to(cp2);
+        assert_eq!(ser.checkpoint_depth(), 1);
+        assert_eq!(hex::encode(ser.get_ref()), "ff01ff02");
+
+        ser.add(&a, items[3]).unwrap();
+        assert_eq!(hex::encode(ser.get_ref()), "ff01ff02ff04");
+
+        // a new checkpoint nested under cp1. Rolling back cp1 discards it too
+        let cp4 = ser.push_checkpoint();
+        assert_ne!(cp4, cp2);
+        ser.add(&a, items[2]).unwrap();
+        assert_eq!(ser.checkpoint_depth(), 2);
+        ser.rollback_to(cp1);
+        assert_eq!(ser.checkpoint_depth(), 0);
+        assert_eq!(hex::encode(ser.get_ref()), "ff01");
+
+        let (done, _) = ser.add(&a, NodePtr::NIL).unwrap();
+        assert!(done);
+        assert_eq!(hex::encode(ser.into_inner()), "ff0180");
+    }
+
+    #[test]
+    #[should_panic(expected = "unknown or discarded checkpoint")]
+    fn test_rollback_discarded_checkpoint() {
+        let mut ser = Serializer::new(None);
+        let cp1 = ser.push_checkpoint();
+        let cp2 = ser.push_checkpoint();
+        ser.commit(cp1);
+        ser.rollback_to(cp2);
+    }
+
     #[test]
     fn test_incremental_restore() {
         let mut a = Allocator::new();
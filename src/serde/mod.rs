@@ -2,6 +2,7 @@ mod bytes32;
 mod de;
 mod de_br;
 mod de_tree;
+mod diff;
 mod errors;
 mod identity_hash;
 mod incremental;
@@ -11,6 +12,7 @@ mod read_cache_lookup;
 mod ser;
 mod ser_br;
 mod serialized_length;
+mod slice_reader;
 mod tools;
 mod utils;
 pub mod write_atom;
@@ -18,15 +20,33 @@ pub mod write_atom;
 #[cfg(test)]
 mod test;
 
-pub use de::node_from_bytes;
-pub use de_br::{node_from_bytes_backrefs, node_from_bytes_backrefs_record};
-pub use de_tree::{parse_triples, ParsedTriple};
+pub use bytes32::Bytes32;
+pub use de::{
+    node_from_bytes, node_from_bytes_canonical, node_from_bytes_with_limits,
+    node_from_bytes_with_stats, node_from_stream, node_from_stream_with_limits,
+    DeserializationLimits, DeserializationStats,
+};
+pub use de_br::{
+    node_from_bytes_backrefs, node_from_bytes_backrefs_canonical, node_from_bytes_backrefs_record,
+    node_from_bytes_backrefs_with_dictionary, node_from_bytes_backrefs_with_limits,
+    node_from_bytes_backrefs_with_stats, node_from_stream_backrefs,
+    node_from_stream_backrefs_with_limits,
+};
+pub use de_tree::{parse_triples, LazyNode, LazyReader, ParsedTriple};
+pub use diff::{apply_diff, diff, Patch, TreeDiff};
+pub use errors::{is_non_canonical, NonCanonicalEncoding};
 pub use identity_hash::RandomState;
-pub use incremental::{Serializer, UndoState};
-pub use object_cache::{serialized_length, treehash, ObjectCache};
-pub use ser::{node_to_bytes, node_to_bytes_limit};
-pub use ser_br::{node_to_bytes_backrefs, node_to_bytes_backrefs_limit};
+pub use incremental::{CheckpointId, Serializer, UndoState};
+#[cfg(feature = "rayon")]
+pub use object_cache::tree_hash_parallel;
+pub use object_cache::{serialized_length, tree_hash, treehash, ObjectCache, TreeHasher};
+pub use ser::{measure_and_hash, node_to_bytes, node_to_bytes_limit};
+pub use ser_br::{
+    node_to_bytes_backrefs, node_to_bytes_backrefs_limit, node_to_bytes_backrefs_with_dictionary,
+    BackrefCache, CompressionLevel, CompressionStats,
+};
 pub use serialized_length::{serialized_length_atom, serialized_length_small_number};
 pub use tools::{
-    serialized_length_from_bytes, serialized_length_from_bytes_trusted, tree_hash_from_stream,
+    backref_stats_from_bytes, extract_subtree, serialized_length_from_bytes,
+    serialized_length_from_bytes_trusted, tree_hash_from_stream, BackrefStats, ChildPos,
 };
@@ -11,6 +11,8 @@ use std::collections::HashMap;
 type CachedFunction<T> = fn(&mut ObjectCache<T>, &Allocator, NodePtr) -> Option<T>;
 use super::bytes32::{hash_blobs, Bytes32};
 use crate::serde::serialized_length_atom;
+#[cfg(feature = "rayon")]
+use std::cmp::max;
 
 pub struct ObjectCache<T> {
     cache: HashMap<NodePtr, T>,
@@ -113,6 +115,138 @@ pub fn treehash(
     }
 }
 
+/// `TreeHasher` computes sha256 tree hashes of nodes in an `Allocator`. The
+/// hashes of all sub-trees are remembered, so hashing multiple roots that share
+/// structure only hashes the shared parts once.
+///
+/// The cache is keyed by `NodePtr`, so a `TreeHasher` must only be used with
+/// a single `Allocator`, and it must not be used across a call to
+/// `Allocator::restore_checkpoint()`, since node pointers may be reused after
+/// that.
+pub struct TreeHasher {
+    cache: ObjectCache<Bytes32>,
+}
+
+impl Default for TreeHasher {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TreeHasher {
+    pub fn new() -> Self {
+        Self {
+            cache: ObjectCache::new(treehash),
+        }
+    }
+
+    /// return the tree hash of `node`, reusing any hashes computed by previous
+    /// calls
+    pub fn tree_hash(&mut self, allocator: &Allocator, node: NodePtr) -> Bytes32 {
+        *self
+            .cache
+            .get_or_calculate(allocator, &node, None)
+            .expect("tree hash is always computable without a stop token")
+    }
+
+    /// return the tree hash of `node`, only if it has already been computed
+    pub fn get(&self, node: NodePtr) -> Option<Bytes32> {
+        self.cache.get_from_cache(&node).copied()
+    }
+
+    /// forget all cached hashes
+    pub fn clear(&mut self) {
+        self.cache.cache.clear();
+    }
+}
+
+/// compute the sha256 tree hash of `node`. To hash many nodes from the same
+/// `Allocator`, use `TreeHasher` to share work between calls
+pub fn tree_hash(allocator: &Allocator, node: NodePtr) -> Bytes32 {
+    TreeHasher::new().tree_hash(allocator, node)
+}
+
+/// sub-trees with at most this many nodes are always hashed by a single thread
+#[cfg(feature = "rayon")]
+const MIN_PARALLEL_CHUNK: u64 = 1024;
+
+/// count the nodes (atoms and pairs) in the tree. Shared sub-trees are counted
+/// once per reference
+#[cfg(feature = "rayon")]
+fn node_count(cache: &mut ObjectCache<u64>, allocator: &Allocator, node: NodePtr) -> Option<u64> {
+    match allocator.sexp(node) {
+        SExp::Pair(left, right) => match cache.get_from_cache(&left) {
+            None => None,
+            Some(left_value) => cache.get_from_cache(&right).map(|right_value| {
+                1_u64
+                    .saturating_add(*left_value)
+                    .saturating_add(*right_value)
+            }),
+        },
+        SExp::Atom => Some(1),
+    }
+}
+
+/// compute the sha256 tree hash of `node`, using the rayon thread pool. The
+/// tree is split into independent sub-trees of roughly equal size, which are
+/// hashed in parallel. The pairs above them (e.g. the spine of a long list)
+/// are then hashed on the calling thread, reusing the sub-tree hashes.
+///
+/// This only pays off for large trees, like the ones produced by block
+/// generators. Small trees, and all trees when the thread pool only has a
+/// single thread, are hashed by `tree_hash()` directly. The result
+/// is always the same as `tree_hash()`.
+#[cfg(feature = "rayon")]
+pub fn tree_hash_parallel(allocator: &Allocator, node: NodePtr) -> Bytes32 {
+    use rayon::prelude::*;
+    use std::collections::HashSet;
+
+    let threads = rayon::current_num_threads() as u64;
+    if threads < 2 {
+        return tree_hash(allocator, node);
+    }
+
+    let mut counts = ObjectCache::new(node_count);
+    let total = *counts
+        .get_or_calculate(allocator, &node, None)
+        .expect("node count is always computable without a stop token");
+
+    // aim for a few chunks per thread, to even out differences in chunk size
+    let chunk_size = max(total / (threads * 4), MIN_PARALLEL_CHUNK);
+    if total <= chunk_size {
+        return tree_hash(allocator, node);
+    }
+
+    // find the largest sub-trees that are no bigger than chunk_size. Every
+    // pair above them is part of the "spine", which is hashed last
+    let mut chunks = Vec::<NodePtr>::new();
+    let mut seen = HashSet::<NodePtr>::new();
+    let mut stack = vec![node];
+    while let Some(n) = stack.pop() {
+        if !seen.insert(n) {
+            continue;
+        }
+        match allocator.sexp(n) {
+            SExp::Pair(left, right) if *counts.get_from_cache(&n).unwrap() > chunk_size => {
+                stack.push(right);
+                stack.push(left);
+            }
+            _ => chunks.push(n),
+        }
+    }
+
+    let hashes: Vec<Bytes32> = chunks
+        .par_iter()
+        .map(|n| tree_hash(allocator, *n))
+        .collect();
+
+    let mut hasher = TreeHasher::new();
+    for (n, h) in chunks.into_iter().zip(hashes) {
+        hasher.cache.set(&n, h);
+    }
+    hasher.tree_hash(allocator, node)
+}
+
 /// calculate the serialized length (without backrefs) of a node. This is used
 /// to check if using backrefs is actually smaller.
 pub fn serialized_length(
@@ -136,34 +270,34 @@ pub fn serialized_length(
     }
 }
 
+/// calculate the depth of a node, i.e. the max nesting of pairs. A lone atom
+/// has depth 0, (1 . 2) has depth 1
+pub(crate) fn tree_depth(
+    cache: &mut ObjectCache<usize>,
+    allocator: &Allocator,
+    node: NodePtr,
+) -> Option<usize> {
+    match allocator.sexp(node) {
+        SExp::Pair(left, right) => match cache.get_from_cache(&left) {
+            None => None,
+            Some(left_value) => cache
+                .get_from_cache(&right)
+                .map(|right_value| 1 + (*left_value).max(*right_value)),
+        },
+        SExp::Atom => Some(0),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
     use hex::FromHex;
-    use std::cmp::max;
     use std::fmt::Debug;
     use std::io::Cursor;
 
     use crate::serde::de::node_from_stream;
 
-    /// calculate the depth of a node. Used for tests
-    fn calculate_depth_simple(
-        cache: &mut ObjectCache<usize>,
-        allocator: &Allocator,
-        node: NodePtr,
-    ) -> Option<usize> {
-        match allocator.sexp(node) {
-            SExp::Pair(left, right) => match cache.get_from_cache(&left) {
-                None => None,
-                Some(left_value) => cache
-                    .get_from_cache(&right)
-                    .map(|right_value| 1 + max(*left_value, *right_value)),
-            },
-            SExp::Atom => Some(0),
-        }
-    }
-
     fn check_cached_function<T>(obj_as_hex: &str, expected_value: T, f: CachedFunction<T>)
     where
         T: Clone + Eq + Debug,
@@ -197,7 +331,7 @@ mod tests {
 
     #[test]
     fn test_depths_cache() {
-        let check = |a, b| check_cached_function(a, b, calculate_depth_simple);
+        let check = |a, b| check_cached_function(a, b, tree_depth);
         check("01", 0); // 1
         check("ff83666f6f83626172", 1); // (foo . bar)
         check("ff83666f6fff8362617280", 2); // (foo bar)
@@ -235,6 +369,89 @@ mod tests {
         check("ff01ff02ff03ff04ff05ff0680", 13); // (1 2 3 4 5 6)
     }
 
+    #[test]
+    fn test_tree_hasher() {
+        let mut a = Allocator::new();
+        let foo = a.new_atom(b"foo").unwrap();
+        let bar = a.new_atom(b"bar").unwrap();
+        let pair = a.new_pair(foo, bar).unwrap();
+        let nil = a.nil();
+        let list = a.new_pair(pair, nil).unwrap();
+
+        let mut hasher = TreeHasher::new();
+        assert_eq!(hasher.get(pair), None);
+        let pair_hash = hasher.tree_hash(&a, pair);
+        assert_eq!(
+            pair_hash,
+            Bytes32::from_hex("c518e45ae6a7b4146017b7a1d81639051b132f1f5572ce3088a3898a9ed1280b")
+                .unwrap()
+        );
+        assert_eq!(pair_hash, tree_hash(&a, pair));
+
+        // sub-trees hashed in previous calls are remembered
+        assert_eq!(hasher.get(foo), Some(tree_hash(&a, foo)));
+        assert_eq!(hasher.get(list), None);
+        let list_hash = hasher.tree_hash(&a, list);
+        assert_eq!(
+            list_hash,
+            hash_blobs(&[&[2], &pair_hash, &tree_hash(&a, nil)])
+        );
+        assert_eq!(hasher.get(list), Some(list_hash));
+
+        hasher.clear();
+        assert_eq!(hasher.get(list), None);
+        assert_eq!(hasher.tree_hash(&a, list), list_hash);
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn test_tree_hash_parallel() {
+        // make sure the tree is actually split, regardless of the number of
+        // CPUs on the machine running the test
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(4)
+            .build()
+            .unwrap();
+        let tree_hash_parallel =
+            |a: &Allocator, n: NodePtr| pool.install(|| tree_hash_parallel(a, n));
+
+        let mut a = Allocator::new();
+
+        // small trees are hashed sequentially
+        let foo = a.new_atom(b"foo").unwrap();
+        assert_eq!(tree_hash_parallel(&a, foo), tree_hash(&a, foo));
+
+        // a long list, where every item is a small tree
+        let mut list = a.nil();
+        for i in 0..20000 {
+            let item = a.new_number(i.into()).unwrap();
+            let item = a.new_pair(item, foo).unwrap();
+            list = a.new_pair(item, list).unwrap();
+        }
+        assert_eq!(tree_hash_parallel(&a, list), tree_hash(&a, list));
+
+        // a balanced tree, with every leaf being a distinct atom
+        let mut layer = Vec::new();
+        for i in 0..(1 << 15) {
+            layer.push(a.new_number(i.into()).unwrap());
+        }
+        while layer.len() > 1 {
+            layer = layer
+                .chunks(2)
+                .map(|c| a.new_pair(c[0], c[1]).unwrap())
+                .collect();
+        }
+        let balanced = layer[0];
+        assert_eq!(tree_hash_parallel(&a, balanced), tree_hash(&a, balanced));
+
+        // a tree with a lot of shared structure
+        let mut shared = a.new_pair(list, balanced).unwrap();
+        for _ in 0..100 {
+            shared = a.new_pair(shared, shared).unwrap();
+        }
+        assert_eq!(tree_hash_parallel(&a, shared), tree_hash(&a, shared));
+    }
+
     // this test takes a very long time (>60s) in debug mode, so it only runs in release mode
 
     #[cfg(not(debug_assertions))]
@@ -1,8 +1,9 @@
-use std::io::{Cursor, Read, Result, Seek, SeekFrom};
+use std::io::{Read, Result};
 
 use crate::allocator::{Allocator, NodePtr};
 
-use super::errors::{bad_encoding, internal_error};
+use super::errors::{bad_encoding, internal_error, non_canonical};
+use super::slice_reader::SliceReader;
 
 const MAX_SINGLE_BYTE: u8 = 0x7f;
 
@@ -48,20 +49,48 @@ pub fn decode_size<R: Read>(f: &mut R, initial_b: u8) -> Result<u64> {
     decode_size_with_offset(f, initial_b).map(|v| v.1)
 }
 
+/// returns true if an atom of `atom_size` bytes, with a length prefix of
+/// `prefix_len` bytes, is using the shortest possible length prefix
+fn is_minimal_size_prefix(prefix_len: u8, atom_size: u64) -> bool {
+    // the largest atom size that can be encoded with a length prefix one byte
+    // shorter than this one
+    match prefix_len {
+        1 => true,
+        2 => atom_size > 0x3f,
+        3 => atom_size > 0x1fff,
+        4 => atom_size > 0xfffff,
+        5 => atom_size > 0x7ffffff,
+        _ => atom_size > 0x3ffffffff,
+    }
+}
+
 /// parse an atom from the stream and return a pointer to it
-/// the first byte has already been read
-fn parse_atom_ptr<'a>(f: &'a mut Cursor<&[u8]>, first_byte: u8) -> Result<&'a [u8]> {
+/// the first byte has already been read. If `canonical` is set, atoms that are
+/// not using the shortest possible encoding are rejected
+fn parse_atom_ptr<'a>(
+    f: &mut SliceReader<'a>,
+    first_byte: u8,
+    canonical: bool,
+) -> Result<&'a [u8]> {
     let blob = if first_byte <= MAX_SINGLE_BYTE {
-        let pos = f.position() as usize;
-        &f.get_ref()[pos - 1..pos]
+        f.preceding(1)
     } else {
-        let blob_size = decode_size(f, first_byte)?;
-        let pos = f.position() as usize;
-        if f.get_ref().len() < pos + blob_size as usize {
+        let (prefix_len, blob_size) = decode_size_with_offset(f, first_byte)?;
+        if canonical && !is_minimal_size_prefix(prefix_len, blob_size) {
+            return Err(non_canonical());
+        }
+        let Some(blob) = usize::try_from(blob_size)
+            .ok()
+            .and_then(|size| f.read_slice(size))
+        else {
             return Err(bad_encoding());
+        };
+        // single bytes in the range 0-0x7f must be encoded without a length
+        // prefix
+        if canonical && blob.len() == 1 && blob[0] <= MAX_SINGLE_BYTE {
+            return Err(non_canonical());
         }
-        f.seek(SeekFrom::Current(blob_size as i64))?;
-        &f.get_ref()[pos..(pos + blob_size as usize)]
+        blob
     };
     Ok(blob)
 }
@@ -70,35 +99,43 @@ fn parse_atom_ptr<'a>(f: &'a mut Cursor<&[u8]>, first_byte: u8) -> Result<&'a [u
 /// At this point, the first byte has already been read to ensure it's
 /// not a special code like `CONS_BOX_MARKER` = 0xff, so it must be
 /// passed in too
+/// If `canonical` is set, non-minimal encodings of the atom are rejected.
 pub fn parse_atom(
     allocator: &mut Allocator,
     first_byte: u8,
-    f: &mut Cursor<&[u8]>,
+    f: &mut SliceReader<'_>,
+    canonical: bool,
 ) -> Result<NodePtr> {
     if first_byte == 0x01 {
         Ok(allocator.one())
     } else if first_byte == 0x80 {
         Ok(allocator.nil())
     } else {
-        let blob = parse_atom_ptr(f, first_byte)?;
+        let blob = parse_atom_ptr(f, first_byte, canonical)?;
         Ok(allocator.new_atom(blob)?)
     }
 }
 
 /// parse an atom from the stream and return a pointer to it
-pub fn parse_path<'a>(f: &'a mut Cursor<&[u8]>) -> Result<&'a [u8]> {
-    let mut buf1: [u8; 1] = [0];
-    f.read_exact(&mut buf1)?;
-    parse_atom_ptr(f, buf1[0])
+/// If `canonical` is set, the path must use the shortest possible encoding.
+/// i.e. no redundant leading zeros and no empty path.
+pub fn parse_path<'a>(f: &mut SliceReader<'a>, canonical: bool) -> Result<&'a [u8]> {
+    let first_byte = f.read_u8()?;
+    let path = parse_atom_ptr(f, first_byte, canonical)?;
+    if canonical && path.first().is_none_or(|b| *b == 0) {
+        return Err(non_canonical());
+    }
+    Ok(path)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::serde::errors::is_non_canonical;
     use crate::serde::write_atom::write_atom;
     use rstest::rstest;
 
-    use std::io::ErrorKind;
+    use std::io::{Cursor, ErrorKind};
 
     #[rstest]
     // single-byte length prefix
@@ -168,13 +205,11 @@ mod tests {
     }
 
     fn check_parse_atom(blob: &[u8], expected_atom: &[u8]) {
-        let mut cursor = Cursor::<&[u8]>::new(blob);
-        let mut first: [u8; 1] = [0];
-        cursor.read_exact(&mut first).unwrap();
-        let first = first[0];
+        let mut cursor = SliceReader::new(blob);
+        let first = cursor.read_u8().unwrap();
 
         let mut allocator = Allocator::new();
-        let atom_node = parse_atom(&mut allocator, first, &mut cursor).unwrap();
+        let atom_node = parse_atom(&mut allocator, first, &mut cursor, false).unwrap();
 
         let atom = allocator.atom(atom_node);
 
@@ -213,14 +248,83 @@ mod tests {
         }
     }
 
+    #[rstest]
+    #[case("80", "")]
+    #[case("00", "00")]
+    #[case("7f", "7f")]
+    #[case("8180", "80")]
+    #[case("83666f6f", "666f6f")]
+    // the shortest atom that needs a 2 byte length prefix
+    #[case(
+        "c04066666666666666666666666666666666666666666666666666666666666666666666666666666666666666666666666666666666666666666666666666666666",
+        "66666666666666666666666666666666666666666666666666666666666666666666666666666666666666666666666666666666666666666666666666666666"
+    )]
+    fn test_parse_atom_canonical(#[case] blob_hex: &str, #[case] expected_hex: &str) {
+        let blob = hex::decode(blob_hex).unwrap();
+        let mut cursor = SliceReader::new(&blob);
+        let first = cursor.read_u8().unwrap();
+        let mut allocator = Allocator::new();
+        let atom_node = parse_atom(&mut allocator, first, &mut cursor, true).unwrap();
+        assert_eq!(
+            allocator.atom(atom_node).as_ref(),
+            hex::decode(expected_hex).unwrap()
+        );
+        assert_eq!(cursor.position(), blob.len());
+    }
+
     #[test]
     fn test_truncated_parse_atom() {
         // the stream is truncated
         let first = 0b11111100;
-        let mut cursor = Cursor::<&[u8]>::new(&[0x4, 0, 0, 0]);
+        let mut cursor = SliceReader::new(&[0x4, 0, 0, 0]);
         let mut allocator = Allocator::new();
-        let ret = parse_atom(&mut allocator, first, &mut cursor);
+        let ret = parse_atom(&mut allocator, first, &mut cursor, false);
         let err = ret.unwrap_err();
         assert_eq!(err.kind(), ErrorKind::UnexpectedEof);
     }
+
+    #[rstest]
+    // a single byte, below 0x80, with a length prefix
+    #[case("8100")]
+    #[case("817f")]
+    // length prefixes that are longer than necessary
+    #[case("c000")]
+    #[case("c00100")]
+    #[case("c03f000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000")]
+    #[case("e0000100")]
+    fn test_non_canonical_atom(#[case] blob_hex: &str) {
+        let blob = hex::decode(blob_hex).unwrap();
+        let mut allocator = Allocator::new();
+
+        let mut cursor = SliceReader::new(&blob[1..]);
+        assert!(parse_atom(&mut allocator, blob[0], &mut cursor, false).is_ok());
+
+        let mut cursor = SliceReader::new(&blob[1..]);
+        let err = parse_atom(&mut allocator, blob[0], &mut cursor, true).unwrap_err();
+        assert!(is_non_canonical(&err));
+        assert_eq!(err.to_string(), "non-canonical encoding");
+    }
+
+    #[rstest]
+    #[case("80", false)]
+    #[case("00", false)]
+    #[case("820001", false)]
+    #[case("8101", false)]
+    #[case("01", true)]
+    #[case("7f", true)]
+    #[case("8180", true)]
+    #[case("820100", true)]
+    fn test_canonical_path(#[case] blob_hex: &str, #[case] canonical: bool) {
+        let blob = hex::decode(blob_hex).unwrap();
+
+        let mut cursor = SliceReader::new(&blob);
+        assert!(parse_path(&mut cursor, false).is_ok());
+
+        let mut cursor = SliceReader::new(&blob);
+        let ret = parse_path(&mut cursor, true);
+        assert_eq!(ret.is_ok(), canonical);
+        if let Err(e) = ret {
+            assert!(is_non_canonical(&e));
+        }
+    }
 }
@@ -125,10 +125,24 @@ impl ReadCacheLookup {
 
     /// return the list of minimal-length paths to the given hash which will serialize to no larger
     /// than the given size (or an empty list if no such path exists)
+    #[cfg(test)]
     pub fn find_paths(&self, id: &Bytes32, serialized_length: u64) -> Vec<Vec<u8>> {
+        self.find_paths_limited(id, serialized_length, 4, usize::MAX)
+    }
+
+    /// like `find_paths()`, but only searches for nodes whose serialized
+    /// length is at least `min_length`, and gives up after having visited
+    /// `max_visited` nodes in the cache
+    fn find_paths_limited(
+        &self,
+        id: &Bytes32,
+        serialized_length: u64,
+        min_length: u64,
+        max_visited: usize,
+    ) -> Vec<Vec<u8>> {
         // this function is not cheap. only keep going if there's potential to
-        // save enough bytes
-        if serialized_length < 4 {
+        // save enough bytes. A back reference is at least 2 bytes
+        if serialized_length < min_length.max(3) {
             return vec![];
         }
 
@@ -172,6 +186,9 @@ impl ReadCacheLookup {
                         seen_ids.insert(parent);
                     }
                 }
+                if seen_ids.len() > max_visited {
+                    return possible_responses;
+                }
             }
             if !possible_responses.is_empty() {
                 break;
@@ -183,7 +200,19 @@ impl ReadCacheLookup {
 
     /// If multiple paths exist, the lexicographically smallest one will be returned.
     pub fn find_path(&self, id: &Bytes32, serialized_length: u64) -> Option<Vec<u8>> {
-        let mut paths = self.find_paths(id, serialized_length);
+        self.find_path_limited(id, serialized_length, 4, usize::MAX)
+    }
+
+    /// like `find_path()`, but with the search bounded the same way as
+    /// `find_paths_limited()`
+    pub fn find_path_limited(
+        &self,
+        id: &Bytes32,
+        serialized_length: u64,
+        min_length: u64,
+        max_visited: usize,
+    ) -> Option<Vec<u8>> {
+        let mut paths = self.find_paths_limited(id, serialized_length, min_length, max_visited);
         if !paths.is_empty() {
             paths.sort();
             paths.truncate(1);
@@ -390,4 +419,29 @@ mod tests {
 
         assert!(!rcl.count.contains_key(&hash_of_1_atom));
     }
+
+    #[test]
+    fn test_find_path_limited() {
+        let mut rcl = ReadCacheLookup::new();
+        let item = hash_blob(b"\x01foobar");
+        rcl.push(item);
+        for i in 0..10 {
+            rcl.push(hash_blob(&[1, i]));
+        }
+
+        // the item is 10 steps down the stack
+        let path = [0b1011, 0b11111111];
+        assert_eq!(rcl.find_path(&item, 7), Some(path.to_vec()));
+        assert_eq!(
+            rcl.find_path_limited(&item, 7, 4, usize::MAX),
+            Some(path.to_vec())
+        );
+        assert_eq!(rcl.find_path_limited(&item, 7, 4, 20), Some(path.to_vec()));
+
+        // giving up too early
+        assert_eq!(rcl.find_path_limited(&item, 7, 4, 10), None);
+
+        // not considering nodes this small
+        assert_eq!(rcl.find_path_limited(&item, 7, 8, usize::MAX), None);
+    }
 }
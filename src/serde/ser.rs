@@ -3,8 +3,11 @@ use std::io::Cursor;
 use std::io::ErrorKind;
 use std::io::Write;
 
-use super::write_atom::write_atom;
-use crate::allocator::{len_for_value, Allocator, NodePtr, NodeVisitor};
+use super::bytes32::{hash_blobs, Bytes32};
+use super::serialized_length::serialized_length_atom;
+use super::write_atom::{write_atom, write_small_atom};
+use crate::allocator::{Allocator, NodePtr, NodeVisitor, SExp};
+use std::collections::HashMap;
 
 const CONS_BOX_MARKER: u8 = 0xff;
 
@@ -43,11 +46,7 @@ pub fn node_to_stream<W: io::Write>(a: &Allocator, node: NodePtr, f: &mut W) ->
     while let Some(v) = values.pop() {
         match a.node(v) {
             NodeVisitor::Buffer(buf) => write_atom(f, buf)?,
-            NodeVisitor::U32(val) => {
-                let buf = val.to_be_bytes();
-                let len = len_for_value(val);
-                write_atom(f, &buf[4 - len..])?
-            }
+            NodeVisitor::U32(val) => write_small_atom(f, val)?,
             NodeVisitor::Pair(left, right) => {
                 f.write_all(&[CONS_BOX_MARKER])?;
                 values.push(right);
@@ -70,6 +69,70 @@ pub fn node_to_bytes(a: &Allocator, node: NodePtr) -> io::Result<Vec<u8>> {
     node_to_bytes_limit(a, node, 2000000)
 }
 
+/// Computes the length of the serialization of `node` (without
+/// back-references) and its tree hash, in a single pass and without
+/// serializing it. Fails with `ErrorKind::OutOfMemory`, like
+/// `node_to_bytes_limit()`, as soon as the length is known to exceed `limit`.
+/// Shared sub-trees are only visited once, so this is cheap even for trees
+/// whose serialization would be huge.
+pub fn measure_and_hash(
+    a: &Allocator,
+    node: NodePtr,
+    limit: usize,
+) -> io::Result<(usize, Bytes32)> {
+    enum Op {
+        Visit(NodePtr),
+        // the results for the first and rest of this pair are on top of the
+        // value stack
+        Combine(NodePtr),
+    }
+
+    let limit = limit as u64;
+    let mut results = HashMap::<NodePtr, (u64, Bytes32)>::new();
+    let mut values = Vec::<(u64, Bytes32)>::new();
+    let mut ops = vec![Op::Visit(node)];
+    while let Some(op) = ops.pop() {
+        let (n, value) = match op {
+            Op::Visit(n) => {
+                if let Some(value) = results.get(&n) {
+                    values.push(*value);
+                    continue;
+                }
+                match a.sexp(n) {
+                    SExp::Pair(first, rest) => {
+                        ops.push(Op::Combine(n));
+                        ops.push(Op::Visit(rest));
+                        ops.push(Op::Visit(first));
+                        continue;
+                    }
+                    SExp::Atom => {
+                        let atom = a.atom(n);
+                        let len = serialized_length_atom(atom.as_ref()) as u64;
+                        (n, (len, hash_blobs(&[&[1], atom.as_ref()])))
+                    }
+                }
+            }
+            Op::Combine(n) => {
+                let rest = values.pop().expect("measure_and_hash: value stack empty");
+                let first = values.pop().expect("measure_and_hash: value stack empty");
+                let Some(len) = first.0.checked_add(rest.0).and_then(|l| l.checked_add(1)) else {
+                    return Err(ErrorKind::OutOfMemory.into());
+                };
+                (n, (len, hash_blobs(&[&[2], &first.1, &rest.1])))
+            }
+        };
+        // every node is part of the serialization of the root, so it's too
+        // big as soon as any of them is
+        if value.0 > limit {
+            return Err(ErrorKind::OutOfMemory.into());
+        }
+        results.insert(n, value);
+        values.push(value);
+    }
+    let (len, hash) = values.pop().expect("measure_and_hash: value stack empty");
+    Ok((len as usize, hash))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -107,4 +170,59 @@ mod tests {
             );
         }
     }
+
+    #[test]
+    fn test_measure_and_hash() {
+        use crate::serde::tree_hash;
+
+        let mut a = Allocator::new();
+        let leaf = a.new_atom(&[1, 2, 3, 4, 5]).unwrap();
+        let small = a.new_small_number(1337).unwrap();
+        let mut node = a.new_pair(leaf, small).unwrap();
+        for _ in 0..3 {
+            node = a.new_pair(node, node).unwrap();
+        }
+        let expected = node_to_bytes(&a, node).unwrap();
+        let hash = tree_hash(&a, node);
+        for limit in [expected.len(), usize::MAX] {
+            assert_eq!(
+                measure_and_hash(&a, node, limit).unwrap(),
+                (expected.len(), hash)
+            );
+        }
+        assert_eq!(
+            measure_and_hash(&a, node, expected.len() - 1)
+                .unwrap_err()
+                .kind(),
+            io::ErrorKind::OutOfMemory
+        );
+        assert_eq!(
+            node_to_bytes_limit(&a, node, expected.len() - 1)
+                .unwrap_err()
+                .kind(),
+            io::ErrorKind::OutOfMemory
+        );
+
+        // an atom on its own
+        assert_eq!(
+            measure_and_hash(&a, leaf, 6).unwrap(),
+            (6, tree_hash(&a, leaf))
+        );
+        assert!(measure_and_hash(&a, leaf, 5).is_err());
+    }
+
+    #[test]
+    fn test_measure_and_hash_huge() {
+        // the serialization of this tree is about 2^64 bytes, measuring it
+        // must not overflow or take long
+        let mut a = Allocator::new();
+        let mut node = a.new_atom(b"foobar").unwrap();
+        for _ in 0..64 {
+            node = a.new_pair(node, node).unwrap();
+        }
+        assert_eq!(
+            measure_and_hash(&a, node, usize::MAX).unwrap_err().kind(),
+            io::ErrorKind::OutOfMemory
+        );
+    }
 }
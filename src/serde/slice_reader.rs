@@ -0,0 +1,102 @@
+use std::io;
+use std::io::Read;
+
+use super::errors::unexpected_eof;
+
+/// `SliceReader` reads from a borrowed byte buffer. It's used by the
+/// deserializers in place of `std::io::Cursor`. Reading single bytes is just
+/// a bounds check and an increment, and atoms are returned as sub-slices of
+/// the input, rather than being copied out.
+pub(crate) struct SliceReader<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> SliceReader<'a> {
+    pub fn new(buf: &'a [u8]) -> Self {
+        Self { buf, pos: 0 }
+    }
+
+    /// the number of bytes read so far
+    pub fn position(&self) -> usize {
+        self.pos
+    }
+
+    #[inline]
+    pub fn read_u8(&mut self) -> io::Result<u8> {
+        let Some(b) = self.buf.get(self.pos) else {
+            return Err(unexpected_eof());
+        };
+        self.pos += 1;
+        Ok(*b)
+    }
+
+    /// returns the next `len` bytes, or `None` if there aren't that many bytes
+    /// left. In that case, nothing is consumed
+    #[inline]
+    pub fn read_slice(&mut self, len: usize) -> Option<&'a [u8]> {
+        let end = self.pos.checked_add(len)?;
+        let ret = self.buf.get(self.pos..end)?;
+        self.pos = end;
+        Some(ret)
+    }
+
+    /// returns the last `len` bytes that were read
+    #[inline]
+    pub fn preceding(&self, len: usize) -> &'a [u8] {
+        &self.buf[self.pos - len..self.pos]
+    }
+}
+
+impl Read for SliceReader<'_> {
+    fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        let len = std::cmp::min(out.len(), self.buf.len() - self.pos);
+        out[..len].copy_from_slice(&self.buf[self.pos..self.pos + len]);
+        self.pos += len;
+        Ok(len)
+    }
+
+    #[inline]
+    fn read_exact(&mut self, out: &mut [u8]) -> io::Result<()> {
+        let Some(buf) = self.read_slice(out.len()) else {
+            self.pos = self.buf.len();
+            return Err(unexpected_eof());
+        };
+        out.copy_from_slice(buf);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_slice_reader() {
+        let mut r = SliceReader::new(b"foobar");
+        assert_eq!(r.read_u8().unwrap(), b'f');
+        assert_eq!(r.preceding(1), b"f");
+        assert_eq!(r.read_slice(2), Some(&b"oo"[..]));
+        assert_eq!(r.preceding(3), b"foo");
+        assert_eq!(r.position(), 3);
+
+        // a failed read_slice() doesn't consume anything
+        assert_eq!(r.read_slice(4), None);
+        assert_eq!(r.read_slice(usize::MAX), None);
+        assert_eq!(r.position(), 3);
+
+        let mut buf = [0_u8; 2];
+        r.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"ba");
+        assert_eq!(r.read(&mut buf).unwrap(), 1);
+        assert_eq!(buf[0], b'r');
+        assert_eq!(r.read(&mut buf).unwrap(), 0);
+
+        // running out of input fails the same way as std::io::Cursor does
+        let e = r.read_u8().unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::UnexpectedEof);
+        assert_eq!(e.to_string(), "failed to fill whole buffer");
+        let e = r.read_exact(&mut buf).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::UnexpectedEof);
+    }
+}
@@ -2,7 +2,9 @@ use hex::FromHex;
 
 use crate::allocator::Allocator;
 use crate::serde::{
-    node_from_bytes, node_from_bytes_backrefs, node_to_bytes, node_to_bytes_backrefs, Serializer,
+    node_from_bytes, node_from_bytes_backrefs, node_from_bytes_backrefs_with_dictionary,
+    node_to_bytes, node_to_bytes_backrefs, node_to_bytes_backrefs_with_dictionary, BackrefCache,
+    Serializer,
 };
 
 fn check_round_trip(obj_ser_br_hex: &str) {
@@ -73,3 +75,52 @@ fn test_round_trip() {
 
     check("ff83666f6ffffe01fffe01fffe01fffe01fffe01fffe0180");
 }
+
+#[test]
+fn test_round_trip_dictionary() {
+    let mut a = Allocator::new();
+
+    // the dictionary is a "puzzle" made up of a few long atoms
+    let dictionary = node_from_bytes(
+        &mut a,
+        &<Vec<u8>>::from_hex(
+            "ff9a6c6f6e6720737472696e67206f6620707567676c652064617461\
+             ff9a616e6f74686572206c6f6e6720737472696e67206f662074657874\
+             ff8b7468697264206174746f6d80",
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    let mut cache = BackrefCache::with_prefix(&a, dictionary);
+
+    // programs that embed the dictionary, or parts of it, along with a few
+    // atoms of their own (like a public key)
+    let (_, rest) = a.next(dictionary).unwrap();
+    let mut programs = vec![dictionary, rest];
+    for key in [
+        b"pubkey 1".as_slice(),
+        b"pubkey 2",
+        b"a much longer pubkey 3",
+    ] {
+        let key = a.new_atom(key).unwrap();
+        programs.push(a.new_pair(key, dictionary).unwrap());
+        programs.push(a.new_pair(rest, key).unwrap());
+    }
+
+    for program in programs {
+        let plain = node_to_bytes(&a, program).unwrap();
+        let compressed = node_to_bytes_backrefs_with_dictionary(&a, program, dictionary).unwrap();
+        assert_eq!(cache.node_to_bytes(&a, program).unwrap(), compressed);
+
+        // referencing the dictionary makes the output smaller than using
+        // back references alone
+        assert!(compressed.len() < node_to_bytes_backrefs(&a, program).unwrap().len());
+
+        let mut a2 = Allocator::new();
+        let dictionary2 =
+            node_from_bytes(&mut a2, &node_to_bytes(&a, dictionary).unwrap()).unwrap();
+        let node =
+            node_from_bytes_backrefs_with_dictionary(&mut a2, &compressed, dictionary2).unwrap();
+        assert_eq!(node_to_bytes(&a2, node).unwrap(), plain);
+    }
+}
@@ -1,51 +1,110 @@
+use std::borrow::Cow;
 use std::io;
-use std::io::{Cursor, Read, Seek, SeekFrom};
+use std::io::{Cursor, Read};
 
-use super::errors::bad_encoding;
+use super::de_br::node_from_bytes_backrefs;
+use super::errors::{bad_encoding, invalid_path};
 use super::parse_atom::decode_size;
+use super::ser::node_to_bytes;
+use super::slice_reader::SliceReader;
 
 const MAX_SINGLE_BYTE: u8 = 0x7f;
 const BACK_REFERENCE: u8 = 0xfe;
 const CONS_BOX_MARKER: u8 = 0xff;
 
 pub fn serialized_length_from_bytes_trusted(b: &[u8]) -> io::Result<u64> {
-    let mut f = Cursor::new(b);
+    let mut f = SliceReader::new(b);
+    skip_object(&mut f)?;
+    Ok(f.position() as u64)
+}
+
+// skip past one serialized object, without validating back-references.
+// Returns whether the object contains any back-references
+fn skip_object(f: &mut SliceReader<'_>) -> io::Result<bool> {
+    let mut has_backrefs = false;
     let mut ops_counter = 1;
-    let mut b = [0; 1];
     while ops_counter > 0 {
         ops_counter -= 1;
-        f.read_exact(&mut b)?;
-        if b[0] == CONS_BOX_MARKER {
+        let b = f.read_u8()?;
+        if b == CONS_BOX_MARKER {
             // we expect to parse two more items from the stream
             // the left and right sub tree
             ops_counter += 2;
-        } else if b[0] == BACK_REFERENCE {
+        } else if b == BACK_REFERENCE {
             // This is a back-ref. We don't actually need to resolve it, just
             // parse the path and move on
-            let mut first_byte = [0; 1];
-            f.read_exact(&mut first_byte)?;
-            if first_byte[0] > MAX_SINGLE_BYTE {
-                let path_size = decode_size(&mut f, first_byte[0])?;
-                f.seek(SeekFrom::Current(path_size as i64))?;
-                if (f.get_ref().len() as u64) < f.position() {
-                    return Err(bad_encoding());
-                }
+            has_backrefs = true;
+            let first_byte = f.read_u8()?;
+            if first_byte > MAX_SINGLE_BYTE {
+                read_blob(f, first_byte)?;
             }
-        } else if b[0] == 0x80 || b[0] <= MAX_SINGLE_BYTE {
+        } else if b == 0x80 || b <= MAX_SINGLE_BYTE {
             // This one byte we just read was the whole atom.
             // or the special case of NIL
         } else {
-            let blob_size = decode_size(&mut f, b[0])?;
-            f.seek(SeekFrom::Current(blob_size as i64))?;
-            if (f.get_ref().len() as u64) < f.position() {
-                return Err(bad_encoding());
+            read_blob(f, b)?;
+        }
+    }
+    Ok(has_backrefs)
+}
+
+/// A step in a path into a tree, picking the left or right side of a pair
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChildPos {
+    Left,
+    Right,
+}
+
+/// return the serialization of the sub tree at `path` in the serialized object
+/// `b`. This is done without deserializing `b` by skipping over the sub trees
+/// not on the path, so those are not validated. If the serialization of the
+/// sub tree is self-contained, it's returned as a slice of `b`. If it (or the
+/// path to it) contains back-references, `b` is deserialized and the sub tree
+/// is returned re-serialized, without back-references.
+pub fn extract_subtree<'a>(b: &'a [u8], path: &[ChildPos]) -> io::Result<Cow<'a, [u8]>> {
+    let mut f = SliceReader::new(b);
+    for step in path {
+        match f.read_u8()? {
+            CONS_BOX_MARKER => {
+                if *step == ChildPos::Right && skip_object(&mut f)? {
+                    // the right sub tree's back-references may point into the
+                    // left one, so we can't just skip it
+                    return extract_subtree_slow(b, path);
+                }
+            }
+            BACK_REFERENCE => {
+                return extract_subtree_slow(b, path);
+            }
+            _ => {
+                return Err(invalid_path());
             }
         }
     }
-    Ok(f.position())
+    let start = f.position();
+    if skip_object(&mut f)? {
+        return extract_subtree_slow(b, path);
+    }
+    Ok(Cow::Borrowed(f.preceding(f.position() - start)))
+}
+
+fn extract_subtree_slow<'a>(b: &[u8], path: &[ChildPos]) -> io::Result<Cow<'a, [u8]>> {
+    use crate::{allocator::SExp, Allocator};
+
+    let mut a = Allocator::new();
+    let mut node = node_from_bytes_backrefs(&mut a, b)?;
+    for step in path {
+        let SExp::Pair(left, right) = a.sexp(node) else {
+            return Err(invalid_path());
+        };
+        node = match step {
+            ChildPos::Left => left,
+            ChildPos::Right => right,
+        };
+    }
+    Ok(Cow::Owned(node_to_bytes(&a, node)?))
 }
 
-use chia_sha2::Sha256;
+use crate::sha256::Sha256;
 
 fn hash_atom(buf: &[u8]) -> [u8; 32] {
     let mut ctx = Sha256::new();
@@ -107,6 +166,16 @@ pub fn tree_hash_from_stream(f: &mut Cursor<&[u8]>) -> io::Result<[u8; 32]> {
     Ok(values.pop().unwrap())
 }
 
+/// read the length prefix (whose first byte has already been read) and the
+/// contents of an atom
+fn read_blob<'a>(f: &mut SliceReader<'a>, first_byte: u8) -> io::Result<&'a [u8]> {
+    let blob_size = decode_size(f, first_byte)?;
+    usize::try_from(blob_size)
+        .ok()
+        .and_then(|size| f.read_slice(size))
+        .ok_or_else(bad_encoding)
+}
+
 /// validate that a buffer is a valid CLVM serialization, and return the length
 /// of the CLVM object. This may fail if the serialization contains an invalid
 /// back-reference or if the buffer is truncated.
@@ -115,8 +184,7 @@ pub fn serialized_length_from_bytes(b: &[u8]) -> io::Result<u64> {
     use crate::traverse_path::traverse_path;
     use crate::{allocator::SExp, Allocator};
 
-    let mut f = Cursor::new(b);
-    let mut b = [0; 1];
+    let mut f = SliceReader::new(b);
 
     // the allocator is just used to track the tree structure, in order to
     // validate back-references
@@ -128,25 +196,21 @@ pub fn serialized_length_from_bytes(b: &[u8]) -> io::Result<u64> {
     while let Some(op) = ops.pop() {
         match op {
             ParseOp::SExp => {
-                f.read_exact(&mut b)?;
-                if b[0] == CONS_BOX_MARKER {
+                let b = f.read_u8()?;
+                if b == CONS_BOX_MARKER {
                     ops.push(ParseOp::Cons);
                     ops.push(ParseOp::SExp);
                     ops.push(ParseOp::SExp);
-                } else if b[0] == BACK_REFERENCE {
-                    let path = parse_path(&mut f)?;
+                } else if b == BACK_REFERENCE {
+                    let path = parse_path(&mut f, false)?;
                     let back_reference = traverse_path(&allocator, path, values)?.1;
                     values = allocator.new_pair(back_reference, values)?;
-                } else if b[0] == 0x80 || b[0] <= MAX_SINGLE_BYTE {
+                } else if b == 0x80 || b <= MAX_SINGLE_BYTE {
                     // This one byte we just read was the whole atom.
                     // or the special case of NIL
                     values = allocator.new_pair(nil, values)?;
                 } else {
-                    let blob_size = decode_size(&mut f, b[0])?;
-                    f.seek(SeekFrom::Current(blob_size as i64))?;
-                    if (f.get_ref().len() as u64) < f.position() {
-                        return Err(bad_encoding());
-                    }
+                    read_blob(&mut f, b)?;
                     values = allocator.new_pair(nil, values)?;
                 }
             }
@@ -166,7 +230,149 @@ pub fn serialized_length_from_bytes(b: &[u8]) -> io::Result<u64> {
         }
     }
     match allocator.sexp(values) {
-        SExp::Pair(_, _) => Ok(f.position()),
+        SExp::Pair(_, _) => Ok(f.position() as u64),
+        _ => Err(bad_encoding()),
+    }
+}
+
+/// Properties of the tree a (possibly back-reference compressed) serialization
+/// expands to, as computed by `backref_stats_from_bytes()`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BackrefStats {
+    /// the number of bytes of the input buffer making up the serialization
+    pub compressed_length: u64,
+    /// the length of the serialization, without back-references
+    pub serialized_length: u64,
+    /// the total number of atoms and pairs in the expanded tree. Shared
+    /// sub trees are counted once for every time they are referenced
+    pub node_count: u64,
+    pub tree_hash: [u8; 32],
+}
+
+#[derive(Clone, Copy)]
+struct NodeInfo {
+    serialized_length: u64,
+    node_count: u64,
+    tree_hash: [u8; 32],
+}
+
+impl NodeInfo {
+    fn atom(serialized_length: u64, buf: &[u8]) -> Self {
+        Self {
+            serialized_length,
+            node_count: 1,
+            tree_hash: hash_atom(buf),
+        }
+    }
+
+    fn pair(left: &Self, right: &Self) -> Self {
+        Self {
+            serialized_length: left
+                .serialized_length
+                .saturating_add(right.serialized_length)
+                .saturating_add(1),
+            node_count: left
+                .node_count
+                .saturating_add(right.node_count)
+                .saturating_add(1),
+            tree_hash: hash_pair(&left.tree_hash, &right.tree_hash),
+        }
+    }
+}
+
+/// validate a CLVM serialization that may contain back-references and compute
+/// the serialized length, node count and tree hash of the tree it expands to,
+/// in a single pass. Back-references are resolved to the properties of the
+/// sub tree they point to, so the cost is proportional to the size of the
+/// compressed input, not the expanded tree. Lengths and counts saturate at
+/// `u64::MAX`.
+pub fn backref_stats_from_bytes(b: &[u8]) -> io::Result<BackrefStats> {
+    use crate::serde::parse_atom::parse_path;
+    use crate::traverse_path::traverse_path;
+    use crate::{allocator::SExp, Allocator, NodePtr};
+    use std::collections::HashMap;
+
+    let mut f = SliceReader::new(b);
+
+    // like in serialized_length_from_bytes(), the allocator only tracks the
+    // tree structure. Every node it holds (including the pairs making up the
+    // "values" stack, since back-references may point into it) has its
+    // properties recorded in "info". Atoms are represented by unique numbers.
+    let mut allocator = Allocator::new();
+    let nil = allocator.nil();
+    let mut info = HashMap::<NodePtr, NodeInfo>::new();
+    info.insert(nil, NodeInfo::atom(1, &[]));
+    let mut atom_counter: u32 = 0;
+    let mut values = nil;
+    let mut ops = vec![ParseOp::SExp];
+
+    fn new_pair(
+        allocator: &mut Allocator,
+        info: &mut HashMap<NodePtr, NodeInfo>,
+        left: NodePtr,
+        right: NodePtr,
+    ) -> io::Result<NodePtr> {
+        let pair = allocator.new_pair(left, right)?;
+        let entry = NodeInfo::pair(&info[&left], &info[&right]);
+        info.insert(pair, entry);
+        Ok(pair)
+    }
+
+    while let Some(op) = ops.pop() {
+        match op {
+            ParseOp::SExp => {
+                let start = f.position();
+                let b = f.read_u8()?;
+                if b == CONS_BOX_MARKER {
+                    ops.push(ParseOp::Cons);
+                    ops.push(ParseOp::SExp);
+                    ops.push(ParseOp::SExp);
+                    continue;
+                }
+                let node = if b == BACK_REFERENCE {
+                    let path = parse_path(&mut f, false)?;
+                    traverse_path(&allocator, path, values)?.1
+                } else {
+                    let atom = if b == 0x80 {
+                        NodeInfo::atom(1, &[])
+                    } else if b <= MAX_SINGLE_BYTE {
+                        NodeInfo::atom(1, &[b])
+                    } else {
+                        let blob = read_blob(&mut f, b)?;
+                        NodeInfo::atom((f.position() - start) as u64, blob)
+                    };
+                    atom_counter += 1;
+                    let node = allocator.new_number(atom_counter.into())?;
+                    info.insert(node, atom);
+                    node
+                };
+                values = new_pair(&mut allocator, &mut info, node, values)?;
+            }
+            ParseOp::Cons => {
+                // cons
+                let SExp::Pair(v1, v2) = allocator.sexp(values) else {
+                    return Err(bad_encoding());
+                };
+
+                let SExp::Pair(v3, v4) = allocator.sexp(v2) else {
+                    return Err(bad_encoding());
+                };
+
+                let new_root = new_pair(&mut allocator, &mut info, v3, v1)?;
+                values = new_pair(&mut allocator, &mut info, new_root, v4)?;
+            }
+        }
+    }
+    match allocator.sexp(values) {
+        SExp::Pair(root, _) => {
+            let root = &info[&root];
+            Ok(BackrefStats {
+                compressed_length: f.position() as u64,
+                serialized_length: root.serialized_length,
+                node_count: root.node_count,
+                tree_hash: root.tree_hash,
+            })
+        }
         _ => Err(bad_encoding()),
     }
 }
@@ -285,7 +491,8 @@ mod tests {
     #[cfg(test)]
     mod test {
         use super::*;
-        use crate::serde::node_from_bytes_backrefs;
+        use crate::allocator::{NodePtr, SExp};
+        use crate::serde::{node_from_bytes_backrefs, node_to_bytes, tree_hash};
         use crate::Allocator;
         use rstest::rstest;
 
@@ -530,6 +737,120 @@ ae5c3c40c50832a7aecc0b3ba4646568a00c01289c45e1f03b2b488080808080"
             assert!(node_from_bytes_backrefs(&mut allocator, &buf).is_ok());
 
             assert_eq!(len, buf.len() as u64);
+
+            // the stats of the expanded tree match the fully materialized one
+            let node = node_from_bytes_backrefs(&mut allocator, &buf).unwrap();
+            let stats = backref_stats_from_bytes(&buf).expect("backref_stats_from_bytes");
+            assert_eq!(stats.compressed_length, len);
+            assert_eq!(
+                stats.serialized_length,
+                node_to_bytes(&allocator, node).unwrap().len() as u64
+            );
+            assert_eq!(stats.node_count, count_nodes(&allocator, node));
+            assert_eq!(stats.tree_hash, tree_hash(&allocator, node));
         }
+
+        fn count_nodes(a: &Allocator, node: NodePtr) -> u64 {
+            let mut count = 0;
+            let mut stack = vec![node];
+            while let Some(n) = stack.pop() {
+                count += 1;
+                if let SExp::Pair(left, right) = a.sexp(n) {
+                    stack.push(left);
+                    stack.push(right);
+                }
+            }
+            count
+        }
+
+        #[test]
+        fn test_backref_stats_from_bytes() {
+            // ("foobar" "foobar")
+            let stats = backref_stats_from_bytes(&Vec::from_hex("ff86666f6f626172fe0100").unwrap())
+                .unwrap();
+            assert_eq!(stats.compressed_length, 10);
+            assert_eq!(stats.serialized_length, 17);
+            assert_eq!(stats.node_count, 5);
+
+            // a back-reference into the "values" stack itself. This refers to
+            // the list of the atoms parsed so far, (1 . (2 . ()))
+            let buf = Vec::from_hex("ff01ff02fe02").unwrap();
+            let mut allocator = Allocator::new();
+            let node = node_from_bytes_backrefs(&mut allocator, &buf).unwrap();
+            let stats = backref_stats_from_bytes(&buf).unwrap();
+            assert_eq!(stats.node_count, count_nodes(&allocator, node));
+            assert_eq!(stats.tree_hash, tree_hash(&allocator, node));
+
+            // every back-reference doubles the size of the expanded tree
+            let mut buf = vec![0x01];
+            for _ in 0..100 {
+                buf.splice(0..0, [0xff, 0xfe, 0x01]);
+            }
+            let stats = backref_stats_from_bytes(&buf).unwrap();
+            assert_eq!(stats.compressed_length, buf.len() as u64);
+            assert_eq!(stats.serialized_length, u64::MAX);
+            assert_eq!(stats.node_count, u64::MAX);
+
+            let e = backref_stats_from_bytes(&[0xff, 0x01, 0xff, 0xfe, 0x10, 0x80]).unwrap_err();
+            assert_eq!(e.to_string(), "path into atom");
+
+            let e = backref_stats_from_bytes(&[0xff, 0x01]).unwrap_err();
+            assert_eq!(e.kind(), std::io::ErrorKind::UnexpectedEof);
+
+            let e = backref_stats_from_bytes(&[0x8f, 0xff]).unwrap_err();
+            assert_eq!(e.kind(), bad_encoding().kind());
+        }
+    }
+
+    use rstest::rstest;
+    use ChildPos::{Left as L, Right as R};
+
+    #[rstest]
+    // (foo bar baz)
+    #[case("ff83666f6fff83626172ff8362617a80", &[], "ff83666f6fff83626172ff8362617a80", true)]
+    #[case("ff83666f6fff83626172ff8362617a80", &[L], "83666f6f", true)]
+    #[case("ff83666f6fff83626172ff8362617a80", &[R], "ff83626172ff8362617a80", true)]
+    #[case("ff83666f6fff83626172ff8362617a80", &[R, L], "83626172", true)]
+    #[case("ff83666f6fff83626172ff8362617a80", &[R, R, R], "80", true)]
+    // (foo . foo), where the second one is a back-reference
+    #[case("ff83666f6ffe02", &[], "ff83666f6f83666f6f", false)]
+    #[case("ff83666f6ffe02", &[L], "83666f6f", true)]
+    #[case("ff83666f6ffe02", &[R], "83666f6f", false)]
+    // ((foo . foo) 1). Extracting 1 requires skipping a back-reference
+    #[case("ffff83666f6ffe02ff0180", &[R, L], "01", false)]
+    #[case("ffff83666f6ffe02ff0180", &[L, L], "83666f6f", true)]
+    fn test_extract_subtree(
+        #[case] input: &str,
+        #[case] path: &[ChildPos],
+        #[case] expected: &str,
+        #[case] borrowed: bool,
+    ) {
+        let input = Vec::from_hex(input).unwrap();
+        let ret = extract_subtree(&input, path).unwrap();
+        assert_eq!(hex::encode(&ret), expected);
+        assert_eq!(matches!(ret, Cow::Borrowed(_)), borrowed);
+    }
+
+    #[rstest]
+    // the path goes past an atom
+    #[case("ff83666f6fff83626172ff8362617a80", &[L, L], "invalid path")]
+    #[case("ff83666f6fff83626172ff8362617a80", &[R, R, R, L], "invalid path")]
+    #[case("ff83666f6ffe02", &[R, R], "invalid path")]
+    // truncated input
+    #[case("ff83666f", &[R], "bad encoding")]
+    #[case("ff83666f", &[L], "bad encoding")]
+    #[case("ff83666f6f", &[R], "failed to fill whole buffer")]
+    // invalid back-reference
+    #[case("ff83666f6ffe07", &[R], "path into atom")]
+    fn test_extract_subtree_error(
+        #[case] input: &str,
+        #[case] path: &[ChildPos],
+        #[case] expected: &str,
+    ) {
+        let input = Vec::from_hex(input).unwrap();
+        assert_eq!(
+            extract_subtree(&input, path).unwrap_err().to_string(),
+            expected
+        );
     }
 }
@@ -1,6 +1,8 @@
 use std::io;
 use std::io::ErrorKind;
 
+use crate::allocator::{len_for_value, Allocator, NodePtr, NodeVisitor};
+
 /// all atoms serialize their contents verbatim. All expect those one-byte atoms
 /// from 0x00-0x7f also have a prefix encoding their length. This function
 /// writes the correct prefix for an atom of size `size` whose first byte is `atom_0`.
@@ -51,6 +53,35 @@ pub fn write_atom<W: io::Write>(f: &mut W, atom: &[u8]) -> io::Result<()> {
     f.write_all(atom)
 }
 
+/// serialize an atom holding the non-negative number `val`, in its minimal
+/// form. This produces the same bytes as `write_atom()`, but it doesn't need
+/// the atom in a buffer and writes it with a single call.
+pub fn write_small_atom<W: io::Write>(f: &mut W, val: u32) -> io::Result<()> {
+    let len = len_for_value(val);
+    match len {
+        0 => f.write_all(&[0x80]),
+        // values below 0x80 are their own serialization
+        1 => f.write_all(&[val as u8]),
+        _ => {
+            // room for the prefix, a sign byte (only needed for values with
+            // the top bit set) and the 4 value bytes
+            let mut buf = [0_u8; 6];
+            buf[2..].copy_from_slice(&val.to_be_bytes());
+            buf[5 - len] = 0x80 | len as u8;
+            f.write_all(&buf[5 - len..])
+        }
+    }
+}
+
+/// serialize the atom `node`, taking the fast path for small atoms
+pub fn write_atom_node<W: io::Write>(f: &mut W, a: &Allocator, node: NodePtr) -> io::Result<()> {
+    match a.node(node) {
+        NodeVisitor::Buffer(buf) => write_atom(f, buf),
+        NodeVisitor::U32(val) => write_small_atom(f, val),
+        NodeVisitor::Pair(_, _) => panic!("write_atom_node() called on a pair"),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -176,4 +207,56 @@ mod tests {
             assert_eq!(buf, expected_prefix);
         }
     }
+
+    #[test]
+    fn test_write_small_atom() {
+        for val in [
+            0,
+            1,
+            0x7f,
+            0x80,
+            0xff,
+            0x100,
+            0x7fff,
+            0x8000,
+            0xffff,
+            0x7f_ffff,
+            0x80_0000,
+            0xff_ffff,
+            0x3ff_ffff,
+            0x7fff_ffff,
+            0x8000_0000,
+            0xffff_ffff,
+        ] {
+            let mut expected = Vec::<u8>::new();
+            let bytes = [&[0_u8][..], &u32::to_be_bytes(val)].concat();
+            write_atom(&mut expected, &bytes[5 - len_for_value(val)..]).unwrap();
+
+            let mut buf = Vec::<u8>::new();
+            write_small_atom(&mut buf, val).unwrap();
+            assert_eq!(buf, expected, "{val:#x}");
+        }
+    }
+
+    #[test]
+    fn test_write_atom_node() {
+        let mut a = Allocator::new();
+        for atom in [
+            &[][..],
+            &[0],
+            &[0x7f],
+            &[0x80],
+            &[0, 0x80],
+            &[1, 2, 3],
+            b"foobar",
+        ] {
+            let node = a.new_atom(atom).unwrap();
+            let mut expected = Vec::<u8>::new();
+            write_atom(&mut expected, atom).unwrap();
+
+            let mut buf = Vec::<u8>::new();
+            write_atom_node(&mut buf, &a, node).unwrap();
+            assert_eq!(buf, expected);
+        }
+    }
 }
@@ -0,0 +1,98 @@
+//! The sha256 implementation used by the sha256 and coinid operators, and for
+//! tree hashing. All hashing in the crate goes through here, so there's a
+//! single place that decides which backend is used.
+//!
+//! By default, the `sha2` crate is used. It detects SHA extensions on x86 and
+//! x86-64 at runtime, and falls back to a portable implementation when they're
+//! not available. The `sha2-asm` feature replaces the portable fallback with
+//! an assembly implementation, and enables the SHA extensions on aarch64. The
+//! `openssl` feature uses OpenSSL instead.
+
+pub use chia_sha2::Sha256;
+
+/// The implementation that `Sha256` uses on the current CPU.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Sha256Backend {
+    OpenSsl,
+    /// the CPU's SHA extensions
+    Hardware,
+    /// the assembly implementation, enabled by the `sha2-asm` feature
+    Assembly,
+    Portable,
+}
+
+pub fn sha256_backend() -> Sha256Backend {
+    if cfg!(feature = "openssl") {
+        return Sha256Backend::OpenSsl;
+    }
+
+    // these are the features the sha2 crate checks for
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    {
+        if is_x86_feature_detected!("sha")
+            && is_x86_feature_detected!("sse2")
+            && is_x86_feature_detected!("ssse3")
+            && is_x86_feature_detected!("sse4.1")
+        {
+            return Sha256Backend::Hardware;
+        }
+        if cfg!(feature = "sha2-asm") {
+            return Sha256Backend::Assembly;
+        }
+    }
+
+    #[cfg(all(target_arch = "aarch64", feature = "sha2-asm"))]
+    {
+        if std::arch::is_aarch64_feature_detected!("sha2") {
+            return Sha256Backend::Hardware;
+        }
+    }
+
+    Sha256Backend::Portable
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    // https://www.di-mgt.com.au/sha_testvectors.html
+    #[rstest]
+    #[case(
+        b"",
+        "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+    )]
+    #[case(
+        b"abc",
+        "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+    )]
+    #[case(
+        b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq",
+        "248d6a61d20638b8e5c026930c3e6039a33ce45964ff2167f6ecedd419db06c1"
+    )]
+    fn test_sha256(#[case] input: &[u8], #[case] expected: &str) {
+        let mut ctx = Sha256::new();
+        ctx.update(input);
+        assert_eq!(hex::encode(ctx.finalize()), expected);
+
+        // feeding the input one byte at a time gives the same result
+        let mut ctx = Sha256::new();
+        for b in input {
+            ctx.update([*b]);
+        }
+        assert_eq!(hex::encode(ctx.finalize()), expected);
+    }
+
+    #[test]
+    fn test_sha256_backend() {
+        let backend = sha256_backend();
+        if cfg!(feature = "openssl") {
+            assert_eq!(backend, Sha256Backend::OpenSsl);
+        } else {
+            assert_ne!(backend, Sha256Backend::OpenSsl);
+        }
+        if !cfg!(feature = "sha2-asm") {
+            assert_ne!(backend, Sha256Backend::Assembly);
+        }
+    }
+}
@@ -1,4 +1,4 @@
-use crate::allocator::{Allocator, NodePtr, SExp};
+use crate::allocator::{Allocator, NodePtr};
 use crate::bls_ops::{
     op_bls_g1_multiply, op_bls_g1_negate, op_bls_g1_subtract, op_bls_g2_add, op_bls_g2_multiply,
     op_bls_g2_negate, op_bls_g2_subtract, op_bls_map_to_g1, op_bls_map_to_g2,
@@ -6,190 +6,34 @@ use crate::bls_ops::{
 };
 use crate::core_ops::{op_cons, op_eq, op_first, op_if, op_listp, op_raise, op_rest};
 use crate::cost::Cost;
+use crate::curry_ops::{op_curry, op_uncurry};
+use crate::hash_ops::{op_sha3_256, op_sha512_256};
 use crate::keccak256_ops::op_keccak256;
 use crate::more_ops::{
     op_add, op_all, op_any, op_ash, op_coinid, op_concat, op_div, op_divmod, op_gr, op_gr_bytes,
     op_logand, op_logior, op_lognot, op_logxor, op_lsh, op_mod, op_modpow, op_multiply, op_not,
     op_point_add, op_pubkey_for_exp, op_sha256, op_strlen, op_substr, op_subtract,
 };
-use crate::number::Number;
 use crate::reduction::{EvalErr, Reduction, Response};
 use crate::secp_ops::{op_secp256k1_verify, op_secp256r1_verify};
+use crate::text::parse_clvm_text;
 
-use hex::FromHex;
-use num_traits::Num;
-use std::cmp::min;
 use std::collections::HashMap;
 
-fn parse_atom(a: &mut Allocator, v: &str) -> NodePtr {
-    if v == "0" {
-        return a.nil();
-    }
-
-    assert!(!v.is_empty());
-
-    if v.starts_with("0x") {
-        let buf = Vec::from_hex(v.strip_prefix("0x").unwrap()).unwrap();
-        return a.new_atom(&buf).unwrap();
-    }
-
-    if v.starts_with('\"') {
-        assert!(v.ends_with('\"'));
-        let buf = v
-            .strip_prefix('\"')
-            .unwrap()
-            .strip_suffix('\"')
-            .unwrap()
-            .as_bytes();
-        return a.new_atom(buf).unwrap();
-    }
-
-    if let Ok(num) = Number::from_str_radix(v, 10) {
-        a.new_number(num).unwrap()
-    } else {
-        let v = v.strip_prefix('#').unwrap_or(v);
-        match v {
-            "q" => a.new_atom(&[1]).unwrap(),
-            "a" => a.new_atom(&[2]).unwrap(),
-            "i" => a.new_atom(&[3]).unwrap(),
-            "c" => a.new_atom(&[4]).unwrap(),
-            "f" => a.new_atom(&[5]).unwrap(),
-            "r" => a.new_atom(&[6]).unwrap(),
-            "l" => a.new_atom(&[7]).unwrap(),
-            "x" => a.new_atom(&[8]).unwrap(),
-            "=" => a.new_atom(&[9]).unwrap(),
-            ">s" => a.new_atom(&[10]).unwrap(),
-            "sha256" => a.new_atom(&[11]).unwrap(),
-            "substr" => a.new_atom(&[12]).unwrap(),
-            "strlen" => a.new_atom(&[13]).unwrap(),
-            "concat" => a.new_atom(&[14]).unwrap(),
-
-            "+" => a.new_atom(&[16]).unwrap(),
-            "-" => a.new_atom(&[17]).unwrap(),
-            "*" => a.new_atom(&[18]).unwrap(),
-            "/" => a.new_atom(&[19]).unwrap(),
-            "divmod" => a.new_atom(&[20]).unwrap(),
-            ">" => a.new_atom(&[21]).unwrap(),
-            "ash" => a.new_atom(&[22]).unwrap(),
-            "lsh" => a.new_atom(&[23]).unwrap(),
-            "logand" => a.new_atom(&[24]).unwrap(),
-            "logior" => a.new_atom(&[25]).unwrap(),
-            "logxor" => a.new_atom(&[26]).unwrap(),
-            "lognot" => a.new_atom(&[27]).unwrap(),
-
-            "point_add" => a.new_atom(&[29]).unwrap(),
-            "pubkey_for_exp" => a.new_atom(&[30]).unwrap(),
-
-            "not" => a.new_atom(&[32]).unwrap(),
-            "any" => a.new_atom(&[33]).unwrap(),
-            "all" => a.new_atom(&[34]).unwrap(),
-
-            "softfork" => a.new_atom(&[36]).unwrap(),
-
-            "coinid" => a.new_atom(&[48]).unwrap(),
-
-            "g1_add" => a.new_atom(&[29]).unwrap(),
-            "g1_subtract" => a.new_atom(&[49]).unwrap(),
-            "g1_multiply" => a.new_atom(&[50]).unwrap(),
-            "g1_negate" => a.new_atom(&[51]).unwrap(),
-            "g2_add" => a.new_atom(&[52]).unwrap(),
-            "g2_subtract" => a.new_atom(&[53]).unwrap(),
-            "g2_multiply" => a.new_atom(&[54]).unwrap(),
-            "g2_negate" => a.new_atom(&[55]).unwrap(),
-            "g1_map" => a.new_atom(&[56]).unwrap(),
-            "g2_map" => a.new_atom(&[57]).unwrap(),
-            "bls_pairing_identity" => a.new_atom(&[58]).unwrap(),
-            "bls_verify" => a.new_atom(&[59]).unwrap(),
-            "modpow" => a.new_atom(&[60]).unwrap(),
-            "%" => a.new_atom(&[61]).unwrap(),
-            "secp256k1_verify" => a.new_atom(&[0x13, 0xd6, 0x1f, 0x00]).unwrap(),
-            "secp256r1_verify" => a.new_atom(&[0x1c, 0x3a, 0x8f, 0x00]).unwrap(),
-            "keccak256" => a.new_atom(&[62]).unwrap(),
-            _ => {
-                panic!("atom not supported \"{}\"", v);
-            }
-        }
-    }
-}
-
-fn pop_token(s: &str) -> (&str, &str) {
-    let s = s.trim();
-    if let Some(stripped) = s.strip_prefix('\"') {
-        if let Some(second_quote) = stripped.find('\"') {
-            let (first, rest) = s.split_at(second_quote + 2);
-            (first.trim(), rest.trim())
-        } else {
-            panic!("mismatching quote")
-        }
-    } else if s.starts_with('(') || s.starts_with(')') {
-        let (first, rest) = s.split_at(1);
-        (first, rest.trim())
-    } else {
-        let space = s.find(' ');
-        let close = s.find(')');
-
-        let split_pos = if let (Some(space_pos), Some(close_pos)) = (space, close) {
-            min(space_pos, close_pos)
-        } else if let Some(pos) = space {
-            pos
-        } else if let Some(pos) = close {
-            pos
-        } else {
-            s.len()
-        };
-
-        let (first, rest) = s.split_at(split_pos);
-        (first.trim(), rest.trim())
-    }
-}
-
-pub fn parse_list<'a>(a: &mut Allocator, v: &'a str) -> (NodePtr, &'a str) {
-    let v = v.trim();
-    let (first, rest) = pop_token(v);
-    if first.is_empty() {
-        return (a.nil(), rest);
-    }
-    if first == ")" {
-        return (a.nil(), rest);
-    }
-    if first == "(" {
-        let (head, new_rest) = parse_list(a, rest);
-        let (tail, new_rest) = parse_list(a, new_rest);
-        (a.new_pair(head, tail).unwrap(), new_rest)
-    } else if first == "." {
-        let (node, new_rest) = parse_exp(a, rest);
-        let (end_list, new_rest) = pop_token(new_rest);
-        assert_eq!(end_list, ")");
-        (node, new_rest)
-    } else {
-        let head = parse_atom(a, first);
-        let (tail, new_rest) = parse_list(a, rest);
-        (a.new_pair(head, tail).unwrap(), new_rest)
-    }
-}
-
-pub fn parse_exp<'a>(a: &mut Allocator, v: &'a str) -> (NodePtr, &'a str) {
-    let (first, rest) = pop_token(v);
-    if first == "(" {
-        parse_list(a, rest)
-    } else {
-        (parse_atom(a, first), rest)
-    }
+/// parse CLVM text in tests, panicking if it's invalid. This is the same
+/// grammar as `parse_clvm_text()`
+pub fn parse_exp(a: &mut Allocator, v: &str) -> NodePtr {
+    parse_clvm_text(a, v).unwrap_or_else(|e| panic!("failed to parse \"{v}\": {e}"))
 }
 
 pub fn node_eq(allocator: &Allocator, s1: NodePtr, s2: NodePtr) -> bool {
-    match (allocator.sexp(s1), allocator.sexp(s2)) {
-        (SExp::Pair(s1a, s1b), SExp::Pair(s2a, s2b)) => {
-            node_eq(allocator, s1a, s2a) && node_eq(allocator, s1b, s2b)
-        }
-        (SExp::Atom, SExp::Atom) => allocator.atom_eq(s1, s2),
-        _ => false,
-    }
+    allocator.tree_eq(s1, s2)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::error::ErrorCode;
 
     #[cfg(feature = "pre-eval")]
     use crate::chia_dialect::{ChiaDialect, NO_UNKNOWN_OPS};
@@ -218,8 +62,7 @@ mod tests {
     fn run_op_test(op: &Opf, args_str: &str, expected: &str, expected_cost: u64) {
         let mut a = Allocator::new();
 
-        let (args, rest) = parse_list(&mut a, args_str);
-        assert_eq!(rest, "");
+        let args = parse_exp(&mut a, &format!("({args_str})"));
         let result = op(&mut a, args, 10000000000 as Cost);
         match result {
             Err(e) => {
@@ -228,8 +71,7 @@ mod tests {
             }
             Ok(Reduction(cost, ret_value)) => {
                 assert_eq!(cost, expected_cost);
-                let (expected, rest) = parse_exp(&mut a, expected);
-                assert_eq!(rest, "");
+                let expected = parse_exp(&mut a, expected);
                 assert!(node_eq(&a, ret_value, expected));
             }
         }
@@ -252,6 +94,8 @@ mod tests {
     #[case("test-sha256")]
     #[case("test-keccak256")]
     #[case("test-keccak256-generated")]
+    #[case("test-hash-ops")]
+    #[case("test-curry-ops")]
     fn test_ops(#[case] filename: &str) {
         use std::fs::read_to_string;
 
@@ -306,6 +150,10 @@ mod tests {
             ("secp256r1_verify", op_secp256r1_verify as Opf),
             ("modpow", op_modpow as Opf),
             ("keccak256", op_keccak256 as Opf),
+            ("sha3_256", op_sha3_256 as Opf),
+            ("sha512_256", op_sha512_256 as Opf),
+            ("curry", op_curry as Opf),
+            ("uncurry", op_uncurry as Opf),
         ]);
 
         println!("Test cases from: {filename}");
@@ -346,7 +194,10 @@ mod tests {
         let a1 = allocator.new_atom(&[65]).unwrap();
         let args = allocator.new_pair(a1, allocator.nil()).unwrap();
         let result = op_raise(&mut allocator, args, 100000);
-        assert_eq!(result, Err(EvalErr(a1, "clvm raise".to_string())));
+        assert_eq!(
+            result,
+            Err(EvalErr(a1, "clvm raise".to_string(), ErrorCode::Raise))
+        );
     }
 
     #[test]
@@ -361,7 +212,10 @@ mod tests {
         // ((a1 a2))
         args = allocator.new_pair(args, allocator.nil()).unwrap();
         let result = op_raise(&mut allocator, args, 100000);
-        assert_eq!(result, Err(EvalErr(args, "clvm raise".to_string())));
+        assert_eq!(
+            result,
+            Err(EvalErr(args, "clvm raise".to_string(), ErrorCode::Raise))
+        );
     }
 
     #[test]
@@ -374,7 +228,10 @@ mod tests {
         // (a1 a2)
         args = allocator.new_pair(a1, args).unwrap();
         let result = op_raise(&mut allocator, args, 100000);
-        assert_eq!(result, Err(EvalErr(args, "clvm raise".to_string())));
+        assert_eq!(
+            result,
+            Err(EvalErr(args, "clvm raise".to_string(), ErrorCode::Raise))
+        );
     }
 
     #[cfg(feature = "pre-eval")]
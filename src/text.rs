@@ -0,0 +1,327 @@
+// A parser and printer for the human readable (symbolic) form of CLVM, e.g.
+// (a (q 2 2 (c 2 ())) (c (q . "foo") 1))
+
+use std::io;
+
+use crate::allocator::{Allocator, NodePtr, SExp};
+use crate::chia_dialect::KEYWORDS;
+use crate::number::{number_from_u8, Number};
+use crate::op_utils::is_canonical_atom_encoding;
+use num_traits::Num;
+
+fn syntax_error(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, msg)
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Token<'a> {
+    Open,
+    Close,
+    Dot,
+    Atom(&'a str),
+    Str(&'a str),
+}
+
+struct Tokenizer<'a> {
+    input: &'a str,
+}
+
+impl<'a> Iterator for Tokenizer<'a> {
+    type Item = io::Result<Token<'a>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // skip whitespace and comments
+        loop {
+            self.input = self.input.trim_start();
+            if !self.input.starts_with(';') {
+                break;
+            }
+            self.input = self.input.find('\n').map_or("", |pos| &self.input[pos..]);
+        }
+
+        let c = self.input.chars().next()?;
+        let (token, len) = match c {
+            '(' => (Token::Open, 1),
+            ')' => (Token::Close, 1),
+            '"' | '\'' => {
+                let Some(end) = self.input[1..].find(c) else {
+                    self.input = "";
+                    return Some(Err(syntax_error("unterminated string")));
+                };
+                (Token::Str(&self.input[1..=end]), end + 2)
+            }
+            _ => {
+                let end = self
+                    .input
+                    .find(|c: char| c.is_whitespace() || "();\"'".contains(c))
+                    .unwrap_or(self.input.len());
+                let atom = &self.input[..end];
+                if atom == "." {
+                    (Token::Dot, 1)
+                } else {
+                    (Token::Atom(atom), end)
+                }
+            }
+        };
+        self.input = &self.input[len..];
+        Some(Ok(token))
+    }
+}
+
+fn parse_atom(a: &mut Allocator, v: &str) -> io::Result<NodePtr> {
+    if let Some(hex) = v.strip_prefix("0x").or_else(|| v.strip_prefix("0X")) {
+        let buf = (0..hex.len())
+            .step_by(2)
+            .map(|i| {
+                hex.get(i..i + 2)
+                    .and_then(|b| u8::from_str_radix(b, 16).ok())
+                    .ok_or_else(|| syntax_error("invalid hex atom"))
+            })
+            .collect::<io::Result<Vec<u8>>>()?;
+        return Ok(a.new_atom(&buf)?);
+    }
+
+    if v.starts_with(|c: char| c.is_ascii_digit() || c == '-') {
+        if let Ok(num) = Number::from_str_radix(v, 10) {
+            return Ok(a.new_number(num)?);
+        }
+    }
+
+    let name = v.strip_prefix('#').unwrap_or(v);
+    match KEYWORDS.iter().find(|(_, kw)| *kw == name) {
+        Some((opcode, _)) => Ok(a.new_number((*opcode).into())?),
+        None => Err(syntax_error(&format!("unknown atom \"{v}\""))),
+    }
+}
+
+// a list that's being parsed. items are in order, the tail is the terminator
+// (nil unless the list uses dot-notation)
+struct ListFrame {
+    items: Vec<NodePtr>,
+    tail: Option<NodePtr>,
+    expect_tail: bool,
+}
+
+/// Parse CLVM in its human readable form. Atoms can be decimal integers, hex
+/// (prefixed by 0x), strings (in single or double quotes) or operator names.
+/// Comments start with ; and run to the end of the line.
+pub fn parse_clvm_text(a: &mut Allocator, input: &str) -> io::Result<NodePtr> {
+    let mut stack: Vec<ListFrame> = vec![];
+    let mut result: Option<NodePtr> = None;
+
+    for token in (Tokenizer { input }) {
+        if result.is_some() {
+            return Err(syntax_error("unexpected trailing input"));
+        }
+        let node = match token? {
+            Token::Open => {
+                stack.push(ListFrame {
+                    items: vec![],
+                    tail: None,
+                    expect_tail: false,
+                });
+                continue;
+            }
+            Token::Dot => {
+                let Some(frame) = stack.last_mut() else {
+                    return Err(syntax_error("unexpected ."));
+                };
+                if frame.items.is_empty() || frame.expect_tail || frame.tail.is_some() {
+                    return Err(syntax_error("unexpected ."));
+                }
+                frame.expect_tail = true;
+                continue;
+            }
+            Token::Close => {
+                let Some(frame) = stack.pop() else {
+                    return Err(syntax_error("unexpected )"));
+                };
+                if frame.expect_tail && frame.tail.is_none() {
+                    return Err(syntax_error("missing expression after ."));
+                }
+                let mut list = frame.tail.unwrap_or(NodePtr::NIL);
+                for item in frame.items.into_iter().rev() {
+                    list = a.new_pair(item, list)?;
+                }
+                list
+            }
+            Token::Atom(v) => parse_atom(a, v)?,
+            Token::Str(v) => a.new_atom(v.as_bytes())?,
+        };
+        match stack.last_mut() {
+            None => result = Some(node),
+            Some(frame) if frame.tail.is_some() => {
+                return Err(syntax_error("expected ) after dotted tail"));
+            }
+            Some(frame) if frame.expect_tail => frame.tail = Some(node),
+            Some(frame) => frame.items.push(node),
+        }
+    }
+
+    if !stack.is_empty() {
+        return Err(syntax_error("missing )"));
+    }
+    result.ok_or_else(|| syntax_error("empty input"))
+}
+
+/// render an atom in the form parse_clvm_text() understands
+pub(crate) fn atom_to_text(buf: &[u8]) -> String {
+    if buf.is_empty() {
+        "()".to_string()
+    } else if buf.len() > 2 && buf.iter().all(|b| (0x20..0x7f).contains(b) && *b != b'"') {
+        format!("\"{}\"", std::str::from_utf8(buf).unwrap())
+    } else if buf.len() <= 4 && is_canonical_atom_encoding(buf) {
+        number_from_u8(buf).to_string()
+    } else {
+        hex_atom(buf)
+    }
+}
+
+pub(crate) fn hex_atom(buf: &[u8]) -> String {
+    let mut ret = "0x".to_string();
+    for b in buf {
+        ret.push_str(&format!("{b:02x}"));
+    }
+    ret
+}
+
+enum PrintOp {
+    Visit(NodePtr),
+    Text(&'static str),
+}
+
+/// Render a CLVM tree in its human readable form. Lists are printed as lists,
+/// and improper lists use dot-notation. Atoms are printed as strings if
+/// printable, as integers if they are small, canonical, integers, and as hex
+/// otherwise. The output can be parsed back by `parse_clvm_text()`
+pub fn to_clvm_text(a: &Allocator, node: NodePtr) -> String {
+    let mut out = String::new();
+    let mut ops = vec![PrintOp::Visit(node)];
+    while let Some(op) = ops.pop() {
+        match op {
+            PrintOp::Text(t) => out.push_str(t),
+            PrintOp::Visit(node) => match a.sexp(node) {
+                SExp::Atom => out.push_str(&atom_to_text(a.atom(node).as_ref())),
+                SExp::Pair(first, rest) => {
+                    out.push('(');
+                    // walk the spine of the list. The operations are pushed
+                    // in reverse order
+                    let mut items = vec![first];
+                    let mut tail = rest;
+                    while let SExp::Pair(f, r) = a.sexp(tail) {
+                        items.push(f);
+                        tail = r;
+                    }
+                    ops.push(PrintOp::Text(")"));
+                    if a.atom_len(tail) != 0 {
+                        ops.push(PrintOp::Visit(tail));
+                        ops.push(PrintOp::Text(" . "));
+                    }
+                    for (i, item) in items.into_iter().enumerate().rev() {
+                        ops.push(PrintOp::Visit(item));
+                        if i > 0 {
+                            ops.push(PrintOp::Text(" "));
+                        }
+                    }
+                }
+            },
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::serde::{node_from_bytes, node_to_bytes};
+    use rstest::rstest;
+
+    #[rstest]
+    #[case("()", "80")]
+    #[case("0", "80")]
+    #[case("1", "01")]
+    #[case("-1", "81ff")]
+    #[case("128", "820080")]
+    #[case("0x", "80")]
+    #[case("0x00", "00")]
+    #[case("0xcafe", "82cafe")]
+    #[case("\"foobar\"", "86666f6f626172")]
+    #[case("'foo bar'", "87666f6f20626172")]
+    #[case("q", "01")]
+    #[case("#a", "02")]
+    #[case("secp256k1_verify", "8413d61f00")]
+    #[case("(1 2 3)", "ff01ff02ff0380")]
+    #[case("(1 2 . 3)", "ff01ff0203")]
+    #[case("(() . ())", "ff8080")]
+    #[case("((1 . 2) . (3 . 4))", "ffff0102ff0304")]
+    #[case("(q . \"foo\")", "ff0183666f6f")]
+    #[case("(a ; this is a comment\n (q . 1) ())", "ff02ffff0101ff8080")]
+    #[case("  (+ 1 (q . 2))  ", "ff10ff01ffff010280")]
+    fn test_parse(#[case] text: &str, #[case] expected: &str) {
+        let mut a = Allocator::new();
+        let node = parse_clvm_text(&mut a, text).unwrap();
+        assert_eq!(hex::encode(node_to_bytes(&a, node).unwrap()), expected);
+    }
+
+    #[rstest]
+    #[case("", "empty input")]
+    #[case("(1 2", "missing )")]
+    #[case("1 2", "unexpected trailing input")]
+    #[case(")", "unexpected )")]
+    #[case("(. 1)", "unexpected .")]
+    #[case("(1 . )", "missing expression after .")]
+    #[case("(1 . 2 3)", "expected ) after dotted tail")]
+    #[case("(1 . 2 . 3)", "unexpected .")]
+    #[case("\"foo", "unterminated string")]
+    #[case("0x123", "invalid hex atom")]
+    #[case("0xzz", "invalid hex atom")]
+    #[case("foobar", "unknown atom \"foobar\"")]
+    fn test_parse_error(#[case] text: &str, #[case] expected: &str) {
+        let mut a = Allocator::new();
+        assert_eq!(
+            parse_clvm_text(&mut a, text).unwrap_err().to_string(),
+            expected
+        );
+    }
+
+    #[rstest]
+    #[case("80", "()")]
+    #[case("01", "1")]
+    #[case("81ff", "-1")]
+    #[case("820080", "128")]
+    #[case("00", "0x00")]
+    #[case("820001", "0x0001")]
+    #[case("8400000001", "0x00000001")]
+    #[case("85ffffffffff", "0xffffffffff")]
+    #[case("86666f6f626172", "\"foobar\"")]
+    #[case("83666f22", "6713122")]
+    #[case("ff01ff02ff0380", "(1 2 3)")]
+    #[case("ff01ff0203", "(1 2 . 3)")]
+    #[case("ff8080", "(())")]
+    #[case("ffff0102ff0304", "((1 . 2) 3 . 4)")]
+    #[case("ff02ffff0101ff8080", "(2 (1 . 1) ())")]
+    fn test_to_text(#[case] serialized: &str, #[case] expected: &str) {
+        let mut a = Allocator::new();
+        let node = node_from_bytes(&mut a, &hex::decode(serialized).unwrap()).unwrap();
+        let text = to_clvm_text(&a, node);
+        assert_eq!(text, expected);
+
+        // make sure we can parse it back
+        let round_trip = parse_clvm_text(&mut a, &text).unwrap();
+        assert_eq!(
+            hex::encode(node_to_bytes(&a, round_trip).unwrap()),
+            serialized
+        );
+    }
+
+    #[test]
+    fn test_deep_tree() {
+        // neither parsing nor printing is recursive
+        let depth = 100_000;
+        let text = "(".repeat(depth) + &")".repeat(depth);
+        let mut a = Allocator::new();
+        let node = parse_clvm_text(&mut a, &text).unwrap();
+        let expected = "(".repeat(depth - 1) + "()" + &")".repeat(depth - 1);
+        assert_eq!(to_clvm_text(&a, node), expected);
+    }
+}
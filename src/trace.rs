@@ -0,0 +1,97 @@
+use crate::allocator::{Allocator, NodePtr};
+use crate::cost::Cost;
+use crate::serde::node_to_bytes;
+use std::fmt::Write;
+
+/// A single reduction, recorded by `run_program_with_trace()`. Every
+/// sub-expression that's evaluated gets its own step, in the order the
+/// evaluation started.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceStep {
+    pub program: NodePtr,
+    pub env: NodePtr,
+    // the value the program evaluated to. This is None if the evaluation
+    // never completed, e.g. because the program failed
+    pub result: Option<NodePtr>,
+    // the cost of evaluating the program, including all of its
+    // sub-expressions. Only valid if result is set.
+    pub cost: Cost,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Trace {
+    pub steps: Vec<TraceStep>,
+}
+
+impl Trace {
+    /// Serializes the trace to JSON. Nodes are included as hex encoded CLVM
+    /// serializations. Nodes that are too large to serialize are null.
+    pub fn to_json(&self, a: &Allocator) -> String {
+        let mut ret = String::from("{\"steps\":[");
+        for (i, step) in self.steps.iter().enumerate() {
+            if i > 0 {
+                ret.push(',');
+            }
+            ret.push_str("{\"program\":");
+            write_node(&mut ret, a, Some(step.program));
+            ret.push_str(",\"env\":");
+            write_node(&mut ret, a, Some(step.env));
+            ret.push_str(",\"result\":");
+            write_node(&mut ret, a, step.result);
+            write!(ret, ",\"cost\":{}}}", step.cost).unwrap();
+        }
+        ret.push_str("]}");
+        ret
+    }
+}
+
+fn write_node(out: &mut String, a: &Allocator, node: Option<NodePtr>) {
+    let Some(Ok(buf)) = node.map(|n| node_to_bytes(a, n)) else {
+        out.push_str("null");
+        return;
+    };
+    out.push('"');
+    for b in buf {
+        write!(out, "{b:02x}").unwrap();
+    }
+    out.push('"');
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_to_json() {
+        let mut a = Allocator::new();
+        let program = a.new_atom(&[0xff, 0x01]).unwrap();
+        let env = a.nil();
+        let result = a.new_small_number(1337).unwrap();
+
+        assert_eq!(Trace::default().to_json(&a), "{\"steps\":[]}");
+
+        let trace = Trace {
+            steps: vec![
+                TraceStep {
+                    program,
+                    env,
+                    result: Some(result),
+                    cost: 20,
+                },
+                TraceStep {
+                    program: env,
+                    env,
+                    result: None,
+                    cost: 0,
+                },
+            ],
+        };
+        assert_eq!(
+            trace.to_json(&a),
+            "{\"steps\":[\
+            {\"program\":\"82ff01\",\"env\":\"80\",\"result\":\"820539\",\"cost\":20},\
+            {\"program\":\"80\",\"env\":\"80\",\"result\":null,\"cost\":0}\
+            ]}"
+        );
+    }
+}
@@ -1,5 +1,6 @@
 use crate::allocator::{Allocator, NodePtr, SExp};
 use crate::cost::Cost;
+use crate::error::ErrorCode;
 use crate::reduction::{EvalErr, Reduction, Response};
 
 // lowered from measured 147 per bit. It doesn't seem to take this long in
@@ -11,16 +12,6 @@ const TRAVERSE_COST_PER_BIT: Cost = 4;
 // `run_program` has two stacks: the operand stack (of `Node` objects) and the
 // operator stack (of Operation)
 
-// return a bitmask with a single bit set, for the most significant set bit in
-// the input byte
-fn msb_mask(byte: u8) -> u8 {
-    let mut byte = (byte | (byte >> 1)) as u32;
-    byte |= byte >> 2;
-    byte |= byte >> 4;
-    debug_assert!((byte + 1) >> 1 <= 0x80);
-    ((byte + 1) >> 1) as u8
-}
-
 // return the index of the first non-zero byte in buf. If all bytes are 0, the
 // length (one past end) will be returned.
 const fn first_non_zero(buf: &[u8]) -> usize {
@@ -31,6 +22,29 @@ const fn first_non_zero(buf: &[u8]) -> usize {
     c
 }
 
+// follow the `num_bits` least significant bits of `path`, starting with the
+// least significant one. A set bit means "rest", a cleared bit means "first"
+#[inline(always)]
+fn follow_bits(
+    allocator: &Allocator,
+    mut path: u64,
+    num_bits: u32,
+    mut arg_list: NodePtr,
+) -> Result<NodePtr, EvalErr> {
+    for _ in 0..num_bits {
+        let SExp::Pair(left, right) = allocator.sexp(arg_list) else {
+            return Err(EvalErr(
+                arg_list,
+                "path into atom".into(),
+                ErrorCode::PathIntoAtom,
+            ));
+        };
+        arg_list = if (path & 1) != 0 { right } else { left };
+        path >>= 1;
+    }
+    Ok(arg_list)
+}
+
 pub fn traverse_path(allocator: &Allocator, node_index: &[u8], args: NodePtr) -> Response {
     let mut arg_list: NodePtr = args;
 
@@ -45,29 +59,24 @@ pub fn traverse_path(allocator: &Allocator, node_index: &[u8], args: NodePtr) ->
         return Ok(Reduction(cost, allocator.nil()));
     }
 
-    // find first non-zero bit (the most significant bit is a sentinel)
-    let last_bitmask = msb_mask(node_index[first_bit_byte_index]);
-
-    // follow through the bits, moving left and right
-    let mut byte_idx = node_index.len() - 1;
-    let mut bitmask = 0x01;
-    while byte_idx > first_bit_byte_index || bitmask < last_bitmask {
-        let is_bit_set: bool = (node_index[byte_idx] & bitmask) != 0;
-        match allocator.sexp(arg_list) {
-            SExp::Atom => {
-                return Err(EvalErr(arg_list, "path into atom".into()));
-            }
-            SExp::Pair(left, right) => {
-                arg_list = if is_bit_set { right } else { left };
-            }
-        }
-        if bitmask == 0x80 {
-            bitmask = 0x01;
-            byte_idx -= 1;
+    // follow through the bits, moving left and right, one 64 bit word at a
+    // time, starting at the end. The most significant set bit is a sentinel
+    // and is not part of the path
+    let path = &node_index[first_bit_byte_index..];
+    let mut end = path.len();
+    while end > 0 {
+        let start = end.saturating_sub(8);
+        let word = path[start..end]
+            .iter()
+            .fold(0_u64, |acc, b| (acc << 8) | *b as u64);
+        let num_bits = if start == 0 {
+            63 - word.leading_zeros()
         } else {
-            bitmask <<= 1;
-        }
-        cost += TRAVERSE_COST_PER_BIT;
+            64
+        };
+        arg_list = follow_bits(allocator, word, num_bits, arg_list)?;
+        cost += num_bits as Cost * TRAVERSE_COST_PER_BIT;
+        end = start;
     }
     Ok(Reduction(cost, arg_list))
 }
@@ -75,7 +84,7 @@ pub fn traverse_path(allocator: &Allocator, node_index: &[u8], args: NodePtr) ->
 // The cost calculation for this version of traverse_path assumes the node_index has the canonical
 // integer representation (which is true for SmallAtom in the allocator). If there are any
 // redundant leading zeros, the slow path must be used
-pub fn traverse_path_fast(allocator: &Allocator, mut node_index: u32, args: NodePtr) -> Response {
+pub fn traverse_path_fast(allocator: &Allocator, node_index: u32, args: NodePtr) -> Response {
     if node_index == 0 {
         return Ok(Reduction(
             TRAVERSE_BASE_COST + TRAVERSE_COST_PER_BIT,
@@ -83,22 +92,11 @@ pub fn traverse_path_fast(allocator: &Allocator, mut node_index: u32, args: Node
         ));
     }
 
-    let mut arg_list: NodePtr = args;
+    let num_bits = 31 - node_index.leading_zeros();
+    let arg_list = follow_bits(allocator, node_index as u64, num_bits, args)?;
 
     let mut cost: Cost = TRAVERSE_BASE_COST + TRAVERSE_COST_PER_BIT;
-    let mut num_bits = 0;
-    while node_index != 1 {
-        let SExp::Pair(left, right) = allocator.sexp(arg_list) else {
-            return Err(EvalErr(arg_list, "path into atom".into()));
-        };
-
-        let is_bit_set: bool = (node_index & 0x01) != 0;
-        arg_list = if is_bit_set { right } else { left };
-        node_index >>= 1;
-        num_bits += 1
-    }
-
-    cost += num_bits * TRAVERSE_COST_PER_BIT;
+    cost += num_bits as Cost * TRAVERSE_COST_PER_BIT;
     // since positive numbers sometimes need a leading zero, e.g. 0x80, 0x8000 etc. We also
     // need to add the cost of that leading zero byte
     if num_bits == 7 || num_bits == 15 || num_bits == 23 || num_bits == 31 {
@@ -111,6 +109,64 @@ pub fn traverse_path_fast(allocator: &Allocator, mut node_index: u32, args: Node
 #[cfg(test)]
 mod tests {
     use super::*;
+    use rstest::rstest;
+
+    // return a bitmask with a single bit set, for the most significant set bit in
+    // the input byte
+    fn msb_mask(byte: u8) -> u8 {
+        let mut byte = (byte | (byte >> 1)) as u32;
+        byte |= byte >> 2;
+        byte |= byte >> 4;
+        debug_assert!((byte + 1) >> 1 <= 0x80);
+        ((byte + 1) >> 1) as u8
+    }
+
+    // the original, bit-at-a-time, implementation of traverse_path(), as a
+    // reference for the word-at-a-time implementation
+    fn traverse_path_bitwise(allocator: &Allocator, node_index: &[u8], args: NodePtr) -> Response {
+        let mut arg_list: NodePtr = args;
+
+        // find first non-zero byte
+        let first_bit_byte_index = first_non_zero(node_index);
+
+        let mut cost: Cost = TRAVERSE_BASE_COST
+            + (first_bit_byte_index as Cost) * TRAVERSE_COST_PER_ZERO_BYTE
+            + TRAVERSE_COST_PER_BIT;
+
+        if first_bit_byte_index >= node_index.len() {
+            return Ok(Reduction(cost, allocator.nil()));
+        }
+
+        // find first non-zero bit (the most significant bit is a sentinel)
+        let last_bitmask = msb_mask(node_index[first_bit_byte_index]);
+
+        // follow through the bits, moving left and right
+        let mut byte_idx = node_index.len() - 1;
+        let mut bitmask = 0x01;
+        while byte_idx > first_bit_byte_index || bitmask < last_bitmask {
+            let is_bit_set: bool = (node_index[byte_idx] & bitmask) != 0;
+            match allocator.sexp(arg_list) {
+                SExp::Atom => {
+                    return Err(EvalErr(
+                        arg_list,
+                        "path into atom".into(),
+                        ErrorCode::PathIntoAtom,
+                    ));
+                }
+                SExp::Pair(left, right) => {
+                    arg_list = if is_bit_set { right } else { left };
+                }
+            }
+            if bitmask == 0x80 {
+                bitmask = 0x01;
+                byte_idx -= 1;
+            } else {
+                bitmask <<= 1;
+            }
+            cost += TRAVERSE_COST_PER_BIT;
+        }
+        Ok(Reduction(cost, arg_list))
+    }
 
     #[test]
     fn test_msb_mask() {
@@ -187,23 +243,23 @@ mod tests {
         // errors
         assert_eq!(
             traverse_path(&a, &[0b1011], list).unwrap_err(),
-            EvalErr(nul, "path into atom".to_string())
+            EvalErr(nul, "path into atom".to_string(), ErrorCode::PathIntoAtom)
         );
         assert_eq!(
             traverse_path(&a, &[0b1101], list).unwrap_err(),
-            EvalErr(n1, "path into atom".to_string())
+            EvalErr(n1, "path into atom".to_string(), ErrorCode::PathIntoAtom)
         );
         assert_eq!(
             traverse_path(&a, &[0b1001], list).unwrap_err(),
-            EvalErr(n1, "path into atom".to_string())
+            EvalErr(n1, "path into atom".to_string(), ErrorCode::PathIntoAtom)
         );
         assert_eq!(
             traverse_path(&a, &[0b1010], list).unwrap_err(),
-            EvalErr(n2, "path into atom".to_string())
+            EvalErr(n2, "path into atom".to_string(), ErrorCode::PathIntoAtom)
         );
         assert_eq!(
             traverse_path(&a, &[0b1110], list).unwrap_err(),
-            EvalErr(n2, "path into atom".to_string())
+            EvalErr(n2, "path into atom".to_string(), ErrorCode::PathIntoAtom)
         );
     }
 
@@ -245,23 +301,60 @@ mod tests {
         // errors
         assert_eq!(
             traverse_path_fast(&a, 0b1011, list).unwrap_err(),
-            EvalErr(nul, "path into atom".to_string())
+            EvalErr(nul, "path into atom".to_string(), ErrorCode::PathIntoAtom)
         );
         assert_eq!(
             traverse_path_fast(&a, 0b1101, list).unwrap_err(),
-            EvalErr(n1, "path into atom".to_string())
+            EvalErr(n1, "path into atom".to_string(), ErrorCode::PathIntoAtom)
         );
         assert_eq!(
             traverse_path_fast(&a, 0b1001, list).unwrap_err(),
-            EvalErr(n1, "path into atom".to_string())
+            EvalErr(n1, "path into atom".to_string(), ErrorCode::PathIntoAtom)
         );
         assert_eq!(
             traverse_path_fast(&a, 0b1010, list).unwrap_err(),
-            EvalErr(n2, "path into atom".to_string())
+            EvalErr(n2, "path into atom".to_string(), ErrorCode::PathIntoAtom)
         );
         assert_eq!(
             traverse_path_fast(&a, 0b1110, list).unwrap_err(),
-            EvalErr(n2, "path into atom".to_string())
+            EvalErr(n2, "path into atom".to_string(), ErrorCode::PathIntoAtom)
+        );
+    }
+
+    #[rstest]
+    #[case(&[0b1])]
+    #[case(&[0, 0, 0b1])]
+    #[case(&[0b10110])]
+    #[case(&[0x80])]
+    #[case(&[0, 0x80, 0, 0, 0, 0, 0, 0, 0])]
+    #[case(&[0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff])]
+    #[case(&[0x01, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff])]
+    #[case(&[0x01, 0, 0, 0, 0, 0, 0, 0, 0])]
+    #[case(&[0x03, 0x55, 0x55, 0x55, 0x55, 0x55, 0x55, 0x55, 0x55, 0xaa, 0xaa, 0xaa, 0xaa, 0xaa, 0xaa, 0xaa, 0xaa])]
+    #[case(&[0, 0, 0x2a, 0xaa, 0xaa, 0xaa, 0xaa, 0xaa, 0xaa, 0xaa, 0xaa, 0xaa, 0xaa, 0xaa, 0xaa, 0xaa, 0xaa, 0xab, 0x00, 0xff])]
+    fn test_traverse_path_words(#[case] path: &[u8]) {
+        // a tree deep enough for all the test paths
+        let mut a = Allocator::new();
+        let mut tree = a.new_atom(&[1, 2, 3]).unwrap();
+        for i in 0..160_u32 {
+            let leaf = a.new_number(i.into()).unwrap();
+            tree = if i % 3 == 0 {
+                a.new_pair(tree, tree).unwrap()
+            } else {
+                a.new_pair(leaf, tree).unwrap()
+            };
+        }
+        assert_eq!(
+            traverse_path(&a, path, tree),
+            traverse_path_bitwise(&a, path, tree)
+        );
+
+        // paths into atoms fail the same way
+        let shallow = a.new_pair(tree, NodePtr::NIL).unwrap();
+        let shallow = a.new_pair(NodePtr::NIL, shallow).unwrap();
+        assert_eq!(
+            traverse_path(&a, path, shallow),
+            traverse_path_bitwise(&a, path, shallow)
         );
     }
 }
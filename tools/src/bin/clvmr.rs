@@ -0,0 +1,338 @@
+use clap::{Parser, Subcommand, ValueEnum};
+use clvmr::allocator::{Allocator, NodePtr};
+use clvmr::chia_dialect::MEMPOOL_MODE;
+use clvmr::disasm::{disassemble_with_options, AtomFormat, DisasmOptions};
+use clvmr::serde::{
+    backref_stats_from_bytes, node_from_bytes_backrefs, node_from_bytes_backrefs_canonical,
+    node_to_bytes, node_to_bytes_backrefs, tree_hash, tree_hash_from_stream, BackrefCache,
+    CompressionLevel,
+};
+use clvmr::{parse_clvm_text, run_serialized_program};
+use serde_json::{Map, Value};
+use std::fs::{read, read_to_string};
+use std::io::Cursor;
+use std::process::ExitCode;
+
+/// Developer tool for running and inspecting CLVM programs. Inputs are hex
+/// strings, or file names prefixed by @. Files may contain either hex or
+/// binary serializations.
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+struct Args {
+    #[command(subcommand)]
+    command: Command,
+
+    /// print the results, or the error, as a JSON object on stdout
+    #[arg(long, global = true, default_value_t = false)]
+    json: bool,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// run a serialized program, printing the serialized result and its cost
+    Run {
+        program: String,
+
+        /// the environment (solution) to run the program with
+        #[arg(default_value = "80")]
+        env: String,
+
+        #[arg(long, default_value_t = 11_000_000_000)]
+        max_cost: u64,
+
+        /// run with the stricter mempool rules
+        #[arg(long, default_value_t = false)]
+        mempool: bool,
+    },
+
+    /// print the tree hash of a serialized CLVM structure
+    Treehash { input: String },
+
+    /// re-serialize with back-references
+    Compress {
+        input: String,
+
+        #[arg(long, value_enum, default_value_t = Level::Default)]
+        level: Level,
+    },
+
+    /// re-serialize without back-references
+    Decompress { input: String },
+
+    /// check that the input is a single, valid serialization, and print
+    /// some facts about it
+    Validate { input: String },
+
+    /// print a serialized program in its human readable form, with operator
+    /// names
+    Disasm {
+        input: String,
+
+        /// how to print atoms
+        #[arg(long, value_enum, default_value_t = Atoms::Auto)]
+        atoms: Atoms,
+    },
+
+    /// serialize a program in its human readable form, e.g. "(+ 2 5)". The
+    /// text may also be read from a file, prefixed by @
+    Asm {
+        text: String,
+
+        /// serialize with back-references
+        #[arg(long, default_value_t = false)]
+        compress: bool,
+    },
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum Atoms {
+    /// strings if printable, small integers in decimal, and hex otherwise
+    Auto,
+    Hex,
+    Decimal,
+}
+
+impl From<Atoms> for AtomFormat {
+    fn from(atoms: Atoms) -> Self {
+        match atoms {
+            Atoms::Auto => AtomFormat::Auto,
+            Atoms::Hex => AtomFormat::Hex,
+            Atoms::Decimal => AtomFormat::Decimal,
+        }
+    }
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum Level {
+    Fast,
+    Default,
+    Max,
+}
+
+impl From<Level> for CompressionLevel {
+    fn from(level: Level) -> Self {
+        match level {
+            Level::Fast => CompressionLevel::Fast,
+            Level::Default => CompressionLevel::Default,
+            Level::Max => CompressionLevel::Max,
+        }
+    }
+}
+
+// the input is invalid (e.g. not hex, a missing file or a bad serialization)
+const EXIT_INVALID_INPUT: u8 = 2;
+// the program failed, or the input didn't validate
+const EXIT_FAILURE: u8 = 1;
+
+fn read_input(input: &str) -> Result<Vec<u8>, String> {
+    let Some(filename) = input.strip_prefix('@') else {
+        return hex::decode(input.trim()).map_err(|e| format!("invalid hex: {e}"));
+    };
+    let buf = read(filename).map_err(|e| format!("failed to read {filename}: {e}"))?;
+    // files may hold hex or binary. A binary serialization is never valid
+    // hex, since one starting with an ASCII character is a single byte
+    let decoded = std::str::from_utf8(&buf)
+        .ok()
+        .and_then(|text| hex::decode(text.trim()).ok());
+    Ok(decoded.unwrap_or(buf))
+}
+
+fn deserialize(a: &mut Allocator, buf: &[u8]) -> Result<NodePtr, String> {
+    node_from_bytes_backrefs(a, buf).map_err(|e| format!("invalid serialization: {e}"))
+}
+
+// the result of a command. The main value (if any) is printed on its own line,
+// followed by the other fields as "name: value". With --json, they're all
+// printed as a single object
+#[derive(Default)]
+struct Output {
+    main: Option<(&'static str, String)>,
+    fields: Vec<(&'static str, Value)>,
+}
+
+impl Output {
+    fn main(name: &'static str, value: String) -> Self {
+        Self {
+            main: Some((name, value)),
+            fields: vec![],
+        }
+    }
+
+    fn field(mut self, name: &'static str, value: impl Into<Value>) -> Self {
+        self.fields.push((name, value.into()));
+        self
+    }
+
+    fn print(self, json: bool) {
+        if json {
+            let mut obj = Map::new();
+            if let Some((name, value)) = self.main {
+                obj.insert(name.to_string(), Value::String(value));
+            }
+            for (name, value) in self.fields {
+                obj.insert(name.to_string(), value);
+            }
+            println!("{}", Value::Object(obj));
+            return;
+        }
+        if let Some((_, value)) = self.main {
+            println!("{value}");
+        }
+        for (name, value) in self.fields {
+            let name = name.replace('_', " ");
+            match value {
+                Value::String(s) => println!("{name}: {s}"),
+                v => println!("{name}: {v}"),
+            }
+        }
+    }
+}
+
+struct Failure {
+    exit_code: u8,
+    message: String,
+    // the ErrorCode, if the program failed
+    error_code: Option<u32>,
+}
+
+impl Failure {
+    fn invalid(message: String) -> Self {
+        Self {
+            exit_code: EXIT_INVALID_INPUT,
+            message,
+            error_code: None,
+        }
+    }
+
+    fn failed(message: String) -> Self {
+        Self {
+            exit_code: EXIT_FAILURE,
+            message,
+            error_code: None,
+        }
+    }
+
+    fn print(&self, json: bool) {
+        if json {
+            let mut obj = Map::new();
+            obj.insert("error".to_string(), self.message.clone().into());
+            if let Some(code) = self.error_code {
+                obj.insert("error_code".to_string(), code.into());
+            }
+            println!("{}", Value::Object(obj));
+        } else if let Some(code) = self.error_code {
+            eprintln!("error: {} (code {code})", self.message);
+        } else {
+            eprintln!("error: {}", self.message);
+        }
+    }
+}
+
+fn run(command: Command) -> Result<Output, Failure> {
+    let output = match command {
+        Command::Run {
+            program,
+            env,
+            max_cost,
+            mempool,
+        } => {
+            let program = read_input(&program).map_err(Failure::invalid)?;
+            let env = read_input(&env).map_err(Failure::invalid)?;
+            let flags = if mempool { MEMPOOL_MODE } else { 0 };
+            let (cost, result) =
+                run_serialized_program(&program, &env, max_cost, flags).map_err(|e| Failure {
+                    exit_code: EXIT_FAILURE,
+                    error_code: Some(e.code().code()),
+                    message: e.1,
+                })?;
+            let hash = tree_hash_from_stream(&mut Cursor::new(&result))
+                .map_err(|e| Failure::failed(e.to_string()))?;
+            Output::main("result", hex::encode(result))
+                .field("cost", cost)
+                .field("tree_hash", hex::encode(hash))
+        }
+        Command::Treehash { input } => {
+            let buf = read_input(&input).map_err(Failure::invalid)?;
+            let mut a = Allocator::new();
+            let node = deserialize(&mut a, &buf).map_err(Failure::invalid)?;
+            Output::main("tree_hash", hex::encode(tree_hash(&a, node)))
+        }
+        Command::Compress { input, level } => {
+            let buf = read_input(&input).map_err(Failure::invalid)?;
+            let mut a = Allocator::new();
+            let node = deserialize(&mut a, &buf).map_err(Failure::invalid)?;
+            let out = BackrefCache::new()
+                .with_compression_level(level.into())
+                .node_to_bytes(&a, node)
+                .map_err(|e| Failure::failed(e.to_string()))?;
+            Output::main("result", hex::encode(out))
+        }
+        Command::Decompress { input } => {
+            let buf = read_input(&input).map_err(Failure::invalid)?;
+            let mut a = Allocator::new();
+            let node = deserialize(&mut a, &buf).map_err(Failure::invalid)?;
+            let out = node_to_bytes(&a, node).map_err(|e| Failure::failed(e.to_string()))?;
+            Output::main("result", hex::encode(out))
+        }
+        Command::Validate { input } => {
+            let buf = read_input(&input).map_err(Failure::invalid)?;
+            let stats = backref_stats_from_bytes(&buf)
+                .map_err(|e| Failure::failed(format!("invalid serialization: {e}")))?;
+            if stats.compressed_length != buf.len() as u64 {
+                return Err(Failure::failed(format!(
+                    "{} bytes of trailing garbage",
+                    buf.len() as u64 - stats.compressed_length
+                )));
+            }
+            let canonical = node_from_bytes_backrefs_canonical(&mut Allocator::new(), &buf).is_ok();
+            Output::default()
+                .field("length", stats.compressed_length)
+                .field("expanded_length", stats.serialized_length)
+                .field("nodes", stats.node_count)
+                .field("tree_hash", hex::encode(stats.tree_hash))
+                .field("canonical", canonical)
+        }
+        Command::Disasm { input, atoms } => {
+            let buf = read_input(&input).map_err(Failure::invalid)?;
+            let mut a = Allocator::new();
+            let node = deserialize(&mut a, &buf).map_err(Failure::invalid)?;
+            let options = DisasmOptions {
+                atom_format: atoms.into(),
+                ..Default::default()
+            };
+            Output::main("text", disassemble_with_options(&a, node, &options))
+        }
+        Command::Asm { text, compress } => {
+            let text = match text.strip_prefix('@') {
+                Some(filename) => read_to_string(filename)
+                    .map_err(|e| Failure::invalid(format!("failed to read {filename}: {e}")))?,
+                None => text,
+            };
+            let mut a = Allocator::new();
+            let node =
+                parse_clvm_text(&mut a, &text).map_err(|e| Failure::invalid(e.to_string()))?;
+            let out = if compress {
+                node_to_bytes_backrefs(&a, node)
+            } else {
+                node_to_bytes(&a, node)
+            }
+            .map_err(|e| Failure::failed(e.to_string()))?;
+            Output::main("result", hex::encode(out))
+        }
+    };
+    Ok(output)
+}
+
+fn main() -> ExitCode {
+    let args = Args::parse();
+    match run(args.command) {
+        Ok(output) => {
+            output.print(args.json);
+            ExitCode::SUCCESS
+        }
+        Err(failure) => {
+            failure.print(args.json);
+            ExitCode::from(failure.exit_code)
+        }
+    }
+}
@@ -0,0 +1,67 @@
+use clap::Parser;
+use serde_json::{json, Map, Value};
+use std::fs::{read_dir, read_to_string};
+use std::path::{Path, PathBuf};
+
+/// Collect the results of the most recent `cargo bench` run into a single
+/// JSON object, mapping each benchmark's full name to its mean, median and
+/// standard deviation (in nanoseconds). The output can be stored and compared
+/// across commits, to catch performance regressions.
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+struct Args {
+    /// the directory criterion writes its results to
+    #[arg(short, long, default_value = "target/criterion")]
+    dir: PathBuf,
+}
+
+fn load(path: &Path) -> Option<Value> {
+    serde_json::from_str(&read_to_string(path).ok()?).ok()
+}
+
+fn collect(dir: &Path, results: &mut Map<String, Value>) {
+    let Ok(entries) = read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if !path.is_dir() {
+            continue;
+        }
+        // criterion stores the latest run of every benchmark in "new"
+        let latest = path.join("new");
+        if let (Some(benchmark), Some(estimates)) = (
+            load(&latest.join("benchmark.json")),
+            load(&latest.join("estimates.json")),
+        ) {
+            let name = benchmark["full_id"]
+                .as_str()
+                .unwrap_or_default()
+                .to_string();
+            results.insert(
+                name,
+                json!({
+                    "mean": estimates["mean"]["point_estimate"],
+                    "median": estimates["median"]["point_estimate"],
+                    "std_dev": estimates["std_dev"]["point_estimate"],
+                }),
+            );
+        } else if path.file_name() != Some("report".as_ref()) {
+            collect(&path, results);
+        }
+    }
+}
+
+fn main() {
+    let args = Args::parse();
+    let mut results = Map::new();
+    collect(&args.dir, &mut results);
+    if results.is_empty() {
+        eprintln!("no benchmark results found in {}", args.dir.display());
+        std::process::exit(1);
+    }
+    println!(
+        "{}",
+        serde_json::to_string_pretty(&Value::Object(results)).expect("to_string_pretty")
+    );
+}
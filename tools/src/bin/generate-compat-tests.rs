@@ -0,0 +1,79 @@
+use clap::Parser;
+use clvmr::allocator::Allocator;
+use clvmr::chia_dialect::ChiaDialect;
+use clvmr::parse_clvm_text;
+use clvmr::run_program::run_program;
+use clvmr::serde::{node_to_bytes, tree_hash};
+use std::fs::OpenOptions;
+use std::io::Write;
+
+/// Append a test case to the compatibility corpus. The program is run with
+/// the current version of clvm_rs, and its serialization, tree hash, result
+/// and cost are recorded. The corpus is checked by the compat tests, to make
+/// sure later versions produce the exact same results.
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+struct Args {
+    /// the program, in its human readable form, e.g. "(+ 2 5)"
+    program: String,
+
+    /// the environment to run the program with
+    #[arg(default_value = "()")]
+    env: String,
+
+    /// the ChiaDialect flags to run the program with, in decimal or hex
+    #[arg(long, default_value = "0", value_parser = parse_flags)]
+    flags: u32,
+
+    #[arg(long, default_value_t = 11_000_000_000)]
+    max_cost: u64,
+
+    /// the corpus file to append the test case to
+    #[arg(long, default_value = "compat-tests/corpus.txt")]
+    output: String,
+}
+
+fn parse_flags(s: &str) -> Result<u32, std::num::ParseIntError> {
+    match s.strip_prefix("0x") {
+        Some(hex) => u32::from_str_radix(hex, 16),
+        None => s.parse(),
+    }
+}
+
+fn main() {
+    let args = Args::parse();
+
+    let mut a = Allocator::new();
+    let program = parse_clvm_text(&mut a, &args.program).expect("invalid program");
+    let env = parse_clvm_text(&mut a, &args.env).expect("invalid environment");
+    let program_hex = hex::encode(node_to_bytes(&a, program).expect("node_to_bytes"));
+    let env_hex = hex::encode(node_to_bytes(&a, env).expect("node_to_bytes"));
+    let hash = hex::encode(tree_hash(&a, program));
+
+    let dialect = ChiaDialect::new(args.flags);
+    let outcome = match run_program(&mut a, &dialect, program, env, args.max_cost) {
+        Ok(reduction) => format!(
+            "{} | {}",
+            hex::encode(node_to_bytes(&a, reduction.1).expect("node_to_bytes")),
+            reduction.0
+        ),
+        Err(err) => format!("FAIL | {}", err.1),
+    };
+
+    let line = format!(
+        "{:#06x} {} {program_hex} {env_hex} {hash} => {outcome}\n",
+        args.flags, args.max_cost
+    );
+    let mut corpus = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&args.output)
+        .expect("failed to open corpus");
+    corpus
+        .write_all(format!("; {} {}\n", args.program, args.env).as_bytes())
+        .expect("failed to write corpus");
+    corpus
+        .write_all(line.as_bytes())
+        .expect("failed to write corpus");
+    print!("{line}");
+}
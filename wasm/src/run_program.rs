@@ -4,12 +4,14 @@ use wasm_bindgen::prelude::*;
 
 use crate::flags::ALLOW_BACKREFS;
 use crate::lazy_node::LazyNode;
-use clvmr::allocator::Allocator;
+use clvmr::allocator::{Allocator, NodePtr};
 use clvmr::chia_dialect::ChiaDialect;
 use clvmr::chia_dialect::NO_UNKNOWN_OPS as _no_unknown_ops;
 use clvmr::cost::Cost;
+use clvmr::reduction::EvalErr;
 use clvmr::run_program::run_program;
 use clvmr::serde::{node_from_bytes, node_from_bytes_backrefs, node_to_bytes};
+use std::io;
 
 #[wasm_bindgen]
 pub struct Flag;
@@ -27,16 +29,22 @@ impl Flag {
     }
 }
 
+type Deserializer = fn(&mut Allocator, &[u8]) -> io::Result<NodePtr>;
+
+fn deserializer(flag: u32) -> Deserializer {
+    if (flag & ALLOW_BACKREFS) != 0 {
+        node_from_bytes_backrefs
+    } else {
+        node_from_bytes
+    }
+}
+
 #[wasm_bindgen]
 pub fn run_clvm(program: &[u8], args: &[u8], flag: u32) -> Vec<u8> {
     let max_cost: Cost = 1_000_000_000_000_000;
 
     let mut allocator = Allocator::new();
-    let deserializer = if (flag & ALLOW_BACKREFS) != 0 {
-        node_from_bytes_backrefs
-    } else {
-        node_from_bytes
-    };
+    let deserializer = deserializer(flag);
     let program = deserializer(&mut allocator, program).unwrap();
     let args = deserializer(&mut allocator, args).unwrap();
     let dialect = ChiaDialect::new(flag);
@@ -56,13 +64,9 @@ pub fn run_chia_program(
     flag: u32,
 ) -> Result<Array, String> {
     let mut allocator = Allocator::new();
-    let deserializer = if (flag & ALLOW_BACKREFS) != 0 {
-        node_from_bytes_backrefs
-    } else {
-        node_from_bytes
-    };
-    let program = deserializer(&mut allocator, program).unwrap();
-    let args = deserializer(&mut allocator, args).unwrap();
+    let deserializer = deserializer(flag);
+    let program = deserializer(&mut allocator, program).map_err(|e| e.to_string())?;
+    let args = deserializer(&mut allocator, args).map_err(|e| e.to_string())?;
     let dialect = ChiaDialect::new(flag);
 
     let r = run_program(&mut allocator, &dialect, program, args, max_cost);
@@ -80,3 +84,113 @@ pub fn run_chia_program(
         Err(_eval_err) => Err(format!("{:?}", _eval_err)),
     }
 }
+
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ErrorCode {
+    InvalidProgram = 1,
+    InvalidArgs = 2,
+    EvalError = 3,
+    CostExceeded = 4,
+    InvalidResult = 5,
+}
+
+/// The error returned by `run_chia_program_with_details()`. `node` is the
+/// serialized node the error refers to, if there is one.
+#[wasm_bindgen]
+#[derive(Clone, Debug)]
+pub struct RunError {
+    code: ErrorCode,
+    message: String,
+    node: Option<Vec<u8>>,
+}
+
+#[wasm_bindgen]
+impl RunError {
+    #[wasm_bindgen(getter)]
+    pub fn code(&self) -> ErrorCode {
+        self.code
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn message(&self) -> String {
+        self.message.clone()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn node(&self) -> Option<Vec<u8>> {
+        self.node.clone()
+    }
+}
+
+impl RunError {
+    fn new(code: ErrorCode, message: String) -> Self {
+        Self {
+            code,
+            message,
+            node: None,
+        }
+    }
+
+    fn from_eval_err(a: &Allocator, err: EvalErr) -> Self {
+        let code = if err.code() == clvmr::error::ErrorCode::CostExceeded {
+            ErrorCode::CostExceeded
+        } else {
+            ErrorCode::EvalError
+        };
+        Self {
+            code,
+            message: err.1,
+            node: node_to_bytes(a, err.0).ok(),
+        }
+    }
+}
+
+#[wasm_bindgen]
+#[derive(Clone, Debug)]
+pub struct RunOutput {
+    cost: Cost,
+    result: Vec<u8>,
+}
+
+#[wasm_bindgen]
+impl RunOutput {
+    #[wasm_bindgen(getter)]
+    pub fn cost(&self) -> Cost {
+        self.cost
+    }
+
+    /// the serialized result of the program
+    #[wasm_bindgen(getter)]
+    pub fn result(&self) -> Vec<u8> {
+        self.result.clone()
+    }
+}
+
+/// Runs a serialized program with serialized arguments. Unlike
+/// `run_chia_program()`, errors are reported as a `RunError` with an error
+/// code, rather than a string, and the result is returned serialized.
+#[wasm_bindgen]
+pub fn run_chia_program_with_details(
+    program: &[u8],
+    args: &[u8],
+    max_cost: Cost,
+    flag: u32,
+) -> Result<RunOutput, RunError> {
+    let mut allocator = Allocator::new();
+    let deserializer = deserializer(flag);
+    let program = deserializer(&mut allocator, program)
+        .map_err(|e| RunError::new(ErrorCode::InvalidProgram, e.to_string()))?;
+    let args = deserializer(&mut allocator, args)
+        .map_err(|e| RunError::new(ErrorCode::InvalidArgs, e.to_string()))?;
+    let dialect = ChiaDialect::new(flag);
+
+    let reduction = run_program(&mut allocator, &dialect, program, args, max_cost)
+        .map_err(|e| RunError::from_eval_err(&allocator, e))?;
+    let result = node_to_bytes(&allocator, reduction.1)
+        .map_err(|e| RunError::new(ErrorCode::InvalidResult, e.to_string()))?;
+    Ok(RunOutput {
+        cost: reduction.0,
+        result,
+    })
+}
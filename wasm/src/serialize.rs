@@ -1,13 +1,17 @@
+use std::io::Cursor;
 use std::rc::Rc;
 use wasm_bindgen::prelude::wasm_bindgen;
 
 use crate::flags::ALLOW_BACKREFS;
 use crate::lazy_node::LazyNode;
 use clvmr::serde::{
-    node_from_bytes as _node_from_bytes, node_from_bytes_backrefs, serialized_length_from_bytes,
+    node_from_bytes as _node_from_bytes, node_from_bytes_backrefs, node_to_bytes,
+    node_to_bytes_backrefs, serialized_length_from_bytes, tree_hash, tree_hash_from_stream,
 };
 use clvmr::Allocator;
 
+// the length of the serialization at the start of the buffer. Back references
+// are supported
 #[wasm_bindgen]
 pub fn serialized_length(program: &[u8]) -> Result<u64, String> {
     serialized_length_from_bytes(program).map_err(|x| x.to_string())
@@ -24,3 +28,39 @@ pub fn node_from_bytes(b: &[u8], flag: u32) -> Result<LazyNode, String> {
     let node = deserializer(&mut allocator, b).map_err(|e| e.to_string())?;
     Ok(LazyNode::new(Rc::new(allocator), node))
 }
+
+// computes the tree hash of a serialized CLVM structure, without building the
+// tree (unless the serialization may contain back references)
+#[wasm_bindgen]
+pub fn tree_hash_from_bytes(b: &[u8], flag: u32) -> Result<Vec<u8>, String> {
+    if (flag & ALLOW_BACKREFS) != 0 {
+        let mut allocator = Allocator::new();
+        let node = node_from_bytes_backrefs(&mut allocator, b).map_err(|e| e.to_string())?;
+        Ok(tree_hash(&allocator, node).to_vec())
+    } else {
+        let mut f = Cursor::new(b);
+        let hash = tree_hash_from_stream(&mut f).map_err(|e| e.to_string())?;
+        Ok(hash.to_vec())
+    }
+}
+
+// re-serialize a CLVM structure using back references, to make it smaller
+#[wasm_bindgen]
+pub fn compress_serialization(b: &[u8], flag: u32) -> Result<Vec<u8>, String> {
+    let mut allocator = Allocator::new();
+    let deserializer = if (flag & ALLOW_BACKREFS) != 0 {
+        node_from_bytes_backrefs
+    } else {
+        _node_from_bytes
+    };
+    let node = deserializer(&mut allocator, b).map_err(|e| e.to_string())?;
+    node_to_bytes_backrefs(&allocator, node).map_err(|e| e.to_string())
+}
+
+// re-serialize a CLVM structure containing back references, without them
+#[wasm_bindgen]
+pub fn decompress_serialization(b: &[u8]) -> Result<Vec<u8>, String> {
+    let mut allocator = Allocator::new();
+    let node = node_from_bytes_backrefs(&mut allocator, b).map_err(|e| e.to_string())?;
+    node_to_bytes(&allocator, node).map_err(|e| e.to_string())
+}
@@ -132,3 +132,71 @@ test_case("Test serialized_length", function () {
     expect_equal(e, "bad encoding");
   }
 });
+
+test_case("Test run_chia_program_with_details", function () {
+  // (+ 1 (q . 3))
+  const prog = bytesFromHex("ff10ff01ffff010380");
+  const arg = bytesFromHex("02");
+  const max_cost = BigInt("100000000000");
+  const output = wasm.run_chia_program_with_details(prog, arg, max_cost, 0);
+  expect_equal(Buffer.from(output.result).toString("hex"), "05");
+  expect_equal(output.cost > BigInt(0), true);
+});
+
+test_case("Test run_chia_program_with_details errors", function () {
+  const max_cost = BigInt("100000000000");
+  const cases = [
+    // invalid program
+    ["ff01", "80", max_cost, wasm.ErrorCode.InvalidProgram],
+    // invalid arguments
+    ["ff017f", "ff", max_cost, wasm.ErrorCode.InvalidArgs],
+    // (x (q . 1337))
+    ["ff08ffff0182053980", "80", max_cost, wasm.ErrorCode.EvalError],
+    // (q . 127), with max_cost too low
+    ["ff017f", "80", BigInt("1"), wasm.ErrorCode.CostExceeded],
+  ];
+  for (const [prog, arg, cost, code] of cases) {
+    try {
+      wasm.run_chia_program_with_details(
+        bytesFromHex(prog),
+        bytesFromHex(arg),
+        cost,
+        0,
+      );
+      throw new Error("Expected an exception but it was not thrown");
+    } catch (e) {
+      expect_equal(e.code, code);
+    }
+  }
+});
+
+test_case("Test tree_hash_from_bytes", function () {
+  // (q . 127)
+  const prog = bytesFromHex("ff017f");
+  const hash = Buffer.from(wasm.tree_hash_from_bytes(prog, 0)).toString("hex");
+  expect_equal(
+    hash,
+    "21f8624ef5b73ea5581bb2298d39de30694e5e5df4ceaaaab280ffa811d43c90",
+  );
+  expect_equal(
+    Buffer.from(
+      wasm.tree_hash_from_bytes(prog, wasm.Flag.allow_backrefs()),
+    ).toString("hex"),
+    hash,
+  );
+  expect_throw(function () {
+    wasm.tree_hash_from_bytes(bytesFromHex("ff01"), 0);
+  });
+});
+
+test_case("Test compress_serialization", function () {
+  // ((1 2 3) . (1 2 3))
+  const prog = bytesFromHex("ffff01ff02ff0380ff01ff02ff0380");
+  const compressed = wasm.compress_serialization(prog, 0);
+  expect_equal(compressed.length < prog.length, true);
+  expect_equal(
+    Buffer.from(wasm.decompress_serialization(compressed)).toString("hex"),
+    "ffff01ff02ff0380ff01ff02ff0380",
+  );
+  expect_equal(wasm.serialized_length(compressed), BigInt(compressed.length));
+});
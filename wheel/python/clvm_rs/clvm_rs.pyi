@@ -13,6 +13,7 @@ def serialized_length(blob: bytes) -> int: ...
 NO_NEG_DIV: int
 NO_UNKNOWN_OPS: int
 LIMIT_HEAP: int
+LIMIT_ATOM_SIZE: int
 MEMPOOL_MODE: int
 
 class LazyNode(CLVMStorage):
@@ -20,3 +21,38 @@ class LazyNode(CLVMStorage):
 
     @property
     def pair(self) -> Optional[Tuple[CLVMStorage, CLVMStorage]]: ...
+
+class Serializer:
+    def __init__(self) -> None: ...
+    def add(self, blob: bytes) -> None: ...
+    def undo(self) -> None: ...
+    def done(self) -> bytes: ...
+    @property
+    def size(self) -> int: ...
+
+class AllocatorNode: ...
+
+class Allocator:
+    def __init__(self, flags: int = 0) -> None: ...
+    def deserialize(self, blob: bytes) -> AllocatorNode: ...
+    def serialize(self, node: AllocatorNode) -> bytes: ...
+    def run(
+        self, program: AllocatorNode, args: AllocatorNode, max_cost: int, flags: int
+    ) -> Tuple[int, AllocatorNode]: ...
+    def to_lazy_node(self, node: AllocatorNode) -> LazyNode: ...
+    def reset(self) -> None: ...
+
+class ClvmError(ValueError):
+    code: int
+    message: str
+    node: Optional[bytes]
+
+class CostExceededError(ClvmError): ...
+class OutOfMemoryError(ClvmError): ...
+class TooManyAtomsError(ClvmError): ...
+class TooManyPairsError(ClvmError): ...
+class ResourceLimitError(ClvmError): ...
+class RaiseError(ClvmError): ...
+class InvalidOperatorError(ClvmError): ...
+class InvalidOperatorArg(ClvmError): ...
+class VerifyFailedError(ClvmError): ...
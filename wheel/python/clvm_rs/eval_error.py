@@ -1,9 +1,14 @@
+from typing import Optional
+
 from .ser import sexp_to_bytes
 
 class EvalError(ValueError):
-    def __init__(self, message: str, sexp):
+    def __init__(self, message: str, sexp, code: int = 0, node: Optional[bytes] = None):
         super().__init__(message)
         self._sexp = sexp
+        # the stable `ErrorCode` value, and the serialized offending node
+        self.code = code
+        self.node = node
 
     def __str__(self) -> str:
         return f"({self.args[0]}, {sexp_to_bytes(self._sexp).hex()})"
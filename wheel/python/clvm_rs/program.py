@@ -4,7 +4,7 @@ from typing import Iterator, List, Tuple, Optional, BinaryIO
 from .at import at
 from .casts import CastableType, to_clvm_object, int_from_bytes, int_to_bytes
 from .chia_dialect import CHIA_DIALECT
-from .clvm_rs import run_serialized_chia_program
+from .clvm_rs import ClvmError, run_serialized_chia_program
 from .clvm_storage import CLVMStorage
 from .clvm_tree import CLVMTree
 from .curry_and_treehash import CurryTreehasher
@@ -267,8 +267,10 @@ class Program(CLVMStorage):
                 prog_bytes, args_bytes, max_cost, flags
             )
             r = self.wrap(lazy_node)
-        except ValueError as ve:
-            raise EvalError(ve.args[0], self.wrap(ve.args[1]))
+        except ClvmError as ce:
+            raise EvalError(
+                ce.args[0], self.wrap(ce.args[1]), code=ce.code, node=ce.node
+            ) from ce
         return cost, r
 
     def run(self, args) -> "Program":
@@ -0,0 +1,54 @@
+import unittest
+
+from clvm_rs.clvm_rs import Allocator, ClvmError, RaiseError
+from clvm_rs.program import Program
+
+
+class AllocatorTest(unittest.TestCase):
+    def test_run(self):
+        a = Allocator()
+        program = a.deserialize(bytes(Program.to([16, 2, 5])))
+        args = a.deserialize(bytes(Program.to([3, 4])))
+        cost, result = a.run(program, args, 100000, 0)
+        self.assertGreater(cost, 0)
+        self.assertEqual(a.serialize(result), bytes(Program.to(7)))
+        self.assertEqual(a.to_lazy_node(result).atom, bytes([7]))
+
+    def test_reuse(self):
+        a = Allocator()
+        for i in range(10):
+            a.reset()
+            program = a.deserialize(bytes(Program.to([16, 1, (1, i)])))
+            cost, result = a.run(program, a.deserialize(bytes([i])), 100000, 0)
+            self.assertEqual(a.serialize(result), bytes(Program.to(i * 2)))
+
+    def test_reset(self):
+        a = Allocator()
+        node = a.deserialize(bytes(Program.to([1, 2, 3])))
+        lazy = a.to_lazy_node(node)
+        a.reset()
+        with self.assertRaises(ValueError):
+            a.serialize(node)
+        # the copy is still valid
+        self.assertEqual(Program.to(lazy), Program.to([1, 2, 3]))
+
+    def test_different_allocator(self):
+        a = Allocator()
+        b = Allocator()
+        node = a.deserialize(bytes(Program.to(1)))
+        with self.assertRaises(ValueError):
+            b.serialize(node)
+        with self.assertRaises(ValueError):
+            b.run(node, node, 100000, 0)
+
+    def test_error(self):
+        a = Allocator()
+        program = a.deserialize(bytes(Program.to([8, (1, b"foo")])))
+        with self.assertRaises(RaiseError) as cm:
+            a.run(program, a.deserialize(bytes(Program.to(0))), 100000, 0)
+        self.assertIsInstance(cm.exception, ClvmError)
+        self.assertEqual(cm.exception.message, "clvm raise")
+        self.assertEqual(cm.exception.node, bytes(Program.to(b"foo")))
+        # the allocator can still be used after a failure
+        node = a.deserialize(bytes(Program.to(1)))
+        self.assertEqual(a.serialize(node), bytes(Program.to(1)))
@@ -4,6 +4,13 @@ from unittest import TestCase
 
 from clvm_rs.clvm_storage import CLVMStorage, is_clvm_storage
 from clvm_rs.chia_dialect import CHIA_DIALECT
+from clvm_rs.clvm_rs import (
+    ClvmError,
+    CostExceededError,
+    InvalidOperatorArg,
+    RaiseError,
+    run_serialized_chia_program,
+)
 from clvm_rs.eval_error import EvalError
 from clvm_rs.program import Program
 
@@ -82,6 +89,22 @@ class TestProgram(TestCase):
             err = ee
         self.assertEqual(err.args, ("clvm raise",))
         self.assertEqual(err._sexp, ["foo", "bar"])
+        self.assertEqual(err.code, 10)
+        self.assertEqual(err.node, bytes.fromhex("ff83666f6fff8362617280"))
+        self.assertIsInstance(err.__cause__, RaiseError)
+
+    def test_run_exception_classes(self):
+        # `(+ (q . 1) (q 2))`
+        p = Program.fromhex("ff10ffff0101ffff01ff028080")
+        with self.assertRaises(InvalidOperatorArg):
+            run_serialized_chia_program(bytes(p), b"\x80", 1000000, 0)
+        # `(q . 1)` can't be run with a max cost of 1
+        p = Program.fromhex("ff0101")
+        with self.assertRaises(CostExceededError) as cm:
+            run_serialized_chia_program(bytes(p), b"\x80", 1, 0)
+        self.assertIsInstance(cm.exception, ClvmError)
+        self.assertIsInstance(cm.exception, ValueError)
+        self.assertEqual(cm.exception.message, "cost exceeded")
 
     def test_hash(self):
         p1 = Program.fromhex("80")
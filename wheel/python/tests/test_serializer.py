@@ -0,0 +1,45 @@
+import unittest
+
+from clvm_rs.clvm_rs import Serializer, serialized_length
+from clvm_rs.program import Program
+
+
+class SerializerTest(unittest.TestCase):
+    def test_list(self):
+        items = [Program.to([1, 2, 3]), Program.to(b"foobar"), Program.to([1, 2, 3])]
+        s = Serializer()
+        for item in items:
+            s.add(bytes(item))
+        blob = s.done()
+        # the repeated item is serialized as a back reference
+        self.assertLess(len(blob), len(bytes(Program.to(items))))
+        self.assertEqual(serialized_length(blob), len(blob))
+
+    def test_no_backrefs(self):
+        items = [Program.to([1, 2, 3]), Program.to(b"foobar")]
+        s = Serializer()
+        for item in items:
+            s.add(bytes(item))
+        self.assertEqual(Program.from_bytes(s.done()), Program.to(items))
+
+    def test_undo(self):
+        s = Serializer()
+        s.add(bytes(Program.to(1)))
+        size = s.size
+        s.add(bytes(Program.to(b"foobar")))
+        self.assertGreater(s.size, size)
+        s.undo()
+        self.assertEqual(s.size, size)
+        self.assertEqual(Program.from_bytes(s.done()), Program.to([1]))
+
+    def test_errors(self):
+        s = Serializer()
+        with self.assertRaises(ValueError):
+            s.undo()
+        with self.assertRaises(ValueError):
+            s.add(bytes.fromhex("ff01"))
+        self.assertEqual(s.done(), bytes.fromhex("80"))
+        with self.assertRaises(ValueError):
+            s.add(bytes(Program.to(1)))
+        with self.assertRaises(ValueError):
+            s.done()
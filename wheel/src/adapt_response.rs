@@ -1,12 +1,13 @@
 use std::rc::Rc;
 
+use crate::errors::new_err;
 use crate::lazy_node::LazyNode;
 use clvmr::allocator::Allocator;
-use clvmr::reduction::Response;
+use clvmr::reduction::{EvalErr, Response};
+use clvmr::serde::node_to_bytes;
 
-use pyo3::exceptions::PyValueError;
 use pyo3::prelude::*;
-use pyo3::types::PyTuple;
+use pyo3::types::{PyBytes, PyTuple};
 
 pub fn adapt_response(
     py: Python,
@@ -18,12 +19,24 @@ pub fn adapt_response(
             let val = LazyNode::new(Rc::new(allocator), reduction.1);
             Ok((reduction.0, val))
         }
-        Err(eval_err) => {
-            let sexp = LazyNode::new(Rc::new(allocator), eval_err.0).to_object(py);
-            let msg = eval_err.1.to_object(py);
-            let tuple = PyTuple::new_bound(py, [msg, sexp]);
-            let value_error: PyErr = PyValueError::new_err(tuple.to_object(py));
-            Err(value_error)
-        }
+        Err(eval_err) => Err(adapt_error(py, allocator, eval_err)?),
     }
 }
+
+/// the exception for a failed program. The offending node must be in
+/// `allocator`
+pub fn adapt_error(py: Python, allocator: Allocator, eval_err: EvalErr) -> PyResult<PyErr> {
+    let code = eval_err.code();
+    let node = node_to_bytes(&allocator, eval_err.0)
+        .ok()
+        .map(|b| PyBytes::new_bound(py, &b).into_any().unbind());
+    let sexp = LazyNode::new(Rc::new(allocator), eval_err.0).to_object(py);
+    let msg = eval_err.1.to_object(py);
+    let tuple = PyTuple::new_bound(py, [msg.clone_ref(py), sexp]);
+    let err = new_err(code, tuple.to_object(py));
+    let value = err.value_bound(py);
+    value.setattr("code", code.code())?;
+    value.setattr("message", msg)?;
+    value.setattr("node", node)?;
+    Ok(err)
+}
@@ -0,0 +1,149 @@
+use std::io;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::adapt_response::adapt_error;
+use crate::lazy_node::LazyNode;
+use clvmr::allocator::{copy_tree, Allocator, NodePtr};
+use clvmr::chia_dialect::ChiaDialect;
+use clvmr::cost::Cost;
+use clvmr::reduction::{EvalErr, Reduction};
+use clvmr::run_program::run_program;
+use clvmr::serde::{node_from_bytes, node_to_bytes};
+use clvmr::{LIMIT_ATOM_SIZE, LIMIT_HEAP, MAX_ATOM_SIZE};
+
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use pyo3::types::PyBytes;
+
+// every PyAllocator gets a unique ID, so nodes can't be passed to the wrong one
+static NEXT_ALLOCATOR_ID: AtomicU64 = AtomicU64::new(0);
+
+/// create an allocator with the limits requested by the LIMIT_HEAP and
+/// LIMIT_ATOM_SIZE flags
+pub fn new_allocator(flags: u32) -> Allocator {
+    let mut allocator = if flags & LIMIT_HEAP != 0 {
+        Allocator::new_limited(500000000)
+    } else {
+        Allocator::new()
+    };
+    if flags & LIMIT_ATOM_SIZE != 0 {
+        allocator.set_max_atom_size(MAX_ATOM_SIZE);
+    }
+    allocator
+}
+
+/// A node in a PyAllocator. It's only valid in the allocator that created it,
+/// until that allocator is reset.
+#[pyclass(frozen)]
+#[derive(Clone, Copy)]
+pub struct AllocatorNode {
+    allocator_id: u64,
+    generation: u64,
+    node: NodePtr,
+}
+
+/// An allocator that can be reused across calls, to avoid growing a new heap
+/// for every program. Nodes are returned as `AllocatorNode` handles, which
+/// can only be passed back to the same allocator, and only until it's reset.
+#[pyclass(name = "Allocator", unsendable)]
+pub struct PyAllocator {
+    allocator: Allocator,
+    id: u64,
+    generation: u64,
+}
+
+impl PyAllocator {
+    fn wrap(&self, node: NodePtr) -> AllocatorNode {
+        AllocatorNode {
+            allocator_id: self.id,
+            generation: self.generation,
+            node,
+        }
+    }
+
+    fn unwrap(&self, node: &AllocatorNode) -> PyResult<NodePtr> {
+        if node.allocator_id != self.id {
+            return Err(PyValueError::new_err(
+                "node belongs to a different allocator",
+            ));
+        }
+        if node.generation != self.generation {
+            return Err(PyValueError::new_err(
+                "node is from before the allocator was reset",
+            ));
+        }
+        Ok(node.node)
+    }
+
+    // copy a node to an allocator of its own, so it can outlive a reset
+    fn detach(&self, node: NodePtr) -> Result<(Allocator, NodePtr), EvalErr> {
+        let mut allocator = Allocator::new();
+        let node = copy_tree(&self.allocator, node, &mut allocator)?;
+        Ok((allocator, node))
+    }
+}
+
+#[pymethods]
+impl PyAllocator {
+    #[new]
+    #[pyo3(signature = (flags=0))]
+    pub fn new(flags: u32) -> Self {
+        Self {
+            allocator: new_allocator(flags),
+            id: NEXT_ALLOCATOR_ID.fetch_add(1, Ordering::Relaxed),
+            generation: 0,
+        }
+    }
+
+    pub fn deserialize(&mut self, blob: &[u8]) -> PyResult<AllocatorNode> {
+        let node = node_from_bytes(&mut self.allocator, blob)?;
+        Ok(self.wrap(node))
+    }
+
+    pub fn serialize<'py>(
+        &self,
+        py: Python<'py>,
+        node: &AllocatorNode,
+    ) -> PyResult<Bound<'py, PyBytes>> {
+        let blob = node_to_bytes(&self.allocator, self.unwrap(node)?)?;
+        Ok(PyBytes::new_bound(py, &blob))
+    }
+
+    /// run `program` with `args`, both of which must be nodes in this
+    /// allocator. The result is added to this allocator too
+    pub fn run(
+        &mut self,
+        py: Python,
+        program: &AllocatorNode,
+        args: &AllocatorNode,
+        max_cost: Cost,
+        flags: u32,
+    ) -> PyResult<(u64, AllocatorNode)> {
+        let program = self.unwrap(program)?;
+        let args = self.unwrap(args)?;
+        let dialect = ChiaDialect::new(flags);
+        let allocator = &mut self.allocator;
+        match py.allow_threads(|| run_program(allocator, &dialect, program, args, max_cost)) {
+            Ok(Reduction(cost, node)) => Ok((cost, self.wrap(node))),
+            Err(EvalErr(node, msg, code)) => {
+                let (allocator, node) = self.detach(node).map_err(io::Error::from)?;
+                Err(adapt_error(py, allocator, EvalErr(node, msg, code))?)
+            }
+        }
+    }
+
+    /// a copy of `node` that can be inspected from Python, and that stays
+    /// valid after this allocator is reset
+    pub fn to_lazy_node(&self, node: &AllocatorNode) -> PyResult<LazyNode> {
+        let (allocator, node) = self.detach(self.unwrap(node)?).map_err(io::Error::from)?;
+        Ok(LazyNode::new(Rc::new(allocator), node))
+    }
+
+    /// free all nodes, but keep the memory to be reused. Any AllocatorNode
+    /// created before this is invalid afterwards
+    pub fn reset(&mut self) {
+        self.allocator.reset();
+        self.generation += 1;
+    }
+}
@@ -1,14 +1,16 @@
 use std::io;
 
+use super::allocator::{new_allocator, AllocatorNode, PyAllocator};
 use super::lazy_node::LazyNode;
+use super::serializer::Serializer;
 use crate::adapt_response::adapt_response;
-use clvmr::allocator::Allocator;
+use crate::errors::add_exceptions;
 use clvmr::chia_dialect::ChiaDialect;
 use clvmr::cost::Cost;
 use clvmr::reduction::Response;
 use clvmr::run_program::run_program;
 use clvmr::serde::{node_from_bytes, parse_triples, serialized_length_from_bytes, ParsedTriple};
-use clvmr::{LIMIT_HEAP, MEMPOOL_MODE, NO_UNKNOWN_OPS};
+use clvmr::{LIMIT_ATOM_SIZE, LIMIT_HEAP, MEMPOOL_MODE, NO_UNKNOWN_OPS};
 use pyo3::prelude::*;
 use pyo3::types::{PyBytes, PyTuple};
 use pyo3::wrap_pyfunction;
@@ -26,11 +28,7 @@ pub fn run_serialized_chia_program(
     max_cost: Cost,
     flags: u32,
 ) -> PyResult<(u64, LazyNode)> {
-    let mut allocator = if flags & LIMIT_HEAP != 0 {
-        Allocator::new_limited(500000000)
-    } else {
-        Allocator::new()
-    };
+    let mut allocator = new_allocator(flags);
 
     let r: Response = (|| -> PyResult<Response> {
         let program = node_from_bytes(&mut allocator, program)?;
@@ -83,8 +81,13 @@ fn clvm_rs(_py: Python, m: &Bound<'_, PyModule>) -> PyResult<()> {
 
     m.add("NO_UNKNOWN_OPS", NO_UNKNOWN_OPS)?;
     m.add("LIMIT_HEAP", LIMIT_HEAP)?;
+    m.add("LIMIT_ATOM_SIZE", LIMIT_ATOM_SIZE)?;
     m.add("MEMPOOL_MODE", MEMPOOL_MODE)?;
     m.add_class::<LazyNode>()?;
+    m.add_class::<Serializer>()?;
+    m.add_class::<PyAllocator>()?;
+    m.add_class::<AllocatorNode>()?;
+    add_exceptions(m)?;
 
     Ok(())
 }
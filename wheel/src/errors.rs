@@ -0,0 +1,105 @@
+// create_exception! in pyo3 0.22 checks for a `gil-refs` feature of our crate
+#![allow(unexpected_cfgs)]
+
+use clvmr::error::ErrorCode;
+
+use pyo3::create_exception;
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+
+// all exceptions raised by a failing program derive from ClvmError, which in
+// turn derives from ValueError, so existing `except ValueError` handlers keep
+// working
+create_exception!(
+    clvm_rs,
+    ClvmError,
+    PyValueError,
+    "A CLVM program failed. args is (message, node), and the `code`, `message` \
+     and `node` attributes hold the stable error code, the message and the \
+     serialized offending node."
+);
+create_exception!(clvm_rs, CostExceededError, ClvmError);
+create_exception!(clvm_rs, OutOfMemoryError, ClvmError);
+create_exception!(clvm_rs, TooManyAtomsError, ClvmError);
+create_exception!(clvm_rs, TooManyPairsError, ClvmError);
+create_exception!(
+    clvm_rs,
+    ResourceLimitError,
+    ClvmError,
+    "An interpreter limit (stack depth, reductions, atom size) was exceeded."
+);
+create_exception!(clvm_rs, RaiseError, ClvmError, "The program called `x`.");
+create_exception!(clvm_rs, InvalidOperatorError, ClvmError);
+create_exception!(
+    clvm_rs,
+    InvalidOperatorArg,
+    ClvmError,
+    "An operator was passed the wrong number of arguments, or an argument of \
+     the wrong type or value."
+);
+create_exception!(clvm_rs, VerifyFailedError, ClvmError);
+
+/// pick the exception class for an error code
+pub fn new_err(code: ErrorCode, args: PyObject) -> PyErr {
+    match code {
+        ErrorCode::CostExceeded => CostExceededError::new_err(args),
+        ErrorCode::OutOfMemory => OutOfMemoryError::new_err(args),
+        ErrorCode::TooManyAtoms => TooManyAtomsError::new_err(args),
+        ErrorCode::TooManyPairs => TooManyPairsError::new_err(args),
+        ErrorCode::TooManyReductions
+        | ErrorCode::ValueStackLimit
+        | ErrorCode::EnvironmentStackLimit
+        | ErrorCode::AtomTooLarge
+        | ErrorCode::LimitExceeded => ResourceLimitError::new_err(args),
+        ErrorCode::Raise => RaiseError::new_err(args),
+        ErrorCode::InvalidOperator => InvalidOperatorError::new_err(args),
+        ErrorCode::InvalidArgCount
+        | ErrorCode::InvalidArgType
+        | ErrorCode::InvalidArgValue
+        | ErrorCode::PathIntoAtom
+        | ErrorCode::FirstOfNonCons
+        | ErrorCode::RestOfNonCons
+        | ErrorCode::Softfork => InvalidOperatorArg::new_err(args),
+        ErrorCode::VerifyFailed => VerifyFailedError::new_err(args),
+        ErrorCode::Unknown
+        | ErrorCode::Cancelled
+        | ErrorCode::DeadlineExceeded
+        | ErrorCode::InternalError => ClvmError::new_err(args),
+    }
+}
+
+pub fn add_exceptions(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    let py = m.py();
+    m.add("ClvmError", py.get_type_bound::<ClvmError>())?;
+    m.add(
+        "CostExceededError",
+        py.get_type_bound::<CostExceededError>(),
+    )?;
+    m.add("OutOfMemoryError", py.get_type_bound::<OutOfMemoryError>())?;
+    m.add(
+        "TooManyAtomsError",
+        py.get_type_bound::<TooManyAtomsError>(),
+    )?;
+    m.add(
+        "TooManyPairsError",
+        py.get_type_bound::<TooManyPairsError>(),
+    )?;
+    m.add(
+        "ResourceLimitError",
+        py.get_type_bound::<ResourceLimitError>(),
+    )?;
+    m.add("RaiseError", py.get_type_bound::<RaiseError>())?;
+    m.add(
+        "InvalidOperatorError",
+        py.get_type_bound::<InvalidOperatorError>(),
+    )?;
+    m.add(
+        "InvalidOperatorArg",
+        py.get_type_bound::<InvalidOperatorArg>(),
+    )?;
+    m.add(
+        "VerifyFailedError",
+        py.get_type_bound::<VerifyFailedError>(),
+    )?;
+    Ok(())
+}
@@ -1,3 +1,9 @@
+// pyo3 0.22 macro expansion trips this lint on newer toolchains
+#![allow(clippy::useless_conversion)]
+
 mod adapt_response;
+pub mod allocator;
 pub mod api;
+mod errors;
 pub mod lazy_node;
+pub mod serializer;
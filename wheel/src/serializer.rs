@@ -0,0 +1,90 @@
+use std::io;
+
+use clvmr::allocator::{Allocator, NodePtr};
+use clvmr::serde::{node_from_bytes_backrefs, Serializer as IncrementalSerializer, UndoState};
+
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use pyo3::types::PyBytes;
+
+/// Incrementally builds the serialization (with back references) of a list,
+/// one item at a time. Items can be removed again with undo(), e.g. when the
+/// result grew too large.
+#[pyclass(unsendable)]
+pub struct Serializer {
+    allocator: Allocator,
+    sentinel: NodePtr,
+    // this is None once done() has been called
+    inner: Option<IncrementalSerializer>,
+    undo_stack: Vec<UndoState>,
+}
+
+impl Serializer {
+    fn inner(&mut self) -> PyResult<&mut IncrementalSerializer> {
+        self.inner
+            .as_mut()
+            .ok_or_else(|| PyValueError::new_err("serializer is done"))
+    }
+}
+
+#[pymethods]
+impl Serializer {
+    #[new]
+    pub fn new() -> PyResult<Self> {
+        let mut allocator = Allocator::new();
+        let sentinel = allocator
+            .new_pair(NodePtr::NIL, NodePtr::NIL)
+            .map_err(io::Error::from)?;
+        Ok(Self {
+            allocator,
+            sentinel,
+            inner: Some(IncrementalSerializer::new(Some(sentinel))),
+            undo_stack: vec![],
+        })
+    }
+
+    /// append the serialized CLVM structure in blob (which may use back
+    /// references) to the list
+    pub fn add(&mut self, blob: &[u8]) -> PyResult<()> {
+        let item = node_from_bytes_backrefs(&mut self.allocator, blob)
+            .map_err(|e| PyValueError::new_err(e.to_string()))?;
+        let node = self
+            .allocator
+            .new_pair(item, self.sentinel)
+            .map_err(io::Error::from)?;
+        let (_, undo_state) = self
+            .inner
+            .as_mut()
+            .ok_or_else(|| PyValueError::new_err("serializer is done"))?
+            .add(&self.allocator, node)?;
+        self.undo_stack.push(undo_state);
+        Ok(())
+    }
+
+    /// remove the most recently added item
+    pub fn undo(&mut self) -> PyResult<()> {
+        let Some(state) = self.undo_stack.pop() else {
+            return Err(PyValueError::new_err("nothing to undo"));
+        };
+        self.inner()?.restore(state);
+        Ok(())
+    }
+
+    /// terminate the list and return its serialization. No more items can be
+    /// added after this
+    pub fn done<'py>(&mut self, py: Python<'py>) -> PyResult<Bound<'py, PyBytes>> {
+        let mut inner = self
+            .inner
+            .take()
+            .ok_or_else(|| PyValueError::new_err("serializer is done"))?;
+        inner.add(&self.allocator, NodePtr::NIL)?;
+        self.undo_stack.clear();
+        Ok(PyBytes::new_bound(py, &inner.into_inner()))
+    }
+
+    /// the number of bytes serialized so far
+    #[getter]
+    pub fn size(&self) -> u64 {
+        self.inner.as_ref().map_or(0, |s| s.size())
+    }
+}